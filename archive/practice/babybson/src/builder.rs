@@ -0,0 +1,844 @@
+// builder writes BSON documents. See https://bsonspec.org/spec.html.
+use std::fmt;
+
+// Binary subtypes.
+pub const BINARY_SUBTYPE_GENERIC : u8 = 0x00;
+pub const BINARY_SUBTYPE_FUNCTION : u8 = 0x01;
+pub const BINARY_SUBTYPE_BINARY_OLD : u8 = 0x02;
+pub const BINARY_SUBTYPE_UUID_OLD : u8 = 0x03;
+pub const BINARY_SUBTYPE_UUID : u8 = 0x04;
+pub const BINARY_SUBTYPE_MD5 : u8 = 0x05;
+pub const BINARY_SUBTYPE_ENCRYPTED : u8 = 0x06;
+pub const BINARY_SUBTYPE_COLUMN : u8 = 0x07;
+pub const BINARY_SUBTYPE_SENSITIVE : u8 = 0x08;
+pub const BINARY_SUBTYPE_USER_DEFINED : u8 = 0x80;
+
+// MAX_DOCUMENT_SIZE is the largest size a length prefix can hold.
+pub const MAX_DOCUMENT_SIZE : usize = i32::MAX as usize;
+// MAX_NESTING_DEPTH is the default limit of nested documents and arrays. MongoDB rejects deeper documents.
+pub const MAX_NESTING_DEPTH : usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildError {
+    // `build` was called before every document and array was ended. The value is the number still open.
+    UnclosedDocument (usize),
+    // `end_document` or `end_array` was called with nothing open.
+    NoOpenDocument,
+    // `end_document` was called to end an array, or `end_array` to end a document.
+    MismatchedEnd,
+    // The document would exceed the maximum size. The value is the size it would have.
+    TooLarge (usize),
+    // A key, regex pattern or regex options contains a NULL byte, so it cannot be written as a cstring.
+    ContainsNul (String),
+    // Starting a document or array would exceed the maximum nesting depth.
+    MaxDepthExceeded (usize),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt (&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::UnclosedDocument (n) => write!(f, "{} document(s) not ended", n),
+            BuildError::NoOpenDocument => write!(f, "no document to end"),
+            BuildError::MismatchedEnd => write!(f, "document ended as an array, or array ended as a document"),
+            BuildError::TooLarge (size) => write!(f, "document size {} exceeds the maximum", size),
+            BuildError::ContainsNul (s) => write!(f, "{:?} contains a NULL byte", s),
+            BuildError::MaxDepthExceeded (depth) => write!(f, "nesting depth exceeds {}", depth),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+// BSONBuilder appends elements to a document. Every method returns an error, and leaves the builder unchanged,
+// if the element cannot be written.
+pub struct BSONBuilder {
+    data : Vec<u8>,
+    // index_stack is a stack of indexes pointing to the first byte of a length in a nested document.
+    index_stack : Vec<usize>,
+    // array_stack has one entry per entry of index_stack. The entry is the next index if the nested document is an array.
+    array_stack : Vec<Option<usize>>,
+    max_size : usize,
+    max_depth : usize,
+}
+
+impl Default for BSONBuilder {
+    fn default () -> BSONBuilder {
+        BSONBuilder::new()
+    }
+}
+
+impl BSONBuilder {
+    pub fn new () -> BSONBuilder {
+        BSONBuilder{
+            // Add space for the length.
+            data: vec![0, 0, 0, 0],
+            index_stack: Vec::new(),
+            array_stack: Vec::new(),
+            max_size: MAX_DOCUMENT_SIZE,
+            max_depth: MAX_NESTING_DEPTH,
+        }
+    }
+
+    // set_max_size limits the size of built documents, e.g. to 16MiB for MongoDB. It cannot exceed MAX_DOCUMENT_SIZE.
+    pub fn set_max_size (&mut self, max_size : usize) {
+        self.max_size = max_size.min(MAX_DOCUMENT_SIZE);
+    }
+
+    // set_max_depth limits the number of documents and arrays that can be open at once.
+    pub fn set_max_depth (&mut self, max_depth : usize) {
+        self.max_depth = max_depth;
+    }
+
+    // usize_to_i32 converts a length. Lengths fit, since the document size is checked before anything is appended.
+    fn usize_to_i32 (x : usize) -> i32 {
+        x.try_into().unwrap()
+    }
+
+    fn _check_cstring (s : &str) -> Result<(), BuildError> {
+        if s.contains('\0') {
+            return Err(BuildError::ContainsNul (s.to_string()));
+        }
+        Ok(())
+    }
+
+    fn _append_key (&mut self, key : &str) {
+        for b in key.bytes() {
+            self.data.push(b);
+        }
+        // Push NULL byte.
+        self.data.push(0);
+    }
+
+    fn _append_bytes (&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    // _append_element checks that an element with a value of `value_len` bytes fits, then appends its type and key.
+    // Inside an array, `key` is ignored and the next array index is used instead.
+    fn _append_element (&mut self, element_type : u8, key : &str, value_len : usize) -> Result<(), BuildError> {
+        let index = match self.array_stack.last() {
+            Some(Some(next)) => Some(next.to_string()),
+            _ => None,
+        };
+        let key = match &index {
+            Some(index) => index,
+            None => {
+                BSONBuilder::_check_cstring (key)?;
+                key
+            }
+        };
+        // Count the terminators of the open documents and the outermost document.
+        let size = self.data.len() + 1 + key.len() + 1 + value_len + self.index_stack.len() + 1;
+        if size > self.max_size {
+            return Err(BuildError::TooLarge (size));
+        }
+        self.data.push(element_type);
+        self._append_key (key);
+        if let Some(Some(next)) = self.array_stack.last_mut() {
+            *next += 1;
+        }
+        Ok(())
+    }
+
+    // _append_string appends the length, bytes, and NULL byte of a string.
+    fn _append_string (&mut self, value : &str) {
+        let len = BSONBuilder::usize_to_i32 (value.len() + 1);
+        self._append_bytes (&len.to_le_bytes());
+        self._append_bytes (value.as_bytes());
+        self.data.push(0);
+    }
+
+    pub fn append_double (&mut self, key : &str, value : f64) -> Result<(), BuildError> {
+        self._append_element (0x01, key, 8)?;
+        self._append_bytes (&value.to_le_bytes());
+        Ok(())
+    }
+
+    pub fn append_string (&mut self, key : &str, value : &str) -> Result<(), BuildError> {
+        self._append_element (0x02, key, 4 + value.len() + 1)?;
+        self._append_string (value);
+        Ok(())
+    }
+
+    // append_binary appends binary data. See the BINARY_SUBTYPE_ constants for `subtype`.
+    pub fn append_binary (&mut self, key : &str, subtype : u8, value : &[u8]) -> Result<(), BuildError> {
+        if subtype == BINARY_SUBTYPE_BINARY_OLD {
+            // The old binary subtype repeats the length inside the data.
+            self._append_element (0x05, key, 4 + 1 + 4 + value.len())?;
+            let len = BSONBuilder::usize_to_i32 (value.len() + 4);
+            self._append_bytes (&len.to_le_bytes());
+            self.data.push(subtype);
+            self._append_bytes (&BSONBuilder::usize_to_i32 (value.len()).to_le_bytes());
+        } else {
+            self._append_element (0x05, key, 4 + 1 + value.len())?;
+            let len = BSONBuilder::usize_to_i32 (value.len());
+            self._append_bytes (&len.to_le_bytes());
+            self.data.push(subtype);
+        }
+        self._append_bytes (value);
+        Ok(())
+    }
+
+    pub fn append_objectid (&mut self, key : &str, value : &[u8; 12]) -> Result<(), BuildError> {
+        self._append_element (0x07, key, 12)?;
+        self._append_bytes (value);
+        Ok(())
+    }
+
+    pub fn append_bool (&mut self, key : &str, value : bool) -> Result<(), BuildError> {
+        self._append_element (0x08, key, 1)?;
+        self.data.push(value as u8);
+        Ok(())
+    }
+
+    // append_datetime appends a UTC datetime. `value` is milliseconds since the Unix epoch.
+    pub fn append_datetime (&mut self, key : &str, value : i64) -> Result<(), BuildError> {
+        self._append_element (0x09, key, 8)?;
+        self._append_bytes (&value.to_le_bytes());
+        Ok(())
+    }
+
+    pub fn append_null (&mut self, key : &str) -> Result<(), BuildError> {
+        self._append_element (0x0A, key, 0)
+    }
+
+    // append_regex appends a regular expression. The options are sorted, as the spec requires.
+    pub fn append_regex (&mut self, key : &str, pattern : &str, options : &str) -> Result<(), BuildError> {
+        BSONBuilder::_check_cstring (pattern)?;
+        BSONBuilder::_check_cstring (options)?;
+        self._append_element (0x0B, key, pattern.len() + 1 + options.len() + 1)?;
+        self._append_key (pattern);
+        let mut options : Vec<char> = options.chars().collect();
+        options.sort();
+        self._append_key (&options.into_iter().collect::<String>());
+        Ok(())
+    }
+
+    pub fn append_javascript (&mut self, key : &str, code : &str) -> Result<(), BuildError> {
+        self._append_element (0x0D, key, 4 + code.len() + 1)?;
+        self._append_string (code);
+        Ok(())
+    }
+
+    pub fn append_int32 (&mut self, key : &str, value : i32) -> Result<(), BuildError> {
+        // Append type and key.
+        self._append_element (0x10, key, 4)?;
+        // Convert value to Little Endian and append.
+        let bytes = value.to_le_bytes();
+        self._append_bytes (&bytes);
+        Ok(())
+    }
+
+    // append_timestamp appends an internal MongoDB timestamp: seconds since the Unix epoch and an increment.
+    pub fn append_timestamp (&mut self, key : &str, timestamp : u32, increment : u32) -> Result<(), BuildError> {
+        self._append_element (0x11, key, 8)?;
+        // The increment is the low 32 bits.
+        self._append_bytes (&increment.to_le_bytes());
+        self._append_bytes (&timestamp.to_le_bytes());
+        Ok(())
+    }
+
+    pub fn append_int64 (&mut self, key : &str, value : i64) -> Result<(), BuildError> {
+        self._append_element (0x12, key, 8)?;
+        self._append_bytes (&value.to_le_bytes());
+        Ok(())
+    }
+
+    // append_decimal128 appends the 16 bytes of an IEEE 754-2008 decimal128, in little endian.
+    pub fn append_decimal128 (&mut self, key : &str, value : &[u8; 16]) -> Result<(), BuildError> {
+        self._append_element (0x13, key, 16)?;
+        self._append_bytes (value);
+        Ok(())
+    }
+
+    pub fn append_minkey (&mut self, key : &str) -> Result<(), BuildError> {
+        self._append_element (0xFF, key, 0)
+    }
+
+    pub fn append_maxkey (&mut self, key : &str) -> Result<(), BuildError> {
+        self._append_element (0x7F, key, 0)
+    }
+
+    fn _start (&mut self, element_type : u8, key : &str, array : bool) -> Result<(), BuildError> {
+        if self.index_stack.len() >= self.max_depth {
+            return Err(BuildError::MaxDepthExceeded (self.max_depth));
+        }
+        // Append type and key. The value is the length and the terminator.
+        self._append_element (element_type, key, 5)?;
+        // Save position for location of length bytes.
+        self.index_stack.push(self.data.len());
+        self.array_stack.push(if array { Some(0) } else { None });
+        // Reserve length.
+        self.data.extend_from_slice(&[0;4]);
+        Ok(())
+    }
+
+    pub fn start_document (&mut self, key : &str) -> Result<(), BuildError> {
+        self._start (0x03, key, false)
+    }
+
+    // start_array starts an array. Elements appended until `end_array` get the keys "0", "1", "2", ...
+    pub fn start_array (&mut self, key : &str) -> Result<(), BuildError> {
+        self._start (0x04, key, true)
+    }
+
+    fn _end (&mut self, array : bool) -> Result<(), BuildError> {
+        match self.array_stack.last() {
+            None => return Err(BuildError::NoOpenDocument),
+            Some(index) if index.is_some() != array => return Err(BuildError::MismatchedEnd),
+            _ => {}
+        }
+        let start_index = self.index_stack.pop().unwrap();
+        self.array_stack.pop();
+        // Push null byte. Its space was counted when the document was started.
+        self.data.push(0x00);
+        let document_len = BSONBuilder::usize_to_i32 (self.data.len() - start_index);
+        self.data[start_index..start_index + 4].copy_from_slice(&document_len.to_le_bytes());
+        Ok(())
+    }
+
+    pub fn end_document (&mut self) -> Result<(), BuildError> {
+        self._end (false)
+    }
+
+    pub fn end_array (&mut self) -> Result<(), BuildError> {
+        // An array is encoded like a document.
+        self._end (true)
+    }
+
+    // build returns the document and resets the builder.
+    pub fn build(&mut self) -> Result<Vec<u8>, BuildError> {
+        if !self.index_stack.is_empty() {
+            return Err(BuildError::UnclosedDocument (self.index_stack.len()));
+        }
+        // Set the starting length of the document.
+        // Add one for trailing NULL byte.
+        let len = BSONBuilder::usize_to_i32 (self.data.len() + 1);
+        self.data[0..4].copy_from_slice(&len.to_le_bytes());
+
+        // Add a NULL byte.
+        self.data.push(0);
+
+        // Swap and reset data.
+        // `build` does not own `self.data`. Do a replace so `self.data` is not deinitialized.
+        Ok(core::mem::replace(&mut self.data, vec![0, 0, 0, 0]))
+    }
+}
+
+#[test]
+fn test_empty () {
+    let mut bb = BSONBuilder::new();
+    let got = bb.build().unwrap();
+    assert_eq!(got, vec![5,0,0,0,0]);
+}
+
+#[test]
+fn test_int32 () {
+    let mut bb = BSONBuilder::new();
+    bb.append_int32 ("x", 1).unwrap();
+    let got = bb.build().unwrap();
+    assert_eq!(got, vec![0x0c, 0x00, 0x00, 0x00, 0x10, 0x78, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00]);
+}
+
+#[test]
+fn test_nested_document () {
+    let mut bb = BSONBuilder::new();
+    bb.start_document("x").unwrap();
+    bb.append_int32 ("y", 1).unwrap();
+    bb.end_document().unwrap();
+    let got = bb.build().unwrap();
+    assert_eq!(got, vec![0x14, 0x00, 0x00, 0x00, 0x03, 0x78, 0x00, 0x0c, 0x00, 0x00, 0x00, 0x10, 0x79, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00]);
+}
+
+#[test]
+fn test_double () {
+    let mut bb = BSONBuilder::new();
+    bb.append_double ("d", 1.0).unwrap();
+    let got = bb.build().unwrap();
+    assert_eq!(got, vec![0x10, 0x00, 0x00, 0x00, 0x01, 0x64, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xf0, 0x3f, 0x00]);
+}
+
+#[test]
+fn test_string () {
+    let mut bb = BSONBuilder::new();
+    bb.append_string ("a", "b").unwrap();
+    bb.append_string ("e", "").unwrap();
+    let got = bb.build().unwrap();
+    assert_eq!(got, vec![
+        0x16, 0x00, 0x00, 0x00,
+        0x02, 0x61, 0x00, 0x02, 0x00, 0x00, 0x00, 0x62, 0x00,
+        0x02, 0x65, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00,
+        0x00]);
+}
+
+#[test]
+fn test_array () {
+    let mut bb = BSONBuilder::new();
+    bb.start_array ("a").unwrap();
+    // Keys are ignored in arrays.
+    bb.append_int32 ("ignored", 10).unwrap();
+    bb.append_string ("", "x").unwrap();
+    bb.start_document ("").unwrap();
+    bb.append_bool ("b", true).unwrap();
+    bb.end_document ().unwrap();
+    bb.end_array ().unwrap();
+    let got = bb.build().unwrap();
+    assert_eq!(got, vec![
+        0x29, 0x00, 0x00, 0x00,
+        0x04, 0x61, 0x00, 0x21, 0x00, 0x00, 0x00,
+        0x10, 0x30, 0x00, 0x0a, 0x00, 0x00, 0x00,
+        0x02, 0x31, 0x00, 0x02, 0x00, 0x00, 0x00, 0x78, 0x00,
+        0x03, 0x32, 0x00, 0x09, 0x00, 0x00, 0x00, 0x08, 0x62, 0x00, 0x01, 0x00,
+        0x00,
+        0x00]);
+}
+
+#[test]
+fn test_nested_arrays () {
+    let mut bb = BSONBuilder::new();
+    bb.start_array ("a").unwrap();
+    bb.start_array ("").unwrap();
+    bb.append_null ("").unwrap();
+    bb.end_array ().unwrap();
+    bb.append_null ("").unwrap();
+    bb.end_array ().unwrap();
+    // Keys are used again after the array ends.
+    bb.append_null ("n").unwrap();
+    let got = bb.build().unwrap();
+    assert_eq!(got, vec![
+        0x1e, 0x00, 0x00, 0x00,
+        0x04, 0x61, 0x00, 0x13, 0x00, 0x00, 0x00,
+        0x04, 0x30, 0x00, 0x08, 0x00, 0x00, 0x00, 0x0a, 0x30, 0x00, 0x00,
+        0x0a, 0x31, 0x00,
+        0x00,
+        0x0a, 0x6e, 0x00,
+        0x00]);
+}
+
+#[test]
+fn test_binary () {
+    let mut bb = BSONBuilder::new();
+    bb.append_binary ("x", BINARY_SUBTYPE_GENERIC, &[0xff, 0x01]).unwrap();
+    bb.append_binary ("y", BINARY_SUBTYPE_USER_DEFINED, &[]).unwrap();
+    let got = bb.build().unwrap();
+    assert_eq!(got, vec![
+        0x17, 0x00, 0x00, 0x00,
+        0x05, 0x78, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0xff, 0x01,
+        0x05, 0x79, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80,
+        0x00]);
+}
+
+#[test]
+fn test_binary_subtypes () {
+    let subtypes = [
+        BINARY_SUBTYPE_FUNCTION,
+        BINARY_SUBTYPE_UUID_OLD,
+        BINARY_SUBTYPE_UUID,
+        BINARY_SUBTYPE_MD5,
+        BINARY_SUBTYPE_ENCRYPTED,
+        BINARY_SUBTYPE_COLUMN,
+        BINARY_SUBTYPE_SENSITIVE,
+    ];
+    for subtype in subtypes {
+        let mut bb = BSONBuilder::new();
+        bb.append_binary ("x", subtype, &[0xab]).unwrap();
+        let got = bb.build().unwrap();
+        assert_eq!(got, vec![0x0e, 0x00, 0x00, 0x00, 0x05, 0x78, 0x00, 0x01, 0x00, 0x00, 0x00, subtype, 0xab, 0x00]);
+    }
+}
+
+#[test]
+fn test_binary_old () {
+    // The old binary subtype has a second length inside the data.
+    let mut bb = BSONBuilder::new();
+    bb.append_binary ("x", BINARY_SUBTYPE_BINARY_OLD, &[0xff, 0xff]).unwrap();
+    let got = bb.build().unwrap();
+    assert_eq!(got, vec![
+        0x13, 0x00, 0x00, 0x00,
+        0x05, 0x78, 0x00, 0x06, 0x00, 0x00, 0x00, 0x02, 0x02, 0x00, 0x00, 0x00, 0xff, 0xff,
+        0x00]);
+}
+
+#[test]
+fn test_objectid () {
+    let mut bb = BSONBuilder::new();
+    bb.append_objectid ("a", &[0x56, 0xe1, 0xfc, 0x72, 0xe0, 0xc9, 0x17, 0xe9, 0xc4, 0x71, 0x41, 0x61]).unwrap();
+    let got = bb.build().unwrap();
+    assert_eq!(got, vec![
+        0x14, 0x00, 0x00, 0x00,
+        0x07, 0x61, 0x00, 0x56, 0xe1, 0xfc, 0x72, 0xe0, 0xc9, 0x17, 0xe9, 0xc4, 0x71, 0x41, 0x61,
+        0x00]);
+}
+
+#[test]
+fn test_bool () {
+    let mut bb = BSONBuilder::new();
+    bb.append_bool ("t", true).unwrap();
+    bb.append_bool ("f", false).unwrap();
+    let got = bb.build().unwrap();
+    assert_eq!(got, vec![0x0d, 0x00, 0x00, 0x00, 0x08, 0x74, 0x00, 0x01, 0x08, 0x66, 0x00, 0x00, 0x00]);
+}
+
+#[test]
+fn test_datetime () {
+    let mut bb = BSONBuilder::new();
+    // 2012-12-24T12:15:30.501Z.
+    bb.append_datetime ("a", 1356351330501).unwrap();
+    bb.append_datetime ("b", -1).unwrap();
+    let got = bb.build().unwrap();
+    assert_eq!(got, vec![
+        0x1b, 0x00, 0x00, 0x00,
+        0x09, 0x61, 0x00, 0xc5, 0xd8, 0xd6, 0xcc, 0x3b, 0x01, 0x00, 0x00,
+        0x09, 0x62, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0x00]);
+}
+
+#[test]
+fn test_null () {
+    let mut bb = BSONBuilder::new();
+    bb.append_null ("a").unwrap();
+    let got = bb.build().unwrap();
+    assert_eq!(got, vec![0x08, 0x00, 0x00, 0x00, 0x0a, 0x61, 0x00, 0x00]);
+}
+
+#[test]
+fn test_regex () {
+    let mut bb = BSONBuilder::new();
+    // Options are sorted.
+    bb.append_regex ("a", "abc", "mi").unwrap();
+    bb.append_regex ("b", "", "").unwrap();
+    let got = bb.build().unwrap();
+    assert_eq!(got, vec![
+        0x14, 0x00, 0x00, 0x00,
+        0x0b, 0x61, 0x00, 0x61, 0x62, 0x63, 0x00, 0x69, 0x6d, 0x00,
+        0x0b, 0x62, 0x00, 0x00, 0x00,
+        0x00]);
+}
+
+#[test]
+fn test_javascript () {
+    let mut bb = BSONBuilder::new();
+    bb.append_javascript ("a", "b").unwrap();
+    let got = bb.build().unwrap();
+    assert_eq!(got, vec![0x0e, 0x00, 0x00, 0x00, 0x0d, 0x61, 0x00, 0x02, 0x00, 0x00, 0x00, 0x62, 0x00, 0x00]);
+}
+
+#[test]
+fn test_int64 () {
+    let mut bb = BSONBuilder::new();
+    bb.append_int64 ("a", -2).unwrap();
+    let got = bb.build().unwrap();
+    assert_eq!(got, vec![0x10, 0x00, 0x00, 0x00, 0x12, 0x61, 0x00, 0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00]);
+}
+
+#[test]
+fn test_timestamp () {
+    let mut bb = BSONBuilder::new();
+    bb.append_timestamp ("a", 123456789, 42).unwrap();
+    let got = bb.build().unwrap();
+    // The increment comes first.
+    assert_eq!(got, vec![0x10, 0x00, 0x00, 0x00, 0x11, 0x61, 0x00, 0x2a, 0x00, 0x00, 0x00, 0x15, 0xcd, 0x5b, 0x07, 0x00]);
+}
+
+#[test]
+fn test_decimal128 () {
+    let mut bb = BSONBuilder::new();
+    // 1 is a coefficient of 1 and an exponent of 0 (0x3040 with the bias).
+    bb.append_decimal128 ("d", &[0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x40, 0x30]).unwrap();
+    let got = bb.build().unwrap();
+    assert_eq!(got, vec![
+        0x18, 0x00, 0x00, 0x00,
+        0x13, 0x64, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x30,
+        0x00]);
+}
+
+#[test]
+fn test_minkey_maxkey () {
+    let mut bb = BSONBuilder::new();
+    bb.append_minkey ("a").unwrap();
+    bb.append_maxkey ("b").unwrap();
+    let got = bb.build().unwrap();
+    assert_eq!(got, vec![0x0b, 0x00, 0x00, 0x00, 0xff, 0x61, 0x00, 0x7f, 0x62, 0x00, 0x00]);
+}
+
+#[test]
+fn test_length_over_16mb () {
+    // The fourth length byte used to be dropped.
+    let mut bb = BSONBuilder::new();
+    let value = "x".repeat(20 << 20);
+    bb.start_document ("d").unwrap();
+    bb.append_string ("s", &value).unwrap();
+    bb.end_document ().unwrap();
+    let got = bb.build().unwrap();
+    assert_eq!(i32::from_le_bytes(got[0..4].try_into().unwrap()) as usize, got.len());
+    assert_eq!(i32::from_le_bytes(got[7..11].try_into().unwrap()) as usize, got.len() - 8);
+}
+
+#[test]
+fn test_build_unclosed () {
+    let mut bb = BSONBuilder::new();
+    bb.start_document ("a").unwrap();
+    bb.start_array ("b").unwrap();
+    assert_eq!(bb.build(), Err(BuildError::UnclosedDocument (2)));
+    // The builder is unchanged, so the documents can still be ended.
+    bb.end_array ().unwrap();
+    bb.end_document ().unwrap();
+    assert_eq!(bb.build().unwrap().len(), 21);
+}
+
+#[test]
+fn test_end_errors () {
+    let mut bb = BSONBuilder::new();
+    assert_eq!(bb.end_document (), Err(BuildError::NoOpenDocument));
+    assert_eq!(bb.end_array (), Err(BuildError::NoOpenDocument));
+    bb.start_array ("a").unwrap();
+    assert_eq!(bb.end_document (), Err(BuildError::MismatchedEnd));
+    bb.start_document ("").unwrap();
+    assert_eq!(bb.end_array (), Err(BuildError::MismatchedEnd));
+}
+
+#[test]
+fn test_contains_nul () {
+    let mut bb = BSONBuilder::new();
+    assert_eq!(bb.append_int32 ("a\0b", 1), Err(BuildError::ContainsNul ("a\0b".to_string())));
+    assert_eq!(bb.start_document ("\0"), Err(BuildError::ContainsNul ("\0".to_string())));
+    assert_eq!(bb.append_regex ("r", "a\0", ""), Err(BuildError::ContainsNul ("a\0".to_string())));
+    assert_eq!(bb.append_regex ("r", "a", "\0"), Err(BuildError::ContainsNul ("\0".to_string())));
+    // Strings may contain NULL bytes.
+    bb.append_string ("s", "a\0b").unwrap();
+    // Keys are ignored in arrays.
+    bb.start_array ("a").unwrap();
+    bb.append_null ("\0").unwrap();
+    bb.end_array ().unwrap();
+    assert_eq!(bb.build().unwrap().len(), 27);
+}
+
+#[test]
+fn test_too_large () {
+    let mut bb = BSONBuilder::new();
+    bb.set_max_size (20);
+    // 4 + (1 + 2 + 4) + 1 = 12 bytes.
+    bb.append_int32 ("a", 1).unwrap();
+    assert_eq!(bb.append_string ("b", "xyz"), Err(BuildError::TooLarge (23)));
+    bb.start_document ("c").unwrap();
+    // The terminators of the open documents are counted.
+    assert_eq!(bb.append_null ("d"), Err(BuildError::TooLarge (23)));
+    bb.end_document ().unwrap();
+    assert_eq!(bb.build().unwrap().len(), 20);
+}
+
+#[test]
+fn test_max_depth () {
+    let mut bb = BSONBuilder::new();
+    bb.set_max_depth (2);
+    bb.start_document ("a").unwrap();
+    bb.start_array ("b").unwrap();
+    assert_eq!(bb.start_document ("c"), Err(BuildError::MaxDepthExceeded (2)));
+    assert_eq!(bb.start_array ("c"), Err(BuildError::MaxDepthExceeded (2)));
+    bb.end_array ().unwrap();
+    bb.start_array ("c").unwrap();
+
+    let mut bb = BSONBuilder::new();
+    for _ in 0..MAX_NESTING_DEPTH {
+        bb.start_document ("a").unwrap();
+    }
+    assert_eq!(bb.start_document ("a"), Err(BuildError::MaxDepthExceeded (MAX_NESTING_DEPTH)));
+}
+
+// Property tests check that any sequence of builder calls yields a valid document that matches a model.
+#[cfg(test)]
+mod property_tests {
+    use super::*;
+    use crate::reader::BsonRef;
+    use proptest::prelude::*;
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Int32 (String, i32),
+        String (String, String),
+        Binary (String, u8, Vec<u8>),
+        Regex (String, String, String),
+        Null (String),
+        StartDocument (String),
+        StartArray (String),
+        EndDocument,
+        EndArray,
+        Build,
+    }
+
+    // Keys are sometimes empty or contain a NULL byte.
+    fn key () -> impl Strategy<Value = String> {
+        "[ab\\x00]{0,2}"
+    }
+
+    fn op () -> impl Strategy<Value = Op> {
+        prop_oneof![
+            (key(), any::<i32>()).prop_map(|(k, v)| Op::Int32 (k, v)),
+            (key(), ".{0,8}").prop_map(|(k, v)| Op::String (k, v)),
+            (key(), 0u8..=3, prop::collection::vec(any::<u8>(), 0..8)).prop_map(|(k, t, v)| Op::Binary (k, t, v)),
+            (key(), key(), "[imx]{0,3}").prop_map(|(k, p, o)| Op::Regex (k, p, o)),
+            key().prop_map(Op::Null),
+            key().prop_map(Op::StartDocument),
+            key().prop_map(Op::StartArray),
+            Just(Op::EndDocument),
+            Just(Op::EndArray),
+            Just(Op::Build),
+        ]
+    }
+
+    // Node is the expected type of an element, or the expected elements of a document or array.
+    #[derive(Debug)]
+    enum Node {
+        Leaf (u8),
+        Document (Vec<(String, Node)>),
+        Array (Vec<(String, Node)>),
+    }
+
+    // Frame is an open document or array of the model.
+    struct Frame {
+        key : String,
+        array : bool,
+        elements : Vec<(String, Node)>,
+    }
+
+    // Model mirrors the builder. The first frame is the outermost document.
+    struct Model {
+        frames : Vec<Frame>,
+        max_depth : usize,
+    }
+
+    impl Model {
+        fn new (max_depth : usize) -> Model {
+            Model { frames: vec![Frame { key: String::new(), array: false, elements: Vec::new() }], max_depth }
+        }
+
+        // key returns the key the builder uses, or the error for an invalid key.
+        fn key (&self, key : &str) -> Result<String, BuildError> {
+            let frame = self.frames.last().unwrap();
+            if frame.array {
+                return Ok(frame.elements.len().to_string());
+            }
+            if key.contains('\0') {
+                return Err(BuildError::ContainsNul (key.to_string()));
+            }
+            Ok(key.to_string())
+        }
+
+        fn push (&mut self, key : String, node : Node) {
+            self.frames.last_mut().unwrap().elements.push((key, node));
+        }
+
+        fn end (&mut self) {
+            let frame = self.frames.pop().unwrap();
+            let node = if frame.array { Node::Array (frame.elements) } else { Node::Document (frame.elements) };
+            self.push(frame.key, node);
+        }
+    }
+
+    // check compares a document read back with the elements of the model.
+    fn check (doc : BsonRef, want : &[(String, Node)]) {
+        let got : Vec<_> = doc.iter().map(|e| e.unwrap()).collect();
+        assert_eq!(got.len(), want.len());
+        for ((key, value), (want_key, want_node)) in got.iter().zip(want) {
+            assert_eq!(key, want_key);
+            match want_node {
+                Node::Leaf (t) => assert_eq!(value.element_type(), *t),
+                Node::Document (elements) => check (value.as_document().unwrap(), elements),
+                Node::Array (elements) => check (value.as_array().unwrap(), elements),
+            }
+        }
+    }
+
+    fn check_built (data : &[u8], model : &Model, max_size : usize) {
+        assert!(data.len() <= max_size);
+        let doc = BsonRef::new (data).unwrap();
+        doc.validate().unwrap();
+        check (doc, &model.frames[0].elements);
+    }
+
+    proptest! {
+        #[test]
+        fn builder_calls_yield_valid_documents (
+            ops in prop::collection::vec(op(), 0..64),
+            max_size in 16usize..256,
+            max_depth in 1usize..4,
+        ) {
+            let mut bb = BSONBuilder::new();
+            bb.set_max_size (max_size);
+            bb.set_max_depth (max_depth);
+            let mut model = Model::new (max_depth);
+            for op in ops {
+                let (key, element_type, result) = match &op {
+                    Op::Int32 (k, v) => (k, 0x10, bb.append_int32 (k, *v)),
+                    Op::String (k, v) => (k, 0x02, bb.append_string (k, v)),
+                    Op::Binary (k, t, v) => (k, 0x05, bb.append_binary (k, *t, v)),
+                    Op::Regex (k, p, o) => {
+                        let result = bb.append_regex (k, p, o);
+                        if p.contains('\0') {
+                            prop_assert_eq!(result, Err(BuildError::ContainsNul (p.clone())));
+                            continue;
+                        }
+                        (k, 0x0B, result)
+                    }
+                    Op::Null (k) => (k, 0x0A, bb.append_null (k)),
+                    Op::StartDocument (k) | Op::StartArray (k) => {
+                        let array = matches!(op, Op::StartArray (_));
+                        let result = if array { bb.start_array (k) } else { bb.start_document (k) };
+                        if model.frames.len() > model.max_depth {
+                            prop_assert_eq!(result, Err(BuildError::MaxDepthExceeded (max_depth)));
+                            continue;
+                        }
+                        match (model.key(k), result) {
+                            (Err(want), got) => prop_assert_eq!(got, Err(want)),
+                            (Ok(_), Err(BuildError::TooLarge (_))) => {}
+                            (Ok(key), got) => {
+                                prop_assert_eq!(got, Ok(()));
+                                model.frames.push(Frame { key, array, elements: Vec::new() });
+                            }
+                        }
+                        continue;
+                    }
+                    Op::EndDocument | Op::EndArray => {
+                        let array = matches!(op, Op::EndArray);
+                        let result = if array { bb.end_array () } else { bb.end_document () };
+                        if model.frames.len() == 1 {
+                            prop_assert_eq!(result, Err(BuildError::NoOpenDocument));
+                        } else if model.frames.last().unwrap().array != array {
+                            prop_assert_eq!(result, Err(BuildError::MismatchedEnd));
+                        } else {
+                            prop_assert_eq!(result, Ok(()));
+                            model.end();
+                        }
+                        continue;
+                    }
+                    Op::Build => {
+                        match bb.build() {
+                            Ok(data) => {
+                                prop_assert_eq!(model.frames.len(), 1);
+                                check_built (&data, &model, max_size);
+                                model = Model::new (max_depth);
+                            }
+                            Err(e) => prop_assert_eq!(e, BuildError::UnclosedDocument (model.frames.len() - 1)),
+                        }
+                        continue;
+                    }
+                };
+                match (model.key(key), result) {
+                    (Err(want), got) => prop_assert_eq!(got, Err(want)),
+                    // The size is checked by `check_built`.
+                    (Ok(_), Err(BuildError::TooLarge (_))) => {}
+                    (Ok(key), got) => {
+                        prop_assert_eq!(got, Ok(()));
+                        model.push(key, Node::Leaf (element_type));
+                    }
+                }
+            }
+            while model.frames.len() > 1 {
+                let array = model.frames.last().unwrap().array;
+                if array { bb.end_array ().unwrap() } else { bb.end_document ().unwrap() }
+                model.end();
+            }
+            check_built (&bb.build().unwrap(), &model, max_size);
+        }
+    }
+}
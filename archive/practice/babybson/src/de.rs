@@ -0,0 +1,408 @@
+// de deserializes Rust values with serde from a `BsonRef`, without copying. Strings and binary data can be borrowed
+// from the document as &str and &[u8] fields.
+//
+// Documents are maps and arrays are sequences. Null and undefined are unit, and are None for options. ObjectIds are
+// 12 bytes and datetimes are milliseconds. JavaScript code and symbols are strings. Enums are externally tagged, as
+// `to_bson` writes them. Other types cannot be deserialized.
+use crate::builder::MAX_NESTING_DEPTH;
+use crate::reader::{BsonRef, ElementIter, ElementRef, ReadError, ReadErrorKind};
+use serde::de::{self, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::forward_to_deserialize_any;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeError {
+    Read (ReadError),
+    // The element type byte of a value that has no serde equivalent.
+    UnsupportedType (u8),
+    // An error from a `Deserialize` implementation, e.g. a missing field or a value of the wrong type.
+    Custom (String),
+}
+
+impl fmt::Display for DeError {
+    fn fmt (&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeError::Read (e) => write!(f, "{}", e),
+            DeError::UnsupportedType (t) => write!(f, "element type 0x{:02x} cannot be deserialized", t),
+            DeError::Custom (msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for DeError {}
+
+impl de::Error for DeError {
+    fn custom<T : fmt::Display> (msg : T) -> DeError {
+        DeError::Custom (msg.to_string())
+    }
+}
+
+impl From<ReadError> for DeError {
+    fn from (e : ReadError) -> DeError {
+        DeError::Read (e)
+    }
+}
+
+// from_bson deserializes a document.
+pub fn from_bson<'a, T : de::Deserialize<'a>> (data : &'a [u8]) -> Result<T, DeError> {
+    from_bson_ref (BsonRef::new (data)?)
+}
+
+pub fn from_bson_ref<'a, T : de::Deserialize<'a>> (doc : BsonRef<'a>) -> Result<T, DeError> {
+    T::deserialize (Deserializer::new (doc))
+}
+
+const ENUM_DOCUMENT : &str = "expected a document with one element for an enum";
+
+// Deserializer deserializes one value.
+pub struct Deserializer<'a> {
+    value : ElementRef<'a>,
+    // depth is the nesting depth of `value`. The outermost document is 0.
+    depth : usize,
+}
+
+impl<'a> Deserializer<'a> {
+    // new returns a deserializer of the document `doc`.
+    pub fn new (doc : BsonRef<'a>) -> Deserializer<'a> {
+        Deserializer { value: ElementRef::Document (doc), depth: 0 }
+    }
+
+    // enter returns the depth of the elements of `doc`, the value of this deserializer. Like `BsonRef::validate`,
+    // documents nested more than MAX_NESTING_DEPTH deep are an error.
+    fn enter (&self, doc : BsonRef) -> Result<usize, DeError> {
+        if self.depth > MAX_NESTING_DEPTH {
+            return Err(DeError::Read (ReadError { offset: doc.offset(), kind: ReadErrorKind::MaxDepthExceeded (MAX_NESTING_DEPTH) }));
+        }
+        Ok(self.depth + 1)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer<'de> {
+    type Error = DeError;
+
+    fn deserialize_any<V : Visitor<'de>> (self, visitor : V) -> Result<V::Value, DeError> {
+        match self.value {
+            ElementRef::Double (v) => visitor.visit_f64 (v),
+            ElementRef::String (s) | ElementRef::JavaScript (s) | ElementRef::Symbol (s) => visitor.visit_borrowed_str (s),
+            ElementRef::Document (doc) => visitor.visit_map (MapAccess { iter: doc.iter(), value: None, depth: self.enter (doc)? }),
+            ElementRef::Array (doc) => visitor.visit_seq (SeqAccess { iter: doc.iter(), depth: self.enter (doc)? }),
+            ElementRef::Binary { data, .. } => visitor.visit_borrowed_bytes (data),
+            ElementRef::ObjectId (id) => visitor.visit_borrowed_bytes (id),
+            ElementRef::Bool (v) => visitor.visit_bool (v),
+            ElementRef::DateTime (v) | ElementRef::Int64 (v) => visitor.visit_i64 (v),
+            ElementRef::Null | ElementRef::Undefined => visitor.visit_unit(),
+            ElementRef::Int32 (v) => visitor.visit_i32 (v),
+            value => Err(DeError::UnsupportedType (value.element_type())),
+        }
+    }
+
+    fn deserialize_option<V : Visitor<'de>> (self, visitor : V) -> Result<V::Value, DeError> {
+        match self.value {
+            ElementRef::Null | ElementRef::Undefined => visitor.visit_none(),
+            _ => visitor.visit_some (self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V : Visitor<'de>> (self, _name : &'static str, visitor : V) -> Result<V::Value, DeError> {
+        visitor.visit_newtype_struct (self)
+    }
+
+    // A unit variant is a string. Other variants are a document with one element, the variant and its value.
+    fn deserialize_enum<V : Visitor<'de>> (
+        self, _name : &'static str, _variants : &'static [&'static str], visitor : V,
+    ) -> Result<V::Value, DeError> {
+        match self.value {
+            ElementRef::String (s) => visitor.visit_enum (s.into_deserializer()),
+            ElementRef::Document (doc) => {
+                let depth = self.enter (doc)?;
+                let mut iter = doc.iter();
+                let (variant, value) = match iter.next() {
+                    Some(element) => element?,
+                    None => return Err(de::Error::custom (ENUM_DOCUMENT)),
+                };
+                if iter.next().is_some() {
+                    return Err(de::Error::custom (ENUM_DOCUMENT));
+                }
+                visitor.visit_enum (EnumAccess { variant, value, depth })
+            }
+            _ => Err(de::Error::invalid_type (unexpected (&self.value), &"a string or document for an enum")),
+        }
+    }
+
+    // Skipped values may have any type.
+    fn deserialize_ignored_any<V : Visitor<'de>> (self, visitor : V) -> Result<V::Value, DeError> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier
+    }
+}
+
+// unexpected describes `value` for type errors.
+fn unexpected<'a> (value : &ElementRef<'a>) -> de::Unexpected<'a> {
+    match *value {
+        ElementRef::Double (v) => de::Unexpected::Float (v),
+        ElementRef::String (s) => de::Unexpected::Str (s),
+        ElementRef::Bool (v) => de::Unexpected::Bool (v),
+        ElementRef::Int32 (v) => de::Unexpected::Signed (v as i64),
+        ElementRef::Int64 (v) => de::Unexpected::Signed (v),
+        ElementRef::Array (_) => de::Unexpected::Seq,
+        ElementRef::Null => de::Unexpected::Unit,
+        _ => de::Unexpected::Other ("BSON value"),
+    }
+}
+
+struct MapAccess<'a> {
+    iter : ElementIter<'a>,
+    // value is the value of the last key.
+    value : Option<ElementRef<'a>>,
+    // depth is the nesting depth of the values.
+    depth : usize,
+}
+
+impl<'de> de::MapAccess<'de> for MapAccess<'de> {
+    type Error = DeError;
+
+    fn next_key_seed<K : DeserializeSeed<'de>> (&mut self, seed : K) -> Result<Option<K::Value>, DeError> {
+        match self.iter.next() {
+            Some(element) => {
+                let (key, value) = element?;
+                self.value = Some(value);
+                seed.deserialize (de::value::BorrowedStrDeserializer::new (key)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V : DeserializeSeed<'de>> (&mut self, seed : V) -> Result<V::Value, DeError> {
+        let value = self.value.take().expect("next_value_seed called before next_key_seed");
+        seed.deserialize (Deserializer { value, depth: self.depth })
+    }
+}
+
+struct SeqAccess<'a> {
+    iter : ElementIter<'a>,
+    depth : usize,
+}
+
+impl<'de> de::SeqAccess<'de> for SeqAccess<'de> {
+    type Error = DeError;
+
+    // Array keys are ignored.
+    fn next_element_seed<T : DeserializeSeed<'de>> (&mut self, seed : T) -> Result<Option<T::Value>, DeError> {
+        match self.iter.next() {
+            Some(element) => seed.deserialize (Deserializer { value: element?.1, depth: self.depth }).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+// EnumAccess reads a variant stored as a document with one element.
+struct EnumAccess<'a> {
+    variant : &'a str,
+    value : ElementRef<'a>,
+    depth : usize,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess<'de> {
+    type Error = DeError;
+    type Variant = Deserializer<'de>;
+
+    fn variant_seed<V : DeserializeSeed<'de>> (self, seed : V) -> Result<(V::Value, Deserializer<'de>), DeError> {
+        let variant = seed.deserialize (de::value::BorrowedStrDeserializer::<DeError>::new (self.variant))?;
+        Ok((variant, Deserializer { value: self.value, depth: self.depth }))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Deserializer<'de> {
+    type Error = DeError;
+
+    // A unit variant written as a document has a null value.
+    fn unit_variant (self) -> Result<(), DeError> {
+        de::Deserialize::deserialize (self)
+    }
+
+    fn newtype_variant_seed<T : DeserializeSeed<'de>> (self, seed : T) -> Result<T::Value, DeError> {
+        seed.deserialize (self)
+    }
+
+    fn tuple_variant<V : Visitor<'de>> (self, _len : usize, visitor : V) -> Result<V::Value, DeError> {
+        de::Deserializer::deserialize_any (self, visitor)
+    }
+
+    fn struct_variant<V : Visitor<'de>> (self, _fields : &'static [&'static str], visitor : V) -> Result<V::Value, DeError> {
+        de::Deserializer::deserialize_any (self, visitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::builder::BSONBuilder;
+    use crate::ser::to_bson;
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Book<'a> {
+        title : &'a str,
+        author : String,
+        pages : u32,
+        rating : Option<f64>,
+        isbn : Option<String>,
+        tags : Vec<&'a str>,
+        format : Format,
+        series : Option<Series<'a>>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Format {
+        Paperback,
+        Ebook (String),
+        Audio { minutes : u16, narrator : String },
+        Bundle (Box<Format>, Box<Format>),
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Series<'a> {
+        name : &'a str,
+        number : i64,
+    }
+
+    #[test]
+    fn test_round_trip () {
+        let books = [
+            Book {
+                title: "Dune", author: "Frank Herbert".to_string(), pages: 412, rating: Some(4.5), isbn: None,
+                tags: vec!["sf", "classic"], format: Format::Paperback, series: Some(Series { name: "Dune", number: 1 }),
+            },
+            Book {
+                title: "", author: String::new(), pages: u32::MAX, rating: None, isbn: Some("0-441".to_string()),
+                tags: vec![], format: Format::Audio { minutes: 1260, narrator: "Simon Vance".to_string() }, series: None,
+            },
+            Book {
+                title: "x", author: "y".to_string(), pages: 0, rating: None, isbn: None, tags: vec!["z"],
+                format: Format::Bundle (Box::new(Format::Ebook ("epub".to_string())), Box::new(Format::Paperback)),
+                series: None,
+            },
+        ];
+        for book in books {
+            let bytes = to_bson (&book).unwrap();
+            let got : Book = from_bson (&bytes).unwrap();
+            assert_eq!(got, book);
+        }
+    }
+
+    #[test]
+    fn test_borrowed () {
+        let mut bb = BSONBuilder::new();
+        bb.append_string ("name", "Dune").unwrap();
+        bb.append_int64 ("number", 1).unwrap();
+        let bytes = bb.build().unwrap();
+        let series : Series = from_bson (&bytes).unwrap();
+        // The string points into the document.
+        let range = bytes.as_ptr_range();
+        assert!(range.contains(&series.name.as_ptr()));
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Raw<'a> {
+        id : &'a [u8],
+        data : &'a [u8],
+        date : i64,
+        code : &'a str,
+        missing : Option<i32>,
+        null : Option<i32>,
+    }
+
+    #[test]
+    fn test_bson_types () {
+        let mut bb = BSONBuilder::new();
+        bb.append_objectid ("id", &[1; 12]).unwrap();
+        bb.append_binary ("data", 0x80, &[1, 2, 3]).unwrap();
+        bb.append_datetime ("date", 1356351330501).unwrap();
+        bb.append_javascript ("code", "x()").unwrap();
+        bb.append_null ("null").unwrap();
+        // Unknown fields are ignored.
+        bb.append_minkey ("ignored").unwrap();
+        let bytes = bb.build().unwrap();
+        let raw : Raw = from_bson (&bytes).unwrap();
+        assert_eq!(raw, Raw { id: &[1; 12], data: &[1, 2, 3], date: 1356351330501, code: "x()", missing: None, null: None });
+    }
+
+    #[test]
+    fn test_maps () {
+        let mut bb = BSONBuilder::new();
+        bb.append_int32 ("a", 1).unwrap();
+        bb.append_int64 ("b", 2).unwrap();
+        let bytes = bb.build().unwrap();
+        let map : HashMap<&str, i64> = from_bson (&bytes).unwrap();
+        assert_eq!(map, HashMap::from([("a", 1), ("b", 2)]));
+        // Tuples are arrays.
+        let mut bb = BSONBuilder::new();
+        bb.start_array ("t").unwrap();
+        bb.append_int32 ("", 1).unwrap();
+        bb.append_string ("", "x").unwrap();
+        bb.end_array ().unwrap();
+        let bytes = bb.build().unwrap();
+        let map : HashMap<String, (u8, char)> = from_bson (&bytes).unwrap();
+        assert_eq!(map["t"], (1, 'x'));
+    }
+
+    #[test]
+    fn test_errors () {
+        let mut bb = BSONBuilder::new();
+        bb.append_string ("name", "Dune").unwrap();
+        let bytes = bb.build().unwrap();
+        let err = from_bson::<Series> (&bytes).unwrap_err();
+        assert_eq!(err, DeError::Custom ("missing field `number`".to_string()));
+
+        let mut bb = BSONBuilder::new();
+        bb.append_string ("name", "Dune").unwrap();
+        bb.append_double ("number", 1.0).unwrap();
+        let bytes = bb.build().unwrap();
+        let err = from_bson::<Series> (&bytes).unwrap_err();
+        assert_eq!(err, DeError::Custom ("invalid type: floating point `1.0`, expected i64".to_string()));
+
+        let mut bb = BSONBuilder::new();
+        bb.append_int32 ("pages", -1).unwrap();
+        let bytes = bb.build().unwrap();
+        let err = from_bson::<HashMap<&str, u32>> (&bytes).unwrap_err();
+        assert_eq!(err, DeError::Custom ("invalid value: integer `-1`, expected u32".to_string()));
+
+        let mut bb = BSONBuilder::new();
+        bb.append_decimal128 ("d", &[0; 16]).unwrap();
+        let bytes = bb.build().unwrap();
+        let err = from_bson::<HashMap<&str, u32>> (&bytes).unwrap_err();
+        assert_eq!(err, DeError::UnsupportedType (0x13));
+
+        let mut bb = BSONBuilder::new();
+        bb.start_document ("Ebook").unwrap();
+        bb.end_document ().unwrap();
+        bb.append_null ("Paperback").unwrap();
+        let bytes = bb.build().unwrap();
+        let err = from_bson::<Format> (&bytes).unwrap_err();
+        assert_eq!(err, DeError::Custom ("expected a document with one element for an enum".to_string()));
+
+        // Read errors are reported as they are found.
+        let mut bytes = to_bson (&Series { name: "Dune", number: 1 }).unwrap();
+        bytes[4] = 0x42;
+        let err = from_bson::<Series> (&bytes).unwrap_err();
+        assert_eq!(err, DeError::Read (ReadError { offset: 4, kind: crate::reader::ReadErrorKind::UnknownType (0x42) }));
+    }
+
+    #[test]
+    fn test_max_depth () {
+        use crate::reader::nested_document;
+        from_bson::<serde_json::Value> (&nested_document (MAX_NESTING_DEPTH)).unwrap();
+        // The innermost document is too deep. Each level adds 7 bytes before it.
+        let err = from_bson::<serde_json::Value> (&nested_document (MAX_NESTING_DEPTH + 1)).unwrap_err();
+        let kind = ReadErrorKind::MaxDepthExceeded (MAX_NESTING_DEPTH);
+        assert_eq!(err, DeError::Read (ReadError { offset: 7 * (MAX_NESTING_DEPTH + 1), kind }));
+        // Deep documents are an error rather than a stack overflow.
+        let err = from_bson::<serde_json::Value> (&nested_document (200_000)).unwrap_err();
+        assert!(matches!(err, DeError::Read (ReadError { kind: k, .. }) if k == kind));
+    }
+}
@@ -0,0 +1,202 @@
+// decimal128 converts IEEE 754-2008 decimal128 values to and from strings. BSON stores them in the binary integer
+// decimal (BID) encoding, in little endian.
+// See https://github.com/mongodb/specifications/blob/master/source/bson-decimal128/decimal128.md.
+
+const EXPONENT_MAX : i64 = 6111;
+const EXPONENT_MIN : i64 = -6176;
+const EXPONENT_BIAS : i64 = 6176;
+const MAX_DIGITS : usize = 34;
+
+// The combination field (bits 122 to 126) of infinity and NaN.
+const COMBINATION_INFINITY : u128 = 0x1E;
+const COMBINATION_NAN : u128 = 0x1F;
+
+// format_decimal128 returns the string form of a decimal128, e.g. "1.5", "-0", "1.23E+40", "Infinity" or "NaN".
+pub fn format_decimal128 (value : &[u8; 16]) -> String {
+    let bits = u128::from_le_bytes(*value);
+    let sign = if bits >> 127 == 1 { "-" } else { "" };
+    match (bits >> 122) & 0x1F {
+        COMBINATION_NAN => return "NaN".to_string(),
+        COMBINATION_INFINITY => return format!("{}Infinity", sign),
+        _ => {}
+    }
+    let (biased, coefficient) = if (bits >> 125) & 0x3 == 0x3 {
+        // The coefficient is 100 followed by 111 bits, which is more than 34 digits. It is treated as zero.
+        ((bits >> 111) & 0x3FFF, 0)
+    } else {
+        ((bits >> 113) & 0x3FFF, bits & ((1 << 113) - 1))
+    };
+    let exponent = biased as i64 - EXPONENT_BIAS;
+    let coefficient = if coefficient >= 10u128.pow(MAX_DIGITS as u32) { 0 } else { coefficient };
+    let digits = coefficient.to_string();
+    let adjusted = exponent + digits.len() as i64 - 1;
+    let mut s = sign.to_string();
+    if exponent <= 0 && adjusted >= -6 {
+        // Plain notation. `point` is the number of digits before the decimal point.
+        let point = digits.len() as i64 + exponent;
+        if exponent == 0 {
+            s.push_str(&digits);
+        } else if point > 0 {
+            s.push_str(&digits[..point as usize]);
+            s.push('.');
+            s.push_str(&digits[point as usize..]);
+        } else {
+            s.push_str("0.");
+            s.push_str(&"0".repeat(-point as usize));
+            s.push_str(&digits);
+        }
+    } else {
+        // Scientific notation, with one digit before the decimal point.
+        s.push_str(&digits[..1]);
+        if digits.len() > 1 {
+            s.push('.');
+            s.push_str(&digits[1..]);
+        }
+        s.push_str(&format!("E{}{}", if adjusted >= 0 { "+" } else { "" }, adjusted));
+    }
+    s
+}
+
+// parse_decimal128 parses a decimal number, "Infinity", "Inf" or "NaN", optionally signed. It returns None if the
+// string is invalid or cannot be represented exactly.
+pub fn parse_decimal128 (s : &str) -> Option<[u8; 16]> {
+    let (negative, rest) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let sign = (negative as u128) << 127;
+    if rest.eq_ignore_ascii_case("infinity") || rest.eq_ignore_ascii_case("inf") {
+        return Some((sign | COMBINATION_INFINITY << 122).to_le_bytes());
+    }
+    if rest.eq_ignore_ascii_case("nan") {
+        return Some((sign | COMBINATION_NAN << 122).to_le_bytes());
+    }
+    let (mantissa, mut exponent) = match rest.find(['e', 'E']) {
+        Some(i) => (&rest[..i], parse_exponent (&rest[i + 1..])?),
+        None => (rest, 0),
+    };
+    let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let all_digits = |s : &str| s.bytes().all(|b| b.is_ascii_digit());
+    if int_part.len() + frac_part.len() == 0 || !all_digits (int_part) || !all_digits (frac_part) {
+        return None;
+    }
+    exponent -= frac_part.len() as i64;
+    let mut digits : Vec<u8> = int_part.bytes().chain(frac_part.bytes()).skip_while(|&b| b == b'0').collect();
+    // Trailing zeros beyond 34 digits are dropped by raising the exponent. Any other digit would need rounding.
+    while digits.len() > MAX_DIGITS {
+        if digits.pop() != Some(b'0') {
+            return None;
+        }
+        exponent += 1;
+    }
+    // Clamp the exponent. A zero keeps its sign and takes the nearest exponent.
+    if digits.is_empty() {
+        exponent = exponent.clamp(EXPONENT_MIN, EXPONENT_MAX);
+    }
+    while exponent > EXPONENT_MAX {
+        if digits.len() == MAX_DIGITS {
+            return None;
+        }
+        digits.push(b'0');
+        exponent -= 1;
+    }
+    while exponent < EXPONENT_MIN {
+        if digits.pop() != Some(b'0') {
+            return None;
+        }
+        exponent += 1;
+    }
+    let coefficient = digits.iter().fold(0u128, |n, &d| n * 10 + (d - b'0') as u128);
+    Some((sign | ((exponent + EXPONENT_BIAS) as u128) << 113 | coefficient).to_le_bytes())
+}
+
+// parse_exponent parses an optionally signed exponent. Exponents too large for any decimal128 are rejected.
+fn parse_exponent (s : &str) -> Option<i64> {
+    let digits = s.strip_prefix(['+', '-']).unwrap_or(s);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let exponent : i64 = s.parse().ok()?;
+    if exponent.abs() > 100_000 {
+        return None;
+    }
+    Some(exponent)
+}
+
+#[cfg(test)]
+fn hex (bits : u128) -> [u8; 16] {
+    bits.to_le_bytes()
+}
+
+#[test]
+fn test_format_decimal128 () {
+    let cases = [
+        (0x30400000000000000000000000000000, "0"),
+        (0xB0400000000000000000000000000000, "-0"),
+        (0x30400000000000000000000000000001, "1"),
+        (0x303E000000000000000000000000000F, "1.5"),
+        (0x30340000000000000000000000000001, "0.000001"),
+        (0x30320000000000000000000000000001, "1E-7"),
+        (0x30420000000000000000000000000001, "1E+1"),
+        (0x30400000000000000000000000003039, "12345"),
+        (0x303A0000000000000000000000003039, "12.345"),
+        (0x5FFFED09BEAD87C0378D8E63FFFFFFFF, "9.999999999999999999999999999999999E+6144"),
+        (0x00000000000000000000000000000001, "1E-6176"),
+        (0x78000000000000000000000000000000, "Infinity"),
+        (0xF8000000000000000000000000000000, "-Infinity"),
+        (0x7C000000000000000000000000000000, "NaN"),
+        (0xFC000000000000000000000000000000, "NaN"),
+        (0x7E000000000000000000000000000000, "NaN"),
+        // A coefficient of more than 34 digits is zero.
+        (0x6C11800000000000000000000000000C, "0E+3"),
+        (0x3041ED09BEAD87C0378D8E6400000000, "0"),
+    ];
+    for (bits, want) in cases {
+        assert_eq!(format_decimal128 (&hex (bits)), want, "{:032x}", bits);
+    }
+}
+
+#[test]
+fn test_parse_decimal128 () {
+    let cases = [
+        ("0", 0x30400000000000000000000000000000),
+        ("-0", 0xB0400000000000000000000000000000),
+        ("+1", 0x30400000000000000000000000000001),
+        ("1.5", 0x303E000000000000000000000000000F),
+        ("0.000001", 0x30340000000000000000000000000001),
+        ("1e-7", 0x30320000000000000000000000000001),
+        ("1E+1", 0x30420000000000000000000000000001),
+        (".5", 0x303E0000000000000000000000000005),
+        ("5.", 0x30400000000000000000000000000005),
+        ("000012.345", 0x303A0000000000000000000000003039),
+        ("9.999999999999999999999999999999999E+6144", 0x5FFFED09BEAD87C0378D8E63FFFFFFFF),
+        ("inf", 0x78000000000000000000000000000000),
+        ("-Infinity", 0xF8000000000000000000000000000000),
+        ("NaN", 0x7C000000000000000000000000000000),
+        // Clamped exponents.
+        ("1E+6112", 0x5FFE000000000000000000000000000A),
+        ("0E+7000", 0x5FFE0000000000000000000000000000),
+        ("0E-7000", 0x00000000000000000000000000000000),
+        ("10E-6177", 0x00000000000000000000000000000001),
+        // Trailing zeros beyond 34 digits.
+        ("10000000000000000000000000000000000", 0x3042314DC6448D9338C15B0A00000000),
+    ];
+    for (s, want) in cases {
+        assert_eq!(parse_decimal128 (s), Some(hex (want)), "{:?}", s);
+    }
+    let invalid = [
+        "", ".", "e", "1e", "E02", "..1", "1.2.3", "1abc", "1.24abcE+02", "1.3e+", "-+1", "+-1", "1e+-1", "Infinit",
+        "1 ", "12345678901234567890123456789012345", "1E+6145", "1E-6177", "1E+99999999999999999999",
+    ];
+    for s in invalid {
+        assert_eq!(parse_decimal128 (s), None, "{:?}", s);
+    }
+}
+
+#[test]
+fn test_decimal128_round_trip () {
+    for s in ["0", "-0", "1.5", "-1.23E+40", "1E-6176", "0.0001234", "1.000000000000000000000000000000000E+6144", "NaN", "Infinity"] {
+        assert_eq!(format_decimal128 (&parse_decimal128 (s).unwrap()), s);
+    }
+}
@@ -0,0 +1,362 @@
+// edit changes documents in place. `RawDocumentMut` owns the bytes of a document, and each change splices them and
+// fixes the length prefix of every enclosing document, without decoding the document to a tree.
+use crate::builder::{BSONBuilder, BuildError, MAX_DOCUMENT_SIZE};
+use crate::reader::{BsonRef, ElementRef, ReadError};
+use std::fmt;
+use std::ops::Range;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditError {
+    Read (ReadError),
+    Build (BuildError),
+    // `set`, `remove` and `rename` need the key of the element.
+    EmptyPath,
+    // The path does not name an element. The value is the path up to the missing key, joined with dots.
+    NotFound (String),
+    // A key before the last one in the path is not a document or array.
+    NotAContainer (String),
+    // `append` was given a path that is not an array.
+    NotAnArray (String),
+    // The keys of array elements are their indexes, so they cannot be renamed.
+    RenameInArray,
+    // `rename` would make a duplicate key.
+    KeyExists (String),
+    // The value function must append exactly one element, with the key it was given.
+    NotOneElement,
+}
+
+impl fmt::Display for EditError {
+    fn fmt (&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EditError::Read (e) => write!(f, "{}", e),
+            EditError::Build (e) => write!(f, "{}", e),
+            EditError::EmptyPath => write!(f, "empty path"),
+            EditError::NotFound (path) => write!(f, "{} not found", path),
+            EditError::NotAContainer (path) => write!(f, "{} is not a document or array", path),
+            EditError::NotAnArray (path) => write!(f, "{} is not an array", path),
+            EditError::RenameInArray => write!(f, "array elements cannot be renamed"),
+            EditError::KeyExists (key) => write!(f, "key {:?} already exists", key),
+            EditError::NotOneElement => write!(f, "value must be exactly one element with the given key"),
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+impl From<ReadError> for EditError {
+    fn from (e : ReadError) -> EditError {
+        EditError::Read (e)
+    }
+}
+
+impl From<BuildError> for EditError {
+    fn from (e : BuildError) -> EditError {
+        EditError::Build (e)
+    }
+}
+
+// Container is a document or array found by following a path.
+struct Container {
+    // index_stack holds the index of the first byte of the length of the container and every enclosing document,
+    // outermost first, like the index_stack of BSONBuilder.
+    index_stack : Vec<usize>,
+    is_array : bool,
+    // elements holds the position of each element, from its type byte to the end of its value.
+    elements : Vec<Range<usize>>,
+    // end is the position of the terminator.
+    end : usize,
+}
+
+// RawDocumentMut is a document that can be changed in place. Elements are named by a path of keys, outermost
+// first, where the keys of array elements are their indexes, e.g. ["items", "0", "name"]. Values are written by a
+// function that appends one element to a `BSONBuilder`, e.g. `|bb, key| bb.append_int32 (key, 1)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawDocumentMut {
+    data : Vec<u8>,
+}
+
+impl RawDocumentMut {
+    // new validates the whole document, so later changes cannot run into malformed bytes.
+    pub fn new (data : Vec<u8>) -> Result<RawDocumentMut, EditError> {
+        BsonRef::new (&data)?.validate()?;
+        Ok(RawDocumentMut { data })
+    }
+
+    pub fn as_bytes (&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn as_document (&self) -> BsonRef<'_> {
+        BsonRef::new (&self.data).expect("document is valid")
+    }
+
+    pub fn into_bytes (self) -> Vec<u8> {
+        self.data
+    }
+
+    // set replaces the first element with the last key of `path`, or adds it to the end of its document if there
+    // is none. Array elements can be replaced but not added; use `append`.
+    pub fn set<F> (&mut self, path : &[&str], value : F) -> Result<(), EditError>
+    where F : FnOnce(&mut BSONBuilder, &str) -> Result<(), BuildError> {
+        let (key, parents) = path.split_last().ok_or(EditError::EmptyPath)?;
+        let container = self.open (parents)?;
+        let element = element_bytes (key, value)?;
+        match self.find (&container, key) {
+            Some(i) => self.splice (&container.index_stack, container.elements[i].clone(), &element),
+            None if container.is_array => Err(EditError::NotFound (path.join("."))),
+            None => self.splice (&container.index_stack, container.end..container.end, &element),
+        }
+    }
+
+    // remove removes the first element with the last key of `path`, and returns whether there was one. The
+    // elements after a removed array element are renumbered.
+    pub fn remove (&mut self, path : &[&str]) -> Result<bool, EditError> {
+        let (key, parents) = path.split_last().ok_or(EditError::EmptyPath)?;
+        let container = self.open (parents)?;
+        let i = match self.find (&container, key) {
+            Some(i) => i,
+            None => return Ok(false),
+        };
+        if !container.is_array {
+            self.splice (&container.index_stack, container.elements[i].clone(), &[])?;
+            return Ok(true);
+        }
+        // Rewrite the following elements with their new indexes.
+        let mut tail = Vec::new();
+        for (index, element) in container.elements[i + 1..].iter().enumerate() {
+            let value_start = element.start + 1 + self.key (element.start).len() + 1;
+            tail.push(self.data[element.start]);
+            tail.extend_from_slice((i + index).to_string().as_bytes());
+            tail.push(0);
+            tail.extend_from_slice(&self.data[value_start..element.end]);
+        }
+        self.splice (&container.index_stack, container.elements[i].start..container.end, &tail)?;
+        Ok(true)
+    }
+
+    // rename changes the last key of `path` to `new_key`. It fails if the document already has `new_key`.
+    pub fn rename (&mut self, path : &[&str], new_key : &str) -> Result<(), EditError> {
+        let (key, parents) = path.split_last().ok_or(EditError::EmptyPath)?;
+        let container = self.open (parents)?;
+        if container.is_array {
+            return Err(EditError::RenameInArray);
+        }
+        let i = self.find (&container, key).ok_or_else(|| EditError::NotFound (path.join(".")))?;
+        if new_key == *key {
+            return Ok(());
+        }
+        if new_key.contains('\0') {
+            return Err(EditError::Build (BuildError::ContainsNul (new_key.to_string())));
+        }
+        if self.find (&container, new_key).is_some() {
+            return Err(EditError::KeyExists (new_key.to_string()));
+        }
+        let key_start = container.elements[i].start + 1;
+        self.splice (&container.index_stack, key_start..key_start + key.len(), new_key.as_bytes())
+    }
+
+    // append adds an element to the end of the array at `path`.
+    pub fn append<F> (&mut self, path : &[&str], value : F) -> Result<(), EditError>
+    where F : FnOnce(&mut BSONBuilder, &str) -> Result<(), BuildError> {
+        let container = self.open (path)?;
+        if !container.is_array {
+            return Err(EditError::NotAnArray (path.join(".")));
+        }
+        let element = element_bytes (&container.elements.len().to_string(), value)?;
+        self.splice (&container.index_stack, container.end..container.end, &element)
+    }
+
+    // open follows `path` from the outermost document, and returns the document or array it names.
+    fn open (&self, path : &[&str]) -> Result<Container, EditError> {
+        let mut doc = self.as_document();
+        let mut is_array = false;
+        let mut index_stack = vec![0];
+        for (i, key) in path.iter().enumerate() {
+            match doc.get (key)? {
+                Some(ElementRef::Document (d)) => (doc, is_array) = (d, false),
+                Some(ElementRef::Array (d)) => (doc, is_array) = (d, true),
+                Some(_) => return Err(EditError::NotAContainer (path[..=i].join("."))),
+                None => return Err(EditError::NotFound (path[..=i].join("."))),
+            }
+            index_stack.push(doc.offset());
+        }
+        let mut elements = Vec::new();
+        let mut iter = doc.iter();
+        loop {
+            let start = iter.position();
+            match iter.next() {
+                Some(element) => {
+                    element?;
+                    elements.push(start..iter.position());
+                }
+                None => break,
+            }
+        }
+        Ok(Container { index_stack, is_array, elements, end: doc.offset() + doc.as_bytes().len() - 1 })
+    }
+
+    // find returns the index of the first element of `container` with `key`.
+    fn find (&self, container : &Container, key : &str) -> Option<usize> {
+        container.elements.iter().position(|element| self.key (element.start) == key)
+    }
+
+    // key returns the key of the element at `start`.
+    fn key (&self, start : usize) -> &str {
+        let len = self.data[start + 1..].iter().position(|&b| b == 0).expect("key is terminated");
+        std::str::from_utf8(&self.data[start + 1..start + 1 + len]).expect("key is UTF-8")
+    }
+
+    // splice replaces `range` with `bytes`, and adds the change in size to every length in `index_stack`.
+    fn splice (&mut self, index_stack : &[usize], range : Range<usize>, bytes : &[u8]) -> Result<(), EditError> {
+        let size = self.data.len() - range.len() + bytes.len();
+        if size > MAX_DOCUMENT_SIZE {
+            return Err(EditError::Build (BuildError::TooLarge (size)));
+        }
+        let delta = bytes.len() as i64 - range.len() as i64;
+        self.data.splice(range, bytes.iter().copied());
+        // Every length is before the spliced range, so their positions have not changed.
+        for &index in index_stack {
+            let len = i32::from_le_bytes(self.data[index..index + 4].try_into().unwrap());
+            self.data[index..index + 4].copy_from_slice(&((len as i64 + delta) as i32).to_le_bytes());
+        }
+        Ok(())
+    }
+}
+
+// element_bytes writes the element that `value` appends with `key`, without the document around it.
+fn element_bytes<F> (key : &str, value : F) -> Result<Vec<u8>, EditError>
+where F : FnOnce(&mut BSONBuilder, &str) -> Result<(), BuildError> {
+    let mut bb = BSONBuilder::new();
+    value (&mut bb, key)?;
+    let doc = bb.build()?;
+    let mut elements = BsonRef::new (&doc)?.iter();
+    match (elements.next(), elements.next()) {
+        (Some(Ok((k, _))), None) if k == key => Ok(doc[4..doc.len() - 1].to_vec()),
+        _ => Err(EditError::NotOneElement),
+    }
+}
+
+#[cfg(test)]
+fn sample () -> RawDocumentMut {
+    let mut bb = BSONBuilder::new();
+    bb.append_int32 ("a", 1).unwrap();
+    bb.start_document ("b").unwrap();
+    bb.append_string ("c", "x").unwrap();
+    bb.start_array ("d").unwrap();
+    bb.append_int32 ("", 10).unwrap();
+    bb.append_int32 ("", 20).unwrap();
+    bb.append_int32 ("", 30).unwrap();
+    bb.end_array().unwrap();
+    bb.end_document().unwrap();
+    bb.append_bool ("e", true).unwrap();
+    RawDocumentMut::new (bb.build().unwrap()).unwrap()
+}
+
+#[test]
+fn test_set () {
+    let mut doc = sample();
+    // Replace with a larger value of another type, inside two documents.
+    doc.set (&["b", "c"], |bb, key| bb.append_string (key, "a longer string")).unwrap();
+    doc.set (&["b", "d", "1"], |bb, key| bb.append_int64 (key, 21)).unwrap();
+    // Add new keys to the end of the outermost and a nested document.
+    doc.set (&["f"], |bb, key| bb.append_null (key)).unwrap();
+    doc.set (&["b", "g"], |bb, key| {
+        bb.start_document (key)?;
+        bb.append_double ("h", 1.5)?;
+        bb.end_document()
+    }).unwrap();
+    // Replace with a smaller value.
+    doc.set (&["a"], |bb, key| bb.append_bool (key, false)).unwrap();
+
+    let mut bb = BSONBuilder::new();
+    bb.append_bool ("a", false).unwrap();
+    bb.start_document ("b").unwrap();
+    bb.append_string ("c", "a longer string").unwrap();
+    bb.start_array ("d").unwrap();
+    bb.append_int32 ("", 10).unwrap();
+    bb.append_int64 ("", 21).unwrap();
+    bb.append_int32 ("", 30).unwrap();
+    bb.end_array().unwrap();
+    bb.start_document ("g").unwrap();
+    bb.append_double ("h", 1.5).unwrap();
+    bb.end_document().unwrap();
+    bb.end_document().unwrap();
+    bb.append_bool ("e", true).unwrap();
+    bb.append_null ("f").unwrap();
+    assert_eq!(doc.as_bytes(), bb.build().unwrap());
+    doc.as_document().validate().unwrap();
+}
+
+#[test]
+fn test_remove () {
+    let mut doc = sample();
+    assert_eq!(doc.remove (&["b", "d", "0"]), Ok(true));
+    assert_eq!(doc.remove (&["b", "c"]), Ok(true));
+    assert_eq!(doc.remove (&["e"]), Ok(true));
+    assert_eq!(doc.remove (&["missing"]), Ok(false));
+
+    // The remaining array elements are renumbered.
+    let mut bb = BSONBuilder::new();
+    bb.append_int32 ("a", 1).unwrap();
+    bb.start_document ("b").unwrap();
+    bb.start_array ("d").unwrap();
+    bb.append_int32 ("", 20).unwrap();
+    bb.append_int32 ("", 30).unwrap();
+    bb.end_array().unwrap();
+    bb.end_document().unwrap();
+    assert_eq!(doc.as_bytes(), bb.build().unwrap());
+
+    assert_eq!(doc.remove (&["b"]), Ok(true));
+    assert_eq!(doc.remove (&["a"]), Ok(true));
+    assert_eq!(doc.into_bytes(), BSONBuilder::new().build().unwrap());
+}
+
+#[test]
+fn test_rename_and_append () {
+    let mut doc = sample();
+    doc.rename (&["b", "c"], "a much longer key").unwrap();
+    doc.rename (&["e"], "e").unwrap();
+    doc.append (&["b", "d"], |bb, key| bb.append_string (key, "s")).unwrap();
+    doc.set (&["b", "d", "3"], |bb, key| bb.start_array (key).and_then(|_| bb.end_array())).unwrap();
+    doc.append (&["b", "d", "3"], |bb, key| bb.append_int32 (key, 1)).unwrap();
+
+    let mut bb = BSONBuilder::new();
+    bb.append_int32 ("a", 1).unwrap();
+    bb.start_document ("b").unwrap();
+    bb.append_string ("a much longer key", "x").unwrap();
+    bb.start_array ("d").unwrap();
+    bb.append_int32 ("", 10).unwrap();
+    bb.append_int32 ("", 20).unwrap();
+    bb.append_int32 ("", 30).unwrap();
+    bb.start_array ("").unwrap();
+    bb.append_int32 ("", 1).unwrap();
+    bb.end_array().unwrap();
+    bb.end_array().unwrap();
+    bb.end_document().unwrap();
+    bb.append_bool ("e", true).unwrap();
+    assert_eq!(doc.as_bytes(), bb.build().unwrap());
+}
+
+#[test]
+fn test_edit_errors () {
+    let mut doc = sample();
+    let int = |bb : &mut BSONBuilder, key : &str| bb.append_int32 (key, 1);
+    assert_eq!(doc.set (&[], int), Err(EditError::EmptyPath));
+    assert_eq!(doc.set (&["x", "y"], int), Err(EditError::NotFound ("x".to_string())));
+    assert_eq!(doc.set (&["a", "y"], int), Err(EditError::NotAContainer ("a".to_string())));
+    assert_eq!(doc.set (&["b", "d", "3"], int), Err(EditError::NotFound ("b.d.3".to_string())));
+    assert_eq!(doc.append (&["b"], int), Err(EditError::NotAnArray ("b".to_string())));
+    assert_eq!(doc.append (&[], int), Err(EditError::NotAnArray ("".to_string())));
+    assert_eq!(doc.rename (&["b", "d", "0"], "x"), Err(EditError::RenameInArray));
+    assert_eq!(doc.rename (&["a"], "e"), Err(EditError::KeyExists ("e".to_string())));
+    assert_eq!(doc.rename (&["x"], "y"), Err(EditError::NotFound ("x".to_string())));
+    assert_eq!(doc.rename (&["a"], "a\0"), Err(EditError::Build (BuildError::ContainsNul ("a\0".to_string()))));
+    assert_eq!(doc.set (&["a"], |bb, _| bb.append_int32 ("other", 1)), Err(EditError::NotOneElement));
+    assert_eq!(doc.set (&["a"], |_, _| Ok(())), Err(EditError::NotOneElement));
+    assert_eq!(doc.set (&["a"], |bb, key| bb.start_document (key)), Err(EditError::Build (BuildError::UnclosedDocument (1))));
+    // Failed edits leave the document unchanged.
+    assert_eq!(doc, sample());
+
+    let err = RawDocumentMut::new (vec![12, 0, 0, 0, 8, b'a', 0, 2, 0, 0, 0, 0]).unwrap_err();
+    assert_eq!(err.to_string(), "offset 7: invalid boolean 0x02");
+}
@@ -0,0 +1,1317 @@
+// ejson converts between BSON and MongoDB Extended JSON v2, in canonical and relaxed modes.
+// See https://github.com/mongodb/specifications/blob/master/source/extended-json/extended-json.md.
+//
+// Canonical mode keeps every type, e.g. {"$numberInt": "1"}. Relaxed mode writes numbers as JSON numbers and recent
+// dates as ISO-8601 strings, so it is easier to read but loses the difference between int32, int64 and double.
+//
+// BSON keys may start with '$', so a document like {"$numberInt": "1"} is ambiguous. `from_ejson` follows the spec
+// and reports an error for a document with a wrapper key that is not a valid wrapper. `from_ejson_strict` only reads
+// a document as a wrapper if it matches one exactly, and keeps any other document as it is.
+use crate::builder::{BSONBuilder, BuildError, BINARY_SUBTYPE_UUID, MAX_NESTING_DEPTH};
+use crate::decimal128::{format_decimal128, parse_decimal128};
+use crate::reader::{BsonRef, ElementRef, ReadError, ReadErrorKind};
+use std::fmt;
+
+// MAX_JSON_DEPTH limits the recursion of the JSON parser. BSONBuilder limits the depth of the document.
+const MAX_JSON_DEPTH : usize = 1000;
+
+// Dates from 1970 to 9999 are written as ISO-8601 strings in relaxed mode. This is 10000-01-01 in milliseconds.
+const MAX_ISO_DATE : i64 = 253402300800000;
+const MS_PER_DAY : i64 = 86_400_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EjsonMode {
+    Canonical,
+    Relaxed,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EjsonErrorKind {
+    // The text is not valid JSON.
+    Syntax (&'static str),
+    // The top level value is not a document.
+    NotADocument,
+    // A document with a wrapper key, e.g. "$numberInt", is not a valid wrapper.
+    InvalidWrapper (&'static str),
+    // A DBRef has a "$ref" or "$db" that is not a string.
+    InvalidDbRef,
+    // The wrapper is for a deprecated type that BSONBuilder does not write.
+    Unsupported (&'static str),
+    Build (BuildError),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EjsonError {
+    // offset is the byte offset of the error in the JSON text.
+    pub offset : usize,
+    pub kind : EjsonErrorKind,
+}
+
+impl fmt::Display for EjsonError {
+    fn fmt (&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "offset {}: ", self.offset)?;
+        match &self.kind {
+            EjsonErrorKind::Syntax (msg) => write!(f, "invalid JSON: {}", msg),
+            EjsonErrorKind::NotADocument => write!(f, "Extended JSON must be a document"),
+            EjsonErrorKind::InvalidWrapper (key) => write!(f, "invalid {}", key),
+            EjsonErrorKind::InvalidDbRef => write!(f, "invalid DBRef"),
+            EjsonErrorKind::Unsupported (key) => write!(f, "{} is a deprecated type and is not supported", key),
+            EjsonErrorKind::Build (e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for EjsonError {}
+
+// to_ejson writes `doc` as Extended JSON, without whitespace. Documents nested more than MAX_NESTING_DEPTH deep are
+// an error, as in `BsonRef::validate`.
+pub fn to_ejson (doc : BsonRef, mode : EjsonMode) -> Result<String, ReadError> {
+    let mut out = String::new();
+    write_document (&mut out, doc, mode, false, 0)?;
+    Ok(out)
+}
+
+// from_ejson reads an Extended JSON document, in canonical or relaxed form, as the spec describes.
+pub fn from_ejson (text : &str) -> Result<Vec<u8>, EjsonError> {
+    convert (text, false)
+}
+
+// from_ejson_strict is like `from_ejson`, but only reads a document as a wrapper if it matches the canonical or
+// relaxed form of one exactly. Other documents are kept, even if they have wrapper keys. Legacy forms such as
+// {"$regex": ..., "$options": ...} are not wrappers.
+pub fn from_ejson_strict (text : &str) -> Result<Vec<u8>, EjsonError> {
+    convert (text, true)
+}
+
+fn convert (text : &str, strict : bool) -> Result<Vec<u8>, EjsonError> {
+    let json = Parser { text, pos: 0, depth: 0 }.parse()?;
+    let Value::Object (members) = &json.value else {
+        return Err(EjsonError { offset: json.offset, kind: EjsonErrorKind::NotADocument });
+    };
+    let mut converter = Converter { builder: BSONBuilder::new(), strict };
+    if converter.wrapper (members, json.offset)?.is_some() {
+        return Err(EjsonError { offset: json.offset, kind: EjsonErrorKind::NotADocument });
+    }
+    converter.check_dbref (members, json.offset)?;
+    converter.members (members)?;
+    converter.builder.build().map_err(|e| EjsonError { offset: json.offset, kind: EjsonErrorKind::Build (e) })
+}
+
+// Writing.
+
+// write_document writes a document or array that is nested `depth` deep.
+fn write_document (out : &mut String, doc : BsonRef, mode : EjsonMode, array : bool, depth : usize) -> Result<(), ReadError> {
+    if depth > MAX_NESTING_DEPTH {
+        return Err(ReadError { offset: doc.offset(), kind: ReadErrorKind::MaxDepthExceeded (MAX_NESTING_DEPTH) });
+    }
+    out.push(if array { '[' } else { '{' });
+    for (i, element) in doc.iter().enumerate() {
+        let (key, value) = element?;
+        if i > 0 {
+            out.push(',');
+        }
+        // Array keys are not written. They are the indexes.
+        if !array {
+            write_string (out, key);
+            out.push(':');
+        }
+        write_value (out, value, mode, depth)?;
+    }
+    out.push(if array { ']' } else { '}' });
+    Ok(())
+}
+
+// write_wrapper writes {"key":value}, where `value` is already JSON.
+fn write_wrapper (out : &mut String, key : &str, value : &str) {
+    out.push('{');
+    write_string (out, key);
+    out.push(':');
+    out.push_str(value);
+    out.push('}');
+}
+
+fn quoted (s : &str) -> String {
+    let mut out = String::new();
+    write_string (&mut out, s);
+    out
+}
+
+fn write_value (out : &mut String, value : ElementRef, mode : EjsonMode, depth : usize) -> Result<(), ReadError> {
+    let relaxed = mode == EjsonMode::Relaxed;
+    match value {
+        ElementRef::Double (v) if relaxed && v.is_finite() => out.push_str(&format_double (v)),
+        ElementRef::Double (v) => write_wrapper (out, "$numberDouble", &quoted (&format_double (v))),
+        ElementRef::String (s) => write_string (out, s),
+        ElementRef::Document (doc) => write_document (out, doc, mode, false, depth + 1)?,
+        ElementRef::Array (doc) => write_document (out, doc, mode, true, depth + 1)?,
+        ElementRef::Binary { subtype, data } => {
+            let binary = format!("{{\"base64\":{},\"subType\":\"{:02x}\"}}", quoted (&base64_encode (data)), subtype);
+            write_wrapper (out, "$binary", &binary);
+        }
+        ElementRef::Undefined => write_wrapper (out, "$undefined", "true"),
+        ElementRef::ObjectId (id) => write_wrapper (out, "$oid", &quoted (&hex_encode (id))),
+        ElementRef::Bool (b) => out.push_str(if b { "true" } else { "false" }),
+        ElementRef::DateTime (ms) if relaxed && (0..MAX_ISO_DATE).contains(&ms) => {
+            write_wrapper (out, "$date", &quoted (&format_iso_date (ms)));
+        }
+        ElementRef::DateTime (ms) => write_wrapper (out, "$date", &format!("{{\"$numberLong\":\"{}\"}}", ms)),
+        ElementRef::Null => out.push_str("null"),
+        ElementRef::Regex { pattern, options } => {
+            // Options are sorted, as BSONBuilder writes them.
+            let mut options : Vec<char> = options.chars().collect();
+            options.sort();
+            let options : String = options.into_iter().collect();
+            let regex = format!("{{\"pattern\":{},\"options\":{}}}", quoted (pattern), quoted (&options));
+            write_wrapper (out, "$regularExpression", &regex);
+        }
+        ElementRef::DbPointer { namespace, id } => {
+            let pointer = format!("{{\"$ref\":{},\"$id\":{{\"$oid\":\"{}\"}}}}", quoted (namespace), hex_encode (id));
+            write_wrapper (out, "$dbPointer", &pointer);
+        }
+        ElementRef::JavaScript (code) => write_wrapper (out, "$code", &quoted (code)),
+        ElementRef::Symbol (s) => write_wrapper (out, "$symbol", &quoted (s)),
+        ElementRef::JavaScriptWithScope { code, scope } => {
+            out.push_str("{\"$code\":");
+            write_string (out, code);
+            out.push_str(",\"$scope\":");
+            write_document (out, scope, mode, false, depth + 1)?;
+            out.push('}');
+        }
+        ElementRef::Int32 (v) if relaxed => out.push_str(&v.to_string()),
+        ElementRef::Int32 (v) => write_wrapper (out, "$numberInt", &quoted (&v.to_string())),
+        ElementRef::Timestamp { timestamp, increment } => {
+            write_wrapper (out, "$timestamp", &format!("{{\"t\":{},\"i\":{}}}", timestamp, increment));
+        }
+        ElementRef::Int64 (v) if relaxed => out.push_str(&v.to_string()),
+        ElementRef::Int64 (v) => write_wrapper (out, "$numberLong", &quoted (&v.to_string())),
+        ElementRef::Decimal128 (v) => write_wrapper (out, "$numberDecimal", &quoted (&format_decimal128 (v))),
+        ElementRef::MinKey => write_wrapper (out, "$minKey", "1"),
+        ElementRef::MaxKey => write_wrapper (out, "$maxKey", "1"),
+    }
+    Ok(())
+}
+
+fn write_string (out : &mut String, s : &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{08}' => out.push_str("\\b"),
+            '\u{0C}' => out.push_str("\\f"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+// format_double returns the shortest string that reads back as `v`. It always has a decimal point or exponent, and
+// uses an exponent for very large or small values, e.g. "1.0", "-0.0", "1.2345678921232E+18" or "Infinity".
+fn format_double (v : f64) -> String {
+    if v.is_nan() {
+        return "NaN".to_string();
+    }
+    if v.is_infinite() {
+        return if v > 0.0 { "Infinity" } else { "-Infinity" }.to_string();
+    }
+    let scientific = format!("{:e}", v);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent : i32 = exponent.parse().unwrap();
+    if (-4..16).contains(&exponent) {
+        let s = v.to_string();
+        return if s.contains('.') { s } else { s + ".0" };
+    }
+    let point = if mantissa.contains('.') { "" } else { ".0" };
+    format!("{}{}E{}{}", mantissa, point, if exponent >= 0 { "+" } else { "-" }, exponent.abs())
+}
+
+// days_from_civil returns the number of days from 1970-01-01 to a date of the proleptic Gregorian calendar.
+// See https://howardhinnant.github.io/date_algorithms.html.
+fn days_from_civil (year : i64, month : i64, day : i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+// civil_from_days is the inverse of `days_from_civil`. It returns the year, month and day.
+fn civil_from_days (days : i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+// format_iso_date formats milliseconds since the Unix epoch, e.g. "2012-12-24T12:15:30.501Z". Whole seconds have no
+// fraction.
+fn format_iso_date (ms : i64) -> String {
+    let (year, month, day) = civil_from_days (ms.div_euclid(MS_PER_DAY));
+    let ms_of_day = ms.rem_euclid(MS_PER_DAY);
+    let (seconds, millis) = (ms_of_day / 1000, ms_of_day % 1000);
+    let mut s = format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}", year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if millis != 0 {
+        s.push_str(&format!(".{:03}", millis));
+    }
+    s.push('Z');
+    s
+}
+
+// parse_iso_date parses "YYYY-MM-DDTHH:MM:SS", an optional fraction of a second, and "Z" or an offset of the form
+// "+HH:MM" or "+HHMM". Digits of the fraction beyond milliseconds are ignored.
+fn parse_iso_date (s : &str) -> Option<i64> {
+    let b = s.as_bytes();
+    let number = |range : std::ops::Range<usize>| -> Option<i64> {
+        let digits = b.get(range)?;
+        if !digits.iter().all(|d| d.is_ascii_digit()) {
+            return None;
+        }
+        Some(digits.iter().fold(0, |n, d| n * 10 + (d - b'0') as i64))
+    };
+    let separators = [(4, b'-'), (7, b'-'), (10, b'T'), (13, b':'), (16, b':')];
+    if separators.iter().any(|&(i, c)| b.get(i) != Some(&c)) {
+        return None;
+    }
+    let (year, month, day) = (number (0..4)?, number (5..7)?, number (8..10)?);
+    let (hour, minute, second) = (number (11..13)?, number (14..16)?, number (17..19)?);
+    let days = days_from_civil (year, month, day);
+    if civil_from_days (days) != (year, month, day) || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    let mut pos = 19;
+    let mut millis = 0;
+    if b.get(pos) == Some(&b'.') {
+        let digits = b[pos + 1..].iter().take_while(|d| d.is_ascii_digit()).count();
+        if digits == 0 {
+            return None;
+        }
+        for i in 0..3 {
+            millis = millis * 10 + if i < digits { (b[pos + 1 + i] - b'0') as i64 } else { 0 };
+        }
+        pos += 1 + digits;
+    }
+    let offset_minutes = match &b[pos..] {
+        b"Z" => 0,
+        [sign @ (b'+' | b'-'), ..] => {
+            let minutes = match b.len() - pos {
+                6 if b[pos + 3] == b':' => number (pos + 1..pos + 3)? * 60 + number (pos + 4..pos + 6)?,
+                5 => number (pos + 1..pos + 3)? * 60 + number (pos + 3..pos + 5)?,
+                _ => return None,
+            };
+            if *sign == b'-' { -minutes } else { minutes }
+        }
+        _ => return None,
+    };
+    let seconds = days * 86400 + hour * 3600 + minute * 60 + second - offset_minutes * 60;
+    Some(seconds * 1000 + millis)
+}
+
+const BASE64_ALPHABET : &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode (data : &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+// base64_decode decodes padded base64 with the standard alphabet.
+fn base64_decode (s : &str) -> Option<Vec<u8>> {
+    let b = s.as_bytes();
+    if !b.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut out = Vec::new();
+    for (i, chunk) in b.chunks(4).enumerate() {
+        let last = i == b.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+        let mut n = 0u32;
+        for &c in &chunk[..4 - padding] {
+            let value = BASE64_ALPHABET.iter().position(|&a| a == c)?;
+            n = n << 6 | value as u32;
+        }
+        n <<= 6 * padding;
+        out.extend_from_slice(&n.to_be_bytes()[1..4 - padding]);
+    }
+    Some(out)
+}
+
+fn hex_encode (data : &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hex_decode<const N : usize> (s : &str) -> Option<[u8; N]> {
+    if s.len() != N * 2 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let mut out = [0; N];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(out)
+}
+
+// parse_uuid parses the "$uuid" form, e.g. "73ffd264-44b3-4c69-90e8-e7d1dfc035d4".
+fn parse_uuid (s : &str) -> Option<[u8; 16]> {
+    let b = s.as_bytes();
+    if b.len() != 36 || [8, 13, 18, 23].iter().any(|&i| b[i] != b'-') {
+        return None;
+    }
+    hex_decode (&s.replace('-', ""))
+}
+
+// Reading JSON.
+
+// Json is a parsed JSON value and its offset in the text.
+#[derive(Debug, Clone, PartialEq)]
+struct Json {
+    offset : usize,
+    value : Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Null,
+    Bool (bool),
+    // Numbers keep their text, so integers can be told from doubles and are read exactly.
+    Number (String),
+    String (String),
+    Array (Vec<Json>),
+    // Objects keep their members in order, including duplicate keys.
+    Object (Vec<(String, Json)>),
+}
+
+impl Json {
+    fn as_str (&self) -> Option<&str> {
+        match &self.value {
+            Value::String (s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_object (&self) -> Option<&[(String, Json)]> {
+        match &self.value {
+            Value::Object (members) => Some(members),
+            _ => None,
+        }
+    }
+
+    fn as_u32 (&self) -> Option<u32> {
+        match &self.value {
+            Value::Number (n) if is_integer (n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+// is_integer reports whether `s` is an optionally negative decimal integer. Integers in wrappers have no '+'.
+fn is_integer (s : &str) -> bool {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+}
+
+struct Parser<'a> {
+    text : &'a str,
+    pos : usize,
+    depth : usize,
+}
+
+impl<'a> Parser<'a> {
+    fn err<T> (&self, msg : &'static str) -> Result<T, EjsonError> {
+        Err(EjsonError { offset: self.pos, kind: EjsonErrorKind::Syntax (msg) })
+    }
+
+    fn peek (&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace (&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect (&mut self, c : u8, msg : &'static str) -> Result<(), EjsonError> {
+        self.skip_whitespace();
+        if self.peek() != Some(c) {
+            return self.err(msg);
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn parse (mut self) -> Result<Json, EjsonError> {
+        let json = self.value()?;
+        self.skip_whitespace();
+        if self.pos != self.text.len() {
+            return self.err("unexpected data after the document");
+        }
+        Ok(json)
+    }
+
+    fn value (&mut self) -> Result<Json, EjsonError> {
+        self.skip_whitespace();
+        let offset = self.pos;
+        let value = match self.peek() {
+            Some(b'{') => self.nested(Parser::object)?,
+            Some(b'[') => self.nested(Parser::array)?,
+            Some(b'"') => Value::String (self.string()?),
+            Some(b'-' | b'0'..=b'9') => Value::Number (self.number()?),
+            Some(_) if self.literal("true") => Value::Bool (true),
+            Some(_) if self.literal("false") => Value::Bool (false),
+            Some(_) if self.literal("null") => Value::Null,
+            Some(_) => return self.err("expected a value"),
+            None => return self.err("unexpected end of text"),
+        };
+        Ok(Json { offset, value })
+    }
+
+    fn literal (&mut self, word : &str) -> bool {
+        if self.text[self.pos..].starts_with(word) {
+            self.pos += word.len();
+            return true;
+        }
+        false
+    }
+
+    fn nested (&mut self, parse : fn (&mut Parser<'a>) -> Result<Value, EjsonError>) -> Result<Value, EjsonError> {
+        if self.depth == MAX_JSON_DEPTH {
+            return self.err("nested too deeply");
+        }
+        self.depth += 1;
+        let value = parse (self)?;
+        self.depth -= 1;
+        Ok(value)
+    }
+
+    fn object (&mut self) -> Result<Value, EjsonError> {
+        self.pos += 1;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Value::Object (members));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return self.err("expected a key");
+            }
+            let key = self.string()?;
+            self.expect(b':', "expected ':'")?;
+            members.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Value::Object (members));
+                }
+                _ => return self.err("expected ',' or '}'"),
+            }
+        }
+    }
+
+    fn array (&mut self) -> Result<Value, EjsonError> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Value::Array (items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Value::Array (items));
+                }
+                _ => return self.err("expected ',' or ']'"),
+            }
+        }
+    }
+
+    fn string (&mut self) -> Result<String, EjsonError> {
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            let Some(c) = self.text[self.pos..].chars().next() else {
+                return self.err("unterminated string");
+            };
+            match c {
+                '"' => {
+                    self.pos += 1;
+                    return Ok(s);
+                }
+                '\\' => {
+                    self.pos += 1;
+                    let escaped = match self.peek() {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{08}',
+                        Some(b'f') => '\u{0C}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            s.push(self.unicode_escape()?);
+                            continue;
+                        }
+                        _ => return self.err("invalid escape"),
+                    };
+                    self.pos += 1;
+                    s.push(escaped);
+                }
+                c if (c as u32) < 0x20 => return self.err("control character in string"),
+                c => {
+                    self.pos += c.len_utf8();
+                    s.push(c);
+                }
+            }
+        }
+    }
+
+    // unicode_escape reads the "uXXXX" of an escape, and a second escape if the first is a high surrogate.
+    fn unicode_escape (&mut self) -> Result<char, EjsonError> {
+        let start = self.pos - 1;
+        let high = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if !self.text[self.pos..].starts_with("\\u") {
+                self.pos = start;
+                return self.err("unpaired surrogate");
+            }
+            self.pos += 1;
+            let low = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                self.pos = start;
+                return self.err("unpaired surrogate");
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        match char::from_u32(code) {
+            Some(c) => Ok(c),
+            None => {
+                self.pos = start;
+                self.err("unpaired surrogate")
+            }
+        }
+    }
+
+    // hex4 reads "uXXXX".
+    fn hex4 (&mut self) -> Result<u32, EjsonError> {
+        let digits = self.text.get(self.pos + 1..self.pos + 5).filter(|d| d.bytes().all(|b| b.is_ascii_hexdigit()));
+        match digits {
+            Some(digits) => {
+                self.pos += 5;
+                Ok(u32::from_str_radix(digits, 16).unwrap())
+            }
+            None => self.err("invalid unicode escape"),
+        }
+    }
+
+    fn number (&mut self) -> Result<String, EjsonError> {
+        let start = self.pos;
+        let b = self.text.as_bytes();
+        let digits = |pos : usize| b[pos..].iter().take_while(|d| d.is_ascii_digit()).count();
+        let mut pos = start;
+        if b[pos] == b'-' {
+            pos += 1;
+        }
+        match digits (pos) {
+            0 => return self.err("invalid number"),
+            n if n > 1 && b[pos] == b'0' => return self.err("leading zero in number"),
+            n => pos += n,
+        }
+        if b.get(pos) == Some(&b'.') {
+            match digits (pos + 1) {
+                0 => return self.err("invalid number"),
+                n => pos += 1 + n,
+            }
+        }
+        if let Some(b'e' | b'E') = b.get(pos) {
+            pos += 1;
+            if let Some(b'+' | b'-') = b.get(pos) {
+                pos += 1;
+            }
+            match digits (pos) {
+                0 => return self.err("invalid number"),
+                n => pos += n,
+            }
+        }
+        self.pos = pos;
+        Ok(self.text[start..pos].to_string())
+    }
+}
+
+// is_json_number reports whether `s` is a number in JSON syntax.
+fn is_json_number (s : &str) -> bool {
+    let mut parser = Parser { text: s, pos: 0, depth: 0 };
+    matches!(s.as_bytes().first(), Some(b'-' | b'0'..=b'9')) && parser.number().is_ok() && parser.pos == s.len()
+}
+
+// Converting JSON to BSON.
+
+// WRAPPER_KEYS are the keys that make a document a type wrapper. "$regex" is only a wrapper key in the legacy
+// {"$regex": ..., "$options": ...} form, since it is also a query operator.
+const WRAPPER_KEYS : [&str; 16] = [
+    "$oid", "$symbol", "$numberInt", "$numberLong", "$numberDouble", "$numberDecimal", "$binary", "$uuid", "$code",
+    "$timestamp", "$regularExpression", "$dbPointer", "$date", "$minKey", "$maxKey", "$undefined",
+];
+
+// Wrapped is the value of a type wrapper.
+enum Wrapped {
+    Double (f64),
+    Int32 (i32),
+    Int64 (i64),
+    Decimal128 ([u8; 16]),
+    Binary (u8, Vec<u8>),
+    ObjectId ([u8; 12]),
+    DateTime (i64),
+    Regex (String, String),
+    JavaScript (String),
+    Timestamp (u32, u32),
+    MinKey,
+    MaxKey,
+    // A valid wrapper of a deprecated type.
+    Deprecated (&'static str),
+}
+
+// exact returns the values of `keys` if `members` has exactly those keys, in any order.
+fn exact<'j, const N : usize> (members : &'j [(String, Json)], keys : [&str; N]) -> Option<[&'j Json; N]> {
+    if members.len() != N {
+        return None;
+    }
+    let mut values = [None; N];
+    for (key, value) in members {
+        let i = keys.iter().position(|k| k == key)?;
+        if values[i].replace(value).is_some() {
+            return None;
+        }
+    }
+    Some(values.map(|v| v.unwrap()))
+}
+
+// wrapper_key returns the key that makes `members` a type wrapper, if any.
+fn wrapper_key (members : &[(String, Json)], legacy : bool) -> Option<&'static str> {
+    for (key, value) in members {
+        if let Some(key) = WRAPPER_KEYS.iter().find(|k| *k == key) {
+            return Some(key);
+        }
+        if legacy && key == "$regex" && value.as_str().is_some() && members.iter().any(|(k, _)| k == "$options") {
+            return Some("$regex");
+        }
+    }
+    None
+}
+
+// parse_wrapper reads the wrapper of type `key`. It returns None if `members` is not a valid wrapper.
+fn parse_wrapper (key : &str, members : &[(String, Json)], legacy : bool) -> Option<Wrapped> {
+    let single = || exact (members, [key]).map(|[v]| v);
+    let wrapped = match key {
+        "$oid" => Wrapped::ObjectId (hex_decode (single ()?.as_str()?)?),
+        "$symbol" => {
+            single ()?.as_str()?;
+            Wrapped::Deprecated ("$symbol")
+        }
+        "$numberInt" => {
+            let s = single ()?.as_str()?;
+            if !is_integer (s) {
+                return None;
+            }
+            Wrapped::Int32 (s.parse().ok()?)
+        }
+        "$numberLong" => Wrapped::Int64 (parse_int64 (single ()?.as_str()?)?),
+        "$numberDouble" => {
+            let s = single ()?.as_str()?;
+            Wrapped::Double (match s {
+                "NaN" => f64::NAN,
+                "Infinity" => f64::INFINITY,
+                "-Infinity" => f64::NEG_INFINITY,
+                s if is_json_number (s) => s.parse().ok().filter(|v : &f64| v.is_finite())?,
+                _ => return None,
+            })
+        }
+        "$numberDecimal" => Wrapped::Decimal128 (parse_decimal128 (single ()?.as_str()?)?),
+        "$binary" => match &members.iter().find(|(k, _)| k == "$binary")?.1.value {
+            Value::Object (_) => {
+                let [data, subtype] = exact (single ()?.as_object()?, ["base64", "subType"])?;
+                Wrapped::Binary (parse_subtype (subtype.as_str()?)?, base64_decode (data.as_str()?)?)
+            }
+            // The legacy form is {"$binary": "<base64>", "$type": "<subtype>"}.
+            Value::String (_) if legacy => {
+                let [data, subtype] = exact (members, ["$binary", "$type"])?;
+                Wrapped::Binary (parse_subtype (subtype.as_str()?)?, base64_decode (data.as_str()?)?)
+            }
+            _ => return None,
+        },
+        "$uuid" => Wrapped::Binary (BINARY_SUBTYPE_UUID, parse_uuid (single ()?.as_str()?)?.to_vec()),
+        "$code" if members.iter().any(|(k, _)| k == "$scope") => {
+            let [code, scope] = exact (members, ["$code", "$scope"])?;
+            code.as_str()?;
+            scope.as_object()?;
+            Wrapped::Deprecated ("$code with $scope")
+        }
+        "$code" => Wrapped::JavaScript (single ()?.as_str()?.to_string()),
+        "$timestamp" => {
+            let [t, i] = exact (single ()?.as_object()?, ["t", "i"])?;
+            Wrapped::Timestamp (t.as_u32()?, i.as_u32()?)
+        }
+        "$regularExpression" => {
+            let [pattern, options] = exact (single ()?.as_object()?, ["pattern", "options"])?;
+            Wrapped::Regex (pattern.as_str()?.to_string(), options.as_str()?.to_string())
+        }
+        "$regex" => {
+            let [pattern, options] = exact (members, ["$regex", "$options"])?;
+            Wrapped::Regex (pattern.as_str()?.to_string(), options.as_str()?.to_string())
+        }
+        "$dbPointer" => {
+            let [namespace, id] = exact (single ()?.as_object()?, ["$ref", "$id"])?;
+            namespace.as_str()?;
+            let [oid] = exact (id.as_object()?, ["$oid"])?;
+            hex_decode::<12> (oid.as_str()?)?;
+            Wrapped::Deprecated ("$dbPointer")
+        }
+        "$date" => match &single ()?.value {
+            Value::Object (date) => Wrapped::DateTime (parse_int64 (exact (date, ["$numberLong"])?[0].as_str()?)?),
+            Value::String (s) => Wrapped::DateTime (parse_iso_date (s)?),
+            _ => return None,
+        },
+        "$minKey" | "$maxKey" => {
+            if single ()?.value != Value::Number ("1".to_string()) {
+                return None;
+            }
+            if key == "$minKey" { Wrapped::MinKey } else { Wrapped::MaxKey }
+        }
+        "$undefined" => {
+            if single ()?.value != Value::Bool (true) {
+                return None;
+            }
+            Wrapped::Deprecated ("$undefined")
+        }
+        _ => return None,
+    };
+    Some(wrapped)
+}
+
+fn parse_int64 (s : &str) -> Option<i64> {
+    if !is_integer (s) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+// parse_subtype parses a binary subtype of one or two hex digits.
+fn parse_subtype (s : &str) -> Option<u8> {
+    if s.is_empty() || s.len() > 2 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u8::from_str_radix(s, 16).ok()
+}
+
+struct Converter {
+    builder : BSONBuilder,
+    strict : bool,
+}
+
+impl Converter {
+    // wrapper returns the value of `members` if it is a type wrapper. Outside strict mode, a document with a wrapper
+    // key that is not a valid wrapper is an error.
+    fn wrapper (&self, members : &[(String, Json)], offset : usize) -> Result<Option<Wrapped>, EjsonError> {
+        let Some(key) = wrapper_key (members, !self.strict) else {
+            return Ok(None);
+        };
+        let kind = match parse_wrapper (key, members, !self.strict) {
+            Some(Wrapped::Deprecated (key)) => EjsonErrorKind::Unsupported (key),
+            Some(wrapped) => return Ok(Some(wrapped)),
+            None if self.strict => return Ok(None),
+            None => EjsonErrorKind::InvalidWrapper (key),
+        };
+        Err(EjsonError { offset, kind })
+    }
+
+    // check_dbref checks the fields of a DBRef, a document with "$ref" and "$id". Strict mode does not check them.
+    fn check_dbref (&self, members : &[(String, Json)], offset : usize) -> Result<(), EjsonError> {
+        let field = |key : &str| members.iter().find(|(k, _)| k == key).map(|(_, v)| v);
+        if self.strict || field ("$ref").is_none() || field ("$id").is_none() {
+            return Ok(());
+        }
+        let invalid = |key : &str| field (key).is_some_and(|v| v.as_str().is_none());
+        if invalid ("$ref") || invalid ("$db") {
+            return Err(EjsonError { offset, kind: EjsonErrorKind::InvalidDbRef });
+        }
+        Ok(())
+    }
+
+    fn members (&mut self, members : &[(String, Json)]) -> Result<(), EjsonError> {
+        for (key, value) in members {
+            self.append (key, value)?;
+        }
+        Ok(())
+    }
+
+    fn append (&mut self, key : &str, json : &Json) -> Result<(), EjsonError> {
+        let build_err = |e| EjsonError { offset: json.offset, kind: EjsonErrorKind::Build (e) };
+        let bb = &mut self.builder;
+        match &json.value {
+            Value::Null => bb.append_null (key),
+            Value::Bool (b) => bb.append_bool (key, *b),
+            Value::Number (n) => {
+                // Relaxed numbers are int32 or int64 if they are integers that fit, and doubles otherwise.
+                if let Some(v) = n.parse::<i32>().ok().filter(|_| is_integer (n)) {
+                    bb.append_int32 (key, v)
+                } else if let Some(v) = n.parse::<i64>().ok().filter(|_| is_integer (n)) {
+                    bb.append_int64 (key, v)
+                } else {
+                    match n.parse::<f64>() {
+                        Ok(v) if v.is_finite() => bb.append_double (key, v),
+                        _ => return Err(EjsonError { offset: json.offset, kind: EjsonErrorKind::Syntax ("number out of range") }),
+                    }
+                }
+            }
+            Value::String (s) => bb.append_string (key, s),
+            Value::Array (items) => {
+                bb.start_array (key).map_err(build_err)?;
+                for item in items {
+                    self.append ("", item)?;
+                }
+                self.builder.end_array()
+            }
+            Value::Object (members) => match self.wrapper (members, json.offset)? {
+                Some(wrapped) => self.append_wrapped (key, wrapped),
+                None => {
+                    self.check_dbref (members, json.offset)?;
+                    self.builder.start_document (key).map_err(build_err)?;
+                    self.members (members)?;
+                    self.builder.end_document()
+                }
+            },
+        }
+        .map_err(build_err)
+    }
+
+    fn append_wrapped (&mut self, key : &str, wrapped : Wrapped) -> Result<(), BuildError> {
+        let bb = &mut self.builder;
+        match wrapped {
+            Wrapped::Double (v) => bb.append_double (key, v),
+            Wrapped::Int32 (v) => bb.append_int32 (key, v),
+            Wrapped::Int64 (v) => bb.append_int64 (key, v),
+            Wrapped::Decimal128 (v) => bb.append_decimal128 (key, &v),
+            Wrapped::Binary (subtype, data) => bb.append_binary (key, subtype, &data),
+            Wrapped::ObjectId (id) => bb.append_objectid (key, &id),
+            Wrapped::DateTime (ms) => bb.append_datetime (key, ms),
+            Wrapped::Regex (pattern, options) => bb.append_regex (key, &pattern, &options),
+            Wrapped::JavaScript (code) => bb.append_javascript (key, &code),
+            Wrapped::Timestamp (t, i) => bb.append_timestamp (key, t, i),
+            Wrapped::MinKey => bb.append_minkey (key),
+            Wrapped::MaxKey => bb.append_maxkey (key),
+            // `wrapper` reports deprecated types as errors.
+            Wrapped::Deprecated (_) => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+fn canonical (bytes : &[u8]) -> String {
+    to_ejson (BsonRef::new (bytes).unwrap(), EjsonMode::Canonical).unwrap()
+}
+
+#[cfg(test)]
+fn relaxed (bytes : &[u8]) -> String {
+    to_ejson (BsonRef::new (bytes).unwrap(), EjsonMode::Relaxed).unwrap()
+}
+
+#[cfg(test)]
+fn all_types () -> Vec<u8> {
+    let mut bb = BSONBuilder::new();
+    bb.append_double ("double", 1.5).unwrap();
+    bb.append_string ("string", "a\"b\\c\n\u{1}é").unwrap();
+    bb.start_document ("document").unwrap();
+    bb.append_null ("null").unwrap();
+    bb.end_document ().unwrap();
+    bb.start_array ("array").unwrap();
+    bb.append_bool ("", true).unwrap();
+    bb.append_bool ("", false).unwrap();
+    bb.end_array ().unwrap();
+    bb.append_binary ("binary", 0x80, &[1, 2, 3, 4]).unwrap();
+    bb.append_objectid ("oid", &[0x56, 0xe1, 0xfc, 0x72, 0xe0, 0xc9, 0x17, 0xe9, 0xc4, 0x71, 0x41, 0x61]).unwrap();
+    bb.append_datetime ("date", 1356351330501).unwrap();
+    bb.append_regex ("regex", "^a", "mi").unwrap();
+    bb.append_javascript ("code", "x()").unwrap();
+    bb.append_int32 ("int32", -7).unwrap();
+    bb.append_timestamp ("timestamp", 123456789, 42).unwrap();
+    bb.append_int64 ("int64", 1 << 40).unwrap();
+    bb.append_decimal128 ("decimal", &[0x0F, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x3E, 0x30]).unwrap();
+    bb.append_minkey ("min").unwrap();
+    bb.append_maxkey ("max").unwrap();
+    bb.build().unwrap()
+}
+
+#[test]
+fn test_canonical () {
+    assert_eq!(canonical (&all_types ()), concat!(
+        r#"{"double":{"$numberDouble":"1.5"},"string":"a\"b\\c\n\u0001é","document":{"null":null},"#,
+        r#""array":[true,false],"binary":{"$binary":{"base64":"AQIDBA==","subType":"80"}},"#,
+        r#""oid":{"$oid":"56e1fc72e0c917e9c4714161"},"date":{"$date":{"$numberLong":"1356351330501"}},"#,
+        r#""regex":{"$regularExpression":{"pattern":"^a","options":"im"}},"code":{"$code":"x()"},"#,
+        r#""int32":{"$numberInt":"-7"},"timestamp":{"$timestamp":{"t":123456789,"i":42}},"#,
+        r#""int64":{"$numberLong":"1099511627776"},"decimal":{"$numberDecimal":"1.5"},"#,
+        r#""min":{"$minKey":1},"max":{"$maxKey":1}}"#));
+}
+
+#[test]
+fn test_relaxed () {
+    assert_eq!(relaxed (&all_types ()), concat!(
+        r#"{"double":1.5,"string":"a\"b\\c\n\u0001é","document":{"null":null},"#,
+        r#""array":[true,false],"binary":{"$binary":{"base64":"AQIDBA==","subType":"80"}},"#,
+        r#""oid":{"$oid":"56e1fc72e0c917e9c4714161"},"date":{"$date":"2012-12-24T12:15:30.501Z"},"#,
+        r#""regex":{"$regularExpression":{"pattern":"^a","options":"im"}},"code":{"$code":"x()"},"#,
+        r#""int32":-7,"timestamp":{"$timestamp":{"t":123456789,"i":42}},"#,
+        r#""int64":1099511627776,"decimal":{"$numberDecimal":"1.5"},"#,
+        r#""min":{"$minKey":1},"max":{"$maxKey":1}}"#));
+}
+
+#[test]
+fn test_round_trip () {
+    let bytes = all_types ();
+    assert_eq!(from_ejson (&canonical (&bytes)), Ok(bytes.clone()));
+    assert_eq!(from_ejson_strict (&canonical (&bytes)), Ok(bytes.clone()));
+    // Relaxed int64s that fit in an int32 become int32s, but these do not.
+    assert_eq!(from_ejson (&relaxed (&bytes)), Ok(bytes));
+}
+
+#[test]
+fn test_doubles () {
+    let cases = [
+        (1.0, "1.0"),
+        (-1.0, "-1.0"),
+        (1.0001220703125, "1.0001220703125"),
+        (1.2345678921232e18, "1.2345678921232E+18"),
+        (-1.2345678921232e18, "-1.2345678921232E+18"),
+        (0.0, "0.0"),
+        (-0.0, "-0.0"),
+        (1e16, "1.0E+16"),
+        (123456789012345.0, "123456789012345.0"),
+        (0.0001, "0.0001"),
+        (0.00001, "1.0E-5"),
+        (5e-324, "5.0E-324"),
+        (f64::MAX, "1.7976931348623157E+308"),
+    ];
+    for (v, want) in cases {
+        let mut bb = BSONBuilder::new();
+        bb.append_double ("d", v).unwrap();
+        let bytes = bb.build().unwrap();
+        assert_eq!(canonical (&bytes), format!(r#"{{"d":{{"$numberDouble":"{}"}}}}"#, want));
+        assert_eq!(relaxed (&bytes), format!(r#"{{"d":{}}}"#, want));
+        assert_eq!(from_ejson (&canonical (&bytes)), Ok(bytes.clone()));
+        assert_eq!(from_ejson (&relaxed (&bytes)), Ok(bytes));
+    }
+    for (v, want) in [(f64::NAN, "NaN"), (f64::INFINITY, "Infinity"), (f64::NEG_INFINITY, "-Infinity")] {
+        let mut bb = BSONBuilder::new();
+        bb.append_double ("d", v).unwrap();
+        let bytes = bb.build().unwrap();
+        let json = format!(r#"{{"d":{{"$numberDouble":"{}"}}}}"#, want);
+        assert_eq!(canonical (&bytes), json);
+        // Relaxed mode has no JSON number for these.
+        assert_eq!(relaxed (&bytes), json);
+        assert_eq!(from_ejson (&json), Ok(bytes));
+    }
+}
+
+#[test]
+fn test_dates () {
+    let cases = [
+        (0, r#"{"$date":"1970-01-01T00:00:00Z"}"#),
+        (1356351330001, r#"{"$date":"2012-12-24T12:15:30.001Z"}"#),
+        (951782400000, r#"{"$date":"2000-02-29T00:00:00Z"}"#),
+        (253402300799999, r#"{"$date":"9999-12-31T23:59:59.999Z"}"#),
+        // Dates outside 1970 to 9999 are numbers.
+        (-284643869501, r#"{"$date":{"$numberLong":"-284643869501"}}"#),
+        (253402300800000, r#"{"$date":{"$numberLong":"253402300800000"}}"#),
+    ];
+    for (ms, want) in cases {
+        let mut bb = BSONBuilder::new();
+        bb.append_datetime ("a", ms).unwrap();
+        let bytes = bb.build().unwrap();
+        assert_eq!(relaxed (&bytes), format!(r#"{{"a":{}}}"#, want));
+        assert_eq!(from_ejson (&relaxed (&bytes)), Ok(bytes));
+    }
+    let parsed = [
+        ("2012-12-24T12:15:30.501Z", 1356351330501),
+        ("2012-12-24T12:15:30.5Z", 1356351330500),
+        ("2012-12-24T12:15:30.501999Z", 1356351330501),
+        ("2012-12-24T13:15:30+01:00", 1356351330000),
+        ("2012-12-24T11:15:30-0100", 1356351330000),
+        ("1960-01-01T00:00:00Z", -315619200000),
+    ];
+    for (s, want) in parsed {
+        assert_eq!(parse_iso_date (s), Some(want), "{:?}", s);
+    }
+    let invalid = [
+        "2012-12-24", "2012-12-24T12:15:30", "2012-13-24T12:15:30Z", "2013-02-29T12:15:30Z", "2012-12-24T24:00:00Z",
+        "2012-12-24T12:15:30.Z", "2012-12-24T12:15:30+1", "2012-12-24 12:15:30Z", "2012-12-24T12:15:30Zjunk",
+    ];
+    for s in invalid {
+        assert_eq!(parse_iso_date (s), None, "{:?}", s);
+    }
+}
+
+#[test]
+fn test_relaxed_numbers () {
+    let mut bb = BSONBuilder::new();
+    bb.append_int32 ("a", 2147483647).unwrap();
+    bb.append_int64 ("b", 2147483648).unwrap();
+    bb.append_int64 ("c", -9223372036854775808).unwrap();
+    bb.append_double ("d", 9223372036854775808.0).unwrap();
+    bb.append_double ("e", 1.0).unwrap();
+    bb.append_double ("f", 100.0).unwrap();
+    let want = bb.build().unwrap();
+    let json = r#"{"a": 2147483647, "b": 2147483648, "c": -9223372036854775808, "d": 9223372036854775808, "e": 1.0, "f": 1e2}"#;
+    assert_eq!(from_ejson (json), Ok(want));
+}
+
+#[test]
+fn test_parse_wrappers () {
+    let mut bb = BSONBuilder::new();
+    bb.append_binary ("uuid", BINARY_SUBTYPE_UUID, &[
+        0x73, 0xff, 0xd2, 0x64, 0x44, 0xb3, 0x4c, 0x69, 0x90, 0xe8, 0xe7, 0xd1, 0xdf, 0xc0, 0x35, 0xd4]).unwrap();
+    bb.append_binary ("legacy", 0x05, &[1, 2]).unwrap();
+    bb.append_binary ("empty", 0x00, &[]).unwrap();
+    bb.append_regex ("regex", "abc", "ix").unwrap();
+    bb.append_datetime ("date", 1356351330501).unwrap();
+    bb.append_decimal128 ("decimal", &parse_decimal128 ("-1.23E+40").unwrap()).unwrap();
+    bb.append_double ("double", 1.2345678921232e18).unwrap();
+    let want = bb.build().unwrap();
+    let json = r#"{
+        "uuid": {"$uuid": "73ffd264-44b3-4c69-90e8-e7d1dfc035d4"},
+        "legacy": {"$type": "5", "$binary": "AQI="},
+        "empty": {"$binary": {"subType": "0", "base64": ""}},
+        "regex": {"$regex": "abc", "$options": "xi"},
+        "date": {"$date": "2012-12-24T12:15:30.501Z"},
+        "decimal": {"$numberDecimal": "-123e38"},
+        "double": {"$numberDouble": "1.2345678921232e+18"}
+    }"#;
+    assert_eq!(from_ejson (json), Ok(want));
+    // The object form of $binary has no other keys.
+    let json = r#"{"a": {"$binary": {"base64": "", "subType": "00"}, "$type": "00"}}"#;
+    assert_eq!(from_ejson (json).unwrap_err().kind, EjsonErrorKind::InvalidWrapper ("$binary"));
+}
+
+#[test]
+fn test_regex_options_sorted () {
+    // {"r": /a/mi}. Other writers may not sort the options, but Extended JSON has them sorted.
+    let bytes = [0x0d, 0, 0, 0, 0x0b, b'r', 0, b'a', 0, b'm', b'i', 0, 0];
+    assert_eq!(
+        to_ejson (BsonRef::new (&bytes).unwrap(), EjsonMode::Canonical).unwrap(),
+        r#"{"r":{"$regularExpression":{"pattern":"a","options":"im"}}}"#);
+}
+
+#[test]
+fn test_dollar_keys () {
+    // From the decode-bson-with-dollars investigation: {"foo": {"$numberInt": "123"}} with a string value. Reading
+    // the BSON never interprets keys, and the Extended JSON is the same as that of an int32.
+    let bytes = [
+        0x23, 0x00, 0x00, 0x00, 0x03, 0x66, 0x6f, 0x6f, 0x00, 0x19, 0x00, 0x00, 0x00, 0x02, 0x24, 0x6e, 0x75, 0x6d,
+        0x62, 0x65, 0x72, 0x49, 0x6e, 0x74, 0x00, 0x04, 0x00, 0x00, 0x00, 0x31, 0x32, 0x33, 0x00, 0x00, 0x00];
+    let doc = BsonRef::new (&bytes).unwrap();
+    let foo = doc.get ("foo").unwrap().unwrap().as_document().unwrap();
+    assert_eq!(foo.get ("$numberInt").unwrap().unwrap().as_str(), Some("123"));
+    assert_eq!(canonical (&bytes), r#"{"foo":{"$numberInt":"123"}}"#);
+
+    // Keys that are not wrapper keys are kept in both modes.
+    let mut bb = BSONBuilder::new();
+    bb.start_document ("q").unwrap();
+    bb.append_int32 ("$gt", 1).unwrap();
+    bb.append_string ("$regex", "^a").unwrap();
+    bb.append_string ("$type", "string").unwrap();
+    bb.end_document ().unwrap();
+    bb.start_document ("ref").unwrap();
+    bb.append_string ("$ref", "c").unwrap();
+    bb.append_int32 ("$id", 1).unwrap();
+    bb.end_document ().unwrap();
+    let want = bb.build().unwrap();
+    let json = r#"{"q": {"$gt": 1, "$regex": "^a", "$type": "string"}, "ref": {"$ref": "c", "$id": 1}}"#;
+    assert_eq!(from_ejson (json), Ok(want.clone()));
+    assert_eq!(from_ejson_strict (json), Ok(want));
+}
+
+#[test]
+fn test_strict () {
+    // Documents that look like wrappers but are not are errors, except in strict mode where they are kept.
+    let cases = [
+        (r#"{"a": {"$numberInt": "123", "x": 1}}"#, 6, "$numberInt"),
+        (r#"{"a": {"$numberInt": 123}}"#, 6, "$numberInt"),
+        (r#"{"a": {"$numberInt": "1.5"}}"#, 6, "$numberInt"),
+        (r#"{"a": {"$numberLong": "+1"}}"#, 6, "$numberLong"),
+        (r#"{"a": {"$date": 0}}"#, 6, "$date"),
+        (r#"{"a": {"$oid": "56e1fc72e0c917e9c471416"}}"#, 6, "$oid"),
+        (r#"{"a": {"$timestamp": {"t": 1, "i": -1}}}"#, 6, "$timestamp"),
+        (r#"{"a": {"$binary": {"base64": "AQ=", "subType": "00"}}}"#, 6, "$binary"),
+        (r#"{"a": {"$minKey": 2}}"#, 6, "$minKey"),
+        (r#"{"a": {"$regularExpression": {"pattern": "a"}}}"#, 6, "$regularExpression"),
+        (r#"{"a": {"$numberDouble": "Inf"}}"#, 6, "$numberDouble"),
+    ];
+    for (json, offset, key) in cases {
+        assert_eq!(from_ejson (json), Err(EjsonError { offset, kind: EjsonErrorKind::InvalidWrapper (key) }), "{}", json);
+        let bytes = from_ejson_strict (json).unwrap();
+        // The document is kept as it is.
+        let doc = BsonRef::new (&bytes).unwrap();
+        let a = doc.get ("a").unwrap().unwrap().as_document().unwrap();
+        assert_eq!(a.iter().next().unwrap().unwrap().0, key, "{}", json);
+    }
+    // Legacy forms are only read outside strict mode.
+    let json = r#"{"a": {"$regex": "abc", "$options": "i"}}"#;
+    assert_eq!(BsonRef::new (&from_ejson (json).unwrap()).unwrap().get ("a").unwrap().unwrap().element_type(), 0x0B);
+    assert_eq!(BsonRef::new (&from_ejson_strict (json).unwrap()).unwrap().get ("a").unwrap().unwrap().element_type(), 0x03);
+}
+
+#[test]
+fn test_deprecated () {
+    // Deprecated types are written, but not read.
+    let bytes = [
+        0x23, 0x00, 0x00, 0x00,
+        0x0E, 0x61, 0x00, 0x02, 0x00, 0x00, 0x00, 0x62, 0x00,
+        0x06, 0x75, 0x00,
+        0x0F, 0x63, 0x00, 0x0F, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x78, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00,
+        0x00];
+    assert_eq!(canonical (&bytes), r#"{"a":{"$symbol":"b"},"u":{"$undefined":true},"c":{"$code":"x","$scope":{}}}"#);
+    let cases = [
+        (r#"{"a": {"$symbol": "b"}}"#, "$symbol"),
+        (r#"{"a": {"$undefined": true}}"#, "$undefined"),
+        (r#"{"a": {"$code": "x", "$scope": {}}}"#, "$code with $scope"),
+        (r#"{"a": {"$dbPointer": {"$ref": "db.c", "$id": {"$oid": "56e1fc72e0c917e9c4714161"}}}}"#, "$dbPointer"),
+    ];
+    for (json, key) in cases {
+        assert_eq!(from_ejson (json), Err(EjsonError { offset: 6, kind: EjsonErrorKind::Unsupported (key) }), "{}", json);
+        assert_eq!(from_ejson_strict (json), Err(EjsonError { offset: 6, kind: EjsonErrorKind::Unsupported (key) }), "{}", json);
+    }
+}
+
+#[test]
+fn test_parse_errors () {
+    let syntax = |offset, msg| Err(EjsonError { offset, kind: EjsonErrorKind::Syntax (msg) });
+    assert_eq!(from_ejson (""), syntax (0, "unexpected end of text"));
+    assert_eq!(from_ejson ("{"), syntax (1, "expected a key"));
+    assert_eq!(from_ejson (r#"{"a" 1}"#), syntax (5, "expected ':'"));
+    assert_eq!(from_ejson (r#"{"a": 1,}"#), syntax (8, "expected a key"));
+    assert_eq!(from_ejson (r#"{"a": [1 2]}"#), syntax (9, "expected ',' or ']'"));
+    assert_eq!(from_ejson (r#"{"a": 01}"#), syntax (6, "leading zero in number"));
+    assert_eq!(from_ejson (r#"{"a": 1.}"#), syntax (6, "invalid number"));
+    assert_eq!(from_ejson (r#"{"a": tru}"#), syntax (6, "expected a value"));
+    assert_eq!(from_ejson (r#"{"a": "\x"}"#), syntax (8, "invalid escape"));
+    assert_eq!(from_ejson (r#"{"a": "\ud800"}"#), syntax (7, "unpaired surrogate"));
+    assert_eq!(from_ejson ("{\"a\": \"\n\"}"), syntax (7, "control character in string"));
+    assert_eq!(from_ejson (r#"{"a": 1e400}"#), syntax (6, "number out of range"));
+    assert_eq!(from_ejson (r#"{} {}"#), syntax (3, "unexpected data after the document"));
+    assert_eq!(from_ejson (&"[".repeat(2000)), syntax (MAX_JSON_DEPTH, "nested too deeply"));
+    assert_eq!(from_ejson ("[]"), Err(EjsonError { offset: 0, kind: EjsonErrorKind::NotADocument }));
+    assert_eq!(from_ejson (r#"{"$numberInt": "1"}"#), Err(EjsonError { offset: 0, kind: EjsonErrorKind::NotADocument }));
+    assert_eq!(from_ejson (r#"{"a": {"$ref": 1, "$id": 2}}"#), Err(EjsonError { offset: 6, kind: EjsonErrorKind::InvalidDbRef }));
+    assert_eq!(from_ejson (r#"{"a\u0000": 1}"#), Err(EjsonError { offset: 12, kind: EjsonErrorKind::Build (BuildError::ContainsNul ("a\0".to_string())) }));
+    assert_eq!(from_ejson (r#"{"a": {"b\u0000": 1}}"#), Err(EjsonError { offset: 18, kind: EjsonErrorKind::Build (BuildError::ContainsNul ("b\0".to_string())) }));
+    assert_eq!(
+        from_ejson (r#"{"a": {"$regularExpression": {"pattern": "a\u0000", "options": ""}}}"#),
+        Err(EjsonError { offset: 6, kind: EjsonErrorKind::Build (BuildError::ContainsNul ("a\0".to_string())) }));
+    assert_eq!(
+        from_ejson (r#"{"a": 1}"#).unwrap(),
+        from_ejson (" \n{ \"a\" :\t1 } ").unwrap());
+}
+
+#[test]
+fn test_write_max_depth () {
+    use crate::reader::nested_document;
+    let data = nested_document (MAX_NESTING_DEPTH);
+    let json = to_ejson (BsonRef::new (&data).unwrap(), EjsonMode::Canonical).unwrap();
+    assert_eq!(json, "{\"a\":".repeat(MAX_NESTING_DEPTH) + "{}" + &"}".repeat(MAX_NESTING_DEPTH));
+    for depth in [MAX_NESTING_DEPTH + 1, 100_000] {
+        let data = nested_document (depth);
+        let err = to_ejson (BsonRef::new (&data).unwrap(), EjsonMode::Relaxed).unwrap_err();
+        // Each level adds 7 bytes before the document inside it.
+        assert_eq!(err, ReadError { offset: 7 * (MAX_NESTING_DEPTH + 1), kind: ReadErrorKind::MaxDepthExceeded (MAX_NESTING_DEPTH) });
+    }
+}
+
+#[test]
+fn test_strings () {
+    let mut bb = BSONBuilder::new();
+    bb.append_string ("s", "\u{0}\u{8}\u{c}/é😀").unwrap();
+    let bytes = bb.build().unwrap();
+    assert_eq!(canonical (&bytes), "{\"s\":\"\\u0000\\b\\f/é😀\"}");
+    assert_eq!(from_ejson (r#"{"s": "\u0000\b\f\/é😀"}"#), Ok(bytes));
+}
+
+#[test]
+fn test_base64 () {
+    let cases : [(&[u8], &str); 5] = [(b"", ""), (b"f", "Zg=="), (b"fo", "Zm8="), (b"foo", "Zm9v"), (b"\xff\xfe\xfd\xfc", "//79/A==")];
+    for (data, s) in cases {
+        assert_eq!(base64_encode (data), s);
+        assert_eq!(base64_decode (s), Some(data.to_vec()));
+    }
+    for s in ["Zg", "Zg=", "Z===", "Zg==Zg==", "Zm9!"] {
+        assert_eq!(base64_decode (s), None, "{:?}", s);
+    }
+}
+
+#[cfg(test)]
+mod property_tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        // Every double, except NaN payloads, reads back from both modes.
+        #[test]
+        fn doubles_round_trip (bits in any::<u64>()) {
+            let v = f64::from_bits(bits);
+            prop_assume!(!v.is_nan());
+            let mut bb = BSONBuilder::new();
+            bb.append_double ("d", v).unwrap();
+            let bytes = bb.build().unwrap();
+            prop_assert_eq!(from_ejson (&canonical (&bytes)), Ok(bytes.clone()));
+            prop_assert_eq!(from_ejson (&relaxed (&bytes)), Ok(bytes));
+        }
+
+        #[test]
+        fn dates_round_trip (ms in any::<i64>()) {
+            let mut bb = BSONBuilder::new();
+            bb.append_datetime ("d", ms).unwrap();
+            let bytes = bb.build().unwrap();
+            prop_assert_eq!(from_ejson (&canonical (&bytes)), Ok(bytes.clone()));
+            prop_assert_eq!(from_ejson (&relaxed (&bytes)), Ok(bytes));
+        }
+
+        #[test]
+        fn strings_round_trip (s in "\\PC*") {
+            let mut bb = BSONBuilder::new();
+            bb.append_string ("s", &s).unwrap();
+            let bytes = bb.build().unwrap();
+            prop_assert_eq!(from_ejson (&canonical (&bytes)), Ok(bytes));
+        }
+    }
+}
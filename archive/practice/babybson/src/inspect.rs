@@ -0,0 +1,358 @@
+// inspect annotates the bytes of BSON for debugging: each length, type byte, key, value and terminator is described
+// with its offset. Unlike `BsonRef`, it reads as far as it can, and the region where reading failed is annotated
+// with the reason. The data may hold several concatenated documents.
+use crate::builder::MAX_NESTING_DEPTH;
+use crate::decimal128::format_decimal128;
+use crate::reader::ReadErrorKind;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    pub offset : usize,
+    pub len : usize,
+    // depth is the nesting depth of the document the bytes belong to. The outermost document is 0.
+    pub depth : usize,
+    // note describes the bytes, e.g. "key \"foo\"" or "int32 1".
+    pub note : String,
+    // error is set on the last annotation if reading failed. The annotation covers the rest of the data.
+    pub error : Option<ReadErrorKind>,
+}
+
+// inspect annotates `data` from the start until the end or the first error.
+pub fn inspect (data : &[u8]) -> Vec<Annotation> {
+    let mut inspector = Inspector { data, annotations: Vec::new() };
+    let mut pos = 0;
+    while pos < data.len() {
+        match inspector.document (pos, data.len(), 0, "document") {
+            Ok(end) => pos = end,
+            Err(Failure { offset, depth, kind }) => {
+                inspector.annotations.push(Annotation {
+                    offset,
+                    len: data.len() - offset,
+                    depth,
+                    note: kind.to_string(),
+                    error: Some(kind),
+                });
+                break;
+            }
+        }
+    }
+    inspector.annotations
+}
+
+struct Failure {
+    offset : usize,
+    depth : usize,
+    kind : ReadErrorKind,
+}
+
+struct Inspector<'a> {
+    data : &'a [u8],
+    annotations : Vec<Annotation>,
+}
+
+// type_name returns the name of an element type, or None if it is unknown.
+fn type_name (element_type : u8) -> Option<&'static str> {
+    Some(match element_type {
+        0x01 => "double",
+        0x02 => "string",
+        0x03 => "document",
+        0x04 => "array",
+        0x05 => "binary",
+        0x06 => "undefined",
+        0x07 => "ObjectId",
+        0x08 => "boolean",
+        0x09 => "datetime",
+        0x0A => "null",
+        0x0B => "regex",
+        0x0C => "DBPointer",
+        0x0D => "JavaScript code",
+        0x0E => "symbol",
+        0x0F => "JavaScript code with scope",
+        0x10 => "int32",
+        0x11 => "timestamp",
+        0x12 => "int64",
+        0x13 => "decimal128",
+        0x7F => "MaxKey",
+        0xFF => "MinKey",
+        _ => return None,
+    })
+}
+
+impl<'a> Inspector<'a> {
+    fn note (&mut self, offset : usize, len : usize, depth : usize, note : String) {
+        self.annotations.push(Annotation { offset, len, depth, note, error: None });
+    }
+
+    // fixed returns the `n` bytes at `pos`, which must end by `limit`.
+    fn fixed<const N : usize> (&self, pos : usize, limit : usize, depth : usize) -> Result<[u8; N], Failure> {
+        if pos + N > limit {
+            return Err(Failure { offset: pos, depth, kind: ReadErrorKind::Truncated });
+        }
+        Ok(self.data[pos..pos + N].try_into().unwrap())
+    }
+
+    // document annotates the document at `start`, which must end by `limit`, and returns its end. Like
+    // `BsonRef::validate`, it stops at documents nested more than MAX_NESTING_DEPTH deep.
+    fn document (&mut self, start : usize, limit : usize, depth : usize, what : &str) -> Result<usize, Failure> {
+        let fail = |offset, kind| Failure { offset, depth, kind };
+        if depth > MAX_NESTING_DEPTH {
+            return Err(fail (start, ReadErrorKind::MaxDepthExceeded (MAX_NESTING_DEPTH)));
+        }
+        let len = i32::from_le_bytes(self.fixed (start, limit, depth)?);
+        if len < 5 {
+            return Err(fail (start, ReadErrorKind::InvalidLength (len)));
+        }
+        let end = start + len as usize;
+        if end > limit {
+            // Like `BsonRef`, an outermost document is cut short, and a nested document is too long.
+            let kind = if depth == 0 { ReadErrorKind::Truncated } else { ReadErrorKind::InvalidLength (len) };
+            return Err(fail (start, kind));
+        }
+        self.note (start, 4, depth, format!("{} length {}", what, len));
+        let mut pos = start + 4;
+        while pos < end - 1 {
+            pos = self.element (pos, end - 1, depth)?;
+        }
+        if self.data[end - 1] != 0 {
+            return Err(fail (end - 1, ReadErrorKind::MissingTerminator));
+        }
+        self.note (end - 1, 1, depth, format!("end of {}", what));
+        Ok(end)
+    }
+
+    // element annotates the element at `pos`, which must end by `limit`, and returns its end.
+    fn element (&mut self, pos : usize, limit : usize, depth : usize) -> Result<usize, Failure> {
+        let element_type = self.data[pos];
+        if element_type == 0 {
+            return Err(Failure { offset: pos, depth, kind: ReadErrorKind::UnexpectedTerminator });
+        }
+        let name = type_name (element_type)
+            .ok_or(Failure { offset: pos, depth, kind: ReadErrorKind::UnknownType (element_type) })?;
+        self.note (pos, 1, depth, format!("type 0x{:02x} {}", element_type, name));
+        let key_end = self.cstring (pos + 1, limit, depth, "key")?;
+        self.value (element_type, key_end, limit, depth)
+    }
+
+    fn value (&mut self, element_type : u8, pos : usize, limit : usize, depth : usize) -> Result<usize, Failure> {
+        let fail = |offset, kind| Failure { offset, depth, kind };
+        let end = match element_type {
+            0x01 => {
+                let value = f64::from_le_bytes(self.fixed (pos, limit, depth)?);
+                self.note (pos, 8, depth, format!("double {:?}", value));
+                pos + 8
+            }
+            0x02 => self.string (pos, limit, depth, "string")?,
+            0x03 => self.document (pos, limit, depth + 1, "document")?,
+            0x04 => self.document (pos, limit, depth + 1, "array")?,
+            0x05 => {
+                let len = i32::from_le_bytes(self.fixed (pos, limit, depth)?);
+                if len < 0 {
+                    return Err(fail (pos, ReadErrorKind::InvalidBinaryLength));
+                }
+                let [subtype] = self.fixed (pos + 4, limit, depth)?;
+                let mut data = pos + 5;
+                let end = data + len as usize;
+                if end > limit {
+                    return Err(fail (pos, ReadErrorKind::Truncated));
+                }
+                self.note (pos, 4, depth, format!("binary length {}", len));
+                self.note (pos + 4, 1, depth, format!("subtype 0x{:02x}", subtype));
+                if subtype == 0x02 {
+                    // The old binary subtype repeats the length, less its own 4 bytes.
+                    let inner = self.fixed (data, end, depth).map(i32::from_le_bytes);
+                    if inner.as_ref().ok() != Some(&(len - 4)) {
+                        return Err(fail (pos, ReadErrorKind::InvalidBinaryLength));
+                    }
+                    self.note (data, 4, depth, format!("old binary length {}", len - 4));
+                    data += 4;
+                }
+                if end > data {
+                    self.note (data, end - data, depth, format!("{} bytes of binary data", end - data));
+                }
+                end
+            }
+            0x06 | 0x0A | 0x7F | 0xFF => pos,
+            0x07 => self.objectid (pos, limit, depth)?,
+            0x08 => {
+                let value = match self.fixed (pos, limit, depth)? {
+                    [0] => false,
+                    [1] => true,
+                    [b] => return Err(fail (pos, ReadErrorKind::InvalidBool (b))),
+                };
+                self.note (pos, 1, depth, format!("{}", value));
+                pos + 1
+            }
+            0x09 => {
+                let value = i64::from_le_bytes(self.fixed (pos, limit, depth)?);
+                self.note (pos, 8, depth, format!("{} ms since the epoch", value));
+                pos + 8
+            }
+            0x0B => {
+                let options = self.cstring (pos, limit, depth, "pattern")?;
+                self.cstring (options, limit, depth, "options")?
+            }
+            0x0C => {
+                let id = self.string (pos, limit, depth, "namespace")?;
+                self.objectid (id, limit, depth)?
+            }
+            0x0D => self.string (pos, limit, depth, "code")?,
+            0x0E => self.string (pos, limit, depth, "symbol")?,
+            0x0F => {
+                let len = i32::from_le_bytes(self.fixed (pos, limit, depth)?);
+                self.note (pos, 4, depth, format!("code with scope length {}", len));
+                let scope = self.string (pos + 4, limit, depth, "code")?;
+                let end = self.document (scope, limit, depth + 1, "scope")?;
+                if len < 0 || len as usize != end - pos {
+                    return Err(fail (pos, ReadErrorKind::InvalidCodeWithScopeLength));
+                }
+                end
+            }
+            0x10 => {
+                let value = i32::from_le_bytes(self.fixed (pos, limit, depth)?);
+                self.note (pos, 4, depth, format!("int32 {}", value));
+                pos + 4
+            }
+            0x11 => {
+                let increment = u32::from_le_bytes(self.fixed (pos, limit, depth)?);
+                let timestamp = u32::from_le_bytes(self.fixed (pos + 4, limit, depth)?);
+                self.note (pos, 4, depth, format!("increment {}", increment));
+                self.note (pos + 4, 4, depth, format!("timestamp {}", timestamp));
+                pos + 8
+            }
+            0x12 => {
+                let value = i64::from_le_bytes(self.fixed (pos, limit, depth)?);
+                self.note (pos, 8, depth, format!("int64 {}", value));
+                pos + 8
+            }
+            0x13 => {
+                let value = self.fixed (pos, limit, depth)?;
+                self.note (pos, 16, depth, format!("decimal128 {}", format_decimal128 (&value)));
+                pos + 16
+            }
+            _ => unreachable!("element types are checked by type_name"),
+        };
+        Ok(end)
+    }
+
+    // cstring annotates the NULL terminated string at `pos`, and returns its end.
+    fn cstring (&mut self, pos : usize, limit : usize, depth : usize, what : &str) -> Result<usize, Failure> {
+        let fail = |offset, kind| Failure { offset, depth, kind };
+        let len = self.data[pos..limit].iter().position(|&b| b == 0)
+            .ok_or(fail (pos, ReadErrorKind::UnterminatedCString))?;
+        let s = std::str::from_utf8(&self.data[pos..pos + len]).map_err(|_| fail (pos, ReadErrorKind::InvalidUtf8))?;
+        self.note (pos, len + 1, depth, format!("{} {:?}", what, s));
+        Ok(pos + len + 1)
+    }
+
+    // string annotates the length and bytes of the string at `pos`, and returns its end.
+    fn string (&mut self, pos : usize, limit : usize, depth : usize, what : &str) -> Result<usize, Failure> {
+        let fail = |offset, kind| Failure { offset, depth, kind };
+        let len = i32::from_le_bytes(self.fixed (pos, limit, depth)?);
+        if len < 1 {
+            return Err(fail (pos, ReadErrorKind::InvalidString));
+        }
+        let start = pos + 4;
+        let end = start + len as usize;
+        if end > limit {
+            return Err(fail (pos, ReadErrorKind::Truncated));
+        }
+        if self.data[end - 1] != 0 {
+            return Err(fail (pos, ReadErrorKind::InvalidString));
+        }
+        let s = std::str::from_utf8(&self.data[start..end - 1]).map_err(|_| fail (start, ReadErrorKind::InvalidUtf8))?;
+        self.note (pos, 4, depth, format!("{} length {}", what, len));
+        self.note (start, len as usize, depth, format!("{:?}", s));
+        Ok(end)
+    }
+
+    fn objectid (&mut self, pos : usize, limit : usize, depth : usize) -> Result<usize, Failure> {
+        let id : [u8; 12] = self.fixed (pos, limit, depth)?;
+        let hex : String = id.iter().map(|b| format!("{:02x}", b)).collect();
+        self.note (pos, 12, depth, format!("ObjectId {}", hex));
+        Ok(pos + 12)
+    }
+}
+
+#[cfg(test)]
+fn notes (data : &[u8]) -> Vec<(usize, usize, usize, String)> {
+    inspect (data).into_iter().map(|a| (a.offset, a.len, a.depth, a.note)).collect()
+}
+
+#[test]
+fn test_inspect () {
+    // From the decode-bson-with-dollars investigation: {"foo": {"$numberInt": "123"}}.
+    let bytes = [
+        0x23, 0x00, 0x00, 0x00, 0x03, 0x66, 0x6f, 0x6f, 0x00, 0x19, 0x00, 0x00, 0x00, 0x02, 0x24, 0x6e, 0x75, 0x6d,
+        0x62, 0x65, 0x72, 0x49, 0x6e, 0x74, 0x00, 0x04, 0x00, 0x00, 0x00, 0x31, 0x32, 0x33, 0x00, 0x00, 0x00];
+    let want = [
+        (0, 4, 0, "document length 35"),
+        (4, 1, 0, "type 0x03 document"),
+        (5, 4, 0, "key \"foo\""),
+        (9, 4, 1, "document length 25"),
+        (13, 1, 1, "type 0x02 string"),
+        (14, 11, 1, "key \"$numberInt\""),
+        (25, 4, 1, "string length 4"),
+        (29, 4, 1, "\"123\""),
+        (33, 1, 1, "end of document"),
+        (34, 1, 0, "end of document"),
+    ];
+    let want : Vec<_> = want.iter().map(|&(o, l, d, n)| (o, l, d, n.to_string())).collect();
+    assert_eq!(notes (&bytes), want);
+    assert!(inspect (&bytes).iter().all(|a| a.error.is_none()));
+}
+
+#[test]
+fn test_inspect_types () {
+    let mut bb = crate::builder::BSONBuilder::new();
+    bb.append_binary ("b", 0x02, &[7]).unwrap();
+    bb.append_timestamp ("t", 2, 1).unwrap();
+    bb.append_regex ("r", "^a", "i").unwrap();
+    bb.start_array ("a").unwrap();
+    bb.append_minkey ("").unwrap();
+    bb.end_array().unwrap();
+    let doc = bb.build().unwrap();
+    let notes : Vec<String> = inspect (&doc).into_iter().map(|a| a.note).collect();
+    assert_eq!(notes, [
+        "document length 48", "type 0x05 binary", "key \"b\"", "binary length 5", "subtype 0x02", "old binary length 1",
+        "1 bytes of binary data", "type 0x11 timestamp", "key \"t\"", "increment 1", "timestamp 2", "type 0x0b regex",
+        "key \"r\"", "pattern \"^a\"", "options \"i\"", "type 0x04 array", "key \"a\"", "array length 8",
+        "type 0xff MinKey", "key \"0\"", "end of array", "end of document",
+    ]);
+}
+
+#[test]
+fn test_inspect_errors () {
+    let cases : Vec<(Vec<u8>, usize, usize, ReadErrorKind)> = vec![
+        (vec![5, 0, 0], 0, 0, ReadErrorKind::Truncated),
+        (vec![4, 0, 0, 0, 0], 0, 0, ReadErrorKind::InvalidLength (4)),
+        (vec![5, 0, 0, 0, 1], 4, 0, ReadErrorKind::MissingTerminator),
+        (vec![9, 0, 0, 0, 0x08, 0x61, 0, 2, 0], 7, 0, ReadErrorKind::InvalidBool (2)),
+        (vec![8, 0, 0, 0, 0x20, 0x61, 0, 0], 4, 0, ReadErrorKind::UnknownType (0x20)),
+        // The nested document eats the terminator of the outer document.
+        (vec![0x0d, 0, 0, 0, 0x03, 0x61, 0, 6, 0, 0, 0, 0, 0], 7, 1, ReadErrorKind::InvalidLength (6)),
+        (vec![0x0e, 0, 0, 0, 0x02, 0x61, 0, 2, 0, 0, 0, 0xff, 0, 0], 11, 0, ReadErrorKind::InvalidUtf8),
+        // A second document is cut short.
+        (vec![5, 0, 0, 0, 0, 6, 0, 0, 0, 0], 5, 0, ReadErrorKind::Truncated),
+    ];
+    for (data, offset, depth, kind) in cases {
+        let annotations = inspect (&data);
+        let last = annotations.last().unwrap();
+        assert_eq!((last.offset, last.len, last.depth, last.error), (offset, data.len() - offset, depth, Some(kind)), "{:?}", data);
+        assert_eq!(last.note, kind.to_string());
+    }
+    assert!(inspect (&[]).is_empty());
+}
+
+#[test]
+fn test_inspect_max_depth () {
+    use crate::reader::nested_document;
+    let annotations = inspect (&nested_document (MAX_NESTING_DEPTH));
+    assert!(annotations.iter().all(|a| a.error.is_none()));
+    // Deep documents are an error rather than a stack overflow.
+    let data = nested_document (100_000);
+    let last = inspect (&data).pop().unwrap();
+    // Each level adds 7 bytes before the document inside it.
+    let offset = 7 * (MAX_NESTING_DEPTH + 1);
+    let want = (offset, data.len() - offset, MAX_NESTING_DEPTH + 1, Some(ReadErrorKind::MaxDepthExceeded (MAX_NESTING_DEPTH)));
+    assert_eq!((last.offset, last.len, last.depth, last.error), want);
+}
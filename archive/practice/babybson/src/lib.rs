@@ -0,0 +1,26 @@
+// babybson is a small BSON library. `BSONBuilder` writes documents and `BsonRef` reads them without copying.
+// `to_ejson` and `from_ejson` convert documents to and from Extended JSON, and `to_bson` and `from_bson` convert
+// them to and from Rust values with serde. `BsonStreamReader` and `BsonStreamWriter` read and write files of
+// concatenated documents, and `RawDocumentMut` changes the bytes of a document in place. `babydoc!` builds a document
+// from JSON-like syntax, and `inspect` annotates the bytes of a document for debugging.
+mod builder;
+mod de;
+mod decimal128;
+mod edit;
+mod inspect;
+mod macros;
+mod ejson;
+mod reader;
+mod ser;
+mod stream;
+
+pub use builder::*;
+pub use de::*;
+pub use decimal128::*;
+pub use edit::*;
+pub use inspect::*;
+pub use macros::*;
+pub use ejson::*;
+pub use reader::*;
+pub use ser::*;
+pub use stream::*;
@@ -0,0 +1,207 @@
+// macros provides `babydoc!`, which builds a document from JSON-like syntax, and the `ToBson` trait it uses to pick
+// the append method for each value at compile time.
+use crate::builder::{BSONBuilder, BuildError};
+
+// ToBson appends a value to a BSONBuilder. Integers map like `to_bson`: i8, i16, i32, u8 and u16 become int32, and
+// i64 and u32 become int64. u64 and usize are not implemented, since they may not fit in an int64. None becomes
+// null, and slices, arrays and vectors become arrays.
+pub trait ToBson {
+    fn append_to (&self, bb : &mut BSONBuilder, key : &str) -> Result<(), BuildError>;
+}
+
+macro_rules! to_bson_as {
+    ($method:ident, $as:ty, $($t:ty),*) => {
+        $(impl ToBson for $t {
+            fn append_to (&self, bb : &mut BSONBuilder, key : &str) -> Result<(), BuildError> {
+                bb.$method (key, *self as $as)
+            }
+        })*
+    };
+}
+
+to_bson_as!(append_int32, i32, i8, i16, i32, u8, u16);
+to_bson_as!(append_int64, i64, i64, u32);
+to_bson_as!(append_double, f64, f32, f64);
+to_bson_as!(append_bool, bool, bool);
+
+impl ToBson for str {
+    fn append_to (&self, bb : &mut BSONBuilder, key : &str) -> Result<(), BuildError> {
+        bb.append_string (key, self)
+    }
+}
+
+impl ToBson for String {
+    fn append_to (&self, bb : &mut BSONBuilder, key : &str) -> Result<(), BuildError> {
+        bb.append_string (key, self)
+    }
+}
+
+impl<T : ToBson> ToBson for Option<T> {
+    fn append_to (&self, bb : &mut BSONBuilder, key : &str) -> Result<(), BuildError> {
+        match self {
+            Some(value) => value.append_to (bb, key),
+            None => bb.append_null (key),
+        }
+    }
+}
+
+impl<T : ToBson> ToBson for [T] {
+    fn append_to (&self, bb : &mut BSONBuilder, key : &str) -> Result<(), BuildError> {
+        bb.start_array (key)?;
+        for value in self {
+            // The builder numbers array elements, so the key is ignored.
+            value.append_to (bb, "")?;
+        }
+        bb.end_array()
+    }
+}
+
+impl<T : ToBson, const N : usize> ToBson for [T; N] {
+    fn append_to (&self, bb : &mut BSONBuilder, key : &str) -> Result<(), BuildError> {
+        self[..].append_to (bb, key)
+    }
+}
+
+impl<T : ToBson> ToBson for Vec<T> {
+    fn append_to (&self, bb : &mut BSONBuilder, key : &str) -> Result<(), BuildError> {
+        self[..].append_to (bb, key)
+    }
+}
+
+impl<T : ToBson + ?Sized> ToBson for &T {
+    fn append_to (&self, bb : &mut BSONBuilder, key : &str) -> Result<(), BuildError> {
+        (**self).append_to (bb, key)
+    }
+}
+
+// babydoc builds a document and returns `Result<Vec<u8>, BuildError>`:
+//
+//     let name = "babybson";
+//     let doc = babydoc!{ "name": name, "version": 1, "tags": ["bson", 2.5, null], "nested": { "ok": true } }?;
+//
+// Keys are string literals, or any single token that is a `&str` or `String`, e.g. a variable or a parenthesized
+// expression. Values are `{...}` documents, `[...]` arrays, `null`, or any expression whose type implements `ToBson`.
+#[macro_export]
+macro_rules! babydoc {
+    // Members of a document.
+    (@members $bb:ident;) => {};
+    (@members $bb:ident; $key:tt : null $(, $($rest:tt)*)?) => {
+        $bb.append_null (::std::convert::AsRef::<str>::as_ref (&$key))?;
+        $crate::babydoc!(@members $bb; $($($rest)*)?);
+    };
+    (@members $bb:ident; $key:tt : { $($doc:tt)* } $(, $($rest:tt)*)?) => {
+        $bb.start_document (::std::convert::AsRef::<str>::as_ref (&$key))?;
+        $crate::babydoc!(@members $bb; $($doc)*);
+        $bb.end_document()?;
+        $crate::babydoc!(@members $bb; $($($rest)*)?);
+    };
+    (@members $bb:ident; $key:tt : [ $($array:tt)* ] $(, $($rest:tt)*)?) => {
+        $bb.start_array (::std::convert::AsRef::<str>::as_ref (&$key))?;
+        $crate::babydoc!(@elements $bb; $($array)*);
+        $bb.end_array()?;
+        $crate::babydoc!(@members $bb; $($($rest)*)?);
+    };
+    (@members $bb:ident; $key:tt : $value:expr $(, $($rest:tt)*)?) => {
+        $crate::ToBson::append_to (&$value, &mut $bb, ::std::convert::AsRef::<str>::as_ref (&$key))?;
+        $crate::babydoc!(@members $bb; $($($rest)*)?);
+    };
+
+    // Elements of an array. The builder numbers them, so the keys are empty.
+    (@elements $bb:ident;) => {};
+    (@elements $bb:ident; null $(, $($rest:tt)*)?) => {
+        $bb.append_null ("")?;
+        $crate::babydoc!(@elements $bb; $($($rest)*)?);
+    };
+    (@elements $bb:ident; { $($doc:tt)* } $(, $($rest:tt)*)?) => {
+        $bb.start_document ("")?;
+        $crate::babydoc!(@members $bb; $($doc)*);
+        $bb.end_document()?;
+        $crate::babydoc!(@elements $bb; $($($rest)*)?);
+    };
+    (@elements $bb:ident; [ $($array:tt)* ] $(, $($rest:tt)*)?) => {
+        $bb.start_array ("")?;
+        $crate::babydoc!(@elements $bb; $($array)*);
+        $bb.end_array()?;
+        $crate::babydoc!(@elements $bb; $($($rest)*)?);
+    };
+    (@elements $bb:ident; $value:expr $(, $($rest:tt)*)?) => {
+        $crate::ToBson::append_to (&$value, &mut $bb, "")?;
+        $crate::babydoc!(@elements $bb; $($($rest)*)?);
+    };
+
+    // The closure returns the first error with `?`.
+    ($($members:tt)*) => {{
+        #[allow(clippy::redundant_closure_call)]
+        let doc = (|| -> ::std::result::Result<::std::vec::Vec<u8>, $crate::BuildError> {
+            let mut bb = $crate::BSONBuilder::new();
+            $crate::babydoc!(@members bb; $($members)*);
+            bb.build()
+        })();
+        doc
+    }};
+}
+
+#[test]
+fn test_babydoc () {
+    let name = String::from("babybson");
+    let key = "dynamic";
+    let count : u8 = 3;
+    let tags = vec!["a", "b"];
+    let doc = babydoc!{
+        "x": 1,
+        "y": { "z": [1, 2, "s"] },
+        "name": name,
+        key: -(count as i32) * 2,
+        ("com".to_string() + "puted"): 1.5,
+        "tags": tags,
+        "nested": [[], {}, [true, null], { "a": None::<i32>, "b": Some(7i64) }],
+        "empty": {},
+        "null": null,
+        "big": 1u32 << 31,
+    }.unwrap();
+
+    let mut bb = BSONBuilder::new();
+    bb.append_int32 ("x", 1).unwrap();
+    bb.start_document ("y").unwrap();
+    bb.start_array ("z").unwrap();
+    bb.append_int32 ("", 1).unwrap();
+    bb.append_int32 ("", 2).unwrap();
+    bb.append_string ("", "s").unwrap();
+    bb.end_array().unwrap();
+    bb.end_document().unwrap();
+    bb.append_string ("name", "babybson").unwrap();
+    bb.append_int32 ("dynamic", -6).unwrap();
+    bb.append_double ("computed", 1.5).unwrap();
+    bb.start_array ("tags").unwrap();
+    bb.append_string ("", "a").unwrap();
+    bb.append_string ("", "b").unwrap();
+    bb.end_array().unwrap();
+    bb.start_array ("nested").unwrap();
+    bb.start_array ("").unwrap();
+    bb.end_array().unwrap();
+    bb.start_document ("").unwrap();
+    bb.end_document().unwrap();
+    bb.start_array ("").unwrap();
+    bb.append_bool ("", true).unwrap();
+    bb.append_null ("").unwrap();
+    bb.end_array().unwrap();
+    bb.start_document ("").unwrap();
+    bb.append_null ("a").unwrap();
+    bb.append_int64 ("b", 7).unwrap();
+    bb.end_document().unwrap();
+    bb.end_array().unwrap();
+    bb.start_document ("empty").unwrap();
+    bb.end_document().unwrap();
+    bb.append_null ("null").unwrap();
+    bb.append_int64 ("big", 1 << 31).unwrap();
+    assert_eq!(doc, bb.build().unwrap());
+    // The variables were borrowed, not moved.
+    assert_eq!((name.len(), tags.len()), (8, 2));
+}
+
+#[test]
+fn test_babydoc_empty_and_errors () {
+    assert_eq!(babydoc!{}, BSONBuilder::new().build());
+    assert_eq!(babydoc!{ "a\0": 1 }, Err(BuildError::ContainsNul ("a\0".to_string())));
+    assert_eq!(babydoc!{ "a": { "b": [{ "c\0": true }] } }, Err(BuildError::ContainsNul ("c\0".to_string())));
+}
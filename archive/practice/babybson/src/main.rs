@@ -1,123 +1,239 @@
-struct BSONBuilder {
-    data : Vec<u8>,
-    // index_stack is a stack of indexes pointing to the first byte of a length in a nested document.
-    index_stack : Vec<usize>,
-}
+// babybson is a command line tool for BSON files.
+//
+//     babybson dump [--canonical] [--max-size BYTES] FILE
+//
+// dump prints each document of a file of concatenated documents, like a mongodump .bson file, as indented relaxed
+// Extended JSON, or canonical with --canonical. FILE is - for standard input. Documents are read one at a time, and
+// documents longer than --max-size are rejected.
+//
+//     babybson inspect HEX|FILE
+//
+// inspect prints an annotated dump of the BSON in a file, or of a hex string if there is no such file. Each line
+// has the offset, the raw bytes and their meaning. Reading stops at the first malformed region, which is shown with
+// the reason.
+use babybson::*;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, IsTerminal, Read, Write};
+use std::process::ExitCode;
 
-impl BSONBuilder {
-    fn new () -> BSONBuilder {
-        return BSONBuilder{
-            // Add space for the length.
-            data: vec![0, 0, 0, 0],
-            index_stack: Vec::new(),
-        };
-    }
+const USAGE : &str = "usage: babybson dump [--canonical] [--max-size BYTES] FILE\n       babybson inspect HEX|FILE";
 
-    fn usize_to_i32 (x : usize) -> i32 {
-        if x > i32::MAX.try_into().unwrap() {
-            panic!("value {} is too large. Cannot fit in i32", x);
+// INSPECT_BYTES_PER_LINE and INSPECT_MAX_LINES limit how many raw bytes inspect prints for one annotation.
+const INSPECT_BYTES_PER_LINE : usize = 8;
+const INSPECT_MAX_LINES : usize = 4;
+
+fn main () -> ExitCode {
+    let args : Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("dump") => dump (&args[1..]),
+        Some("inspect") => inspect_command (&args[1..]),
+        _ => Err(USAGE.to_string()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("babybson: {}", e);
+            ExitCode::FAILURE
         }
-        return x.try_into().unwrap();
     }
+}
 
-    fn _append_key (&mut self, key : &str) {
-        for b in key.bytes() {
-            self.data.push(b);
+fn dump (args : &[String]) -> Result<(), String> {
+    let mut mode = EjsonMode::Relaxed;
+    let mut max_size = DEFAULT_MAX_STREAM_DOCUMENT_SIZE;
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--canonical" => mode = EjsonMode::Canonical,
+            "--max-size" => {
+                max_size = args.next().and_then(|s| s.parse().ok()).ok_or("--max-size needs a number of bytes")?;
+            }
+            _ if path.is_none() => path = Some(arg.as_str()),
+            _ => return Err(USAGE.to_string()),
         }
-        // Push NULL byte.
-        self.data.push(0);
     }
-
-    fn _append_bytes (&mut self, bytes: &[u8]) {
-        self.data.extend_from_slice(bytes);
+    let path = path.ok_or(USAGE)?;
+    let input : Box<dyn Read> = if path == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(File::open(path).map_err(|e| format!("{}: {}", path, e))?)
+    };
+    let mut reader = BsonStreamReader::with_max_size (BufReader::new(input), max_size);
+    let mut out = BufWriter::new(io::stdout().lock());
+    loop {
+        let offset = reader.offset();
+        let doc = match reader.next_document().map_err(|e| format!("{}: {}", path, e))? {
+            Some(doc) => doc,
+            None => break,
+        };
+        let json = to_ejson (doc, mode).map_err(|e| format!("{}: document at offset {}: {}", path, offset, e))?;
+        writeln!(out, "{}", pretty (&json)).map_err(|e| e.to_string())?;
     }
+    out.flush().map_err(|e| e.to_string())
+}
 
-    fn append_int32 (&mut self, key : &str, value : i32) {
-        // Append type.
-        self.data.push(0x10);
-        // Append key.
-        self._append_key (&key);
-        // Convert value to Little Endian and append.
-        let bytes = value.to_le_bytes();
-        self._append_bytes (&bytes);
+fn inspect_command (args : &[String]) -> Result<(), String> {
+    let [arg] = args else {
+        return Err(USAGE.to_string());
+    };
+    let data = match std::fs::read(arg) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            hex_decode (arg).ok_or_else(|| format!("{} is not a file or a hex string", arg))?
+        }
+        Err(e) => return Err(format!("{}: {}", arg, e)),
+    };
+    let annotations = inspect (&data);
+    let mut out = BufWriter::new(io::stdout().lock());
+    let color = io::stdout().is_terminal();
+    for line in annotate (&data, &annotations) {
+        let line = match &line {
+            // Highlight the malformed region in red on a terminal.
+            (true, line) if color => format!("\x1b[31m{}\x1b[0m", line),
+            (_, line) => line.to_string(),
+        };
+        writeln!(out, "{}", line).map_err(|e| e.to_string())?;
     }
-
-    fn start_document (&mut self, key : &str) {
-        // Append type.
-        self.data.push(0x03);
-        // Append key.
-        self._append_key (key);
-        // Save position for location of length bytes.
-        self.index_stack.push(self.data.len());
-        // Reserve length.
-        self.data.extend_from_slice(&[0;4]);
+    out.flush().map_err(|e| e.to_string())?;
+    match annotations.last() {
+        Some(Annotation { offset, error : Some(_), .. }) => Err(format!("malformed BSON at offset {}", offset)),
+        _ => Ok(()),
     }
+}
 
-    fn end_document (&mut self) {
-        if self.index_stack.len() == 0 {
-            panic!("Attempting to end document, but no document is being appended.");
-        }
-        let start_index = self.index_stack.pop().unwrap();
-        // Push null byte.
-        self.data.push(0x00);
-        let document_len = BSONBuilder::usize_to_i32 (self.data.len() - start_index);
-        let document_len_bytes = document_len.to_le_bytes();
-        for i in 0..3 {
-            self.data[start_index + i] = document_len_bytes[i];
+// annotate formats the lines printed by inspect. Each line is returned with whether it is part of an error.
+fn annotate (data : &[u8], annotations : &[Annotation]) -> Vec<(bool, String)> {
+    let mut lines = vec![(false, format!("{:>8}  {:<23}  {}", "offset", "bytes", "meaning"))];
+    for a in annotations {
+        let bytes = &data[a.offset..a.offset + a.len];
+        let note = match a.error {
+            Some(_) => format!("{}ERROR: {}", "  ".repeat(a.depth), a.note),
+            None => format!("{}{}", "  ".repeat(a.depth), a.note),
+        };
+        let mut chunks = bytes.chunks(INSPECT_BYTES_PER_LINE).take(INSPECT_MAX_LINES).enumerate().peekable();
+        if chunks.peek().is_none() {
+            lines.push((a.error.is_some(), format!("{:>8}  {:<23}  {}", a.offset, "", note)));
         }
-    }
-
-    fn build(&mut self) -> Vec<u8> {
-        // TODO: panic if there is an un-ended document.
-        // Set the starting length of the document.
-        // Add one for trailing NULL byte.
-        let len = self.data.len() + 1;
-        if len > i32::MAX.try_into().unwrap() {
-            panic!("document too large");
+        for (i, chunk) in chunks {
+            let hex : Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+            let note = if i == 0 { note.as_str() } else { "" };
+            let line = format!("{:>8}  {:<23}  {}", a.offset + i * INSPECT_BYTES_PER_LINE, hex.join(" "), note);
+            lines.push((a.error.is_some(), line.trim_end().to_string()));
         }
-        let len : i32 = len.try_into().unwrap();
-        let lenbytes = len.to_le_bytes();
-        assert!(lenbytes.len() == 4);
-        for i in 0..3 {
-            self.data[i] = lenbytes[i];
+        let shown = INSPECT_BYTES_PER_LINE * INSPECT_MAX_LINES;
+        if bytes.len() > shown {
+            lines.push((a.error.is_some(), format!("{:>8}  ... {} more bytes", "", bytes.len() - shown)));
         }
-
-        // Add a NULL byte.
-        self.data.push(0);
-
-        // Swap and reset data.
-        // `build` does not own `self.data`. Do a replace so `self.data` is not deinitialized.
-        let toreturn = core::mem::replace(&mut self.data, vec![0, 0, 0, 0]);
-        return toreturn;
     }
+    lines
 }
 
-fn main() {
-    println!("Hello, world!");
+// hex_decode decodes a hex string, ignoring whitespace and a leading 0x.
+fn hex_decode (s : &str) -> Option<Vec<u8>> {
+    let digits : Vec<u8> = s.trim().trim_start_matches("0x").bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    digits.chunks(2).map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()).collect()
 }
 
-
-#[test]
-fn test_empty () {
-    let mut bb = BSONBuilder::new();
-    let got = bb.build();
-    assert_eq!(got, vec![5,0,0,0,0]);
+// pretty indents the compact JSON written by `to_ejson`, with one member or array element per line.
+fn pretty (json : &str) -> String {
+    let mut out = String::with_capacity(json.len() * 2);
+    let mut depth = 0;
+    let mut chars = json.chars().peekable();
+    let newline = |out : &mut String, depth : usize| {
+        out.push('\n');
+        out.push_str(&"  ".repeat(depth));
+    };
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                out.push(c);
+                while let Some(c) = chars.next() {
+                    out.push(c);
+                    match c {
+                        '\\' => out.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '{' | '[' => {
+                out.push(c);
+                // Empty documents and arrays stay on one line.
+                if let Some(&close) = chars.peek().filter(|&&next| next == '}' || next == ']') {
+                    out.push(close);
+                    chars.next();
+                } else {
+                    depth += 1;
+                    newline (&mut out, depth);
+                }
+            }
+            '}' | ']' => {
+                depth -= 1;
+                newline (&mut out, depth);
+                out.push(c);
+            }
+            ',' => {
+                out.push(c);
+                newline (&mut out, depth);
+            }
+            ':' => out.push_str(": "),
+            _ => out.push(c),
+        }
+    }
+    out
 }
 
 #[test]
-fn test_int32 () {
-    let mut bb = BSONBuilder::new();
-    bb.append_int32 ("x", 1);
-    let got = bb.build();
-    assert_eq!(got, vec![0x0c, 0x00, 0x00, 0x00, 0x10, 0x78, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00]);
+fn test_pretty () {
+    let json = r#"{"a":{"$numberInt":"1"},"b":[],"c":{},"d":["x:{,}\"",[1,2]]}"#;
+    let want = r#"{
+  "a": {
+    "$numberInt": "1"
+  },
+  "b": [],
+  "c": {},
+  "d": [
+    "x:{,}\"",
+    [
+      1,
+      2
+    ]
+  ]
+}"#;
+    assert_eq!(pretty (json), want);
 }
 
 #[test]
-fn test_nested_document () {
-    let mut bb = BSONBuilder::new();
-    bb.start_document("x");
-    bb.append_int32 ("y", 1);
-    bb.end_document();
-    let got = bb.build();
-    assert_eq!(got, vec![0x14, 0x00, 0x00, 0x00, 0x03, 0x78, 0x00, 0x0c, 0x00, 0x00, 0x00, 0x10, 0x79, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00]);
+fn test_annotate () {
+    // From the decode-bson-with-dollars investigation.
+    let data = hex_decode ("2300000003666f6f001900000002246e756d626572496e740004000000313233000000").unwrap();
+    let lines : Vec<(bool, String)> = annotate (&data, &inspect (&data));
+    let want = [
+        "  offset  bytes                    meaning",
+        "       0  23 00 00 00              document length 35",
+        "       4  03                       type 0x03 document",
+        "       5  66 6f 6f 00              key \"foo\"",
+        "       9  19 00 00 00                document length 25",
+        "      13  02                         type 0x02 string",
+        "      14  24 6e 75 6d 62 65 72 49    key \"$numberInt\"",
+        "      22  6e 74 00",
+        "      25  04 00 00 00                string length 4",
+        "      29  31 32 33 00                \"123\"",
+        "      33  00                         end of document",
+        "      34  00                       end of document",
+    ];
+    assert_eq!(lines.iter().map(|(_, line)| line.as_str()).collect::<Vec<_>>(), want);
+    assert!(lines.iter().all(|(error, _)| !error));
+
+    // With the last byte missing.
+    let lines = annotate (&data[..34], &inspect (&data[..34]));
+    assert_eq!(lines[1], (true, "       0  23 00 00 00 03 66 6f 6f  ERROR: unexpected end of data".to_string()));
+    assert_eq!(lines.last().unwrap(), &(true, "          ... 2 more bytes".to_string()));
+    assert_eq!(hex_decode ("0x05 00 00 00 00"), Some(vec![5, 0, 0, 0, 0]));
+    assert_eq!(hex_decode ("050"), None);
+    assert_eq!(hex_decode ("zz"), None);
 }
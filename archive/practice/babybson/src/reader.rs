@@ -0,0 +1,652 @@
+// reader reads BSON documents without copying. A `BsonRef` borrows the bytes of a document, and elements are
+// decoded as they are iterated. Errors carry the offset of the malformed bytes from the start of the outermost document.
+use crate::builder::MAX_NESTING_DEPTH;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReadErrorKind {
+    // A value or document extends past the end of the enclosing document or data.
+    Truncated,
+    // A document length is less than 5, or does not fit the enclosing document.
+    InvalidLength (i32),
+    // A document does not end with a NULL byte.
+    MissingTerminator,
+    // A NULL type byte appears before the end of a document.
+    UnexpectedTerminator,
+    UnknownType (u8),
+    // A key or regex is not followed by a NULL byte.
+    UnterminatedCString,
+    InvalidUtf8,
+    // A string length is less than 1, or the string does not end with a NULL byte.
+    InvalidString,
+    InvalidBool (u8),
+    // A binary length is negative, or the two lengths of an old binary (subtype 2) disagree.
+    InvalidBinaryLength,
+    // The length of code with scope does not match the code and scope.
+    InvalidCodeWithScopeLength,
+    // Documents and arrays are nested more than the value deep.
+    MaxDepthExceeded (usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReadError {
+    pub offset : usize,
+    pub kind : ReadErrorKind,
+}
+
+impl fmt::Display for ReadErrorKind {
+    fn fmt (&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadErrorKind::Truncated => write!(f, "unexpected end of data"),
+            ReadErrorKind::InvalidLength (len) => write!(f, "invalid document length {}", len),
+            ReadErrorKind::MissingTerminator => write!(f, "document does not end with a NULL byte"),
+            ReadErrorKind::UnexpectedTerminator => write!(f, "NULL byte before the end of the document"),
+            ReadErrorKind::UnknownType (t) => write!(f, "unknown element type 0x{:02x}", t),
+            ReadErrorKind::UnterminatedCString => write!(f, "string is not NULL terminated"),
+            ReadErrorKind::InvalidUtf8 => write!(f, "invalid UTF-8"),
+            ReadErrorKind::InvalidString => write!(f, "invalid string length or terminator"),
+            ReadErrorKind::InvalidBool (b) => write!(f, "invalid boolean 0x{:02x}", b),
+            ReadErrorKind::InvalidBinaryLength => write!(f, "invalid binary length"),
+            ReadErrorKind::InvalidCodeWithScopeLength => write!(f, "invalid code with scope length"),
+            ReadErrorKind::MaxDepthExceeded (depth) => write!(f, "nesting depth exceeds {}", depth),
+        }
+    }
+}
+
+impl fmt::Display for ReadError {
+    fn fmt (&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "offset {}: {}", self.offset, self.kind)
+    }
+}
+
+impl std::error::Error for ReadError {}
+
+// BsonRef is a document (or array) whose length prefix and trailing NULL byte have been checked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BsonRef<'a> {
+    data : &'a [u8],
+    // offset is the position of `data` in the outermost document.
+    offset : usize,
+}
+
+impl<'a> BsonRef<'a> {
+    // new checks that `data` is exactly one document. Elements are checked as they are read, or by `validate`.
+    pub fn new (data : &'a [u8]) -> Result<BsonRef<'a>, ReadError> {
+        let doc = BsonRef::from_prefix (data, 0)?;
+        if doc.data.len() != data.len() {
+            return Err(ReadError { offset: 0, kind: ReadErrorKind::InvalidLength (doc.data.len() as i32) });
+        }
+        Ok(doc)
+    }
+
+    // from_prefix reads the document at the start of `data`, which starts `offset` bytes into the outermost document.
+    fn from_prefix (data : &'a [u8], offset : usize) -> Result<BsonRef<'a>, ReadError> {
+        let err = |kind| ReadError { offset, kind };
+        let len = match data.get(..4) {
+            Some(bytes) => i32::from_le_bytes(bytes.try_into().unwrap()),
+            None => return Err(err(ReadErrorKind::Truncated)),
+        };
+        if len < 5 {
+            return Err(err(ReadErrorKind::InvalidLength (len)));
+        }
+        let data = match data.get(..len as usize) {
+            Some(data) => data,
+            // The outermost document is cut short. A nested document claims more than the enclosing document has.
+            None if offset == 0 => return Err(err(ReadErrorKind::Truncated)),
+            None => return Err(err(ReadErrorKind::InvalidLength (len))),
+        };
+        if data[data.len() - 1] != 0 {
+            return Err(ReadError { offset: offset + data.len() - 1, kind: ReadErrorKind::MissingTerminator });
+        }
+        Ok(BsonRef { data, offset })
+    }
+
+    // offset returns the position of the document in the outermost document.
+    pub(crate) fn offset (&self) -> usize {
+        self.offset
+    }
+
+    // as_bytes returns the whole document, including the length and trailing NULL byte.
+    pub fn as_bytes (&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn is_empty (&self) -> bool {
+        self.data.len() == 5
+    }
+
+    pub fn iter (&self) -> ElementIter<'a> {
+        ElementIter { doc: *self, pos: 4, failed: false }
+    }
+
+    // get returns the first element with `key`.
+    pub fn get (&self, key : &str) -> Result<Option<ElementRef<'a>>, ReadError> {
+        for element in self.iter() {
+            let (k, value) = element?;
+            if k == key {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
+    // validate reads every element, including those of nested documents, and returns the first error. Documents
+    // nested more than MAX_NESTING_DEPTH deep are rejected, like the builder does.
+    pub fn validate (&self) -> Result<(), ReadError> {
+        self.validate_depth (0)
+    }
+
+    fn validate_depth (&self, depth : usize) -> Result<(), ReadError> {
+        for element in self.iter() {
+            let nested = match element?.1 {
+                ElementRef::Document (doc) | ElementRef::Array (doc) => doc,
+                ElementRef::JavaScriptWithScope { scope, .. } => scope,
+                _ => continue,
+            };
+            if depth >= MAX_NESTING_DEPTH {
+                return Err(ReadError { offset: nested.offset, kind: ReadErrorKind::MaxDepthExceeded (MAX_NESTING_DEPTH) });
+            }
+            nested.validate_depth (depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> IntoIterator for BsonRef<'a> {
+    type Item = Result<(&'a str, ElementRef<'a>), ReadError>;
+    type IntoIter = ElementIter<'a>;
+
+    fn into_iter (self) -> ElementIter<'a> {
+        self.iter()
+    }
+}
+
+// ElementRef is the value of an element. Strings and binary data borrow the document.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ElementRef<'a> {
+    Double (f64),
+    String (&'a str),
+    Document (BsonRef<'a>),
+    Array (BsonRef<'a>),
+    // Binary data. For the old binary subtype (2), `data` excludes the inner length.
+    Binary { subtype : u8, data : &'a [u8] },
+    // Deprecated.
+    Undefined,
+    ObjectId (&'a [u8; 12]),
+    Bool (bool),
+    // Milliseconds since the Unix epoch.
+    DateTime (i64),
+    Null,
+    Regex { pattern : &'a str, options : &'a str },
+    // Deprecated.
+    DbPointer { namespace : &'a str, id : &'a [u8; 12] },
+    JavaScript (&'a str),
+    // Deprecated.
+    Symbol (&'a str),
+    // Deprecated.
+    JavaScriptWithScope { code : &'a str, scope : BsonRef<'a> },
+    Int32 (i32),
+    Timestamp { timestamp : u32, increment : u32 },
+    Int64 (i64),
+    // The 16 bytes of an IEEE 754-2008 decimal128, in little endian.
+    Decimal128 (&'a [u8; 16]),
+    MinKey,
+    MaxKey,
+}
+
+impl<'a> ElementRef<'a> {
+    // element_type returns the type byte of the element.
+    pub fn element_type (&self) -> u8 {
+        match self {
+            ElementRef::Double (_) => 0x01,
+            ElementRef::String (_) => 0x02,
+            ElementRef::Document (_) => 0x03,
+            ElementRef::Array (_) => 0x04,
+            ElementRef::Binary { .. } => 0x05,
+            ElementRef::Undefined => 0x06,
+            ElementRef::ObjectId (_) => 0x07,
+            ElementRef::Bool (_) => 0x08,
+            ElementRef::DateTime (_) => 0x09,
+            ElementRef::Null => 0x0A,
+            ElementRef::Regex { .. } => 0x0B,
+            ElementRef::DbPointer { .. } => 0x0C,
+            ElementRef::JavaScript (_) => 0x0D,
+            ElementRef::Symbol (_) => 0x0E,
+            ElementRef::JavaScriptWithScope { .. } => 0x0F,
+            ElementRef::Int32 (_) => 0x10,
+            ElementRef::Timestamp { .. } => 0x11,
+            ElementRef::Int64 (_) => 0x12,
+            ElementRef::Decimal128 (_) => 0x13,
+            ElementRef::MinKey => 0xFF,
+            ElementRef::MaxKey => 0x7F,
+        }
+    }
+
+    pub fn as_f64 (&self) -> Option<f64> {
+        match self {
+            ElementRef::Double (v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str (&self) -> Option<&'a str> {
+        match self {
+            ElementRef::String (v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_document (&self) -> Option<BsonRef<'a>> {
+        match self {
+            ElementRef::Document (v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_array (&self) -> Option<BsonRef<'a>> {
+        match self {
+            ElementRef::Array (v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    // as_binary returns the subtype and data.
+    pub fn as_binary (&self) -> Option<(u8, &'a [u8])> {
+        match self {
+            ElementRef::Binary { subtype, data } => Some((*subtype, data)),
+            _ => None,
+        }
+    }
+
+    pub fn as_objectid (&self) -> Option<&'a [u8; 12]> {
+        match self {
+            ElementRef::ObjectId (v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool (&self) -> Option<bool> {
+        match self {
+            ElementRef::Bool (v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_datetime (&self) -> Option<i64> {
+        match self {
+            ElementRef::DateTime (v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_i32 (&self) -> Option<i32> {
+        match self {
+            ElementRef::Int32 (v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64 (&self) -> Option<i64> {
+        match self {
+            ElementRef::Int64 (v) => Some(*v),
+            _ => None,
+        }
+    }
+}
+
+// ElementIter reads the elements of a document in order. It stops after the first error.
+pub struct ElementIter<'a> {
+    doc : BsonRef<'a>,
+    // pos is the position of the next type byte in `doc.data`.
+    pos : usize,
+    failed : bool,
+}
+
+impl<'a> ElementIter<'a> {
+    // position returns the position of the next element in the outermost document.
+    pub(crate) fn position (&self) -> usize {
+        self.doc.offset + self.pos
+    }
+}
+
+impl<'a> Iterator for ElementIter<'a> {
+    type Item = Result<(&'a str, ElementRef<'a>), ReadError>;
+
+    fn next (&mut self) -> Option<Self::Item> {
+        // The last byte is the document terminator.
+        if self.failed || self.pos >= self.doc.data.len() - 1 {
+            return None;
+        }
+        let mut cursor = Cursor {
+            data: &self.doc.data[..self.doc.data.len() - 1],
+            pos: self.pos,
+            offset: self.doc.offset,
+        };
+        match cursor.element() {
+            Ok(element) => {
+                self.pos = cursor.pos;
+                Some(Ok(element))
+            }
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+// Cursor reads values from the elements of a document. `data` excludes the document terminator.
+struct Cursor<'a> {
+    data : &'a [u8],
+    pos : usize,
+    offset : usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn err (&self, pos : usize, kind : ReadErrorKind) -> ReadError {
+        ReadError { offset: self.offset + pos, kind }
+    }
+
+    fn bytes (&mut self, n : usize) -> Result<&'a [u8], ReadError> {
+        let bytes = self.data.get(self.pos..self.pos + n).ok_or(self.err(self.pos, ReadErrorKind::Truncated))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn array<const N : usize> (&mut self) -> Result<&'a [u8; N], ReadError> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    fn i32 (&mut self) -> Result<i32, ReadError> {
+        Ok(i32::from_le_bytes(*self.array()?))
+    }
+
+    fn u32 (&mut self) -> Result<u32, ReadError> {
+        Ok(u32::from_le_bytes(*self.array()?))
+    }
+
+    fn i64 (&mut self) -> Result<i64, ReadError> {
+        Ok(i64::from_le_bytes(*self.array()?))
+    }
+
+    fn utf8 (&self, bytes : &'a [u8], pos : usize) -> Result<&'a str, ReadError> {
+        std::str::from_utf8(bytes).map_err(|e| self.err(pos + e.valid_up_to(), ReadErrorKind::InvalidUtf8))
+    }
+
+    fn cstring (&mut self) -> Result<&'a str, ReadError> {
+        let start = self.pos;
+        let len = self.data[start..].iter().position(|&b| b == 0)
+            .ok_or(self.err(start, ReadErrorKind::UnterminatedCString))?;
+        self.pos += len + 1;
+        self.utf8(&self.data[start..start + len], start)
+    }
+
+    fn string (&mut self) -> Result<&'a str, ReadError> {
+        let start = self.pos;
+        let len = self.i32()?;
+        if len < 1 {
+            return Err(self.err(start, ReadErrorKind::InvalidString));
+        }
+        let bytes = self.bytes(len as usize).map_err(|_| self.err(start, ReadErrorKind::Truncated))?;
+        if bytes[bytes.len() - 1] != 0 {
+            return Err(self.err(start, ReadErrorKind::InvalidString));
+        }
+        self.utf8(&bytes[..bytes.len() - 1], start + 4)
+    }
+
+    fn document (&mut self) -> Result<BsonRef<'a>, ReadError> {
+        let doc = BsonRef::from_prefix (&self.data[self.pos..], self.offset + self.pos)?;
+        self.pos += doc.data.len();
+        Ok(doc)
+    }
+
+    fn element (&mut self) -> Result<(&'a str, ElementRef<'a>), ReadError> {
+        let start = self.pos;
+        let element_type = self.bytes(1)?[0];
+        if element_type == 0 {
+            return Err(self.err(start, ReadErrorKind::UnexpectedTerminator));
+        }
+        let key = self.cstring()?;
+        let value = match element_type {
+            0x01 => ElementRef::Double (f64::from_le_bytes(*self.array()?)),
+            0x02 => ElementRef::String (self.string()?),
+            0x03 => ElementRef::Document (self.document()?),
+            0x04 => ElementRef::Array (self.document()?),
+            0x05 => {
+                let len_pos = self.pos;
+                let len = self.i32()?;
+                if len < 0 {
+                    return Err(self.err(len_pos, ReadErrorKind::InvalidBinaryLength));
+                }
+                let subtype = self.bytes(1)?[0];
+                let mut data = self.bytes(len as usize)?;
+                if subtype == 0x02 {
+                    let inner = data.get(..4).map(|b| i32::from_le_bytes(b.try_into().unwrap()));
+                    if inner != Some(len - 4) {
+                        return Err(self.err(len_pos, ReadErrorKind::InvalidBinaryLength));
+                    }
+                    data = &data[4..];
+                }
+                ElementRef::Binary { subtype, data }
+            }
+            0x06 => ElementRef::Undefined,
+            0x07 => ElementRef::ObjectId (self.array()?),
+            0x08 => match self.bytes(1)?[0] {
+                0 => ElementRef::Bool (false),
+                1 => ElementRef::Bool (true),
+                b => return Err(self.err(self.pos - 1, ReadErrorKind::InvalidBool (b))),
+            },
+            0x09 => ElementRef::DateTime (self.i64()?),
+            0x0A => ElementRef::Null,
+            0x0B => ElementRef::Regex { pattern: self.cstring()?, options: self.cstring()? },
+            0x0C => ElementRef::DbPointer { namespace: self.string()?, id: self.array()? },
+            0x0D => ElementRef::JavaScript (self.string()?),
+            0x0E => ElementRef::Symbol (self.string()?),
+            0x0F => {
+                let len_pos = self.pos;
+                let len = self.i32()?;
+                let code = self.string()?;
+                let scope = self.document()?;
+                if len < 0 || len as usize != self.pos - len_pos {
+                    return Err(self.err(len_pos, ReadErrorKind::InvalidCodeWithScopeLength));
+                }
+                ElementRef::JavaScriptWithScope { code, scope }
+            }
+            0x10 => ElementRef::Int32 (self.i32()?),
+            0x11 => {
+                let increment = self.u32()?;
+                let timestamp = self.u32()?;
+                ElementRef::Timestamp { timestamp, increment }
+            }
+            0x12 => ElementRef::Int64 (self.i64()?),
+            0x13 => ElementRef::Decimal128 (self.array()?),
+            0xFF => ElementRef::MinKey,
+            0x7F => ElementRef::MaxKey,
+            t => return Err(self.err(start, ReadErrorKind::UnknownType (t))),
+        };
+        Ok((key, value))
+    }
+}
+
+#[cfg(test)]
+use crate::builder::*;
+
+#[test]
+fn test_read_empty () {
+    let doc = BsonRef::new (&[5, 0, 0, 0, 0]).unwrap();
+    assert!(doc.is_empty());
+    assert_eq!(doc.iter().count(), 0);
+}
+
+#[test]
+fn test_read_all_types () {
+    let mut bb = BSONBuilder::new();
+    bb.append_double ("double", 1.5).unwrap();
+    bb.append_string ("string", "héllo").unwrap();
+    bb.start_document ("document").unwrap();
+    bb.append_int32 ("x", 1).unwrap();
+    bb.end_document ().unwrap();
+    bb.start_array ("array").unwrap();
+    bb.append_int32 ("", 2).unwrap();
+    bb.end_array ().unwrap();
+    bb.append_binary ("binary", BINARY_SUBTYPE_UUID, &[1, 2, 3]).unwrap();
+    bb.append_binary ("binary_old", BINARY_SUBTYPE_BINARY_OLD, &[4, 5]).unwrap();
+    bb.append_objectid ("objectid", &[7; 12]).unwrap();
+    bb.append_bool ("bool", true).unwrap();
+    bb.append_datetime ("datetime", -5).unwrap();
+    bb.append_null ("null").unwrap();
+    bb.append_regex ("regex", "^a", "xi").unwrap();
+    bb.append_javascript ("javascript", "f()").unwrap();
+    bb.append_int32 ("int32", -7).unwrap();
+    bb.append_timestamp ("timestamp", 10, 20).unwrap();
+    bb.append_int64 ("int64", 1 << 40).unwrap();
+    bb.append_decimal128 ("decimal128", &[9; 16]).unwrap();
+    bb.append_minkey ("minkey").unwrap();
+    bb.append_maxkey ("maxkey").unwrap();
+    let data = bb.build().unwrap();
+    let doc = BsonRef::new (&data).unwrap();
+    doc.validate().unwrap();
+    let got : Vec<(&str, ElementRef)> = doc.iter().map(|e| e.unwrap()).collect();
+    let nested = got[2].1.as_document().unwrap();
+    let array = got[3].1.as_array().unwrap();
+    assert_eq!(got, vec![
+        ("double", ElementRef::Double (1.5)),
+        ("string", ElementRef::String ("héllo")),
+        ("document", ElementRef::Document (nested)),
+        ("array", ElementRef::Array (array)),
+        ("binary", ElementRef::Binary { subtype: BINARY_SUBTYPE_UUID, data: &[1, 2, 3] }),
+        ("binary_old", ElementRef::Binary { subtype: BINARY_SUBTYPE_BINARY_OLD, data: &[4, 5] }),
+        ("objectid", ElementRef::ObjectId (&[7; 12])),
+        ("bool", ElementRef::Bool (true)),
+        ("datetime", ElementRef::DateTime (-5)),
+        ("null", ElementRef::Null),
+        ("regex", ElementRef::Regex { pattern: "^a", options: "ix" }),
+        ("javascript", ElementRef::JavaScript ("f()")),
+        ("int32", ElementRef::Int32 (-7)),
+        ("timestamp", ElementRef::Timestamp { timestamp: 10, increment: 20 }),
+        ("int64", ElementRef::Int64 (1 << 40)),
+        ("decimal128", ElementRef::Decimal128 (&[9; 16])),
+        ("minkey", ElementRef::MinKey),
+        ("maxkey", ElementRef::MaxKey),
+    ]);
+    assert_eq!(nested.get("x").unwrap().and_then(|v| v.as_i32()), Some(1));
+    assert_eq!(array.get("0").unwrap().and_then(|v| v.as_i32()), Some(2));
+    assert_eq!(got[0].1.element_type(), 0x01);
+    assert_eq!(got[17].1.element_type(), 0x7F);
+}
+
+#[test]
+fn test_read_zero_copy () {
+    let mut bb = BSONBuilder::new();
+    bb.start_document ("x").unwrap();
+    bb.append_string ("y", "value").unwrap();
+    bb.end_document ().unwrap();
+    let data = bb.build().unwrap();
+    let doc = BsonRef::new (&data).unwrap();
+    let y = doc.get("x").unwrap().unwrap().as_document().unwrap().get("y").unwrap().unwrap();
+    // The string points into `data`.
+    let s = y.as_str().unwrap();
+    assert!(data.as_ptr_range().contains(&s.as_ptr()));
+    assert_eq!(s, "value");
+    assert_eq!(doc.get("missing"), Ok(None));
+}
+
+#[test]
+fn test_read_deprecated_types () {
+    let data = [
+        0x3f, 0x00, 0x00, 0x00,
+        0x06, 0x75, 0x00,
+        0x0c, 0x70, 0x00, 0x02, 0x00, 0x00, 0x00, 0x62, 0x00, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+        0x0e, 0x73, 0x00, 0x02, 0x00, 0x00, 0x00, 0x73, 0x00,
+        0x0f, 0x63, 0x00, 0x16, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x66, 0x00,
+        0x0c, 0x00, 0x00, 0x00, 0x10, 0x78, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00,
+        0x00];
+    let doc = BsonRef::new (&data).unwrap();
+    doc.validate().unwrap();
+    let got : Vec<ElementRef> = doc.iter().map(|e| e.unwrap().1).collect();
+    assert_eq!(got[0], ElementRef::Undefined);
+    assert_eq!(got[1], ElementRef::DbPointer { namespace: "b", id: &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12] });
+    assert_eq!(got[2], ElementRef::Symbol ("s"));
+    match got[3] {
+        ElementRef::JavaScriptWithScope { code, scope } => {
+            assert_eq!(code, "f");
+            assert_eq!(scope.get("x").unwrap().and_then(|v| v.as_i32()), Some(1));
+        }
+        _ => panic!("got {:?}", got[3]),
+    }
+}
+
+#[test]
+fn test_read_errors () {
+    let cases : Vec<(Vec<u8>, usize, ReadErrorKind)> = vec![
+        (vec![5, 0, 0], 0, ReadErrorKind::Truncated),
+        (vec![4, 0, 0, 0, 0], 0, ReadErrorKind::InvalidLength (4)),
+        (vec![6, 0, 0, 0, 0], 0, ReadErrorKind::Truncated),
+        (vec![5, 0, 0, 0, 0, 0], 0, ReadErrorKind::InvalidLength (5)),
+        (vec![5, 0, 0, 0, 1], 4, ReadErrorKind::MissingTerminator),
+        // The key runs into the terminator.
+        (vec![7, 0, 0, 0, 0x0a, 0x61, 0], 5, ReadErrorKind::UnterminatedCString),
+        (vec![7, 0, 0, 0, 0, 0, 0], 4, ReadErrorKind::UnexpectedTerminator),
+        (vec![8, 0, 0, 0, 0x20, 0x61, 0, 0], 4, ReadErrorKind::UnknownType (0x20)),
+        (vec![9, 0, 0, 0, 0x0a, 0xff, 0, 0x0a, 0], 5, ReadErrorKind::InvalidUtf8),
+        (vec![9, 0, 0, 0, 0x08, 0x61, 0, 2, 0], 7, ReadErrorKind::InvalidBool (2)),
+        (vec![0x0b, 0, 0, 0, 0x10, 0x61, 0, 1, 0, 0, 0], 7, ReadErrorKind::Truncated),
+        (vec![0x0e, 0, 0, 0, 0x02, 0x61, 0, 0, 0, 0, 0, 0x62, 0, 0], 7, ReadErrorKind::InvalidString),
+        (vec![0x0e, 0, 0, 0, 0x02, 0x61, 0, 2, 0, 0, 0, 0x62, 0x63, 0], 7, ReadErrorKind::InvalidString),
+        (vec![0x0e, 0, 0, 0, 0x02, 0x61, 0, 3, 0, 0, 0, 0x62, 0, 0], 7, ReadErrorKind::Truncated),
+        (vec![0x0e, 0, 0, 0, 0x02, 0x61, 0, 2, 0, 0, 0, 0xc3, 0, 0], 11, ReadErrorKind::InvalidUtf8),
+        // The nested document eats the terminator of the outer document.
+        (vec![0x0c, 0, 0, 0, 0x03, 0x61, 0, 6, 0, 0, 0, 0], 7, ReadErrorKind::InvalidLength (6)),
+        (vec![0x0d, 0, 0, 0, 0x03, 0x61, 0, 5, 0, 0, 0, 1, 0], 11, ReadErrorKind::MissingTerminator),
+        (vec![0x10, 0, 0, 0, 0x05, 0x61, 0, 3, 0, 0, 0, 0x02, 0, 0, 0, 0], 7, ReadErrorKind::InvalidBinaryLength),
+        (vec![0x0c, 0, 0, 0, 0x05, 0x61, 0, 0xff, 0xff, 0xff, 0xff, 0], 7, ReadErrorKind::InvalidBinaryLength),
+    ];
+    for (data, offset, kind) in cases {
+        let got = BsonRef::new (&data).and_then(|doc| doc.validate());
+        assert_eq!(got, Err(ReadError { offset, kind }), "{:02x?}", data);
+    }
+}
+
+#[test]
+fn test_read_error_stops_iteration () {
+    let data = [0x0c, 0, 0, 0, 0x0a, 0x61, 0, 0x20, 0x62, 0, 0x0a, 0];
+    let doc = BsonRef::new (&data).unwrap();
+    let got : Vec<_> = doc.iter().collect();
+    assert_eq!(got.len(), 2);
+    assert_eq!(got[0], Ok(("a", ElementRef::Null)));
+    assert_eq!(got[1].unwrap_err().to_string(), "offset 7: unknown element type 0x20");
+}
+
+// nested_document returns {"a": {"a": ...}} with `depth` nested documents.
+#[cfg(test)]
+pub(crate) fn nested_document (depth : usize) -> Vec<u8> {
+    let mut data = Vec::with_capacity(8 * depth + 5);
+    for level in 0..depth {
+        // Each level adds a length, a type, a key and a terminator to the document inside it.
+        data.extend(((8 * (depth - level) + 5) as i32).to_le_bytes());
+        data.extend([0x03, b'a', 0]);
+    }
+    data.extend([5, 0, 0, 0, 0]);
+    data.resize(8 * depth + 5, 0);
+    data
+}
+
+#[test]
+fn test_max_depth () {
+    BsonRef::new (&nested_document (MAX_NESTING_DEPTH)).unwrap().validate().unwrap();
+    let data = nested_document (MAX_NESTING_DEPTH + 1);
+    // The innermost document is too deep. Each level adds 7 bytes before it.
+    let err = ReadError { offset: 7 * (MAX_NESTING_DEPTH + 1), kind: ReadErrorKind::MaxDepthExceeded (MAX_NESTING_DEPTH) };
+    assert_eq!(BsonRef::new (&data).unwrap().validate(), Err(err));
+    // Deep documents are an error rather than a stack overflow.
+    let data = nested_document (100_000);
+    assert_eq!(BsonRef::new (&data).unwrap().validate().unwrap_err().kind, ReadErrorKind::MaxDepthExceeded (MAX_NESTING_DEPTH));
+}
+
+#[test]
+fn test_nested_error_offset () {
+    // Offsets are from the start of the outermost document.
+    let data = [0x11, 0, 0, 0, 0x03, 0x61, 0, 9, 0, 0, 0, 0x08, 0x62, 0, 5, 0, 0];
+    let doc = BsonRef::new (&data).unwrap();
+    assert_eq!(doc.validate(), Err(ReadError { offset: 14, kind: ReadErrorKind::InvalidBool (5) }));
+}
@@ -0,0 +1,647 @@
+// ser serializes Rust values with serde straight into a `BSONBuilder`, without building an intermediate document.
+//
+// Values map to BSON like the official driver: i8 to i32 and u8 and u16 become int32, i64, u32 and u64 become int64,
+// floats become doubles, and bytes become generic binary. None and () become null. Sequences and tuples become
+// arrays, and structs and maps become documents. Enums are externally tagged: a unit variant is a string with its
+// name, and other variants are a document with one element named after the variant.
+use crate::builder::{BSONBuilder, BuildError, BINARY_SUBTYPE_GENERIC};
+use serde::ser::{self, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SerError {
+    Build (BuildError),
+    // Only structs, maps and enum variants with fields can be the outermost document.
+    NotADocument,
+    // Map keys must be strings, chars or integers.
+    InvalidKey,
+    // A u64 does not fit in an int64.
+    UnsignedTooLarge (u64),
+    // An error from a `Serialize` implementation.
+    Custom (String),
+}
+
+impl fmt::Display for SerError {
+    fn fmt (&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SerError::Build (e) => write!(f, "{}", e),
+            SerError::NotADocument => write!(f, "only a struct or map can be serialized as a document"),
+            SerError::InvalidKey => write!(f, "map keys must be strings, chars or integers"),
+            SerError::UnsignedTooLarge (v) => write!(f, "{} does not fit in an int64", v),
+            SerError::Custom (msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SerError {}
+
+impl ser::Error for SerError {
+    fn custom<T : fmt::Display> (msg : T) -> SerError {
+        SerError::Custom (msg.to_string())
+    }
+}
+
+impl From<BuildError> for SerError {
+    fn from (e : BuildError) -> SerError {
+        SerError::Build (e)
+    }
+}
+
+// to_bson serializes `value` as a document.
+pub fn to_bson<T : Serialize + ?Sized> (value : &T) -> Result<Vec<u8>, SerError> {
+    let mut builder = BSONBuilder::new();
+    to_builder (&mut builder, value)?;
+    Ok(builder.build()?)
+}
+
+// to_builder serializes the fields of `value` into the document `builder` is writing, so they can be mixed with
+// appended elements.
+pub fn to_builder<T : Serialize + ?Sized> (builder : &mut BSONBuilder, value : &T) -> Result<(), SerError> {
+    value.serialize (Serializer { builder, key: None })
+}
+
+// Serializer appends one value with `key`. A `key` of None is the outermost document, whose fields are appended
+// directly. Inside arrays the key is ignored, since BSONBuilder writes the index.
+pub struct Serializer<'b> {
+    builder : &'b mut BSONBuilder,
+    key : Option<&'b str>,
+}
+
+impl<'b> Serializer<'b> {
+    fn key (&self) -> Result<&'b str, SerError> {
+        self.key.ok_or(SerError::NotADocument)
+    }
+
+    // start starts the document of a struct or map, unless it is the outermost document.
+    fn start (self, end : End) -> Result<Compound<'b>, SerError> {
+        let end = match self.key {
+            Some(key) => {
+                self.builder.start_document (key)?;
+                end
+            }
+            None => End::Nothing,
+        };
+        Ok(Compound { builder: self.builder, key: None, end })
+    }
+
+    // start_variant starts the document holding a variant, and the array or document of its fields.
+    fn start_variant (self, variant : &str, array : bool) -> Result<Compound<'b>, SerError> {
+        if let Some(key) = self.key {
+            self.builder.start_document (key)?;
+        }
+        if array {
+            self.builder.start_array (variant)?;
+        } else {
+            self.builder.start_document (variant)?;
+        }
+        let end = match (self.key, array) {
+            (Some(_), true) => End::VariantArray,
+            (Some(_), false) => End::VariantDocument,
+            (None, true) => End::Array,
+            (None, false) => End::Document,
+        };
+        Ok(Compound { builder: self.builder, key: None, end })
+    }
+}
+
+// End is what to end when a compound value is done.
+enum End {
+    // The fields of the outermost document.
+    Nothing,
+    Document,
+    Array,
+    // The variant's document and the outer document.
+    VariantDocument,
+    // The variant's array and the outer document.
+    VariantArray,
+}
+
+impl<'b> ser::Serializer for Serializer<'b> {
+    type Ok = ();
+    type Error = SerError;
+    type SerializeSeq = Compound<'b>;
+    type SerializeTuple = Compound<'b>;
+    type SerializeTupleStruct = Compound<'b>;
+    type SerializeTupleVariant = Compound<'b>;
+    type SerializeMap = Compound<'b>;
+    type SerializeStruct = Compound<'b>;
+    type SerializeStructVariant = Compound<'b>;
+
+    fn serialize_bool (self, v : bool) -> Result<(), SerError> {
+        Ok(self.builder.append_bool (self.key()?, v)?)
+    }
+
+    fn serialize_i8 (self, v : i8) -> Result<(), SerError> {
+        self.serialize_i32 (v as i32)
+    }
+
+    fn serialize_i16 (self, v : i16) -> Result<(), SerError> {
+        self.serialize_i32 (v as i32)
+    }
+
+    fn serialize_i32 (self, v : i32) -> Result<(), SerError> {
+        Ok(self.builder.append_int32 (self.key()?, v)?)
+    }
+
+    fn serialize_i64 (self, v : i64) -> Result<(), SerError> {
+        Ok(self.builder.append_int64 (self.key()?, v)?)
+    }
+
+    fn serialize_u8 (self, v : u8) -> Result<(), SerError> {
+        self.serialize_i32 (v as i32)
+    }
+
+    fn serialize_u16 (self, v : u16) -> Result<(), SerError> {
+        self.serialize_i32 (v as i32)
+    }
+
+    // u32 is always an int64, so the type of a field does not depend on its value.
+    fn serialize_u32 (self, v : u32) -> Result<(), SerError> {
+        self.serialize_i64 (v as i64)
+    }
+
+    fn serialize_u64 (self, v : u64) -> Result<(), SerError> {
+        let v = i64::try_from(v).map_err(|_| SerError::UnsignedTooLarge (v))?;
+        self.serialize_i64 (v)
+    }
+
+    fn serialize_f32 (self, v : f32) -> Result<(), SerError> {
+        self.serialize_f64 (v as f64)
+    }
+
+    fn serialize_f64 (self, v : f64) -> Result<(), SerError> {
+        Ok(self.builder.append_double (self.key()?, v)?)
+    }
+
+    fn serialize_char (self, v : char) -> Result<(), SerError> {
+        self.serialize_str (v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str (self, v : &str) -> Result<(), SerError> {
+        Ok(self.builder.append_string (self.key()?, v)?)
+    }
+
+    fn serialize_bytes (self, v : &[u8]) -> Result<(), SerError> {
+        Ok(self.builder.append_binary (self.key()?, BINARY_SUBTYPE_GENERIC, v)?)
+    }
+
+    fn serialize_none (self) -> Result<(), SerError> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some<T : Serialize + ?Sized> (self, value : &T) -> Result<(), SerError> {
+        value.serialize (self)
+    }
+
+    fn serialize_unit (self) -> Result<(), SerError> {
+        Ok(self.builder.append_null (self.key()?)?)
+    }
+
+    fn serialize_unit_struct (self, _name : &'static str) -> Result<(), SerError> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant (self, _name : &'static str, _index : u32, variant : &'static str) -> Result<(), SerError> {
+        self.serialize_str (variant)
+    }
+
+    fn serialize_newtype_struct<T : Serialize + ?Sized> (self, _name : &'static str, value : &T) -> Result<(), SerError> {
+        value.serialize (self)
+    }
+
+    fn serialize_newtype_variant<T : Serialize + ?Sized> (
+        self, _name : &'static str, _index : u32, variant : &'static str, value : &T,
+    ) -> Result<(), SerError> {
+        match self.key {
+            Some(key) => {
+                self.builder.start_document (key)?;
+                value.serialize (Serializer { builder: self.builder, key: Some(variant) })?;
+                Ok(self.builder.end_document()?)
+            }
+            None => value.serialize (Serializer { builder: self.builder, key: Some(variant) }),
+        }
+    }
+
+    fn serialize_seq (self, _len : Option<usize>) -> Result<Compound<'b>, SerError> {
+        self.builder.start_array (self.key()?)?;
+        Ok(Compound { builder: self.builder, key: None, end: End::Array })
+    }
+
+    fn serialize_tuple (self, len : usize) -> Result<Compound<'b>, SerError> {
+        self.serialize_seq (Some(len))
+    }
+
+    fn serialize_tuple_struct (self, _name : &'static str, len : usize) -> Result<Compound<'b>, SerError> {
+        self.serialize_seq (Some(len))
+    }
+
+    fn serialize_tuple_variant (
+        self, _name : &'static str, _index : u32, variant : &'static str, _len : usize,
+    ) -> Result<Compound<'b>, SerError> {
+        self.start_variant (variant, true)
+    }
+
+    fn serialize_map (self, _len : Option<usize>) -> Result<Compound<'b>, SerError> {
+        self.start (End::Document)
+    }
+
+    fn serialize_struct (self, _name : &'static str, _len : usize) -> Result<Compound<'b>, SerError> {
+        self.start (End::Document)
+    }
+
+    fn serialize_struct_variant (
+        self, _name : &'static str, _index : u32, variant : &'static str, _len : usize,
+    ) -> Result<Compound<'b>, SerError> {
+        self.start_variant (variant, false)
+    }
+}
+
+// Compound appends the elements of an array, document or variant.
+pub struct Compound<'b> {
+    builder : &'b mut BSONBuilder,
+    // key is the key of the next map value.
+    key : Option<String>,
+    end : End,
+}
+
+impl<'b> Compound<'b> {
+    fn element<T : Serialize + ?Sized> (&mut self, key : &str, value : &T) -> Result<(), SerError> {
+        value.serialize (Serializer { builder: self.builder, key: Some(key) })
+    }
+
+    fn end (self) -> Result<(), SerError> {
+        match self.end {
+            End::Nothing => {}
+            End::Document => self.builder.end_document()?,
+            End::Array => self.builder.end_array()?,
+            End::VariantDocument => {
+                self.builder.end_document()?;
+                self.builder.end_document()?;
+            }
+            End::VariantArray => {
+                self.builder.end_array()?;
+                self.builder.end_document()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'b> ser::SerializeSeq for Compound<'b> {
+    type Ok = ();
+    type Error = SerError;
+
+    fn serialize_element<T : Serialize + ?Sized> (&mut self, value : &T) -> Result<(), SerError> {
+        self.element ("", value)
+    }
+
+    fn end (self) -> Result<(), SerError> {
+        Compound::end (self)
+    }
+}
+
+impl<'b> ser::SerializeTuple for Compound<'b> {
+    type Ok = ();
+    type Error = SerError;
+
+    fn serialize_element<T : Serialize + ?Sized> (&mut self, value : &T) -> Result<(), SerError> {
+        self.element ("", value)
+    }
+
+    fn end (self) -> Result<(), SerError> {
+        Compound::end (self)
+    }
+}
+
+impl<'b> ser::SerializeTupleStruct for Compound<'b> {
+    type Ok = ();
+    type Error = SerError;
+
+    fn serialize_field<T : Serialize + ?Sized> (&mut self, value : &T) -> Result<(), SerError> {
+        self.element ("", value)
+    }
+
+    fn end (self) -> Result<(), SerError> {
+        Compound::end (self)
+    }
+}
+
+impl<'b> ser::SerializeTupleVariant for Compound<'b> {
+    type Ok = ();
+    type Error = SerError;
+
+    fn serialize_field<T : Serialize + ?Sized> (&mut self, value : &T) -> Result<(), SerError> {
+        self.element ("", value)
+    }
+
+    fn end (self) -> Result<(), SerError> {
+        Compound::end (self)
+    }
+}
+
+impl<'b> ser::SerializeMap for Compound<'b> {
+    type Ok = ();
+    type Error = SerError;
+
+    fn serialize_key<T : Serialize + ?Sized> (&mut self, key : &T) -> Result<(), SerError> {
+        self.key = Some(key.serialize (KeySerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T : Serialize + ?Sized> (&mut self, value : &T) -> Result<(), SerError> {
+        let key = self.key.take().expect("serialize_value called before serialize_key");
+        self.element (&key, value)
+    }
+
+    fn end (self) -> Result<(), SerError> {
+        Compound::end (self)
+    }
+}
+
+impl<'b> ser::SerializeStruct for Compound<'b> {
+    type Ok = ();
+    type Error = SerError;
+
+    fn serialize_field<T : Serialize + ?Sized> (&mut self, key : &'static str, value : &T) -> Result<(), SerError> {
+        self.element (key, value)
+    }
+
+    fn end (self) -> Result<(), SerError> {
+        Compound::end (self)
+    }
+}
+
+impl<'b> ser::SerializeStructVariant for Compound<'b> {
+    type Ok = ();
+    type Error = SerError;
+
+    fn serialize_field<T : Serialize + ?Sized> (&mut self, key : &'static str, value : &T) -> Result<(), SerError> {
+        self.element (key, value)
+    }
+
+    fn end (self) -> Result<(), SerError> {
+        Compound::end (self)
+    }
+}
+
+// KeySerializer turns a map key into a string. Integer keys are written in decimal, like serde_json does.
+struct KeySerializer;
+
+impl ser::Serializer for KeySerializer {
+    type Ok = String;
+    type Error = SerError;
+    type SerializeSeq = ser::Impossible<String, SerError>;
+    type SerializeTuple = ser::Impossible<String, SerError>;
+    type SerializeTupleStruct = ser::Impossible<String, SerError>;
+    type SerializeTupleVariant = ser::Impossible<String, SerError>;
+    type SerializeMap = ser::Impossible<String, SerError>;
+    type SerializeStruct = ser::Impossible<String, SerError>;
+    type SerializeStructVariant = ser::Impossible<String, SerError>;
+
+    fn serialize_str (self, v : &str) -> Result<String, SerError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_char (self, v : char) -> Result<String, SerError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i8 (self, v : i8) -> Result<String, SerError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i16 (self, v : i16) -> Result<String, SerError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i32 (self, v : i32) -> Result<String, SerError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i64 (self, v : i64) -> Result<String, SerError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u8 (self, v : u8) -> Result<String, SerError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u16 (self, v : u16) -> Result<String, SerError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u32 (self, v : u32) -> Result<String, SerError> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u64 (self, v : u64) -> Result<String, SerError> {
+        Ok(v.to_string())
+    }
+
+    // Unit variants, e.g. enum keys, are their names.
+    fn serialize_unit_variant (self, _name : &'static str, _index : u32, variant : &'static str) -> Result<String, SerError> {
+        Ok(variant.to_string())
+    }
+
+    fn serialize_newtype_struct<T : Serialize + ?Sized> (self, _name : &'static str, value : &T) -> Result<String, SerError> {
+        value.serialize (self)
+    }
+
+    fn serialize_bool (self, _v : bool) -> Result<String, SerError> {
+        Err(SerError::InvalidKey)
+    }
+
+    fn serialize_f32 (self, _v : f32) -> Result<String, SerError> {
+        Err(SerError::InvalidKey)
+    }
+
+    fn serialize_f64 (self, _v : f64) -> Result<String, SerError> {
+        Err(SerError::InvalidKey)
+    }
+
+    fn serialize_bytes (self, _v : &[u8]) -> Result<String, SerError> {
+        Err(SerError::InvalidKey)
+    }
+
+    fn serialize_none (self) -> Result<String, SerError> {
+        Err(SerError::InvalidKey)
+    }
+
+    fn serialize_some<T : Serialize + ?Sized> (self, _value : &T) -> Result<String, SerError> {
+        Err(SerError::InvalidKey)
+    }
+
+    fn serialize_unit (self) -> Result<String, SerError> {
+        Err(SerError::InvalidKey)
+    }
+
+    fn serialize_unit_struct (self, _name : &'static str) -> Result<String, SerError> {
+        Err(SerError::InvalidKey)
+    }
+
+    fn serialize_newtype_variant<T : Serialize + ?Sized> (
+        self, _name : &'static str, _index : u32, _variant : &'static str, _value : &T,
+    ) -> Result<String, SerError> {
+        Err(SerError::InvalidKey)
+    }
+
+    fn serialize_seq (self, _len : Option<usize>) -> Result<Self::SerializeSeq, SerError> {
+        Err(SerError::InvalidKey)
+    }
+
+    fn serialize_tuple (self, _len : usize) -> Result<Self::SerializeTuple, SerError> {
+        Err(SerError::InvalidKey)
+    }
+
+    fn serialize_tuple_struct (self, _name : &'static str, _len : usize) -> Result<Self::SerializeTupleStruct, SerError> {
+        Err(SerError::InvalidKey)
+    }
+
+    fn serialize_tuple_variant (
+        self, _name : &'static str, _index : u32, _variant : &'static str, _len : usize,
+    ) -> Result<Self::SerializeTupleVariant, SerError> {
+        Err(SerError::InvalidKey)
+    }
+
+    fn serialize_map (self, _len : Option<usize>) -> Result<Self::SerializeMap, SerError> {
+        Err(SerError::InvalidKey)
+    }
+
+    fn serialize_struct (self, _name : &'static str, _len : usize) -> Result<Self::SerializeStruct, SerError> {
+        Err(SerError::InvalidKey)
+    }
+
+    fn serialize_struct_variant (
+        self, _name : &'static str, _index : u32, _variant : &'static str, _len : usize,
+    ) -> Result<Self::SerializeStructVariant, SerError> {
+        Err(SerError::InvalidKey)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize)]
+    struct Book {
+        title : String,
+        pages : i32,
+    }
+
+    #[test]
+    fn test_struct () {
+        // The bytes of the serde-example investigation.
+        let book = Book { title: "foo".to_string(), pages: 123 };
+        assert_eq!(to_bson (&book).unwrap(), vec![
+            31, 0, 0, 0, 2, 116, 105, 116, 108, 101, 0, 4, 0, 0, 0, 102, 111, 111, 0, 16, 112, 97,
+            103, 101, 115, 0, 123, 0, 0, 0, 0]);
+    }
+
+    #[derive(Serialize)]
+    struct Numbers {
+        i8 : i8,
+        u16 : u16,
+        u32 : u32,
+        u64 : u64,
+        f32 : f32,
+        c : char,
+        none : Option<i32>,
+        some : Option<i32>,
+        unit : (),
+    }
+
+    #[test]
+    fn test_types () {
+        let numbers = Numbers { i8: -1, u16: 2, u32: 3, u64: 4, f32: 0.5, c: 'x', none: None, some: Some(5), unit: () };
+        let mut bb = BSONBuilder::new();
+        bb.append_int32 ("i8", -1).unwrap();
+        bb.append_int32 ("u16", 2).unwrap();
+        bb.append_int64 ("u32", 3).unwrap();
+        bb.append_int64 ("u64", 4).unwrap();
+        bb.append_double ("f32", 0.5).unwrap();
+        bb.append_string ("c", "x").unwrap();
+        bb.append_null ("none").unwrap();
+        bb.append_int32 ("some", 5).unwrap();
+        bb.append_null ("unit").unwrap();
+        assert_eq!(to_bson (&numbers), Ok(bb.build().unwrap()));
+    }
+
+    #[derive(Serialize)]
+    enum Shape {
+        Empty,
+        Circle (f64),
+        Point (i32, i32),
+        Rect { w : i32, h : i32 },
+    }
+
+    #[test]
+    fn test_enums () {
+        let shapes = vec![Shape::Empty, Shape::Circle (1.0), Shape::Point (1, 2), Shape::Rect { w: 3, h: 4 }];
+        let mut map = BTreeMap::new();
+        map.insert("shapes", shapes);
+        let mut bb = BSONBuilder::new();
+        bb.start_array ("shapes").unwrap();
+        bb.append_string ("", "Empty").unwrap();
+        bb.start_document ("").unwrap();
+        bb.append_double ("Circle", 1.0).unwrap();
+        bb.end_document ().unwrap();
+        bb.start_document ("").unwrap();
+        bb.start_array ("Point").unwrap();
+        bb.append_int32 ("", 1).unwrap();
+        bb.append_int32 ("", 2).unwrap();
+        bb.end_array ().unwrap();
+        bb.end_document ().unwrap();
+        bb.start_document ("").unwrap();
+        bb.start_document ("Rect").unwrap();
+        bb.append_int32 ("w", 3).unwrap();
+        bb.append_int32 ("h", 4).unwrap();
+        bb.end_document ().unwrap();
+        bb.end_document ().unwrap();
+        bb.end_array ().unwrap();
+        assert_eq!(to_bson (&map), Ok(bb.build().unwrap()));
+
+        // A variant with fields can be the outermost document.
+        let mut bb = BSONBuilder::new();
+        bb.start_document ("Rect").unwrap();
+        bb.append_int32 ("w", 3).unwrap();
+        bb.append_int32 ("h", 4).unwrap();
+        bb.end_document ().unwrap();
+        assert_eq!(to_bson (&Shape::Rect { w: 3, h: 4 }), Ok(bb.build().unwrap()));
+    }
+
+    #[test]
+    fn test_to_builder () {
+        let mut bb = BSONBuilder::new();
+        bb.append_int32 ("_id", 1).unwrap();
+        to_builder (&mut bb, &Book { title: "foo".to_string(), pages: 123 }).unwrap();
+        let mut want = BSONBuilder::new();
+        want.append_int32 ("_id", 1).unwrap();
+        want.append_string ("title", "foo").unwrap();
+        want.append_int32 ("pages", 123).unwrap();
+        assert_eq!(bb.build(), want.build());
+    }
+
+    #[test]
+    fn test_errors () {
+        assert_eq!(to_bson (&1), Err(SerError::NotADocument));
+        assert_eq!(to_bson (&vec![1]), Err(SerError::NotADocument));
+        assert_eq!(to_bson (&Shape::Empty), Err(SerError::NotADocument));
+        let mut map = BTreeMap::new();
+        map.insert(true, 1);
+        assert_eq!(to_bson (&map), Err(SerError::InvalidKey));
+        let mut map = BTreeMap::new();
+        map.insert("big", u64::MAX);
+        assert_eq!(to_bson (&map), Err(SerError::UnsignedTooLarge (u64::MAX)));
+        let mut map = BTreeMap::new();
+        map.insert("a\0", 1);
+        assert_eq!(to_bson (&map), Err(SerError::Build (BuildError::ContainsNul ("a\0".to_string()))));
+        // Integer keys are written in decimal.
+        let mut map = BTreeMap::new();
+        map.insert(7, "x");
+        let mut bb = BSONBuilder::new();
+        bb.append_string ("7", "x").unwrap();
+        assert_eq!(to_bson (&map), Ok(bb.build().unwrap()));
+    }
+}
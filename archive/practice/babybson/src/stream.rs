@@ -0,0 +1,284 @@
+// stream reads and writes sequences of BSON documents, like the .bson files written by mongodump, one document at a
+// time. Only the current document is held in memory, so files can be far larger than memory.
+use crate::builder::{BSONBuilder, BuildError};
+use crate::reader::{BsonRef, ReadError};
+use std::fmt;
+use std::io::{self, Read, Write};
+
+// DEFAULT_MAX_STREAM_DOCUMENT_SIZE is the default limit of a document in a stream. MongoDB documents are at most
+// 16 MiB, and mongodump output adds at most 16 KiB of overhead.
+pub const DEFAULT_MAX_STREAM_DOCUMENT_SIZE : usize = 16 * 1024 * 1024 + 16 * 1024;
+
+#[derive(Debug)]
+pub enum StreamErrorKind {
+    Io (io::Error),
+    // The stream ends part way through a document.
+    Truncated,
+    // A document length is less than 5.
+    InvalidLength (i32),
+    // A document length exceeds the maximum size. The value is the length.
+    TooLarge (usize),
+    // The document is not valid. The offset of the error is from the start of the document.
+    Read (ReadError),
+    Build (BuildError),
+}
+
+#[derive(Debug)]
+pub struct StreamError {
+    // offset is the position in the stream of the start of the document.
+    pub offset : u64,
+    pub kind : StreamErrorKind,
+}
+
+impl fmt::Display for StreamError {
+    fn fmt (&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "document at offset {}: ", self.offset)?;
+        match &self.kind {
+            StreamErrorKind::Io (e) => write!(f, "{}", e),
+            StreamErrorKind::Truncated => write!(f, "unexpected end of stream"),
+            StreamErrorKind::InvalidLength (len) => write!(f, "invalid document length {}", len),
+            StreamErrorKind::TooLarge (len) => write!(f, "document length {} exceeds the maximum", len),
+            StreamErrorKind::Read (e) => write!(f, "{}", e),
+            StreamErrorKind::Build (e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for StreamError {
+    fn source (&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            StreamErrorKind::Io (e) => Some(e),
+            StreamErrorKind::Read (e) => Some(e),
+            StreamErrorKind::Build (e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+// BsonStreamReader reads concatenated documents. Wrap unbuffered readers, like a `File`, in a `BufReader`.
+pub struct BsonStreamReader<R> {
+    reader : R,
+    max_size : usize,
+    // buf holds the current document. It is reused, so it grows to the size of the largest document.
+    buf : Vec<u8>,
+    offset : u64,
+    failed : bool,
+}
+
+impl<R : Read> BsonStreamReader<R> {
+    pub fn new (reader : R) -> BsonStreamReader<R> {
+        BsonStreamReader::with_max_size (reader, DEFAULT_MAX_STREAM_DOCUMENT_SIZE)
+    }
+
+    // with_max_size returns a reader that rejects documents longer than `max_size` bytes before reading them.
+    pub fn with_max_size (reader : R, max_size : usize) -> BsonStreamReader<R> {
+        BsonStreamReader { reader, max_size, buf: Vec::new(), offset: 0, failed: false }
+    }
+
+    // offset returns the position in the stream of the next document.
+    pub fn offset (&self) -> u64 {
+        self.offset
+    }
+
+    pub fn into_inner (self) -> R {
+        self.reader
+    }
+
+    // next_document reads the next document, or returns None at the end of the stream. The document borrows the
+    // reader's buffer, so it must be dropped before the next call. After an error, the position in the stream is
+    // unknown, and next_document returns None.
+    pub fn next_document (&mut self) -> Result<Option<BsonRef<'_>>, StreamError> {
+        if self.failed {
+            return Ok(None);
+        }
+        match self.read_document () {
+            Ok(false) => Ok(None),
+            Ok(true) => {
+                let doc = BsonRef::new (&self.buf).expect("document length and terminator were checked");
+                self.offset += self.buf.len() as u64;
+                Ok(Some(doc))
+            }
+            Err(kind) => {
+                self.failed = true;
+                Err(StreamError { offset: self.offset, kind })
+            }
+        }
+    }
+
+    // read_document reads the next document into `buf`. It returns false if the stream ends before it.
+    fn read_document (&mut self) -> Result<bool, StreamErrorKind> {
+        let mut len_bytes = [0u8; 4];
+        match read_full (&mut self.reader, &mut len_bytes).map_err(StreamErrorKind::Io)? {
+            0 => return Ok(false),
+            4 => {}
+            _ => return Err(StreamErrorKind::Truncated),
+        }
+        let len = i32::from_le_bytes(len_bytes);
+        if len < 5 {
+            return Err(StreamErrorKind::InvalidLength (len));
+        }
+        if len as usize > self.max_size {
+            return Err(StreamErrorKind::TooLarge (len as usize));
+        }
+        self.buf.clear();
+        self.buf.extend_from_slice(&len_bytes);
+        self.buf.resize(len as usize, 0);
+        if read_full (&mut self.reader, &mut self.buf[4..]).map_err(StreamErrorKind::Io)? != len as usize - 4 {
+            return Err(StreamErrorKind::Truncated);
+        }
+        // Check the terminator now, so a bad length is reported here rather than by the next document.
+        BsonRef::new (&self.buf).map_err(StreamErrorKind::Read)?;
+        Ok(true)
+    }
+}
+
+// The iterator returns copies of the documents. Use `next_document` to avoid the copy.
+impl<R : Read> Iterator for BsonStreamReader<R> {
+    type Item = Result<Vec<u8>, StreamError>;
+
+    fn next (&mut self) -> Option<Self::Item> {
+        self.next_document().map(|doc| doc.map(|doc| doc.as_bytes().to_vec())).transpose()
+    }
+}
+
+// read_full reads until `buf` is full or the reader ends, and returns the number of bytes read.
+fn read_full<R : Read> (reader : &mut R, buf : &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match reader.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(read) => n += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+// BsonStreamWriter writes concatenated documents. Wrap unbuffered writers, like a `File`, in a `BufWriter`.
+pub struct BsonStreamWriter<W> {
+    writer : W,
+    offset : u64,
+}
+
+impl<W : Write> BsonStreamWriter<W> {
+    pub fn new (writer : W) -> BsonStreamWriter<W> {
+        BsonStreamWriter { writer, offset: 0 }
+    }
+
+    // offset returns the number of bytes written.
+    pub fn offset (&self) -> u64 {
+        self.offset
+    }
+
+    // write_document writes one document. Its length and terminator are checked first, so a bad document cannot
+    // corrupt the rest of the stream.
+    pub fn write_document (&mut self, doc : &[u8]) -> Result<(), StreamError> {
+        let offset = self.offset;
+        let err = |kind| StreamError { offset, kind };
+        BsonRef::new (doc).map_err(|e| err (StreamErrorKind::Read (e)))?;
+        self.writer.write_all(doc).map_err(|e| err (StreamErrorKind::Io (e)))?;
+        self.offset += doc.len() as u64;
+        Ok(())
+    }
+
+    // write_builder builds the document in `bb` and writes it. The builder is reset, so it can be reused.
+    pub fn write_builder (&mut self, bb : &mut BSONBuilder) -> Result<(), StreamError> {
+        let doc = bb.build().map_err(|e| StreamError { offset: self.offset, kind: StreamErrorKind::Build (e) })?;
+        self.write_document (&doc)
+    }
+
+    pub fn flush (&mut self) -> Result<(), StreamError> {
+        self.writer.flush().map_err(|e| StreamError { offset: self.offset, kind: StreamErrorKind::Io (e) })
+    }
+
+    pub fn into_inner (self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+fn int_document (key : &str, value : i32) -> Vec<u8> {
+    let mut bb = BSONBuilder::new();
+    bb.append_int32 (key, value).unwrap();
+    bb.build().unwrap()
+}
+
+#[test]
+fn test_stream_round_trip () {
+    let mut writer = BsonStreamWriter::new (Vec::new());
+    let mut bb = BSONBuilder::new();
+    for i in 0..3 {
+        bb.append_int32 ("i", i).unwrap();
+        bb.append_string ("s", &"x".repeat(i as usize)).unwrap();
+        writer.write_builder (&mut bb).unwrap();
+    }
+    writer.write_document (&BSONBuilder::new().build().unwrap()).unwrap();
+    writer.flush().unwrap();
+    // Each document is 20 bytes plus the length of the string.
+    assert_eq!(writer.offset(), 20 + 21 + 22 + 5);
+    let data = writer.into_inner();
+
+    let mut reader = BsonStreamReader::new (&data[..]);
+    for i in 0..3 {
+        assert_eq!(reader.offset(), [0, 20, 41][i as usize]);
+        let doc = reader.next_document().unwrap().unwrap();
+        assert_eq!(doc.get ("i").unwrap().unwrap().as_i32(), Some(i));
+    }
+    assert!(reader.next_document().unwrap().unwrap().is_empty());
+    assert!(reader.next_document().unwrap().is_none());
+    assert_eq!(reader.offset(), data.len() as u64);
+}
+
+#[test]
+fn test_stream_iterator () {
+    let docs = [int_document ("a", 1), int_document ("b", 2)];
+    let data = docs.concat();
+    let read : Vec<Vec<u8>> = BsonStreamReader::new (&data[..]).collect::<Result<_, _>>().unwrap();
+    assert_eq!(read, docs);
+    assert_eq!(BsonStreamReader::new (&[][..]).count(), 0);
+}
+
+#[test]
+fn test_stream_errors () {
+    let doc = int_document ("a", 1);
+    let cases : Vec<(Vec<u8>, usize, u64, &str)> = vec![
+        // The stream ends in the length of the second document.
+        ([&doc[..], &doc[..2]].concat(), 1, 12, "unexpected end of stream"),
+        // The stream ends in the second document.
+        ([&doc[..], &doc[..11]].concat(), 1, 12, "unexpected end of stream"),
+        (vec![4, 0, 0, 0, 0], 0, 0, "invalid document length 4"),
+        (vec![0xff, 0xff, 0xff, 0xff], 0, 0, "invalid document length -1"),
+        // The length is larger than the maximum, so nothing past it is read.
+        ([&[0, 0, 0, 1][..], &doc[..]].concat(), 0, 0, "document length 16777216 exceeds the maximum"),
+        // The length is one byte short, so the document does not end with a NULL byte.
+        ([&[11, 0, 0, 0][..], &int_document ("a", -1)[4..]].concat(), 0, 0, "offset 10: document does not end with a NULL byte"),
+    ];
+    for (data, good, offset, message) in cases {
+        let mut reader = BsonStreamReader::with_max_size (&data[..], 1024);
+        for _ in 0..good {
+            reader.next_document().unwrap().unwrap();
+        }
+        let err = reader.next_document().unwrap_err();
+        assert_eq!(err.offset, offset, "{:?}", data);
+        assert_eq!(err.to_string(), format!("document at offset {}: {}", offset, message));
+        // The reader stops after an error.
+        assert!(reader.next_document().unwrap().is_none());
+    }
+}
+
+#[test]
+fn test_stream_writer_errors () {
+    let mut writer = BsonStreamWriter::new (Vec::new());
+    writer.write_document (&int_document ("a", 1)).unwrap();
+    let err = writer.write_document (&[6, 0, 0, 0, 0]).unwrap_err();
+    assert_eq!(err.offset, 12);
+    assert!(matches!(err.kind, StreamErrorKind::Read (_)));
+
+    let mut bb = BSONBuilder::new();
+    bb.start_document ("a").unwrap();
+    let err = writer.write_builder (&mut bb).unwrap_err();
+    assert!(matches!(err.kind, StreamErrorKind::Build (BuildError::UnclosedDocument (1))));
+    // Nothing was written for the failed documents.
+    assert_eq!(writer.into_inner(), int_document ("a", 1));
+}
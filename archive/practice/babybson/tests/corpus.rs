@@ -0,0 +1,148 @@
+// corpus runs the MongoDB BSON corpus, copied unchanged into tests/corpus. Every valid case must read, convert to the
+// expected Extended JSON and build back to the same bytes; every decodeErrors case must be rejected by the reader and
+// every parseErrors case by from_ejson. The report lists the results for each file, counting cases that need a
+// deprecated type BSONBuilder does not write as unsupported. The corpus is not checked in. See tests/corpus/README.md.
+use babybson::*;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+#[derive(Default)]
+struct Tally {
+    passed : usize,
+    unsupported : usize,
+    failed : Vec<String>,
+}
+
+enum Outcome {
+    Pass,
+    Unsupported,
+}
+
+fn hex_decode (s : &str) -> Vec<u8> {
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).expect("corpus hex")).collect()
+}
+
+fn hex_encode (b : &[u8]) -> String {
+    b.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+// read reads and validates a whole document, as a driver would before using it.
+fn read (data : &[u8]) -> Result<BsonRef<'_>, ReadError> {
+    let doc = BsonRef::new(data)?;
+    doc.validate()?;
+    Ok(doc)
+}
+
+// same_json compares two JSON texts by value, so whitespace and escapes do not matter.
+fn same_json (a : &str, b : &str) -> bool {
+    match (serde_json::from_str::<Value>(a), serde_json::from_str::<Value>(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn check_ejson (doc : BsonRef, mode : EjsonMode, want : &str, what : &str) -> Result<(), String> {
+    let got = to_ejson (doc, mode).map_err(|e| format!("{}: {}", what, e))?;
+    if !same_json (&got, want) {
+        return Err(format!("{}: got {} want {}", what, got, want));
+    }
+    Ok(())
+}
+
+fn run_valid (case : &Value) -> Result<Outcome, String> {
+    let canonical_bson = hex_decode (case["canonical_bson"].as_str().unwrap());
+    let canonical_extjson = case["canonical_extjson"].as_str().unwrap();
+    let relaxed_extjson = case["relaxed_extjson"].as_str();
+    let lossy = case["lossy"].as_bool().unwrap_or(false);
+
+    let doc = read (&canonical_bson).map_err(|e| format!("reading canonical_bson: {}", e))?;
+    check_ejson (doc, EjsonMode::Canonical, canonical_extjson, "canonical_bson to canonical_extjson")?;
+    if let Some(relaxed) = relaxed_extjson {
+        check_ejson (doc, EjsonMode::Relaxed, relaxed, "canonical_bson to relaxed_extjson")?;
+    }
+    if let Some(degenerate) = case["degenerate_bson"].as_str() {
+        let degenerate = hex_decode (degenerate);
+        let doc = read (&degenerate).map_err(|e| format!("reading degenerate_bson: {}", e))?;
+        check_ejson (doc, EjsonMode::Canonical, canonical_extjson, "degenerate_bson to canonical_extjson")?;
+    }
+
+    let mut inputs = vec![("canonical_extjson", canonical_extjson)];
+    if let Some(degenerate) = case["degenerate_extjson"].as_str() {
+        inputs.push(("degenerate_extjson", degenerate));
+    }
+    for (what, text) in inputs {
+        match from_ejson (text) {
+            Ok(bytes) if !lossy && bytes != canonical_bson => {
+                return Err(format!("{} built {}", what, hex_encode (&bytes)));
+            }
+            Ok(_) => {}
+            Err(EjsonError{kind : EjsonErrorKind::Unsupported (_), ..}) => return Ok(Outcome::Unsupported),
+            Err(e) => return Err(format!("building {}: {}", what, e)),
+        }
+    }
+    if let Some(relaxed) = relaxed_extjson {
+        let bytes = from_ejson (relaxed).map_err(|e| format!("building relaxed_extjson: {}", e))?;
+        let doc = read (&bytes).map_err(|e| format!("reading relaxed_extjson: {}", e))?;
+        check_ejson (doc, EjsonMode::Relaxed, relaxed, "relaxed_extjson round trip")?;
+    }
+    Ok(Outcome::Pass)
+}
+
+fn run_file (file : &Value, tally : &mut Tally) {
+    for case in file["valid"].as_array().into_iter().flatten() {
+        let description = case["description"].as_str().unwrap();
+        match run_valid (case) {
+            Ok(Outcome::Pass) => tally.passed += 1,
+            Ok(Outcome::Unsupported) => tally.unsupported += 1,
+            Err(e) => tally.failed.push(format!("{}: {}", description, e)),
+        }
+    }
+    for case in file["decodeErrors"].as_array().into_iter().flatten() {
+        let description = case["description"].as_str().unwrap();
+        match read (&hex_decode (case["bson"].as_str().unwrap())) {
+            Ok(_) => tally.failed.push(format!("{}: decode error not detected", description)),
+            Err(_) => tally.passed += 1,
+        }
+    }
+    for case in file["parseErrors"].as_array().into_iter().flatten() {
+        let description = case["description"].as_str().unwrap();
+        let mut text = case["string"].as_str().unwrap().to_string();
+        if file["bson_type"] == "0x13" {
+            // Decimal128 parse errors are the string of a $numberDecimal.
+            text = format!("{{\"{}\" : {{\"$numberDecimal\" : {}}}}}", file["test_key"].as_str().unwrap(), Value::from(text));
+        }
+        match from_ejson (&text) {
+            Ok(bytes) => tally.failed.push(format!("{}: parse error not detected, built {}", description, hex_encode (&bytes))),
+            Err(_) => tally.passed += 1,
+        }
+    }
+}
+
+#[test]
+#[ignore = "needs the BSON corpus in tests/corpus"]
+fn test_bson_corpus () {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let mut paths : Vec<_> = fs::read_dir(&dir).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no corpus files in {}. See tests/corpus/README.md", dir.display());
+
+    let mut report = String::new();
+    let mut failures = Vec::new();
+    for path in paths {
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        let file : Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let mut tally = Tally::default();
+        run_file (&file, &mut tally);
+        report.push_str(&format!(
+            "{:<24} {:>3} passed {:>3} failed {:>3} unsupported\n",
+            name, tally.passed, tally.failed.len(), tally.unsupported,
+        ));
+        failures.extend(tally.failed.into_iter().map(|f| format!("{}: {}", name, f)));
+    }
+    println!("{}", report);
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
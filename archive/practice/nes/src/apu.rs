@@ -0,0 +1,270 @@
+// Apu is the audio processing unit of the 2A03. See https://www.nesdev.org/wiki/APU
+//
+// The APU has two pulse channels, a triangle channel, a noise channel and a DMC (sample) channel, and a frame counter
+// that clocks their envelopes, length counters and sweeps. The channels are mixed into a mono PCM buffer at a
+// configurable sample rate. Sample output is disabled by default.
+pub mod dmc;
+pub mod envelope;
+pub mod noise;
+pub mod pulse;
+pub mod triangle;
+
+use crate::savestate::{StateReader, StateWriter};
+use dmc::Dmc;
+use noise::Noise;
+use pulse::Pulse;
+use triangle::Triangle;
+
+// CPU_CLOCK is the NTSC CPU clock rate in Hz.
+pub const CPU_CLOCK: u64 = 1_789_773;
+
+pub const STATUS: u16 = 0x4015;
+pub const FRAME_COUNTER: u16 = 0x4017;
+
+// Frame counter steps in CPU cycles. See https://www.nesdev.org/wiki/APU_Frame_Counter
+const STEP_1: u32 = 7457;
+const STEP_2: u32 = 14913;
+const STEP_3: u32 = 22371;
+const STEP_4: u32 = 29829;
+const FOUR_STEP_PERIOD: u32 = 29830;
+const STEP_5: u32 = 37281;
+const FIVE_STEP_PERIOD: u32 = 37282;
+
+pub struct Apu {
+    pub pulse1: Pulse,
+    pub pulse2: Pulse,
+    pub triangle: Triangle,
+    pub noise: Noise,
+    pub dmc: Dmc,
+    // cycles is the number of CPU cycles elapsed. Pulse and noise timers are clocked on every other cycle.
+    cycles: u64,
+    // frame_cycle is the position in the frame counter sequence, in CPU cycles.
+    frame_cycle: u32,
+    five_step: bool,
+    irq_inhibit: bool,
+    frame_irq: bool,
+    sample_rate: Option<u32>,
+    // sample_clock accumulates `sample_rate` every cycle. A sample is output each time it reaches CPU_CLOCK.
+    sample_clock: u64,
+    sample_sum: f32,
+    sample_count: u32,
+    samples: Vec<i16>,
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Apu {
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            triangle: Triangle::default(),
+            noise: Noise::new(),
+            dmc: Dmc::new(),
+            cycles: 0,
+            frame_cycle: 0,
+            five_step: false,
+            irq_inhibit: false,
+            frame_irq: false,
+            sample_rate: None,
+            sample_clock: 0,
+            sample_sum: 0.0,
+            sample_count: 0,
+            samples: Vec::new(),
+        }
+    }
+
+    // set_sample_rate enables sample output at `rate` Hz, or disables it. Pending samples are discarded.
+    pub fn set_sample_rate(&mut self, rate: Option<u32>) {
+        self.sample_rate = rate;
+        self.sample_clock = 0;
+        self.sample_sum = 0.0;
+        self.sample_count = 0;
+        self.samples.clear();
+    }
+
+    pub fn sample_rate(&self) -> Option<u32> {
+        self.sample_rate
+    }
+
+    // take_samples returns the samples output since the last call.
+    pub fn take_samples(&mut self) -> Vec<i16> {
+        std::mem::take(&mut self.samples)
+    }
+
+    // irq returns true while the frame counter or the DMC asserts the /IRQ line.
+    pub fn irq(&self) -> bool {
+        self.frame_irq || self.dmc.irq
+    }
+
+    // write_register handles writes to 0x4000-0x4013, 0x4015 and 0x4017.
+    pub fn write_register(&mut self, addr: u16, val: u8) {
+        let reg = addr & 0b11;
+        match addr {
+            0x4000..=0x4003 => self.pulse1.write(reg, val),
+            0x4004..=0x4007 => self.pulse2.write(reg, val),
+            0x4008..=0x400B => self.triangle.write(reg, val),
+            0x400C..=0x400F => self.noise.write(reg, val),
+            0x4010..=0x4013 => self.dmc.write(reg, val),
+            STATUS => {
+                self.pulse1.length.set_enabled(val & 0b0000_0001 != 0);
+                self.pulse2.length.set_enabled(val & 0b0000_0010 != 0);
+                self.triangle.length.set_enabled(val & 0b0000_0100 != 0);
+                self.noise.length.set_enabled(val & 0b0000_1000 != 0);
+                self.dmc.set_enabled(val & 0b0001_0000 != 0);
+                self.dmc.irq = false;
+            }
+            FRAME_COUNTER => {
+                // The reset is delayed by 3 or 4 cycles on hardware. It is applied immediately here.
+                self.five_step = val & 0b1000_0000 != 0;
+                self.irq_inhibit = val & 0b0100_0000 != 0;
+                if self.irq_inhibit {
+                    self.frame_irq = false;
+                }
+                self.frame_cycle = 0;
+                if self.five_step {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            }
+            _ => panic!("Attempt to write to unmapped APU register {:04x}", addr),
+        }
+    }
+
+    // read_status reads 0x4015. Reading clears the frame IRQ flag.
+    pub fn read_status(&mut self) -> u8 {
+        let status = self.peek_status();
+        self.frame_irq = false;
+        status
+    }
+
+    // peek_status reads 0x4015 without side effects.
+    pub fn peek_status(&self) -> u8 {
+        (self.pulse1.length.active() as u8)
+            | (self.pulse2.length.active() as u8) << 1
+            | (self.triangle.length.active() as u8) << 2
+            | (self.noise.length.active() as u8) << 3
+            | ((self.dmc.bytes_remaining > 0) as u8) << 4
+            | (self.frame_irq as u8) << 6
+            | (self.dmc.irq as u8) << 7
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.envelope.clock();
+        self.pulse2.envelope.clock();
+        self.triangle.clock_linear();
+        self.noise.envelope.clock();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse1.length.clock();
+        self.pulse1.clock_sweep();
+        self.pulse2.length.clock();
+        self.pulse2.clock_sweep();
+        self.triangle.length.clock();
+        self.noise.length.clock();
+    }
+
+    fn clock_frame_counter(&mut self) {
+        self.frame_cycle += 1;
+        match (self.frame_cycle, self.five_step) {
+            (STEP_1, _) | (STEP_3, _) => self.clock_quarter_frame(),
+            (STEP_2, _) | (STEP_4, false) | (STEP_5, true) => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+                if self.frame_cycle == STEP_4 && !self.irq_inhibit {
+                    self.frame_irq = true;
+                }
+            }
+            (FOUR_STEP_PERIOD, false) | (FIVE_STEP_PERIOD, true) => self.frame_cycle = 0,
+            _ => {}
+        }
+    }
+
+    // tick advances the APU by `cycles` CPU cycles. `read` reads CPU memory for DMC samples.
+    pub fn tick(&mut self, cycles: u16, read: &mut dyn FnMut(u16) -> u8) {
+        for _ in 0..cycles {
+            self.clock_frame_counter();
+            if self.cycles % 2 == 1 {
+                self.pulse1.clock_timer();
+                self.pulse2.clock_timer();
+                self.noise.clock_timer();
+            }
+            self.triangle.clock_timer();
+            self.dmc.clock_timer(read);
+            self.cycles += 1;
+            if let Some(rate) = self.sample_rate {
+                self.sample(rate);
+            }
+        }
+    }
+
+    // sample averages the mixer output over each output sample period.
+    fn sample(&mut self, rate: u32) {
+        self.sample_sum += self.mix();
+        self.sample_count += 1;
+        self.sample_clock += rate as u64;
+        if self.sample_clock >= CPU_CLOCK {
+            self.sample_clock -= CPU_CLOCK;
+            let mean = self.sample_sum / self.sample_count as f32;
+            self.samples.push((mean * i16::MAX as f32) as i16);
+            self.sample_sum = 0.0;
+            self.sample_count = 0;
+        }
+    }
+
+    // mix returns the output level in [0, 1] using the nonlinear mixer formulas.
+    // See https://www.nesdev.org/wiki/APU_Mixer
+    pub fn mix(&self) -> f32 {
+        let pulse = (self.pulse1.output() + self.pulse2.output()) as f32;
+        let pulse_out = if pulse == 0.0 {
+            0.0
+        } else {
+            95.88 / (8128.0 / pulse + 100.0)
+        };
+        let tnd = self.triangle.output() as f32 / 8227.0
+            + self.noise.output() as f32 / 12241.0
+            + self.dmc.output() as f32 / 22638.0;
+        let tnd_out = if tnd == 0.0 {
+            0.0
+        } else {
+            159.79 / (1.0 / tnd + 100.0)
+        };
+        pulse_out + tnd_out
+    }
+
+    // save_state writes the channels and the frame counter. The sample rate and pending samples are not saved.
+    pub fn save_state(&self, w: &mut StateWriter) {
+        self.pulse1.save_state(w);
+        self.pulse2.save_state(w);
+        self.triangle.save_state(w);
+        self.noise.save_state(w);
+        self.dmc.save_state(w);
+        w.u64(self.cycles);
+        w.u32(self.frame_cycle);
+        w.bool(self.five_step);
+        w.bool(self.irq_inhibit);
+        w.bool(self.frame_irq);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.pulse1.load_state(r)?;
+        self.pulse2.load_state(r)?;
+        self.triangle.load_state(r)?;
+        self.noise.load_state(r)?;
+        self.dmc.load_state(r)?;
+        self.cycles = r.u64()?;
+        self.frame_cycle = r.u32()?;
+        self.five_step = r.bool()?;
+        self.irq_inhibit = r.bool()?;
+        self.frame_irq = r.bool()?;
+        Ok(())
+    }
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,177 @@
+// Dmc plays delta-encoded 1-bit samples from CPU memory. See https://www.nesdev.org/wiki/APU_DMC
+//
+// The CPU stalls while the DMC fetches a sample byte are not emulated.
+use crate::savestate::{StateReader, StateWriter};
+
+// RATE_TABLE is in CPU cycles (NTSC).
+const RATE_TABLE: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+
+pub struct Dmc {
+    irq_enabled: bool,
+    pub irq: bool,
+    looping: bool,
+    rate: u16,
+    timer: u16,
+    // level is the 7-bit output level.
+    level: u8,
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    pub bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+    shift: u8,
+    bits_remaining: u8,
+    silence: bool,
+}
+
+impl Dmc {
+    pub fn new() -> Self {
+        Dmc {
+            irq_enabled: false,
+            irq: false,
+            looping: false,
+            rate: RATE_TABLE[0],
+            timer: 0,
+            level: 0,
+            sample_address: 0xC000,
+            sample_length: 1,
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift: 0,
+            bits_remaining: 8,
+            silence: true,
+        }
+    }
+
+    // write handles the registers at 0x4010-0x4013. `reg` is 0-3.
+    pub fn write(&mut self, reg: u16, val: u8) {
+        match reg {
+            0 => {
+                self.irq_enabled = val & 0b1000_0000 != 0;
+                self.looping = val & 0b0100_0000 != 0;
+                self.rate = RATE_TABLE[(val & 0b1111) as usize];
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+            }
+            1 => self.level = val & 0b0111_1111,
+            2 => self.sample_address = 0xC000 | ((val as u16) << 6),
+            _ => self.sample_length = ((val as u16) << 4) | 1,
+        }
+    }
+
+    // set_enabled is controlled by 0x4015. Enabling restarts the sample only if it has finished.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    // fill_buffer fetches the next sample byte with `read` if the buffer is empty.
+    fn fill_buffer(&mut self, read: &mut dyn FnMut(u16) -> u8) {
+        if self.sample_buffer.is_some() || self.bytes_remaining == 0 {
+            return;
+        }
+        self.sample_buffer = Some(read(self.current_address));
+        // The address wraps around to 0x8000.
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    // clock_timer is called every CPU cycle.
+    pub fn clock_timer(&mut self, read: &mut dyn FnMut(u16) -> u8) {
+        self.fill_buffer(read);
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.rate - 1;
+        if !self.silence {
+            if self.shift & 1 == 1 {
+                if self.level <= 125 {
+                    self.level += 2;
+                }
+            } else if self.level >= 2 {
+                self.level -= 2;
+            }
+        }
+        self.shift >>= 1;
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(byte) => {
+                    self.silence = false;
+                    self.shift = byte;
+                }
+                None => self.silence = true,
+            }
+            self.fill_buffer(read);
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        self.level
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.irq_enabled);
+        w.bool(self.irq);
+        w.bool(self.looping);
+        w.u16(self.rate);
+        w.u16(self.timer);
+        w.u8(self.level);
+        w.u16(self.sample_address);
+        w.u16(self.sample_length);
+        w.u16(self.current_address);
+        w.u16(self.bytes_remaining);
+        w.bool(self.sample_buffer.is_some());
+        w.u8(self.sample_buffer.unwrap_or(0));
+        w.u8(self.shift);
+        w.u8(self.bits_remaining);
+        w.bool(self.silence);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.irq_enabled = r.bool()?;
+        self.irq = r.bool()?;
+        self.looping = r.bool()?;
+        self.rate = r.u16()?;
+        self.timer = r.u16()?;
+        self.level = r.u8()?;
+        self.sample_address = r.u16()?;
+        self.sample_length = r.u16()?;
+        self.current_address = r.u16()?;
+        self.bytes_remaining = r.u16()?;
+        let has_buffer = r.bool()?;
+        let buffer = r.u8()?;
+        self.sample_buffer = if has_buffer { Some(buffer) } else { None };
+        self.shift = r.u8()?;
+        self.bits_remaining = r.u8()?;
+        self.silence = r.bool()?;
+        Ok(())
+    }
+}
+
+impl Default for Dmc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,132 @@
+// Units shared by several channels. See https://www.nesdev.org/wiki/APU_Envelope and
+// https://www.nesdev.org/wiki/APU_Length_Counter
+use crate::savestate::{StateReader, StateWriter};
+
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+// Envelope produces a decaying volume, or a constant volume.
+#[derive(Default)]
+pub struct Envelope {
+    start: bool,
+    // looping restarts the decay at 15. It shares a bit with the length counter halt flag.
+    looping: bool,
+    constant: bool,
+    // period is the constant volume, or the period of the decay divider.
+    period: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    // write handles the --LC VVVV bits of the channel's first register.
+    pub fn write(&mut self, val: u8) {
+        self.looping = val & 0b0010_0000 != 0;
+        self.constant = val & 0b0001_0000 != 0;
+        self.period = val & 0b0000_1111;
+    }
+
+    pub fn restart(&mut self) {
+        self.start = true;
+    }
+
+    // clock is called on quarter frames.
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.period;
+        } else if self.divider == 0 {
+            self.divider = self.period;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub fn volume(&self) -> u8 {
+        if self.constant {
+            self.period
+        } else {
+            self.decay
+        }
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.start);
+        w.bool(self.looping);
+        w.bool(self.constant);
+        w.u8(self.period);
+        w.u8(self.divider);
+        w.u8(self.decay);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.start = r.bool()?;
+        self.looping = r.bool()?;
+        self.constant = r.bool()?;
+        self.period = r.u8()?;
+        self.divider = r.u8()?;
+        self.decay = r.u8()?;
+        Ok(())
+    }
+}
+
+// LengthCounter silences a channel after a number of half frames.
+#[derive(Default)]
+pub struct LengthCounter {
+    enabled: bool,
+    halt: bool,
+    pub counter: u8,
+}
+
+impl LengthCounter {
+    // set_enabled is controlled by 0x4015. Disabling clears the counter.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    pub fn set_halt(&mut self, halt: bool) {
+        self.halt = halt;
+    }
+
+    // load handles the LLLL L--- bits of the channel's last register.
+    pub fn load(&mut self, val: u8) {
+        if self.enabled {
+            self.counter = LENGTH_TABLE[(val >> 3) as usize];
+        }
+    }
+
+    // clock is called on half frames.
+    pub fn clock(&mut self) {
+        if !self.halt && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.counter > 0
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.enabled);
+        w.bool(self.halt);
+        w.u8(self.counter);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.enabled = r.bool()?;
+        self.halt = r.bool()?;
+        self.counter = r.u8()?;
+        Ok(())
+    }
+}
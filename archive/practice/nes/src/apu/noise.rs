@@ -0,0 +1,95 @@
+// Noise is a pseudo-random noise channel. See https://www.nesdev.org/wiki/APU_Noise
+use super::envelope::{Envelope, LengthCounter};
+use crate::savestate::{StateReader, StateWriter};
+
+// PERIOD_TABLE is in CPU cycles (NTSC).
+const PERIOD_TABLE: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+
+pub struct Noise {
+    // shift is a 15-bit linear feedback shift register.
+    shift: u16,
+    // mode uses bit 6 for feedback instead of bit 1, which gives a short, metallic sequence.
+    mode: bool,
+    // period is in APU cycles.
+    period: u16,
+    timer: u16,
+    pub envelope: Envelope,
+    pub length: LengthCounter,
+}
+
+impl Noise {
+    pub fn new() -> Self {
+        Noise {
+            shift: 1,
+            mode: false,
+            period: PERIOD_TABLE[0] / 2,
+            timer: 0,
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+        }
+    }
+
+    // write handles the registers at 0x400C-0x400F. `reg` is 0-3.
+    pub fn write(&mut self, reg: u16, val: u8) {
+        match reg {
+            0 => {
+                self.length.set_halt(val & 0b0010_0000 != 0);
+                self.envelope.write(val);
+            }
+            1 => { /* Unused. */ }
+            2 => {
+                self.mode = val & 0b1000_0000 != 0;
+                self.period = PERIOD_TABLE[(val & 0b1111) as usize] / 2;
+            }
+            _ => {
+                self.length.load(val);
+                self.envelope.restart();
+            }
+        }
+    }
+
+    // clock_timer is called every APU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period - 1;
+            let tap = if self.mode { 6 } else { 1 };
+            let feedback = (self.shift ^ (self.shift >> tap)) & 1;
+            self.shift = (self.shift >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length.active() || self.shift & 1 == 1 {
+            return 0;
+        }
+        self.envelope.volume()
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.u16(self.shift);
+        w.bool(self.mode);
+        w.u16(self.period);
+        w.u16(self.timer);
+        self.envelope.save_state(w);
+        self.length.save_state(w);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.shift = r.u16()?;
+        self.mode = r.bool()?;
+        self.period = r.u16()?;
+        self.timer = r.u16()?;
+        self.envelope.load_state(r)?;
+        self.length.load_state(r)
+    }
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self::new()
+    }
+}
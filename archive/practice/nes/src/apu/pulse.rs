@@ -0,0 +1,143 @@
+// Pulse is a square wave channel with a sweep unit. See https://www.nesdev.org/wiki/APU_Pulse and
+// https://www.nesdev.org/wiki/APU_Sweep
+use super::envelope::{Envelope, LengthCounter};
+use crate::savestate::{StateReader, StateWriter};
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+#[derive(Default)]
+pub struct Pulse {
+    // ones_complement is set for pulse 1, whose sweep subtracts one more than pulse 2's.
+    ones_complement: bool,
+    duty: u8,
+    step: u8,
+    // period is the 11-bit timer period, clocked every APU cycle (2 CPU cycles).
+    period: u16,
+    timer: u16,
+    pub envelope: Envelope,
+    pub length: LengthCounter,
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_divider: u8,
+    sweep_reload: bool,
+}
+
+impl Pulse {
+    pub fn new(ones_complement: bool) -> Self {
+        Pulse {
+            ones_complement,
+            ..Default::default()
+        }
+    }
+
+    // write handles the registers at 0x4000-0x4003 (pulse 1) or 0x4004-0x4007 (pulse 2). `reg` is 0-3.
+    pub fn write(&mut self, reg: u16, val: u8) {
+        match reg {
+            0 => {
+                self.duty = val >> 6;
+                self.length.set_halt(val & 0b0010_0000 != 0);
+                self.envelope.write(val);
+            }
+            1 => {
+                self.sweep_enabled = val & 0b1000_0000 != 0;
+                self.sweep_period = (val >> 4) & 0b111;
+                self.sweep_negate = val & 0b0000_1000 != 0;
+                self.sweep_shift = val & 0b111;
+                self.sweep_reload = true;
+            }
+            2 => self.period = (self.period & 0xFF00) | val as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | ((val as u16 & 0b111) << 8);
+                self.length.load(val);
+                self.envelope.restart();
+                self.step = 0;
+            }
+        }
+    }
+
+    // clock_timer is called every APU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            self.step = (self.step + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn sweep_target(&self) -> u16 {
+        let change = self.period >> self.sweep_shift;
+        if self.sweep_negate {
+            let extra = self.ones_complement as u16;
+            self.period.saturating_sub(change + extra)
+        } else {
+            self.period + change
+        }
+    }
+
+    // muted is set while the period is out of range, even if the sweep is disabled.
+    fn muted(&self) -> bool {
+        self.period < 8 || self.sweep_target() > 0x7FF
+    }
+
+    // clock_sweep is called on half frames.
+    pub fn clock_sweep(&mut self) {
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.muted() {
+            self.period = self.sweep_target();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length.active()
+            || self.muted()
+            || DUTY_TABLE[self.duty as usize][self.step as usize] == 0
+        {
+            return 0;
+        }
+        self.envelope.volume()
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.duty);
+        w.u8(self.step);
+        w.u16(self.period);
+        w.u16(self.timer);
+        self.envelope.save_state(w);
+        self.length.save_state(w);
+        w.bool(self.sweep_enabled);
+        w.u8(self.sweep_period);
+        w.bool(self.sweep_negate);
+        w.u8(self.sweep_shift);
+        w.u8(self.sweep_divider);
+        w.bool(self.sweep_reload);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.duty = r.u8()?;
+        self.step = r.u8()?;
+        self.period = r.u16()?;
+        self.timer = r.u16()?;
+        self.envelope.load_state(r)?;
+        self.length.load_state(r)?;
+        self.sweep_enabled = r.bool()?;
+        self.sweep_period = r.u8()?;
+        self.sweep_negate = r.bool()?;
+        self.sweep_shift = r.u8()?;
+        self.sweep_divider = r.u8()?;
+        self.sweep_reload = r.bool()?;
+        Ok(())
+    }
+}
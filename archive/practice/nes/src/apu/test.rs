@@ -0,0 +1,334 @@
+use super::*;
+use crate::bus::Bus;
+use crate::cpu::CPU;
+use crate::headless::{self, Options};
+use crate::input::ScriptInput;
+use crate::machine::Nes;
+use crate::wav::test::decode;
+use std::path::PathBuf;
+
+// no_memory is a DMC reader for tests that do not play samples.
+fn no_memory(_addr: u16) -> u8 {
+    0
+}
+
+fn tick(apu: &mut Apu, cycles: u32) {
+    for _ in 0..cycles {
+        apu.tick(1, &mut no_memory);
+    }
+}
+
+#[test]
+fn test_status_length_counters() {
+    let mut apu = Apu::new();
+    // Length counters are not loaded while the channel is disabled.
+    apu.write_register(0x4003, 0b0000_1000);
+    assert_eq!(apu.peek_status(), 0);
+
+    apu.write_register(STATUS, 0b0000_1111);
+    apu.write_register(0x4003, 0b0000_1000);
+    apu.write_register(0x4007, 0b0000_1000);
+    apu.write_register(0x400B, 0b0000_1000);
+    apu.write_register(0x400F, 0b0000_1000);
+    assert_eq!(apu.peek_status(), 0b0000_1111);
+
+    // Disabling a channel clears its length counter.
+    apu.write_register(STATUS, 0b0000_1101);
+    assert_eq!(apu.peek_status(), 0b0000_1101);
+}
+
+#[test]
+fn test_length_counter() {
+    let mut apu = Apu::new();
+    apu.write_register(STATUS, 0b0000_0001);
+    // Index 1 loads 254, index 0 loads 10.
+    apu.write_register(0x4003, 0b0000_0000);
+    assert_eq!(apu.pulse1.length.counter, 10);
+    // The 4-step sequence clocks the length counters twice per 29830 cycles.
+    tick(&mut apu, FOUR_STEP_PERIOD * 4);
+    assert_eq!(apu.pulse1.length.counter, 2);
+    tick(&mut apu, FOUR_STEP_PERIOD);
+    assert_eq!(apu.peek_status() & 1, 0);
+
+    // The halt flag stops the counter.
+    apu.write_register(0x4000, 0b0010_0000);
+    apu.write_register(0x4003, 0b0000_1000);
+    tick(&mut apu, FOUR_STEP_PERIOD * 2);
+    assert_eq!(apu.pulse1.length.counter, 254);
+}
+
+#[test]
+fn test_frame_irq() {
+    let mut apu = Apu::new();
+    tick(&mut apu, STEP_4 - 1);
+    assert!(!apu.irq());
+    tick(&mut apu, 1);
+    assert!(apu.irq());
+    assert_eq!(apu.peek_status(), 0b0100_0000);
+    // Reading the status acknowledges the IRQ.
+    assert_eq!(apu.read_status(), 0b0100_0000);
+    assert!(!apu.irq());
+
+    // Setting the inhibit flag clears the IRQ and stops new ones.
+    tick(&mut apu, FOUR_STEP_PERIOD);
+    assert!(apu.irq());
+    apu.write_register(FRAME_COUNTER, 0b0100_0000);
+    assert!(!apu.irq());
+    tick(&mut apu, FOUR_STEP_PERIOD * 2);
+    assert!(!apu.irq());
+
+    // The 5-step sequence does not raise IRQs.
+    apu.write_register(FRAME_COUNTER, 0b1000_0000);
+    tick(&mut apu, FIVE_STEP_PERIOD * 2);
+    assert!(!apu.irq());
+}
+
+#[test]
+fn test_five_step_clocks_on_write() {
+    let mut apu = Apu::new();
+    apu.write_register(STATUS, 0b0000_0001);
+    apu.write_register(0x4003, 0b0000_0000);
+    apu.write_register(FRAME_COUNTER, 0b1000_0000);
+    assert_eq!(apu.pulse1.length.counter, 9);
+    // Writing 4-step mode does not clock.
+    apu.write_register(FRAME_COUNTER, 0);
+    assert_eq!(apu.pulse1.length.counter, 9);
+}
+
+// count_rising_edges counts the transitions from the low half to the high half of `samples`.
+// The output is never 0 because the triangle channel holds its last value.
+fn count_rising_edges(samples: &[i16]) -> usize {
+    let min = *samples.iter().min().unwrap();
+    let max = *samples.iter().max().unwrap();
+    let mid = (min as i32 + max as i32) / 2;
+    samples
+        .windows(2)
+        .filter(|w| (w[0] as i32) <= mid && (w[1] as i32) > mid)
+        .count()
+}
+
+#[test]
+fn test_pulse_frequency() {
+    let mut apu = Apu::new();
+    apu.set_sample_rate(Some(44100));
+    apu.write_register(STATUS, 0b0000_0001);
+    // 50% duty, halted length counter, constant volume 15.
+    apu.write_register(0x4000, 0b1011_1111);
+    // f = CPU_CLOCK / (16 * (period + 1)). A period of 253 is 440Hz.
+    apu.write_register(0x4002, 253);
+    apu.write_register(0x4003, 0b0000_1000);
+    tick(&mut apu, CPU_CLOCK as u32);
+
+    let samples = apu.take_samples();
+    assert_eq!(samples.len(), 44100);
+    let edges = count_rising_edges(&samples);
+    assert!((439..=441).contains(&edges), "{} periods", edges);
+    assert!(apu.take_samples().is_empty());
+}
+
+#[test]
+fn test_pulse_sweep_mutes() {
+    let mut apu = Apu::new();
+    apu.set_sample_rate(Some(44100));
+    apu.write_register(STATUS, 0b0000_0001);
+    apu.write_register(0x4000, 0b1011_1111);
+    // The sweep target (0x700 + 0x700) overflows, which mutes the channel even though the sweep is disabled.
+    apu.write_register(0x4001, 0b0000_0000);
+    apu.write_register(0x4002, 0x00);
+    apu.write_register(0x4003, 0b0000_1111);
+    tick(&mut apu, 10_000);
+    assert!(apu.take_samples().windows(2).all(|w| w[0] == w[1]));
+}
+
+#[test]
+fn test_triangle_needs_linear_counter() {
+    let mut apu = Apu::new();
+    apu.set_sample_rate(Some(44100));
+    apu.write_register(STATUS, 0b0000_0100);
+    apu.write_register(0x400A, 100);
+    // The linear counter is 0, so the sequencer does not advance and the output stays at 15.
+    apu.write_register(0x4008, 0b1000_0000);
+    apu.write_register(0x400B, 0b0000_1000);
+    tick(&mut apu, 10_000);
+    let samples = apu.take_samples();
+    assert!(samples.windows(2).all(|w| w[0] == w[1]));
+
+    apu.write_register(0x4008, 0b1111_1111);
+    apu.write_register(0x400B, 0b0000_1000);
+    tick(&mut apu, 10_000);
+    let samples = apu.take_samples();
+    assert!(samples.windows(2).any(|w| w[0] != w[1]));
+}
+
+// noise_bits clocks the noise LFSR `n` times and returns its output after each clock.
+fn noise_bits(mode: u8, n: usize) -> Vec<bool> {
+    let mut noise = Noise::new();
+    noise.length.set_enabled(true);
+    noise.write(0, 0b0011_1111);
+    // The shortest period is 4 CPU cycles, i.e. 2 APU cycles.
+    noise.write(2, mode << 7);
+    noise.write(3, 0b0000_1000);
+    (0..n)
+        .map(|_| {
+            noise.clock_timer();
+            noise.clock_timer();
+            noise.output() != 0
+        })
+        .collect()
+}
+
+// period returns the smallest of `candidates` after which `bits` repeats.
+fn period(bits: &[bool], candidates: &[usize]) -> Option<usize> {
+    candidates
+        .iter()
+        .copied()
+        .find(|&p| (0..bits.len() - p).all(|i| bits[i] == bits[i + p]))
+}
+
+#[test]
+fn test_noise_lfsr() {
+    // Mode 0 is a 32767-step sequence. 32767 = 7 * 31 * 151.
+    let bits = noise_bits(0, 32767 * 2);
+    assert_eq!(
+        period(&bits, &[7, 31, 151, 217, 1057, 4681, 32767]),
+        Some(32767)
+    );
+    // Mode 1 is a 93-step sequence from the power-on state.
+    let bits = noise_bits(1, 93 * 4);
+    assert_eq!(period(&bits, &[3, 31, 93]), Some(93));
+}
+
+#[test]
+fn test_dmc() {
+    let mut bus = Bus::new();
+    bus.load(0xC040, &[0xFF, 0x00]);
+    // IRQ enabled, fastest rate (54 cycles per bit).
+    bus.mem_write(0x4010, 0b1000_1111);
+    bus.mem_write(0x4011, 64);
+    // Sample at 0xC000 + 1 * 64, 1 * 16 + 1 = 17 bytes.
+    bus.mem_write(0x4012, 1);
+    bus.mem_write(0x4013, 1);
+    bus.mem_write(STATUS, 0b0001_0000);
+    assert_eq!(bus.mem_read(STATUS) & 0b0001_0000, 0b0001_0000);
+    assert_eq!(bus.apu.dmc.output(), 64);
+
+    // The first 8 bits are silent while the first byte is fetched, then each 1 bit adds 2.
+    for _ in 0..16 {
+        bus.tick(54);
+    }
+    assert_eq!(bus.apu.dmc.output(), 80);
+    // Each 0 bit subtracts 2.
+    for _ in 0..8 {
+        bus.tick(54);
+    }
+    assert_eq!(bus.apu.dmc.output(), 64);
+
+    // The IRQ is raised when the last byte is fetched and acknowledged by writing 0x4015.
+    for _ in 0..15 * 8 {
+        bus.tick(54);
+    }
+    assert!(bus.irq());
+    assert_eq!(bus.peek(STATUS), 0b1000_0000);
+    bus.mem_write(STATUS, 0);
+    assert!(!bus.irq());
+}
+
+#[test]
+fn test_mix() {
+    let mut apu = Apu::new();
+    // The triangle channel starts at its highest level.
+    let idle = apu.mix();
+    assert!(idle > 0.0);
+    apu.write_register(0x4011, 127);
+    let mix = apu.mix();
+    assert!(mix > idle && mix < 1.0);
+}
+
+#[test]
+fn test_save_state_round_trip() -> Result<(), String> {
+    let mut apu = Apu::new();
+    apu.write_register(STATUS, 0b0000_1111);
+    apu.write_register(0x4000, 0b1011_1111);
+    apu.write_register(0x4002, 100);
+    apu.write_register(0x4003, 0b0000_1000);
+    apu.write_register(0x400E, 0b1000_0011);
+    apu.write_register(0x400F, 0b0000_1000);
+    tick(&mut apu, 12345);
+
+    let mut w = StateWriter::new();
+    apu.save_state(&mut w);
+    let mut loaded = Apu::new();
+    loaded.load_state(&mut StateReader::new(&w.data)?)?;
+
+    apu.set_sample_rate(Some(48000));
+    loaded.set_sample_rate(Some(48000));
+    tick(&mut apu, 20000);
+    tick(&mut loaded, 20000);
+    assert_eq!(apu.take_samples(), loaded.take_samples());
+    Ok(())
+}
+
+// correlation returns the normalized cross-correlation of `a` and `b` over their common length. Reference captures
+// from hardware or other emulators are filtered differently, so they are compared by shape rather than exactly.
+fn correlation(a: &[i16], b: &[i16]) -> f64 {
+    let n = a.len().min(b.len());
+    let mean = |s: &[i16]| s[..n].iter().map(|&x| x as f64).sum::<f64>() / n as f64;
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let (mut ab, mut aa, mut bb) = (0.0, 0.0, 0.0);
+    for i in 0..n {
+        let (x, y) = (a[i] as f64 - mean_a, b[i] as f64 - mean_b);
+        ab += x * y;
+        aa += x * x;
+        bb += y * y;
+    }
+    ab / (aa * bb).sqrt()
+}
+
+// test_reference_captures runs each test_roms/audio/NAME.nes for the length of NAME.wav and compares the audio.
+// The files are not checked in, so it only runs with `cargo test -- --ignored`. See test_roms/README.md.
+#[test]
+#[ignore = "needs ROMs and captures in test_roms/audio"]
+fn test_reference_captures() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_roms/audio");
+    let entries = std::fs::read_dir(&dir)
+        .unwrap_or_else(|e| panic!("{}: {}. See test_roms/README.md", dir.display(), e));
+    let mut compared = 0;
+    for entry in entries {
+        let rom_path = entry.unwrap().path();
+        if rom_path.extension().is_none_or(|e| e != "nes") {
+            continue;
+        }
+        compared += 1;
+        let wav_path = rom_path.with_extension("wav");
+        let (rate, expected) = decode(&std::fs::read(&wav_path).unwrap()).unwrap();
+        let out_path = std::env::temp_dir().join(format!(
+            "nes_apu_test_{}_{}.wav",
+            std::process::id(),
+            rom_path.file_stem().unwrap().to_string_lossy()
+        ));
+
+        let mut cpu = CPU::new();
+        cpu.load_rom(crate::cartridge::Rom::new(&std::fs::read(&rom_path).unwrap()).unwrap());
+        cpu.reset();
+        // NTSC runs at about 60.1 frames per second.
+        let frames = expected.len() as u64 * 601 / (rate as u64 * 10) + 1;
+        let options = Options {
+            frames: Some(frames),
+            wav: Some(out_path.clone()),
+            sample_rate: Some(rate),
+            ..Default::default()
+        };
+        headless::run(
+            &mut Nes::new(cpu),
+            &mut ScriptInput::new(Vec::new()),
+            &options,
+        )
+        .unwrap();
+        let (_, actual) = decode(&std::fs::read(&out_path).unwrap()).unwrap();
+        std::fs::remove_file(&out_path).unwrap();
+
+        let c = correlation(&actual, &expected);
+        assert!(c > 0.9, "{}: correlation {:.3}", rom_path.display(), c);
+    }
+    assert!(compared > 0, "{} has no ROMs. See test_roms/README.md", dir.display());
+}
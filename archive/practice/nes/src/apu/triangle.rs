@@ -0,0 +1,94 @@
+// Triangle is a triangle wave channel with a linear counter. See https://www.nesdev.org/wiki/APU_Triangle
+use super::envelope::LengthCounter;
+use crate::savestate::{StateReader, StateWriter};
+
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+#[derive(Default)]
+pub struct Triangle {
+    step: u8,
+    // period is the 11-bit timer period, clocked every CPU cycle.
+    period: u16,
+    timer: u16,
+    pub length: LengthCounter,
+    // control halts the length counter and keeps reloading the linear counter.
+    control: bool,
+    linear_reload_value: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+}
+
+impl Triangle {
+    // write handles the registers at 0x4008-0x400B. `reg` is 0-3.
+    pub fn write(&mut self, reg: u16, val: u8) {
+        match reg {
+            0 => {
+                self.control = val & 0b1000_0000 != 0;
+                self.length.set_halt(self.control);
+                self.linear_reload_value = val & 0b0111_1111;
+            }
+            1 => { /* Unused. */ }
+            2 => self.period = (self.period & 0xFF00) | val as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | ((val as u16 & 0b111) << 8);
+                self.length.load(val);
+                self.linear_reload = true;
+            }
+        }
+    }
+
+    // clock_timer is called every CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.length.active() && self.linear_counter > 0 {
+                self.step = (self.step + 1) % 32;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    // clock_linear is called on quarter frames.
+    pub fn clock_linear(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    // output holds the last value when the channel is silenced, as on hardware, to avoid pops.
+    pub fn output(&self) -> u8 {
+        SEQUENCE[self.step as usize]
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.step);
+        w.u16(self.period);
+        w.u16(self.timer);
+        self.length.save_state(w);
+        w.bool(self.control);
+        w.u8(self.linear_reload_value);
+        w.u8(self.linear_counter);
+        w.bool(self.linear_reload);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.step = r.u8()?;
+        self.period = r.u16()?;
+        self.timer = r.u16()?;
+        self.length.load_state(r)?;
+        self.control = r.bool()?;
+        self.linear_reload_value = r.u8()?;
+        self.linear_counter = r.u8()?;
+        self.linear_reload = r.bool()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,417 @@
+// asm assembles 6502 source into machine code using `opcodes::CPU_OP_CODES` and `opcodes::CMOS_OP_CODES`.
+// 65C02 instructions only run with `Variant::Cmos65C02`.
+//
+// Each line holds an optional `label:`, an optional instruction or directive, and an optional `; comment`.
+// Operands use the syntax printed by `disasm`: `A`, `#$10`, `$10`, `$10,X`, `$1234,Y`, `($1234)`, `($10,X)`,
+// `($10),Y`, `($10)` and `($1234,X)`. Branch operands are target addresses.
+// Values are `$hex`, `%binary`, decimal or label terms joined by `+` and `-`, optionally prefixed with `<` (low byte)
+// or `>` (high byte).
+// Directives:
+//   .org ADDR        continue at ADDR, padding with zeros
+//   .byte V, ...     emit bytes
+//   .word V, ...     emit little-endian words
+// Operands that fit in a byte use zero page addressing if the instruction has it, unless they are written with
+// more than 2 hex digits. Labels that are not defined yet use absolute addressing.
+use crate::cpu::AddressingMode;
+use crate::opcodes::{OpCode, CMOS_OP_CODES, CPU_OP_CODES};
+use std::collections::HashMap;
+
+// asm assembles `source` at 0x8000, where `CPU::load` puts programs, or at `origin`. It panics on errors.
+#[cfg(test)]
+macro_rules! asm {
+    ($origin:expr, $source:expr) => {
+        crate::asm::assemble($source, $origin).unwrap_or_else(|e| panic!("{}", e))
+    };
+    ($source:expr) => {
+        asm!(0x8000, $source)
+    };
+}
+#[cfg(test)]
+pub(crate) use asm;
+
+enum Term {
+    Number(i32),
+    Label(String),
+}
+
+enum Part {
+    Low,
+    High,
+}
+
+// Expr is an operand value.
+struct Expr {
+    terms: Vec<(i32, Term)>,
+    part: Option<Part>,
+    // wide is set if a number is written with more than 2 hex digits or 8 binary digits.
+    wide: bool,
+}
+
+impl Expr {
+    fn parse(s: &str) -> Result<Expr, String> {
+        let (part, rest) = match s.as_bytes().first() {
+            Some(b'<') => (Some(Part::Low), &s[1..]),
+            Some(b'>') => (Some(Part::High), &s[1..]),
+            _ => (None, s),
+        };
+        let mut expr = Expr {
+            terms: Vec::new(),
+            part,
+            wide: false,
+        };
+        let mut sign = 1;
+        let mut rest = rest;
+        if let Some(r) = rest.strip_prefix('-') {
+            sign = -1;
+            rest = r;
+        }
+        loop {
+            let end = rest.find(['+', '-']).unwrap_or(rest.len());
+            let (term, digits) = parse_term(&rest[..end])?;
+            expr.wide |= digits;
+            expr.terms.push((sign, term));
+            if end == rest.len() {
+                return Ok(expr);
+            }
+            sign = if &rest[end..end + 1] == "-" { -1 } else { 1 };
+            rest = &rest[end + 1..];
+        }
+    }
+
+    // value returns the value of the expression, or an error if it uses an undefined label.
+    fn value(&self, labels: &HashMap<String, u16>) -> Result<i32, String> {
+        let mut value = 0;
+        for (sign, term) in &self.terms {
+            value += sign
+                * match term {
+                    Term::Number(n) => *n,
+                    Term::Label(label) => match labels.get(label) {
+                        Some(addr) => *addr as i32,
+                        None => return Err(format!("undefined label {:?}", label)),
+                    },
+                };
+        }
+        Ok(match self.part {
+            Some(Part::Low) => value & 0xFF,
+            Some(Part::High) => (value >> 8) & 0xFF,
+            None => value,
+        })
+    }
+
+    // is_byte returns true if the expression is known to fit in a zero page address.
+    fn is_byte(&self, labels: &HashMap<String, u16>) -> bool {
+        self.part.is_some()
+            || (!self.wide && matches!(self.value(labels), Ok(v) if (0..=0xFF).contains(&v)))
+    }
+}
+
+// parse_term parses a number or a label. The flag is set for wide numbers.
+fn parse_term(s: &str) -> Result<(Term, bool), String> {
+    let number = |digits: &str, radix, wide_len| match i32::from_str_radix(digits, radix) {
+        Ok(n) if n <= 0xFFFF => Ok((Term::Number(n), digits.len() > wide_len)),
+        _ => Err(format!("invalid number {:?}", s)),
+    };
+    if let Some(hex) = s.strip_prefix('$') {
+        number(hex, 16, 2)
+    } else if let Some(binary) = s.strip_prefix('%') {
+        number(binary, 2, 8)
+    } else if s.starts_with(|c: char| c.is_ascii_digit()) {
+        number(s, 10, usize::MAX)
+    } else if is_label(s) {
+        Ok((Term::Label(s.to_string()), false))
+    } else {
+        Err(format!("invalid value {:?}", s))
+    }
+}
+
+fn is_label(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+enum Index {
+    None,
+    X,
+    Y,
+}
+
+enum Operand {
+    Implied,
+    Accumulator,
+    Immediate(Expr),
+    Direct(Expr, Index),
+    Indirect(Expr),
+    IndirectX(Expr),
+    IndirectY(Expr),
+}
+
+impl Operand {
+    // parse parses an operand with whitespace removed.
+    fn parse(s: &str) -> Result<Operand, String> {
+        let upper = s.to_ascii_uppercase();
+        let strip = |suffix: &str| &s[..s.len() - suffix.len()];
+        if s.is_empty() {
+            Ok(Operand::Implied)
+        } else if upper == "A" {
+            Ok(Operand::Accumulator)
+        } else if let Some(value) = s.strip_prefix('#') {
+            Ok(Operand::Immediate(Expr::parse(value)?))
+        } else if let Some(inner) = s.strip_prefix('(') {
+            if upper.ends_with(",X)") {
+                Ok(Operand::IndirectX(Expr::parse(&strip(",X)")[1..])?))
+            } else if upper.ends_with("),Y") {
+                Ok(Operand::IndirectY(Expr::parse(&strip("),Y")[1..])?))
+            } else if let Some(value) = inner.strip_suffix(')') {
+                Ok(Operand::Indirect(Expr::parse(value)?))
+            } else {
+                Err(format!("invalid operand {:?}", s))
+            }
+        } else if upper.ends_with(",X") {
+            Ok(Operand::Direct(Expr::parse(strip(",X"))?, Index::X))
+        } else if upper.ends_with(",Y") {
+            Ok(Operand::Direct(Expr::parse(strip(",Y"))?, Index::Y))
+        } else {
+            Ok(Operand::Direct(Expr::parse(s)?, Index::None))
+        }
+    }
+}
+
+fn all_opcodes() -> impl Iterator<Item = &'static OpCode> {
+    CPU_OP_CODES.iter().chain(CMOS_OP_CODES)
+}
+
+// find returns the opcode of `name` in `mode`, preferring official opcodes.
+fn find(name: &str, mode: AddressingMode) -> Option<&'static OpCode> {
+    all_opcodes()
+        .filter(|opcode| opcode.name == name && opcode.mode == mode)
+        .min_by_key(|opcode| opcode.unofficial)
+}
+
+// select returns the opcode of `name` for `operand`. `labels` holds the labels defined so far.
+fn select(
+    name: &str,
+    operand: Operand,
+    labels: &HashMap<String, u16>,
+) -> Result<(&'static OpCode, Option<Expr>), String> {
+    if !all_opcodes().any(|opcode| opcode.name == name) {
+        return Err(format!("unknown instruction {:?}", name));
+    }
+    // modes lists the modes that may encode the operand, in order of preference.
+    let (modes, expr) = match operand {
+        Operand::Implied => (
+            vec![AddressingMode::NoneAddressing, AddressingMode::Accumulator],
+            None,
+        ),
+        Operand::Accumulator => (vec![AddressingMode::Accumulator], None),
+        Operand::Immediate(expr) => (vec![AddressingMode::Immediate], Some(expr)),
+        Operand::Direct(expr, index) => {
+            let (zero_page, absolute) = match index {
+                Index::None if find(name, AddressingMode::Relative).is_some() => {
+                    (AddressingMode::Relative, AddressingMode::Relative)
+                }
+                Index::None => (AddressingMode::ZeroPage, AddressingMode::Absolute),
+                Index::X => (AddressingMode::ZeroPage_X, AddressingMode::Absolute_X),
+                Index::Y => (AddressingMode::ZeroPage_Y, AddressingMode::Absolute_Y),
+            };
+            if expr.is_byte(labels) {
+                (vec![zero_page, absolute], Some(expr))
+            } else {
+                (vec![absolute, zero_page], Some(expr))
+            }
+        }
+        Operand::Indirect(expr) if expr.is_byte(labels) => (
+            vec![AddressingMode::ZeroPage_Indirect, AddressingMode::Indirect],
+            Some(expr),
+        ),
+        Operand::Indirect(expr) => (
+            vec![AddressingMode::Indirect, AddressingMode::ZeroPage_Indirect],
+            Some(expr),
+        ),
+        Operand::IndirectX(expr) if expr.is_byte(labels) => (
+            vec![
+                AddressingMode::Indirect_X,
+                AddressingMode::Absolute_Indirect_X,
+            ],
+            Some(expr),
+        ),
+        Operand::IndirectX(expr) => (
+            vec![
+                AddressingMode::Absolute_Indirect_X,
+                AddressingMode::Indirect_X,
+            ],
+            Some(expr),
+        ),
+        Operand::IndirectY(expr) => (vec![AddressingMode::Indirect_Y], Some(expr)),
+    };
+    match modes.iter().find_map(|mode| find(name, *mode)) {
+        Some(opcode) => Ok((opcode, expr)),
+        None => Err(format!(
+            "{} does not support {:?} addressing",
+            name, modes[0]
+        )),
+    }
+}
+
+enum Data {
+    Instruction(&'static OpCode, Option<Expr>),
+    Bytes(Vec<Expr>),
+    Words(Vec<Expr>),
+    Fill(usize),
+}
+
+// Item is the output of a line, located at `addr`.
+struct Item {
+    line: usize,
+    addr: u16,
+    data: Data,
+}
+
+impl Item {
+    fn len(&self) -> usize {
+        match &self.data {
+            Data::Instruction(opcode, _) => opcode.bytes as usize,
+            Data::Bytes(values) => values.len(),
+            Data::Words(values) => 2 * values.len(),
+            Data::Fill(len) => *len,
+        }
+    }
+
+    fn emit(&self, labels: &HashMap<String, u16>, out: &mut Vec<u8>) -> Result<(), String> {
+        match &self.data {
+            Data::Instruction(opcode, expr) => {
+                out.push(opcode.code);
+                let Some(expr) = expr else {
+                    return Ok(());
+                };
+                let value = expr.value(labels)?;
+                match opcode.mode {
+                    AddressingMode::Relative => {
+                        // Branch targets are relative to the next instruction.
+                        let offset = value - (self.addr as i32 + 2);
+                        if !(-128..=127).contains(&offset) {
+                            return Err(format!("branch target ${:04X} is out of range", value));
+                        }
+                        out.push(offset as u8);
+                    }
+                    AddressingMode::Immediate => out.push(to_byte(value)?),
+                    _ if opcode.bytes == 2 => {
+                        if !(0..=0xFF).contains(&value) {
+                            return Err(format!("address ${:04X} is not in the zero page", value));
+                        }
+                        out.push(value as u8);
+                    }
+                    _ => out.extend(to_word(value)?.to_le_bytes()),
+                }
+            }
+            Data::Bytes(values) => {
+                for value in values {
+                    out.push(to_byte(value.value(labels)?)?);
+                }
+            }
+            Data::Words(values) => {
+                for value in values {
+                    out.extend(to_word(value.value(labels)?)?.to_le_bytes());
+                }
+            }
+            Data::Fill(len) => out.resize(out.len() + len, 0),
+        }
+        Ok(())
+    }
+}
+
+fn to_byte(value: i32) -> Result<u8, String> {
+    if (-0x80..=0xFF).contains(&value) {
+        Ok(value as u8)
+    } else {
+        Err(format!("value {} does not fit in a byte", value))
+    }
+}
+
+fn to_word(value: i32) -> Result<u16, String> {
+    if (-0x8000..=0xFFFF).contains(&value) {
+        Ok(value as u16)
+    } else {
+        Err(format!("value {} does not fit in a word", value))
+    }
+}
+
+// parse_line parses `line`, which is located at `addr`, and defines its label.
+fn parse_line(
+    line: &str,
+    addr: u32,
+    labels: &mut HashMap<String, u16>,
+) -> Result<Option<Data>, String> {
+    let mut line = line.split(';').next().unwrap().trim();
+    if let Some((label, rest)) = line.split_once(':') {
+        let label = label.trim();
+        if !is_label(label) {
+            return Err(format!("invalid label {:?}", label));
+        }
+        if labels.insert(label.to_string(), addr as u16).is_some() {
+            return Err(format!("label {:?} is already defined", label));
+        }
+        line = rest.trim();
+    }
+    if line.is_empty() {
+        return Ok(None);
+    }
+    let (name, operand) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let operand: String = operand.chars().filter(|c| !c.is_whitespace()).collect();
+    let values = || -> Result<Vec<Expr>, String> { operand.split(',').map(Expr::parse).collect() };
+    let data = match name.to_ascii_lowercase().as_str() {
+        ".org" => {
+            let target = Expr::parse(&operand)?.value(labels)?;
+            if target < addr as i32 {
+                return Err(format!(
+                    ".org ${:04X} is before the current address ${:04X}",
+                    target, addr
+                ));
+            }
+            Data::Fill((target - addr as i32) as usize)
+        }
+        ".byte" => Data::Bytes(values()?),
+        ".word" => Data::Words(values()?),
+        _ if name.starts_with('.') => return Err(format!("unknown directive {:?}", name)),
+        _ => {
+            let name = name.to_ascii_uppercase();
+            let (opcode, expr) = select(&name, Operand::parse(&operand)?, labels)?;
+            Data::Instruction(opcode, expr)
+        }
+    };
+    Ok(Some(data))
+}
+
+// assemble assembles `source`, whose first byte is located at `origin`.
+// Errors are prefixed with the line number.
+pub fn assemble(source: &str, origin: u16) -> Result<Vec<u8>, String> {
+    // The first pass defines labels and sizes every line. The second pass emits the bytes.
+    let mut labels = HashMap::new();
+    let mut items = Vec::new();
+    let mut addr = origin as u32;
+    for (i, line) in source.lines().enumerate() {
+        let data =
+            parse_line(line, addr, &mut labels).map_err(|e| format!("line {}: {}", i + 1, e))?;
+        if let Some(data) = data {
+            let item = Item {
+                line: i + 1,
+                addr: addr as u16,
+                data,
+            };
+            addr += item.len() as u32;
+            if addr > 0x10000 {
+                return Err(format!("line {}: program does not fit below $10000", i + 1));
+            }
+            items.push(item);
+        }
+    }
+
+    let mut out = Vec::new();
+    for item in &items {
+        item.emit(&labels, &mut out)
+            .map_err(|e| format!("line {}: {}", item.line, e))?;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,211 @@
+use super::*;
+use crate::cpu::{Variant, CPU};
+use crate::disasm;
+use crate::opcodes;
+
+#[test]
+fn test_assemble_modes() -> Result<(), String> {
+    let source = "
+        BRK
+        ASL
+        ASL A
+        LDA #$10
+        LDA $10
+        LDA $10,X
+        LDX $10,Y
+        LDA $1234
+        LDA $1234,X
+        LDA $1234,Y
+        JMP ($1234)
+        LDA ($10,X)
+        LDA ($10),Y
+    ";
+    let want = vec![
+        0x00, 0x0A, 0x0A, 0xA9, 0x10, 0xA5, 0x10, 0xB5, 0x10, 0xB6, 0x10, 0xAD, 0x34, 0x12, 0xBD,
+        0x34, 0x12, 0xB9, 0x34, 0x12, 0x6C, 0x34, 0x12, 0xA1, 0x10, 0xB1, 0x10,
+    ];
+    assert_eq!(assemble(source, 0x8000)?, want);
+    Ok(())
+}
+
+#[test]
+fn test_assemble_syntax() -> Result<(), String> {
+    // Case, whitespace, comments and number formats.
+    let source = "lda # %1010 ; comment\n\tsta ( $10 ) , y\n; only a comment\n\nldx #10";
+    assert_eq!(
+        assemble(source, 0x8000)?,
+        vec![0xA9, 0x0A, 0x91, 0x10, 0xA2, 0x0A]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_zero_page_or_absolute() -> Result<(), String> {
+    let source = "
+        LDA $0010
+        LDA $10
+        LDA 300
+        LDA forward
+        LDA $10,Y ; There is no LDA zero page,Y.
+    forward:
+        LDA forward
+    ";
+    assert_eq!(
+        assemble(source, 0x8000)?,
+        vec![
+            0xAD, 0x10, 0x00, 0xA5, 0x10, 0xAD, 0x2C, 0x01, 0xAD, 0x0E, 0x80, 0xB9, 0x10, 0x00,
+            0xAD, 0x0E, 0x80,
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_labels_and_branches() -> Result<(), String> {
+    let source = "
+    start:
+        LDX #3
+    loop: DEX
+        BNE loop
+        BEQ done
+        JMP start
+    done:
+        JSR start
+    ";
+    assert_eq!(
+        assemble(source, 0x0600)?,
+        vec![0xA2, 0x03, 0xCA, 0xD0, 0xFD, 0xF0, 0x03, 0x4C, 0x00, 0x06, 0x20, 0x00, 0x06]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_directives() -> Result<(), String> {
+    let source = "
+        JMP reset
+    data:
+        .byte 1, $FF, -1, <data, >data
+        .word data, data+2, $1234
+        .org $8010
+    reset:
+        LDA data-1+2,X
+    ";
+    let bytes = assemble(source, 0x8000)?;
+    assert_eq!(
+        bytes,
+        vec![
+            0x4C, 0x10, 0x80, 0x01, 0xFF, 0xFF, 0x03, 0x80, 0x03, 0x80, 0x05, 0x80, 0x34, 0x12,
+            0x00, 0x00, 0xBD, 0x04, 0x80,
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_round_trip() -> Result<(), String> {
+    // Every opcode disassembles to source that assembles to the same instruction.
+    let lists = [
+        (opcodes::CPU_OP_CODES, Variant::Nmos6502),
+        (opcodes::CMOS_OP_CODES, Variant::Cmos65C02),
+    ];
+    for (opcode, variant) in lists
+        .iter()
+        .flat_map(|(list, variant)| list.iter().map(move |opcode| (opcode, *variant)))
+    {
+        let bytes = [opcode.code, 0x12, 0x34];
+        let bytes = &bytes[..opcode.bytes as usize];
+        let operand = disasm::format_operand(opcode, bytes, 0x8000);
+        let source = format!("{} {}", opcode.name, operand);
+        let assembled = assemble(&source, 0x8000)?;
+        let decoded = opcodes::lookup_variant(variant, assembled[0]).unwrap();
+        if (decoded.name, decoded.mode) != (opcode.name, opcode.mode)
+            || assembled[1..] != bytes[1..]
+        {
+            return Err(format!(
+                "{:?} assembled to {:02X?}, want {:02X?}",
+                source, assembled, bytes
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_prefers_official_opcodes() -> Result<(), String> {
+    assert_eq!(assemble("NOP\nSBC #1", 0x8000)?, vec![0xEA, 0xE9, 0x01]);
+    assert_eq!(assemble("NOP $10", 0x8000)?, vec![0x04, 0x10]);
+    Ok(())
+}
+
+#[test]
+fn test_65c02_modes() -> Result<(), String> {
+    // (zp) and (abs) are chosen by the operand size, like zero page and absolute.
+    assert_eq!(
+        assemble("LDA ($10)\nJMP ($10)\nJMP ($1234,X)\nINC", 0x8000)?,
+        vec![0xB2, 0x10, 0x6C, 0x10, 0x00, 0x7C, 0x34, 0x12, 0x1A]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_assemble_errors() {
+    let cases = [
+        ("FOO", "line 1: unknown instruction \"FOO\""),
+        ("\n.bytes 1", "line 2: unknown directive \".bytes\""),
+        ("JMP nowhere", "line 1: undefined label \"nowhere\""),
+        ("a: NOP\na: NOP", "line 2: label \"a\" is already defined"),
+        ("1a: NOP", "line 1: invalid label \"1a\""),
+        ("LDA #$100", "line 1: value 256 does not fit in a byte"),
+        ("LDA #$10000", "line 1: invalid number \"$10000\""),
+        (
+            "LDA ($1234),Y",
+            "line 1: address $1234 is not in the zero page",
+        ),
+        (
+            "JMP $10,X",
+            "line 1: JMP does not support ZeroPage_X addressing",
+        ),
+        ("LDA ($10", "line 1: invalid operand \"($10\""),
+        ("LDA #", "line 1: invalid value \"\""),
+        (
+            ".org $7FFF",
+            "line 1: .org $7FFF is before the current address $8000",
+        ),
+        (
+            "BNE far\n.org $8100\nfar: NOP",
+            "line 1: branch target $8100 is out of range",
+        ),
+        (
+            ".org $FFFF\n.word 1",
+            "line 2: program does not fit below $10000",
+        ),
+    ];
+    for (source, want) in cases {
+        assert_eq!(
+            assemble(source, 0x8000),
+            Err(want.to_string()),
+            "{:?}",
+            source
+        );
+    }
+}
+
+#[test]
+fn test_asm_macro_runs() {
+    let mut cpu = CPU::new();
+    cpu.load_and_run(asm!(
+        "
+        LDX #0
+    loop:
+        TXA
+        STA $0200,X
+        INX
+        CPX #5
+        BNE loop
+        BRK
+        "
+    ))
+    .unwrap();
+    assert_eq!(cpu.mem_read(0x0204), 4);
+    assert_eq!(cpu.register_x, 5);
+}
@@ -0,0 +1,74 @@
+// bench measures emulation speed in instructions per second, e.g. to compare CPU dispatch strategies.
+// Build with `cargo build --release`. Debug builds are much slower.
+use crate::cpu::{CpuError, CPU};
+use std::time::{Duration, Instant};
+
+// BENCH_PROGRAM is a raw program that loops forever over a mix of loads, stores, arithmetic, branches and calls.
+pub const BENCH_PROGRAM: [u8; 28] = [
+    0xA2, 0x00, // 8000: LDX #$00.
+    0xBD, 0x00, 0x02, // 8002: LDA $0200,X.
+    0x18, // 8005: CLC.
+    0x69, 0x01, // 8006: ADC #$01.
+    0x9D, 0x00, 0x02, // 8008: STA $0200,X.
+    0x20, 0x14, 0x80, // 800B: JSR $8014.
+    0xE8, // 800E: INX.
+    0xD0, 0xF1, // 800F: BNE $8002.
+    0x4C, 0x00, 0x80, // 8011: JMP $8000.
+    0x48, // 8014: PHA.
+    0x4A, // 8015: LSR A.
+    0x49, 0x55, // 8016: EOR #$55.
+    0x68, // 8018: PLA.
+    0xC9, 0x80, // 8019: CMP #$80.
+    0x60, // 801B: RTS.
+];
+
+// BenchResult is the number of instructions executed and the time taken.
+pub struct BenchResult {
+    pub instructions: u64,
+    pub elapsed: Duration,
+}
+
+impl BenchResult {
+    pub fn per_second(&self) -> f64 {
+        self.instructions as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+impl std::fmt::Display for BenchResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} instructions in {:.3}s: {:.0} instructions/s",
+            self.instructions,
+            self.elapsed.as_secs_f64(),
+            self.per_second()
+        )
+    }
+}
+
+// bench_cpu returns a CPU that runs BENCH_PROGRAM.
+pub fn bench_cpu() -> CPU {
+    let mut cpu = CPU::new();
+    cpu.load(BENCH_PROGRAM.to_vec());
+    cpu.reset();
+    cpu
+}
+
+// run executes `instructions` steps of `cpu`. It stops early if the CPU halts.
+pub fn run(cpu: &mut CPU, instructions: u64) -> Result<BenchResult, CpuError> {
+    let start = Instant::now();
+    let mut executed = 0;
+    while executed < instructions {
+        executed += 1;
+        if !cpu.try_step()? {
+            break;
+        }
+    }
+    Ok(BenchResult {
+        instructions: executed,
+        elapsed: start.elapsed(),
+    })
+}
+
+#[cfg(test)]
+mod test;
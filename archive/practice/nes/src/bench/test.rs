@@ -0,0 +1,37 @@
+use super::*;
+
+#[test]
+fn test_bench_program_loops() -> Result<(), String> {
+    let mut cpu = bench_cpu();
+    let result = run(&mut cpu, 10_000).map_err(|e| e.to_string())?;
+    assert_eq!(result.instructions, 10_000);
+    // The stack is balanced. At most JSR and PHA are outstanding.
+    assert!(
+        cpu.stack_pointer >= 0xFD - 3,
+        "SP:{:02X}",
+        cpu.stack_pointer
+    );
+    // Each pass of the inner loop increments one byte of page 2.
+    assert!(cpu.mem_read(0x0200) > 0);
+    Ok(())
+}
+
+#[test]
+fn test_bench_stops_on_halt() -> Result<(), String> {
+    let mut cpu = CPU::new();
+    cpu.load(vec![0xE8, 0x00]);
+    cpu.reset();
+    let result = run(&mut cpu, 100).map_err(|e| e.to_string())?;
+    assert_eq!(result.instructions, 2);
+    Ok(())
+}
+
+// test_report prints the speed of the built-in program. Run it with
+// `cargo test --release -- --ignored --nocapture bench::test::test_report`.
+#[test]
+#[ignore]
+fn test_report() -> Result<(), String> {
+    let result = run(&mut bench_cpu(), 10_000_000).map_err(|e| e.to_string())?;
+    println!("{}", result);
+    Ok(())
+}
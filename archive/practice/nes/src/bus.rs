@@ -0,0 +1,259 @@
+// Bus connects the CPU to memory and devices.
+// See https://www.nesdev.org/wiki/CPU_memory_map
+//
+// Without a cartridge, the whole address space is plain memory (except PPU registers). Raw programs
+// (e.g. the tests and the snake game) are loaded into this memory.
+// With a cartridge, the NES memory map is used: 2KiB of RAM mirrored up to 0x2000 and PRG ROM at 0x8000.
+use crate::apu::{self, Apu};
+use crate::cartridge::{Mirroring, Rom};
+use crate::joypad::Joypad;
+use crate::ppu::Ppu;
+use crate::savestate::{self, StateReader, StateWriter};
+use std::collections::HashSet;
+
+const RAM_MIRRORS_END: u16 = 0x1FFF;
+const PPU_REGISTERS: u16 = 0x2000;
+const PPU_REGISTERS_MIRRORS_END: u16 = 0x3FFF;
+const APU_REGISTERS: u16 = 0x4000;
+const APU_REGISTERS_END: u16 = 0x4013;
+const OAM_DMA: u16 = 0x4014;
+const JOYPAD_1: u16 = 0x4016;
+const JOYPAD_2: u16 = 0x4017;
+const PRG_ROM: u16 = 0x8000;
+
+// Access is the kind of a memory access.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+// WatchHit records an access to a watched address.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatchHit {
+    pub addr: u16,
+    pub access: Access,
+    pub value: u8,
+}
+
+pub struct Bus {
+    memory: Box<[u8; 0x10000]>,
+    prg_rom: Option<Vec<u8>>,
+    pub ppu: Ppu,
+    pub apu: Apu,
+    pub joypads: [Joypad; 2],
+    // cycles is the number of CPU cycles elapsed.
+    pub cycles: usize,
+    read_watchpoints: HashSet<u16>,
+    write_watchpoints: HashSet<u16>,
+    // watch_hits records accesses to watched addresses. The debugger clears it before each instruction.
+    pub watch_hits: Vec<WatchHit>,
+}
+
+impl Bus {
+    pub fn new() -> Self {
+        Bus {
+            memory: Box::new([0; 0x10000]),
+            prg_rom: None,
+            ppu: Ppu::new(Vec::new(), Mirroring::Horizontal),
+            apu: Apu::new(),
+            joypads: [Joypad::new(), Joypad::new()],
+            cycles: 0,
+            read_watchpoints: HashSet::new(),
+            write_watchpoints: HashSet::new(),
+            watch_hits: Vec::new(),
+        }
+    }
+
+    pub fn with_rom(rom: Rom) -> Self {
+        Bus {
+            memory: Box::new([0; 0x10000]),
+            prg_rom: Some(rom.prg_rom),
+            ppu: Ppu::new(rom.chr_rom, rom.mirroring),
+            apu: Apu::new(),
+            joypads: [Joypad::new(), Joypad::new()],
+            cycles: 0,
+            read_watchpoints: HashSet::new(),
+            write_watchpoints: HashSet::new(),
+            watch_hits: Vec::new(),
+        }
+    }
+
+    pub fn has_rom(&self) -> bool {
+        self.prg_rom.is_some()
+    }
+
+    // load copies `bytes` into memory starting at `addr`, bypassing the memory map.
+    pub fn load(&mut self, addr: u16, bytes: &[u8]) {
+        let start = addr as usize;
+        self.memory[start..(start + bytes.len())].copy_from_slice(bytes);
+    }
+
+    fn read_prg_rom(prg_rom: &[u8], addr: u16) -> u8 {
+        // A 16KiB PRG ROM is mirrored at 0xC000.
+        let index = (addr - PRG_ROM) as usize % prg_rom.len();
+        prg_rom[index]
+    }
+
+    // read_memory reads an address that is not mapped to a device.
+    fn read_memory(&self, addr: u16) -> u8 {
+        Bus::read_unmapped(&self.memory, &self.prg_rom, addr)
+    }
+
+    // read_unmapped implements read_memory over the fields it needs, so devices can read memory while the bus is
+    // borrowed (e.g. the DMC fetching samples during `tick`).
+    fn read_unmapped(memory: &[u8; 0x10000], prg_rom: &Option<Vec<u8>>, addr: u16) -> u8 {
+        match prg_rom {
+            None => memory[addr as usize],
+            Some(_) if addr <= RAM_MIRRORS_END => memory[(addr & 0x07FF) as usize],
+            Some(prg_rom) if addr >= PRG_ROM => Bus::read_prg_rom(prg_rom, addr),
+            Some(_) => memory[addr as usize],
+        }
+    }
+
+    // add_watchpoint records accesses of kind `access` to `addr` in `watch_hits`.
+    pub fn add_watchpoint(&mut self, addr: u16, access: Access) {
+        match access {
+            Access::Read => self.read_watchpoints.insert(addr),
+            Access::Write => self.write_watchpoints.insert(addr),
+        };
+    }
+
+    // remove_watchpoint removes read and write watchpoints on `addr`.
+    pub fn remove_watchpoint(&mut self, addr: u16) {
+        self.read_watchpoints.remove(&addr);
+        self.write_watchpoints.remove(&addr);
+    }
+
+    pub fn mem_read(&mut self, addr: u16) -> u8 {
+        let value = match addr {
+            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => self.ppu.read_register(addr),
+            apu::STATUS => self.apu.read_status(),
+            JOYPAD_1 => self.joypads[0].read(),
+            JOYPAD_2 => self.joypads[1].read(),
+            _ => self.read_memory(addr),
+        };
+        if self.read_watchpoints.contains(&addr) {
+            self.watch_hits.push(WatchHit {
+                addr,
+                access: Access::Read,
+                value,
+            });
+        }
+        value
+    }
+
+    // peek reads memory without side effects (e.g. clearing vblank when reading PPUSTATUS). It is used for tracing.
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => self.ppu.peek_register(addr),
+            apu::STATUS => self.apu.peek_status(),
+            JOYPAD_1 => self.joypads[0].peek(),
+            JOYPAD_2 => self.joypads[1].peek(),
+            _ => self.read_memory(addr),
+        }
+    }
+
+    pub fn mem_write(&mut self, addr: u16, val: u8) {
+        if self.write_watchpoints.contains(&addr) {
+            self.watch_hits.push(WatchHit {
+                addr,
+                access: Access::Write,
+                value: val,
+            });
+        }
+        match addr {
+            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => self.ppu.write_register(addr, val),
+            // 0x4017 is the APU frame counter when written and controller 2 when read.
+            APU_REGISTERS..=APU_REGISTERS_END | apu::STATUS | apu::FRAME_COUNTER => {
+                self.apu.write_register(addr, val)
+            }
+            OAM_DMA => {
+                let mut page = [0u8; 256];
+                let base = (val as u16) << 8;
+                for (i, b) in page.iter_mut().enumerate() {
+                    *b = self.mem_read(base + i as u16);
+                }
+                self.ppu.write_oam_dma(&page);
+                // The CPU is suspended during the transfer.
+                self.tick(513);
+            }
+            // The strobe is connected to both controllers.
+            JOYPAD_1 => {
+                self.joypads[0].write(val);
+                self.joypads[1].write(val);
+            }
+            _ => match &self.prg_rom {
+                None => self.memory[addr as usize] = val,
+                Some(_) if addr <= RAM_MIRRORS_END => self.memory[(addr & 0x07FF) as usize] = val,
+                Some(_) if addr >= PRG_ROM => { /* PRG ROM is read-only. */ }
+                Some(_) => self.memory[addr as usize] = val,
+            },
+        }
+    }
+
+    // cartridge_checksum identifies the PRG ROM, or returns None if a raw program is loaded.
+    pub fn cartridge_checksum(&self) -> Option<u32> {
+        self.prg_rom.as_deref().map(savestate::checksum)
+    }
+
+    // save_state writes memory and device state. PRG ROM is identified by a checksum rather than saved.
+    // Watchpoints are not saved.
+    pub fn save_state(&self, w: &mut StateWriter) {
+        match self.cartridge_checksum() {
+            None => w.bool(false),
+            Some(checksum) => {
+                w.bool(true);
+                w.u32(checksum);
+            }
+        }
+        w.bytes(&self.memory[..]);
+        w.u64(self.cycles as u64);
+        self.ppu.save_state(w);
+        self.apu.save_state(w);
+        for joypad in &self.joypads {
+            joypad.save_state(w);
+        }
+    }
+
+    // load_state returns an error without changing the bus if the state was saved with another cartridge.
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        let has_rom = r.bool()?;
+        let checksum = if has_rom { Some(r.u32()?) } else { None };
+        if checksum != self.cartridge_checksum() {
+            return Err("save state is for a different cartridge".to_string());
+        }
+        self.memory.copy_from_slice(r.bytes(0x10000)?);
+        self.cycles = r.u64()? as usize;
+        self.ppu.load_state(r)?;
+        self.apu.load_state(r)?;
+        for joypad in &mut self.joypads {
+            joypad.load_state(r)?;
+        }
+        Ok(())
+    }
+
+    // tick advances devices by `cycles` CPU cycles.
+    pub fn tick(&mut self, cycles: u16) {
+        self.cycles += cycles as usize;
+        self.ppu.tick(cycles as usize * 3);
+        let (memory, prg_rom) = (&self.memory, &self.prg_rom);
+        self.apu.tick(cycles, &mut |addr| {
+            Bus::read_unmapped(memory, prg_rom, addr)
+        });
+    }
+
+    // irq returns true while a device asserts the /IRQ line.
+    pub fn irq(&self) -> bool {
+        self.apu.irq()
+    }
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test;
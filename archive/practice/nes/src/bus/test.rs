@@ -0,0 +1,117 @@
+use super::*;
+use crate::cartridge::test::make_ines;
+use crate::joypad::Button;
+
+fn make_bus(prg_rom: &[u8]) -> Bus {
+    let rom = Rom::new(&make_ines(prg_rom, &[], 0)).unwrap();
+    Bus::with_rom(rom)
+}
+
+#[test]
+fn test_flat_memory_without_rom() {
+    let mut bus = Bus::new();
+    bus.mem_write(0x0801, 1);
+    bus.mem_write(0x8000, 2);
+    bus.mem_write(0xFFFF, 3);
+    assert_eq!(bus.mem_read(0x0001), 0);
+    assert_eq!(bus.mem_read(0x0801), 1);
+    assert_eq!(bus.mem_read(0x8000), 2);
+    assert_eq!(bus.mem_read(0xFFFF), 3);
+}
+
+#[test]
+fn test_ram_mirroring() {
+    let mut bus = make_bus(&[]);
+    bus.mem_write(0x0801, 1);
+    assert_eq!(bus.mem_read(0x0001), 1);
+    assert_eq!(bus.mem_read(0x1801), 1);
+}
+
+#[test]
+fn test_prg_rom() {
+    let mut bus = make_bus(&[0xA9, 0x01]);
+    assert_eq!(bus.mem_read(0x8000), 0xA9);
+    // A single 16KiB bank is mirrored.
+    assert_eq!(bus.mem_read(0xC001), 0x01);
+    // Writes are ignored.
+    bus.mem_write(0x8000, 0);
+    assert_eq!(bus.mem_read(0x8000), 0xA9);
+}
+
+#[test]
+fn test_ppu_registers() {
+    let mut bus = Bus::new();
+    bus.mem_write(0x2006, 0x21);
+    // 0x200E mirrors 0x2006.
+    bus.mem_write(0x200E, 0x00);
+    bus.mem_write(0x2007, 0x66);
+    assert_eq!(bus.ppu.read_vram(0x2100), 0x66);
+}
+
+#[test]
+fn test_oam_dma() {
+    let mut bus = make_bus(&[]);
+    for i in 0..=255u8 {
+        bus.mem_write(0x0200 + i as u16, i);
+    }
+    bus.mem_write(0x4014, 0x02);
+    assert_eq!(bus.cycles, 513);
+    bus.mem_write(0x2003, 0x05);
+    assert_eq!(bus.mem_read(0x2004), 0x05);
+}
+
+#[test]
+fn test_tick() {
+    let mut bus = Bus::new();
+    bus.tick(7);
+    assert_eq!(bus.cycles, 7);
+    assert_eq!(bus.ppu.cycle, 21);
+}
+
+#[test]
+fn test_watchpoints() {
+    let mut bus = Bus::new();
+    bus.add_watchpoint(0x10, Access::Read);
+    bus.add_watchpoint(0x11, Access::Write);
+    bus.mem_write(0x10, 1);
+    bus.mem_read(0x11);
+    assert_eq!(bus.watch_hits, vec![]);
+    bus.mem_read(0x10);
+    bus.mem_write(0x11, 2);
+    // peek does not trigger watchpoints.
+    bus.peek(0x10);
+    assert_eq!(
+        bus.watch_hits,
+        vec![
+            WatchHit {
+                addr: 0x10,
+                access: Access::Read,
+                value: 1
+            },
+            WatchHit {
+                addr: 0x11,
+                access: Access::Write,
+                value: 2
+            },
+        ]
+    );
+    bus.remove_watchpoint(0x10);
+    bus.watch_hits.clear();
+    bus.mem_read(0x10);
+    assert_eq!(bus.watch_hits, vec![]);
+}
+
+#[test]
+fn test_joypad_registers() {
+    let mut bus = make_bus(&[]);
+    bus.joypads[0].set_button(Button::B, true);
+    bus.joypads[1].set_button(Button::A, true);
+    // The strobe latches both controllers.
+    bus.mem_write(0x4016, 1);
+    bus.mem_write(0x4016, 0);
+    assert_eq!(bus.peek(0x4016), 0);
+    assert_eq!(bus.mem_read(0x4016), 0);
+    assert_eq!(bus.mem_read(0x4016), 1);
+    assert_eq!(bus.mem_read(0x4017), 1);
+    assert_eq!(bus.mem_read(0x4017), 0);
+}
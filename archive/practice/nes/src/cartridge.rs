@@ -0,0 +1,72 @@
+// Cartridges are loaded from the iNES file format.
+// See https://www.nesdev.org/wiki/INES
+
+pub const NES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A]; // "NES" followed by MS-DOS end-of-file.
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+pub const PRG_ROM_BANK_SIZE: usize = 0x4000;
+pub const CHR_ROM_BANK_SIZE: usize = 0x2000;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Mirroring {
+    Vertical,
+    Horizontal,
+    FourScreen,
+}
+
+pub struct Rom {
+    pub prg_rom: Vec<u8>,
+    // chr_rom is empty if the cartridge uses CHR RAM.
+    pub chr_rom: Vec<u8>,
+    pub mapper: u8,
+    pub mirroring: Mirroring,
+}
+
+impl Rom {
+    pub fn new(raw: &[u8]) -> Result<Rom, String> {
+        if raw.len() < HEADER_SIZE || raw[0..4] != NES_TAG {
+            return Err("file is not in iNES file format".to_string());
+        }
+
+        let mapper = (raw[7] & 0b1111_0000) | (raw[6] >> 4);
+        if mapper != 0 {
+            // Only NROM is supported.
+            return Err(format!("mapper {} is not supported", mapper));
+        }
+
+        let four_screen = raw[6] & 0b1000 != 0;
+        let vertical_mirroring = raw[6] & 0b1 != 0;
+        let mirroring = match (four_screen, vertical_mirroring) {
+            (true, _) => Mirroring::FourScreen,
+            (false, true) => Mirroring::Vertical,
+            (false, false) => Mirroring::Horizontal,
+        };
+
+        if raw[4] == 0 {
+            return Err("file has no PRG ROM".to_string());
+        }
+        let prg_rom_size = raw[4] as usize * PRG_ROM_BANK_SIZE;
+        let chr_rom_size = raw[5] as usize * CHR_ROM_BANK_SIZE;
+        let skip_trainer = raw[6] & 0b100 != 0;
+
+        let prg_rom_start = HEADER_SIZE + if skip_trainer { TRAINER_SIZE } else { 0 };
+        let chr_rom_start = prg_rom_start + prg_rom_size;
+        if raw.len() < chr_rom_start + chr_rom_size {
+            return Err(format!(
+                "file is truncated: expected at least {} bytes, got {}",
+                chr_rom_start + chr_rom_size,
+                raw.len()
+            ));
+        }
+
+        Ok(Rom {
+            prg_rom: raw[prg_rom_start..chr_rom_start].to_vec(),
+            chr_rom: raw[chr_rom_start..(chr_rom_start + chr_rom_size)].to_vec(),
+            mapper,
+            mirroring,
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod test;
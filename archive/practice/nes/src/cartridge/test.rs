@@ -0,0 +1,108 @@
+use super::*;
+
+// make_ines returns the bytes of an iNES file with the given PRG ROM and CHR ROM.
+pub fn make_ines(prg_rom: &[u8], chr_rom: &[u8], flags6: u8) -> Vec<u8> {
+    let prg_banks = prg_rom.len().div_ceil(PRG_ROM_BANK_SIZE).max(1);
+    let chr_banks = chr_rom.len().div_ceil(CHR_ROM_BANK_SIZE);
+    let mut raw = vec![
+        0x4E,
+        0x45,
+        0x53,
+        0x1A,
+        prg_banks as u8,
+        chr_banks as u8,
+        flags6,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+    ];
+    let mut prg = prg_rom.to_vec();
+    prg.resize(prg_banks * PRG_ROM_BANK_SIZE, 0);
+    raw.extend(prg);
+    let mut chr = chr_rom.to_vec();
+    chr.resize(chr_banks * CHR_ROM_BANK_SIZE, 0);
+    raw.extend(chr);
+    raw
+}
+
+// make_counter_rom returns an iNES file that uses CHR RAM and enables NMI and background rendering.
+// Each NMI increments 0x00 and draws a solid tile at nametable position 0x00, so every frame differs.
+pub fn make_counter_rom() -> Vec<u8> {
+    let mut prg = vec![
+        // 8000: Mask IRQs. The APU frame counter raises them from power-on.
+        0x78, // SEI.
+        // 8001: Palette: black background, white color 1.
+        0xA9, 0x3F, 0x8D, 0x06, 0x20, // LDA #$3F. STA $2006.
+        0xA9, 0x00, 0x8D, 0x06, 0x20, // LDA #$00. STA $2006.
+        0xA9, 0x0F, 0x8D, 0x07, 0x20, // LDA #$0F. STA $2007.
+        0xA9, 0x30, 0x8D, 0x07, 0x20, // LDA #$30. STA $2007.
+        // 8015: Tile 1 is solid color 1.
+        0xA9, 0x00, 0x8D, 0x06, 0x20, // LDA #$00. STA $2006.
+        0xA9, 0x10, 0x8D, 0x06, 0x20, // LDA #$10. STA $2006.
+        0xA2, 0x08, // LDX #$08.
+        0xA9, 0xFF, // LDA #$FF.
+        0x8D, 0x07, 0x20, // 8023: STA $2007.
+        0xCA, // DEX.
+        0xD0, 0xFA, // BNE $8023.
+        // 8029: Enable NMI and background.
+        0xA9, 0x80, 0x8D, 0x00, 0x20, // LDA #$80. STA $2000.
+        0xA9, 0x0A, 0x8D, 0x01, 0x20, // LDA #$0A. STA $2001.
+        0x4C, 0x33, 0x80, // 8033: JMP $8033.
+        // 8036: NMI handler.
+        0xE6, 0x00, // INC $00.
+        0xA9, 0x20, 0x8D, 0x06, 0x20, // LDA #$20. STA $2006.
+        0xA5, 0x00, 0x8D, 0x06, 0x20, // LDA $00. STA $2006.
+        0xA9, 0x01, 0x8D, 0x07, 0x20, // LDA #$01. STA $2007.
+        0xA9, 0x00, 0x8D, 0x05, 0x20, 0x8D, 0x05, 0x20, // LDA #$00. STA $2005. STA $2005.
+        0xA9, 0x80, 0x8D, 0x00, 0x20, // LDA #$80. STA $2000.
+        0x40, // RTI.
+    ];
+    prg.resize(PRG_ROM_BANK_SIZE, 0);
+    // NMI, reset and IRQ vectors.
+    prg[0x3FFA..].copy_from_slice(&[0x36, 0x80, 0x00, 0x80, 0x00, 0x80]);
+    make_ines(&prg, &[], 0)
+}
+
+#[test]
+fn test_parse() {
+    let raw = make_ines(&[1, 2, 3], &[4, 5, 6], 0b1);
+    let rom = Rom::new(&raw).unwrap();
+    assert_eq!(rom.prg_rom.len(), PRG_ROM_BANK_SIZE);
+    assert_eq!(rom.prg_rom[0..3], [1, 2, 3]);
+    assert_eq!(rom.chr_rom.len(), CHR_ROM_BANK_SIZE);
+    assert_eq!(rom.chr_rom[0..3], [4, 5, 6]);
+    assert_eq!(rom.mirroring, Mirroring::Vertical);
+    assert_eq!(rom.mapper, 0);
+}
+
+#[test]
+fn test_parse_with_trainer() {
+    let mut raw = make_ines(&[1, 2, 3], &[], 0b100);
+    raw.splice(HEADER_SIZE..HEADER_SIZE, [0xFF; TRAINER_SIZE]);
+    let rom = Rom::new(&raw).unwrap();
+    assert_eq!(rom.prg_rom[0..3], [1, 2, 3]);
+    assert!(rom.chr_rom.is_empty());
+    assert_eq!(rom.mirroring, Mirroring::Horizontal);
+}
+
+#[test]
+fn test_parse_errors() {
+    assert!(Rom::new(&[0; 16]).is_err());
+
+    let mut raw = make_ines(&[], &[], 0);
+    raw[6] |= 0b0001_0000; // Mapper 1.
+    assert_eq!(Rom::new(&raw).err().unwrap(), "mapper 1 is not supported");
+
+    let raw = make_ines(&[], &[], 0);
+    assert!(Rom::new(&raw[0..100]).is_err());
+
+    let mut raw = make_ines(&[], &[], 0);
+    raw[4] = 0;
+    assert_eq!(Rom::new(&raw).err().unwrap(), "file has no PRG ROM");
+}
@@ -1,4 +1,8 @@
-use crate::opcodes::OPCODES_MAP;
+use crate::bus::Bus;
+use crate::cartridge::Rom;
+use crate::opcodes::{self, Mnemonic};
+use crate::savestate::{StateReader, StateWriter};
+use crate::trace::trace;
 
 enum StatusFlag {
     Zero = 0b0000_0010,
@@ -21,10 +25,10 @@ impl Status {
     fn reset(&mut self) {
         self.status = 0;
     }
-    fn set_all(&mut self, val: u8) {
+    pub fn set_all(&mut self, val: u8) {
         self.status = val;
     }
-    fn get_all(&self) -> u8 {
+    pub fn get_all(&self) -> u8 {
         return self.status;
     }
     fn set(&mut self, fl: StatusFlag, val: bool) {
@@ -40,7 +44,7 @@ impl Status {
         if self.status & fl_u8 != 0 {
             return true;
         }
-        return false;
+        false
     }
 }
 
@@ -53,18 +57,134 @@ pub struct CPU {
     pub stack_pointer: u8,
     pub program_counter: u16,
     pub status: Status,
-    memory: [u8; 0xFFFF],
+    pub bus: Bus,
     trace: bool,
     pub snake_mode: bool, // If true, applies behavior needed to test the snake game described in https://bugzmanov.github.io/nes_ebook/chapter_3_4.html.
+    // If true, BRK stops `run_with_callback` instead of performing an interrupt. Raw programs (e.g. the tests) end with BRK.
+    pub halt_on_brk: bool,
+    // If true, unofficial opcodes (e.g. LAX and DCP) stop the CPU with `CpuError::UnofficialOpcode` instead of executing.
+    pub strict: bool,
+    // variant selects the instruction set and the behavior of decimal mode.
+    pub variant: Variant,
+    // step_limit stops `run_with_callback` with `CpuError::StepLimitExceeded` after this many steps.
+    pub step_limit: Option<u64>,
+    // stop_requested is set by `request_stop` and stops `run_with_callback` before the next step.
+    stop_requested: bool,
+    // program is the range of addresses written by `load`. Running past its end is an error, unless the next byte is BRK.
+    program: Option<std::ops::Range<u32>>,
+    // nmi_pending is set by `trigger_nmi` and cleared when the NMI is serviced.
+    nmi_pending: bool,
+    // irq_line is the level of the /IRQ line. An IRQ is serviced while it is asserted and InterruptDisable is clear.
+    irq_line: bool,
+    // page_crossed is set by `get_operand_address` if indexing crossed a page boundary.
+    page_crossed: bool,
+    // extra_cycles counts cycles taken by the current instruction beyond `OpCode::cycles`.
+    extra_cycles: u8,
 }
 
+// CpuError is an error that stops the CPU. The program counter is left at the instruction that caused it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CpuError {
+    // UnknownOpcode is an opcode that is not implemented for the CPU variant. See `opcodes::lookup_variant`.
+    UnknownOpcode { opcode: u8, addr: u16 },
+    // UnofficialOpcode is an unofficial opcode executed while `strict` is set.
+    UnofficialOpcode { opcode: u8, addr: u16 },
+    // Jam is a KIL opcode, which locks up the CPU until reset.
+    Jam { opcode: u8, addr: u16 },
+    // RanOffProgram means the PC ran past the end of the program passed to `load`, or an instruction was truncated by it.
+    RanOffProgram { addr: u16 },
+    // StepLimitExceeded means `run_with_callback` ran for `step_limit` steps without stopping.
+    StepLimitExceeded { limit: u64 },
+}
+
+// StopReason is why `run_with_callback` stopped without an error.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopReason {
+    // Halted means the CPU executed BRK while `halt_on_brk` is set.
+    Halted,
+    // Requested means a callback called `request_stop`.
+    Requested,
+}
+
+impl std::fmt::Display for CpuError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CpuError::UnknownOpcode { opcode, addr } => {
+                write!(f, "unknown opcode ${:02X} at ${:04X}", opcode, addr)
+            }
+            CpuError::UnofficialOpcode { opcode, addr } => write!(
+                f,
+                "unofficial opcode ${:02X} at ${:04X} in strict mode",
+                opcode, addr
+            ),
+            CpuError::Jam { opcode, addr } => {
+                write!(f, "CPU jammed by opcode ${:02X} at ${:04X}", opcode, addr)
+            }
+            CpuError::RanOffProgram { addr } => {
+                write!(f, "PC ran off the end of the program at ${:04X}", addr)
+            }
+            CpuError::StepLimitExceeded { limit } => {
+                write!(f, "step limit of {} exceeded", limit)
+            }
+        }
+    }
+}
+
+// Interrupt describes the interrupt sequence. See https://www.nesdev.org/wiki/CPU_interrupts
+#[derive(PartialEq)]
+enum Interrupt {
+    Nmi,
+    Irq,
+    Brk,
+}
+
+const NMI_VECTOR: u16 = 0xFFFA;
+const RESET_VECTOR: u16 = 0xFFFC;
+const IRQ_BRK_VECTOR: u16 = 0xFFFE;
+
 // STACK is the starting address of the stack.
 const STACK: u16 = 0x0100;
 // STACK_RESET is the initial value of `stack_pointer`.
 // I expect STACK_RESET could be 0xFF. https://github.com/bugzmanov/nes_ebook/blob/master/code/ch3.3/src/cpu.rs#L346 shows STACK_RESET as 0xFD.
 const STACK_RESET: u8 = 0xFD;
 
-#[derive(Debug)]
+// Variant is a model of the 6502. See http://www.6502.org/tutorials/65c02opcodes.html
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Variant {
+    // Nes2A03 is the NES CPU: an NMOS 6502 whose ADC and SBC ignore the Decimal flag.
+    Nes2A03,
+    // Nmos6502 is the original 6502, with decimal mode.
+    Nmos6502,
+    // Cmos65C02 adds instructions and the (zp) and (abs,X) addressing modes, and has valid N and Z flags in
+    // decimal mode. Unofficial opcodes are not implemented.
+    Cmos65C02,
+}
+
+impl Variant {
+    // parse parses "2a03", "6502" or "65c02".
+    pub fn parse(s: &str) -> Result<Variant, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "2a03" => Ok(Variant::Nes2A03),
+            "6502" => Ok(Variant::Nmos6502),
+            "65c02" => Ok(Variant::Cmos65C02),
+            _ => Err(format!(
+                "unknown CPU variant {:?}, want 2a03, 6502 or 65c02",
+                s
+            )),
+        }
+    }
+
+    // indirect_high returns the address of the high byte of the pointer at `ptr` for JMP ($nnnn). NMOS CPUs do not
+    // carry into the high byte of `ptr`, so JMP ($02FF) reads $02FF and $0200. The 65C02 fixed this.
+    pub fn indirect_high(self, ptr: u16) -> u16 {
+        match self {
+            Variant::Nes2A03 | Variant::Nmos6502 => (ptr & 0xFF00) | (ptr as u8).wrapping_add(1) as u16,
+            Variant::Cmos65C02 => ptr.wrapping_add(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 #[allow(non_camel_case_types)]
 pub enum AddressingMode {
     Accumulator,
@@ -78,6 +198,9 @@ pub enum AddressingMode {
     Indirect,
     Indirect_X, // Deref, Shift, Deref
     Indirect_Y, // Deref, Deref, Shift
+    // 65C02 only.
+    ZeroPage_Indirect,   // Deref
+    Absolute_Indirect_X, // Shift, Deref
     Relative,
     NoneAddressing,
 }
@@ -92,9 +215,19 @@ impl CPU {
             stack_pointer: STACK_RESET,
             status: Status::new(),
             program_counter: 0,
-            memory: [0; 0xFFFF],
+            bus: Bus::new(),
             trace: false,
             snake_mode: false,
+            halt_on_brk: true,
+            strict: false,
+            variant: Variant::Nes2A03,
+            step_limit: None,
+            stop_requested: false,
+            program: None,
+            nmi_pending: false,
+            irq_line: false,
+            page_crossed: false,
+            extra_cycles: 0,
         }
     }
 
@@ -102,20 +235,15 @@ impl CPU {
         self.trace = val;
     }
 
+    // stack_push and stack_pop wrap the stack pointer within page 1, like the hardware.
     pub fn stack_push(&mut self, val: u8) {
-        if self.stack_pointer == 0 {
-            panic!("stack overflow: attempting to push when stack_pointer == 0x00");
-        }
         let addr = STACK.wrapping_add(self.stack_pointer as u16);
         self.mem_write(addr, val);
-        self.stack_pointer -= 1;
+        self.stack_pointer = self.stack_pointer.wrapping_sub(1);
     }
 
     pub fn stack_pop(&mut self) -> u8 {
-        if self.stack_pointer == 0xFF {
-            panic!("stack underflow: attempting to pop when stack_pointer == 0xFF");
-        }
-        self.stack_pointer += 1;
+        self.stack_pointer = self.stack_pointer.wrapping_add(1);
         let addr = STACK.wrapping_add(self.stack_pointer as u16);
         return self.mem_read(addr);
     }
@@ -133,29 +261,31 @@ impl CPU {
         return (hi << 8) | lo;
     }
 
-    fn get_operand_address(&self, mode: &AddressingMode) -> u16 {
+    fn get_operand_address(&mut self, mode: &AddressingMode) -> u16 {
         match mode {
             AddressingMode::Immediate => self.program_counter,
             AddressingMode::ZeroPage => self.mem_read(self.program_counter) as u16,
             AddressingMode::Absolute => self.mem_read_u16(self.program_counter),
             AddressingMode::ZeroPage_X => {
-                let pos = self.mem_read(self.program_counter) as u16;
-                let addr = pos.wrapping_add(self.register_x as u16);
-                return addr;
+                // The address wraps within the zero page.
+                let pos = self.mem_read(self.program_counter);
+                pos.wrapping_add(self.register_x) as u16
             }
             AddressingMode::ZeroPage_Y => {
-                let pos = self.mem_read(self.program_counter) as u16;
-                let addr = pos.wrapping_add(self.register_y as u16);
-                return addr;
+                // The address wraps within the zero page.
+                let pos = self.mem_read(self.program_counter);
+                pos.wrapping_add(self.register_y) as u16
             }
             AddressingMode::Absolute_X => {
                 let pos = self.mem_read_u16(self.program_counter);
                 let addr = pos.wrapping_add(self.register_x as u16);
+                self.page_crossed = pos & 0xFF00 != addr & 0xFF00;
                 return addr;
             }
             AddressingMode::Absolute_Y => {
                 let pos = self.mem_read_u16(self.program_counter);
                 let addr = pos.wrapping_add(self.register_y as u16);
+                self.page_crossed = pos & 0xFF00 != addr & 0xFF00;
                 return addr;
             }
             AddressingMode::Indirect_X => {
@@ -172,16 +302,29 @@ impl CPU {
                 let hi = self.mem_read((base as u8).wrapping_add(1) as u16);
                 let deref_base = (hi as u16) << 8 | (lo as u16);
                 let deref = deref_base.wrapping_add(self.register_y as u16);
+                self.page_crossed = deref_base & 0xFF00 != deref & 0xFF00;
                 deref
             }
             AddressingMode::Indirect => {
                 let base = self.mem_read_u16(self.program_counter);
 
                 let lo = self.mem_read(base);
-                let hi = self.mem_read(base.wrapping_add(1));
+                let hi = self.mem_read(self.variant.indirect_high(base));
                 let deref = (hi as u16) << 8 | (lo as u16);
                 deref
             }
+            AddressingMode::ZeroPage_Indirect => {
+                let base = self.mem_read(self.program_counter);
+                let lo = self.mem_read(base as u16);
+                let hi = self.mem_read(base.wrapping_add(1) as u16);
+                (hi as u16) << 8 | (lo as u16)
+            }
+            AddressingMode::Absolute_Indirect_X => {
+                let ptr = self
+                    .mem_read_u16(self.program_counter)
+                    .wrapping_add(self.register_x as u16);
+                self.mem_read_u16(ptr)
+            }
             AddressingMode::Relative => {
                 let signed_offset = self.mem_read(self.program_counter) as i8;
                 let mut base = self.program_counter;
@@ -196,6 +339,20 @@ impl CPU {
         }
     }
 
+    // branch jumps to the relative address if `condition` is true.
+    // Taking a branch costs one cycle, plus one more if it crosses a page.
+    fn branch(&mut self, condition: bool) {
+        let addr = self.get_operand_address(&AddressingMode::Relative);
+        self.program_counter = self.program_counter.wrapping_add(1);
+        if condition {
+            self.extra_cycles += 1;
+            if self.program_counter & 0xFF00 != addr & 0xFF00 {
+                self.extra_cycles += 1;
+            }
+            self.program_counter = addr;
+        }
+    }
+
     fn lda(&mut self, mode: &AddressingMode) {
         let addr = self.get_operand_address(mode);
         let value = self.mem_read(addr);
@@ -222,17 +379,187 @@ impl CPU {
         self.mem_write(addr, self.register_a);
     }
 
-    pub fn mem_read(&self, addr: u16) -> u8 {
-        return self.memory[addr as usize];
+    // decimal_mode returns true if ADC and SBC use BCD. The 2A03 ignores the Decimal flag.
+    fn decimal_mode(&self) -> bool {
+        self.variant != Variant::Nes2A03 && self.status.get(StatusFlag::Decimal)
+    }
+
+    // add_to_a adds `value` and the carry to A. It is shared by ADC and RRA.
+    fn add_to_a(&mut self, value: u8) {
+        if self.decimal_mode() {
+            self.add_decimal(value);
+            return;
+        }
+        let carry = self.get_carry();
+        self.clear_carry();
+        let (a, m) = (self.register_a, value);
+        // Add carry.
+        let (res, overflowed) = self.register_a.overflowing_add(carry);
+        if overflowed {
+            self.set_carry();
+        }
+        self.register_a = res;
+
+        // Add value.
+        let (res, overflowed) = self.register_a.overflowing_add(value);
+        if overflowed {
+            self.set_carry();
+        }
+        self.register_a = res;
+        // V is set if the operands have the same sign and the result has a different sign.
+        self.status.set(StatusFlag::Overflow, (a ^ res) & (m ^ res) & 0x80 != 0);
+        self.set_zero_and_negative_flags(self.register_a);
+    }
+
+    // sub_from_a subtracts `value` and the borrow (not carry) from A. It is shared by SBC and ISB.
+    fn sub_from_a(&mut self, value: u8) {
+        if self.decimal_mode() {
+            self.sub_decimal(value);
+            return;
+        }
+        let carry = match self.get_carry() {
+            0 => 0,
+            _ => 1,
+        };
+        self.set_carry();
+        let (a, m) = (self.register_a, value);
+        // Subtract 1 - carry.
+        let (res, underflowed) = self.register_a.overflowing_sub(1 - carry);
+        if underflowed {
+            self.clear_carry();
+        }
+        self.register_a = res;
+
+        // Subtract value.
+        let (res, underflowed) = self.register_a.overflowing_sub(value);
+        if underflowed {
+            self.clear_carry();
+        }
+        self.register_a = res;
+
+        // A - M is A + !M + C, so V is computed as for ADC with !M.
+        self.status.set(StatusFlag::Overflow, (a ^ res) & (!m ^ res) & 0x80 != 0);
+        self.set_zero_and_negative_flags(self.register_a);
+    }
+
+    // add_decimal is ADC in decimal mode, including the results of invalid BCD digits.
+    // See http://www.6502.org/tutorials/decimal_mode.html#A
+    fn add_decimal(&mut self, value: u8) {
+        let (a, b, c) = (
+            self.register_a as i32,
+            value as i32,
+            self.get_carry() as i32,
+        );
+        let mut lo = (a & 0x0F) + (b & 0x0F) + c;
+        if lo >= 0x0A {
+            lo = ((lo + 0x06) & 0x0F) + 0x10;
+        }
+        // V, and N on the NMOS 6502, come from the sum before the high digit is adjusted.
+        let signed = (a & 0xF0) as u8 as i8 as i32 + (b & 0xF0) as u8 as i8 as i32 + lo;
+        let mut sum = (a & 0xF0) + (b & 0xF0) + lo;
+        if sum >= 0xA0 {
+            sum += 0x60;
+        }
+        self.register_a = sum as u8;
+        self.status.set(StatusFlag::Carry, sum >= 0x100);
+        self.status
+            .set(StatusFlag::Overflow, !(-128..=127).contains(&signed));
+        if self.variant == Variant::Cmos65C02 {
+            self.set_zero_and_negative_flags(self.register_a);
+            self.extra_cycles += 1;
+        } else {
+            // Z comes from the binary sum.
+            self.status.set(StatusFlag::Zero, (a + b + c) & 0xFF == 0);
+            self.status.set(StatusFlag::Negative, signed & 0x80 != 0);
+        }
+    }
+
+    // sub_decimal is SBC in decimal mode, including the results of invalid BCD digits.
+    // See http://www.6502.org/tutorials/decimal_mode.html#A
+    fn sub_decimal(&mut self, value: u8) {
+        let (a, b, c) = (
+            self.register_a as i32,
+            value as i32,
+            self.get_carry() as i32,
+        );
+        let binary = a - b + c - 1;
+        let lo = (a & 0x0F) - (b & 0x0F) + c - 1;
+        let mut diff;
+        if self.variant == Variant::Cmos65C02 {
+            diff = binary;
+            if diff < 0 {
+                diff -= 0x60;
+            }
+            if lo < 0 {
+                diff -= 0x06;
+            }
+        } else {
+            let lo = if lo < 0 {
+                ((lo - 0x06) & 0x0F) - 0x10
+            } else {
+                lo
+            };
+            diff = (a & 0xF0) - (b & 0xF0) + lo;
+            if diff < 0 {
+                diff -= 0x60;
+            }
+        }
+        self.register_a = diff as u8;
+        // C and V are the same as in binary mode.
+        self.status.set(StatusFlag::Carry, binary >= 0);
+        self.status
+            .set(StatusFlag::Overflow, (a ^ b) & (a ^ binary) & 0x80 != 0);
+        if self.variant == Variant::Cmos65C02 {
+            self.set_zero_and_negative_flags(self.register_a);
+            self.extra_cycles += 1;
+        } else {
+            // N and Z come from the binary difference.
+            self.set_zero_and_negative_flags(binary as u8);
+        }
+    }
+
+    // compare sets flags for `register` - `val`. It is shared by CMP and DCP.
+    fn compare(&mut self, register: u8, val: u8) {
+        self.status.set(StatusFlag::Carry, register >= val);
+        self.status.set(StatusFlag::Zero, register == val);
+        self.status.set(StatusFlag::Negative, register < val);
+    }
+
+    // read_modify_write applies `f` to the value at the operand address and writes the result back.
+    // Returns the result. It is used by the unofficial read-modify-write opcodes.
+    fn read_modify_write(
+        &mut self,
+        mode: &AddressingMode,
+        f: impl FnOnce(&mut CPU, u8) -> u8,
+    ) -> u8 {
+        let addr = self.get_operand_address(mode);
+        let value = self.mem_read(addr);
+        let result = f(self, value);
+        self.mem_write(addr, result);
+        result
+    }
+
+    fn shift_left(&mut self, val: u8, carry_in: bool) -> u8 {
+        self.status.set(StatusFlag::Carry, val & 0b1000_0000 != 0);
+        (val << 1) | carry_in as u8
+    }
+
+    fn shift_right(&mut self, val: u8, carry_in: bool) -> u8 {
+        self.status.set(StatusFlag::Carry, val & 0b0000_0001 != 0);
+        (val >> 1) | (carry_in as u8) << 7
+    }
+
+    pub fn mem_read(&mut self, addr: u16) -> u8 {
+        return self.bus.mem_read(addr);
     }
 
     pub fn mem_write(&mut self, addr: u16, val: u8) {
-        self.memory[addr as usize] = val;
+        self.bus.mem_write(addr, val);
     }
 
-    fn mem_read_u16(&self, addr: u16) -> u16 {
+    fn mem_read_u16(&mut self, addr: u16) -> u16 {
         let lo: u16 = self.mem_read(addr) as u16;
-        let hi: u16 = self.mem_read(addr + 1) as u16;
+        let hi: u16 = self.mem_read(addr.wrapping_add(1)) as u16;
         return lo + (hi << 8);
     }
 
@@ -240,634 +567,750 @@ impl CPU {
         let lo: u8 = (val & 0xFF) as u8;
         let hi: u8 = ((val & 0xFF00) >> 8) as u8;
         self.mem_write(addr, lo);
-        self.mem_write(addr + 1, hi);
+        self.mem_write(addr.wrapping_add(1), hi);
     }
 
     pub fn reset(&mut self) {
         self.register_a = 0;
         self.register_x = 0;
 
-        // TODO: set ONE_FLAG and INTERRUPT_DISABLE?
-        // See: https://github.com/bugzmanov/nes_ebook/blob/c4f905346b27e3ab17277e9651d191ff310f480b/code/ch3.3/src/cpu.rs#L246
+        // Reset sets InterruptDisable, like the hardware, so the APU frame IRQ is masked until a program clears it.
+        // The unused flag is not stored. It is set when the flags are pushed.
         self.status.reset();
-        self.program_counter = self.mem_read_u16(0xFFFC);
+        self.status.set(StatusFlag::InterruptDisable, true);
+        self.nmi_pending = false;
+        self.program_counter = self.mem_read_u16(RESET_VECTOR);
         self.stack_pointer = STACK_RESET;
+        // The reset sequence takes 7 cycles.
+        self.bus.tick(7);
     }
 
     pub fn load(&mut self, program: Vec<u8>) {
         assert!(program.len() <= 0x8000);
-        if self.snake_mode {
-            self.memory[0x0600..(0x0600 + program.len())].copy_from_slice(&program[..]);
-            self.mem_write_u16(0xFFFC, 0x0600);
-            return;
+        let start = if self.snake_mode { 0x0600 } else { 0x8000 };
+        self.bus.load(start, &program[..]);
+        self.mem_write_u16(RESET_VECTOR, start);
+        self.program = Some(start as u32..start as u32 + program.len() as u32);
+    }
+
+    // load_rom inserts a cartridge. Call `reset` to start running it.
+    // BRK performs an interrupt rather than stopping `run_with_callback`.
+    pub fn load_rom(&mut self, rom: Rom) {
+        self.bus = Bus::with_rom(rom);
+        self.halt_on_brk = false;
+        self.program = None;
+    }
+
+    // save_state snapshots the CPU, memory and devices. The cartridge must be loaded separately before `load_state`.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        self.bus.save_state(&mut w);
+        w.u8(self.register_a);
+        w.u8(self.register_x);
+        w.u8(self.register_y);
+        w.u8(self.stack_pointer);
+        w.u16(self.program_counter);
+        w.u8(self.status.get_all());
+        w.bool(self.nmi_pending);
+        w.bool(self.irq_line);
+        w.data
+    }
+
+    // load_state restores a state returned by `save_state`. On error, the CPU is unchanged.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), String> {
+        let mut r = StateReader::new(data)?;
+        // The layout only depends on the cartridge, so checking the length up front means loading cannot fail halfway.
+        let want = self.save_state().len();
+        if data.len() != want {
+            return Err(format!("save state is {} bytes, want {}", data.len(), want));
+        }
+        self.bus.load_state(&mut r)?;
+        self.register_a = r.u8()?;
+        self.register_x = r.u8()?;
+        self.register_y = r.u8()?;
+        self.stack_pointer = r.u8()?;
+        self.program_counter = r.u16()?;
+        self.status.set_all(r.u8()?);
+        self.nmi_pending = r.bool()?;
+        self.irq_line = r.bool()?;
+        r.finish()
+    }
+
+    // trigger_nmi requests a non-maskable interrupt. It is serviced before the next instruction.
+    pub fn trigger_nmi(&mut self) {
+        self.nmi_pending = true;
+    }
+
+    // set_irq_line sets the level of the /IRQ line. Devices keep it asserted until the IRQ is acknowledged.
+    pub fn set_irq_line(&mut self, asserted: bool) {
+        self.irq_line = asserted;
+    }
+
+    fn interrupt(&mut self, interrupt: Interrupt) {
+        self.stack_push_u16(self.program_counter);
+        // B is 0 when pushed by interrupts (/IRQ and /NMI) and 1 when pushed by instructions (BRK and PHP).
+        let mut flags = self.status.get_all() | CPU::ONE_FLAG;
+        if interrupt == Interrupt::Brk {
+            flags |= CPU::B_FLAG;
+        } else {
+            flags &= !CPU::B_FLAG;
         }
-        self.memory[0x8000..(0x8000 + program.len())].copy_from_slice(&program[..]);
-        self.mem_write_u16(0xFFFC, 0x8000);
+        self.stack_push(flags);
+        self.status.set(StatusFlag::InterruptDisable, true);
+        if self.variant == Variant::Cmos65C02 {
+            self.status.set(StatusFlag::Decimal, false);
+        }
+        let vector = match interrupt {
+            Interrupt::Nmi => NMI_VECTOR,
+            Interrupt::Irq | Interrupt::Brk => IRQ_BRK_VECTOR,
+        };
+        self.program_counter = self.mem_read_u16(vector);
+        if interrupt != Interrupt::Brk {
+            // BRK cycles are counted by the opcode.
+            self.bus.tick(7);
+        }
+    }
+
+    // poll_interrupts services a pending NMI or IRQ. NMI has priority. Returns true if an interrupt was serviced.
+    fn poll_interrupts(&mut self) -> bool {
+        if self.bus.ppu.poll_nmi_interrupt() {
+            self.nmi_pending = true;
+        }
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            self.interrupt(Interrupt::Nmi);
+            return true;
+        }
+        if (self.irq_line || self.bus.irq()) && !self.status.get(StatusFlag::InterruptDisable) {
+            self.interrupt(Interrupt::Irq);
+            return true;
+        }
+        false
     }
 
-    pub fn load_and_run(&mut self, program: Vec<u8>) {
+    pub fn load_and_run(&mut self, program: Vec<u8>) -> Result<StopReason, CpuError> {
         self.load(program);
         self.reset();
-        self.run();
+        self.run()
     }
 
-    pub fn run(&mut self) {
+    pub fn run(&mut self) -> Result<StopReason, CpuError> {
         self.run_with_callback(|_| {})
     }
 
-    pub fn run_with_callback<F>(&mut self, mut cb: F)
+    // run_with_callback calls `cb` before each step until the CPU halts, `cb` calls `request_stop`, or an error occurs.
+    pub fn run_with_callback<F>(&mut self, mut cb: F) -> Result<StopReason, CpuError>
     where
         F: FnMut(&mut CPU),
     {
+        self.stop_requested = false;
+        let mut steps = 0;
         loop {
             cb(self);
-
-            // TODO: return error with context if self.program_counter >= len(program)?
-            let op = self.mem_read(self.program_counter);
-            self.program_counter += 1;
-
-            let opcode = OPCODES_MAP.get(&op);
-            if opcode.is_none() {
-                todo!("opcode 0x{:02x} is not implemented", op);
+            if self.stop_requested {
+                self.stop_requested = false;
+                return Ok(StopReason::Requested);
             }
-            let opcode = *opcode.unwrap();
-
-            if self.trace {
-                print!(
-                    "\npc={:04x} {} ({:02x})",
-                    self.program_counter, opcode.name, opcode.code
-                );
-                for i in 0..(opcode.bytes - 1) {
-                    print!(" {:02x}", self.mem_read(self.program_counter + i));
-                }
-                println!();
+            if let Some(limit) = self.step_limit.filter(|&limit| steps >= limit) {
+                return Err(CpuError::StepLimitExceeded { limit });
             }
+            steps += 1;
+            if !self.try_step()? {
+                return Ok(StopReason::Halted);
+            }
+        }
+    }
 
-            match opcode.name {
-                "LDA" => {
-                    // Load A.
-                    self.lda(&opcode.mode);
-                    // TODO: return error with context if self.program_counter >= len(program)?
-                    self.program_counter += opcode.bytes - 1;
-                }
-                "STA" => {
-                    // Store A.
-                    self.sta(&opcode.mode);
-                    // TODO: return error with context if self.program_counter >= len(program)?
-                    self.program_counter += opcode.bytes - 1;
-                }
-                "BRK" => {
-                    // Break.
-                    return;
-                }
-                "JMP" => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    if self.trace {
-                        println!("  JMP to address: 0x{:02x}", addr);
-                    }
-                    self.program_counter = addr;
-                }
-                "ADC" => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    let value = self.mem_read(addr);
-                    if self.trace {
-                        println!(
-                            "  ADC with value: 0x{:02x}. Carry is: {}",
-                            value,
-                            self.get_carry()
-                        );
-                    }
+    // request_stop stops `run_with_callback` before the next step. It is meant to be called from callbacks.
+    pub fn request_stop(&mut self) {
+        self.stop_requested = true;
+    }
 
-                    let carry = self.get_carry();
-                    self.clear_carry();
-                    // Add carry.
-                    let (res, overflowed) = self.register_a.overflowing_add(carry);
-                    if overflowed {
-                        self.set_carry();
-                    }
-                    self.register_a = res;
+    // step services a pending interrupt or executes one instruction.
+    // Returns false if the CPU halted on BRK. See `halt_on_brk`. Panics on errors. See `try_step`.
+    pub fn step(&mut self) -> bool {
+        self.try_step().unwrap_or_else(|e| panic!("{}", e))
+    }
 
-                    // Add value.
-                    let (res, overflowed) = self.register_a.overflowing_add(value);
-                    if overflowed {
-                        self.set_carry();
-                    }
-                    self.register_a = res;
-                    self.status.set(StatusFlag::Overflow, overflowed);
-                    self.set_zero_and_negative_flags(self.register_a);
+    // try_step is `step`, but returns an error instead of panicking. See `CpuError`.
+    pub fn try_step(&mut self) -> Result<bool, CpuError> {
+        if self.poll_interrupts() {
+            // Return so callbacks run before the first instruction of the handler.
+            return Ok(true);
+        }
+
+        if self.trace {
+            println!("{}", trace(self));
+        }
 
-                    self.program_counter += opcode.bytes - 1;
+        let addr = self.program_counter;
+        let op = self.mem_read(addr);
+        // Programs may omit their final BRK, which is then read from the memory after them.
+        if op != 0x00 && self.program.as_ref().is_some_and(|p| p.end == addr as u32) {
+            return Err(CpuError::RanOffProgram { addr });
+        }
+        let opcode = match opcodes::lookup_variant(self.variant, op) {
+            None => return Err(CpuError::UnknownOpcode { opcode: op, addr }),
+            Some(opcode) if opcode.unofficial && self.strict => {
+                return Err(CpuError::UnofficialOpcode { opcode: op, addr })
+            }
+            Some(opcode) if opcode.mnemonic == Mnemonic::JAM => {
+                return Err(CpuError::Jam { opcode: op, addr })
+            }
+            Some(opcode) => opcode,
+        };
+        if let Some(program) = &self.program {
+            if program.contains(&(addr as u32)) && addr as u32 + opcode.bytes as u32 > program.end {
+                return Err(CpuError::RanOffProgram { addr });
+            }
+        }
+        self.program_counter = self.program_counter.wrapping_add(1);
+        self.page_crossed = false;
+        self.extra_cycles = 0;
+
+        match opcode.mnemonic {
+            Mnemonic::LDA => {
+                // Load A.
+                self.lda(&opcode.mode);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::STA => {
+                // Store A.
+                self.sta(&opcode.mode);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::BRK => {
+                // Break.
+                if self.halt_on_brk {
+                    return Ok(false);
                 }
-                "AND" => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    let value = self.mem_read(addr);
-                    if self.trace {
-                        println!(
-                            "  AND with value: 0b{:08b}. Register A is: 0b{:08b}",
-                            value, self.register_a
-                        );
-                    }
+                // The byte after BRK is padding. The return address skips it.
+                self.program_counter = self.program_counter.wrapping_add(1);
+                self.interrupt(Interrupt::Brk);
+            }
+            Mnemonic::JMP => {
+                let addr = self.get_operand_address(&opcode.mode);
+                self.program_counter = addr;
+            }
+            Mnemonic::ADC => {
+                let addr = self.get_operand_address(&opcode.mode);
+                let value = self.mem_read(addr);
+                self.add_to_a(value);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::AND => {
+                let addr = self.get_operand_address(&opcode.mode);
+                let value = self.mem_read(addr);
 
-                    self.register_a = self.register_a & value;
-                    self.set_zero_and_negative_flags(self.register_a);
-                    self.program_counter += opcode.bytes - 1;
+                self.register_a = self.register_a & value;
+                self.set_zero_and_negative_flags(self.register_a);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::ASL => {
+                let val;
+                let mut addr = 0;
+                if let AddressingMode::Accumulator = opcode.mode {
+                    val = self.register_a;
+                } else {
+                    addr = self.get_operand_address(&opcode.mode);
+                    val = self.mem_read(addr);
                 }
-                "ASL" => {
-                    let val;
-                    if let AddressingMode::Immediate = opcode.mode {
-                        val = self.register_a;
-                    } else {
-                        let addr = self.get_operand_address(&opcode.mode);
-                        val = self.mem_read(addr);
-                    }
 
-                    self.status
-                        .set(StatusFlag::Carry, val & 0b1000_0000 == 0b1000_0000);
+                self.status
+                    .set(StatusFlag::Carry, val & 0b1000_0000 == 0b1000_0000);
 
-                    let result = val << 1;
+                let result = val << 1;
 
-                    self.set_zero_and_negative_flags(result);
+                self.set_zero_and_negative_flags(result);
 
+                if let AddressingMode::Accumulator = opcode.mode {
                     self.register_a = result;
+                } else {
+                    self.mem_write(addr, result);
                 }
-                "BCC" => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    if !self.status.get(StatusFlag::Carry) {
-                        self.program_counter = addr;
-                    } else {
-                        self.program_counter += opcode.bytes - 1;
-                    }
-                }
-                "BCS" => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    if self.status.get(StatusFlag::Carry) {
-                        self.program_counter = addr;
-                    } else {
-                        self.program_counter += opcode.bytes - 1;
-                    }
-                }
-                "BEQ" => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    if self.status.get(StatusFlag::Zero) {
-                        self.program_counter = addr;
-                    } else {
-                        self.program_counter += opcode.bytes - 1;
-                    }
-                }
-                "BNE" => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    if !self.status.get(StatusFlag::Zero) {
-                        self.program_counter = addr;
-                    } else {
-                        self.program_counter += opcode.bytes - 1;
-                    }
-                }
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::BCC => {
+                self.branch(!self.status.get(StatusFlag::Carry));
+            }
+            Mnemonic::BCS => {
+                self.branch(self.status.get(StatusFlag::Carry));
+            }
+            Mnemonic::BEQ => {
+                self.branch(self.status.get(StatusFlag::Zero));
+            }
+            Mnemonic::BNE => {
+                self.branch(!self.status.get(StatusFlag::Zero));
+            }
 
-                "BIT" => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    let val = self.mem_read(addr);
+            Mnemonic::BIT => {
+                let addr = self.get_operand_address(&opcode.mode);
+                let val = self.mem_read(addr);
 
-                    self.status
-                        .set(StatusFlag::Zero, val & self.register_a == 0);
+                self.status
+                    .set(StatusFlag::Zero, val & self.register_a == 0);
+                // The 65C02's BIT #imm only sets Z.
+                if opcode.mode != AddressingMode::Immediate {
                     self.status
                         .set(StatusFlag::Overflow, (val & 0b0100_0000) != 0);
                     self.status
                         .set(StatusFlag::Negative, val & 0b1000_0000 != 0);
-
-                    self.program_counter += opcode.bytes - 1;
-                }
-                "BMI" => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    if self.status.get(StatusFlag::Negative) {
-                        self.program_counter = addr;
-                    } else {
-                        self.program_counter += opcode.bytes - 1;
-                    }
-                }
-                "BPL" => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    if self.status.get(StatusFlag::Negative) {
-                        // negative. Do not jump.
-                        self.program_counter += opcode.bytes - 1;
-                    } else {
-                        self.program_counter = addr;
-                    }
-                }
-                "BVC" => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    if self.status.get(StatusFlag::Overflow) {
-                        // Overflow set. Do not jump.
-                        self.program_counter += opcode.bytes - 1;
-                    } else {
-                        self.program_counter = addr;
-                    }
-                }
-                "BVS" => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    if self.status.get(StatusFlag::Overflow) {
-                        self.program_counter = addr;
-                    } else {
-                        self.program_counter += opcode.bytes - 1;
-                    }
-                }
-                "CLC" => {
-                    self.status.set(StatusFlag::Carry, false);
-                    self.program_counter += opcode.bytes - 1;
-                }
-                "CLD" => {
-                    self.status.set(StatusFlag::Decimal, false);
-                    self.program_counter += opcode.bytes - 1;
-                }
-                "CLI" => {
-                    self.status.set(StatusFlag::InterruptDisable, false);
-                    self.program_counter += opcode.bytes - 1;
-                }
-                "CLV" => {
-                    self.status.set(StatusFlag::Overflow, false);
-                    self.program_counter += opcode.bytes - 1;
-                }
-                "CMP" => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    let val = self.mem_read(addr);
-                    if self.trace {
-                        println!("  CMP A={} with M={}", self.register_a, val);
-                    }
-                    self.status.set(StatusFlag::Carry, self.register_a >= val);
-                    self.status.set(StatusFlag::Zero, self.register_a == val);
-                    self.status.set(StatusFlag::Negative, self.register_a < val);
-                    self.program_counter += opcode.bytes - 1;
                 }
 
-                "CPX" => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    let val = self.mem_read(addr);
-                    if self.trace {
-                        println!("  CMP X={} with M={}", self.register_x, val);
-                    }
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::BMI => {
+                self.branch(self.status.get(StatusFlag::Negative));
+            }
+            Mnemonic::BPL => {
+                self.branch(!self.status.get(StatusFlag::Negative));
+            }
+            Mnemonic::BVC => {
+                self.branch(!self.status.get(StatusFlag::Overflow));
+            }
+            Mnemonic::BVS => {
+                self.branch(self.status.get(StatusFlag::Overflow));
+            }
+            Mnemonic::CLC => {
+                self.status.set(StatusFlag::Carry, false);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::CLD => {
+                self.status.set(StatusFlag::Decimal, false);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::CLI => {
+                self.status.set(StatusFlag::InterruptDisable, false);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::CLV => {
+                self.status.set(StatusFlag::Overflow, false);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::CMP => {
+                let addr = self.get_operand_address(&opcode.mode);
+                let val = self.mem_read(addr);
+                self.compare(self.register_a, val);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
 
-                    self.status.set(StatusFlag::Carry, self.register_x >= val);
-                    self.status.set(StatusFlag::Zero, self.register_x == val);
-                    self.status.set(StatusFlag::Negative, self.register_x < val);
+            Mnemonic::CPX => {
+                let addr = self.get_operand_address(&opcode.mode);
+                let val = self.mem_read(addr);
 
-                    self.program_counter += opcode.bytes - 1;
-                }
+                self.status.set(StatusFlag::Carry, self.register_x >= val);
+                self.status.set(StatusFlag::Zero, self.register_x == val);
+                self.status.set(StatusFlag::Negative, self.register_x < val);
 
-                "CPY" => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    let val = self.mem_read(addr);
-                    if self.trace {
-                        println!("  CMP Y={} with M={}", self.register_y, val);
-                    }
-                    self.status.set(StatusFlag::Carry, self.register_y >= val);
-                    self.status.set(StatusFlag::Zero, self.register_y == val);
-                    self.status.set(StatusFlag::Negative, self.register_y < val);
-                    self.program_counter += opcode.bytes - 1;
-                }
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
 
-                "DEC" => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    let mut m = self.mem_read(addr);
-                    if self.trace {
-                        println!("  DEC M={}", m);
-                    }
-                    m = m.wrapping_sub(1);
-                    self.set_zero_and_negative_flags(m);
-                    self.mem_write(addr, m);
-                    self.program_counter += opcode.bytes - 1;
-                }
+            Mnemonic::CPY => {
+                let addr = self.get_operand_address(&opcode.mode);
+                let val = self.mem_read(addr);
+                self.status.set(StatusFlag::Carry, self.register_y >= val);
+                self.status.set(StatusFlag::Zero, self.register_y == val);
+                self.status.set(StatusFlag::Negative, self.register_y < val);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
 
-                "DEX" => {
-                    let mut x = self.register_x;
-                    if self.trace {
-                        println!("  DEC X={}", x);
-                    }
-                    x = x.wrapping_sub(1);
-                    self.set_zero_and_negative_flags(x);
-                    self.register_x = x;
-                    self.program_counter += opcode.bytes - 1;
-                }
-                "DEY" => {
-                    let mut y = self.register_y;
-                    if self.trace {
-                        println!("  DEC Y={}", y);
-                    }
-                    y = y.wrapping_sub(1);
-                    self.set_zero_and_negative_flags(y);
-                    self.register_y = y;
-                    self.program_counter += opcode.bytes - 1;
-                }
+            Mnemonic::DEC if opcode.mode == AddressingMode::Accumulator => {
+                self.register_a = self.register_a.wrapping_sub(1);
+                self.set_zero_and_negative_flags(self.register_a);
+            }
+            Mnemonic::DEC => {
+                let addr = self.get_operand_address(&opcode.mode);
+                let mut m = self.mem_read(addr);
+                m = m.wrapping_sub(1);
+                self.set_zero_and_negative_flags(m);
+                self.mem_write(addr, m);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
 
-                "EOR" => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    let m = self.mem_read(addr);
-                    if self.trace {
-                        println!("  EOR M={}", m);
-                    }
+            Mnemonic::DEX => {
+                let mut x = self.register_x;
+                x = x.wrapping_sub(1);
+                self.set_zero_and_negative_flags(x);
+                self.register_x = x;
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::DEY => {
+                let mut y = self.register_y;
+                y = y.wrapping_sub(1);
+                self.set_zero_and_negative_flags(y);
+                self.register_y = y;
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
 
-                    self.register_a = m ^ self.register_a;
-                    self.set_zero_and_negative_flags(self.register_a);
-                    self.program_counter += opcode.bytes - 1;
-                }
+            Mnemonic::EOR => {
+                let addr = self.get_operand_address(&opcode.mode);
+                let m = self.mem_read(addr);
 
-                "INC" => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    let mut m = self.mem_read(addr);
-                    if self.trace {
-                        println!("  INC M={}", m);
-                    }
-                    m = m.wrapping_add(1);
-                    self.set_zero_and_negative_flags(m);
-                    self.mem_write(addr, m);
-                    self.program_counter += opcode.bytes - 1;
-                }
+                self.register_a = m ^ self.register_a;
+                self.set_zero_and_negative_flags(self.register_a);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
 
-                "INX" => {
-                    let mut x = self.register_x;
-                    if self.trace {
-                        println!("  INC X={}", x);
-                    }
-                    x = x.wrapping_add(1);
-                    self.set_zero_and_negative_flags(x);
-                    self.register_x = x;
-                    self.program_counter += opcode.bytes - 1;
-                }
-                "INY" => {
-                    let mut y = self.register_y;
-                    if self.trace {
-                        println!("  INC Y={}", y);
-                    }
-                    y = y.wrapping_add(1);
-                    self.set_zero_and_negative_flags(y);
-                    self.register_y = y;
-                    self.program_counter += opcode.bytes - 1;
-                }
+            Mnemonic::INC if opcode.mode == AddressingMode::Accumulator => {
+                self.register_a = self.register_a.wrapping_add(1);
+                self.set_zero_and_negative_flags(self.register_a);
+            }
+            Mnemonic::INC => {
+                let addr = self.get_operand_address(&opcode.mode);
+                let mut m = self.mem_read(addr);
+                m = m.wrapping_add(1);
+                self.set_zero_and_negative_flags(m);
+                self.mem_write(addr, m);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
 
-                "JSR" => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    if self.trace {
-                        println!("  JSR to {}", addr);
-                    }
-                    let ret = self.program_counter + opcode.bytes - 1 - 1;
-                    self.stack_push_u16(ret);
-                    self.program_counter = addr;
-                }
+            Mnemonic::INX => {
+                let mut x = self.register_x;
+                x = x.wrapping_add(1);
+                self.set_zero_and_negative_flags(x);
+                self.register_x = x;
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::INY => {
+                let mut y = self.register_y;
+                y = y.wrapping_add(1);
+                self.set_zero_and_negative_flags(y);
+                self.register_y = y;
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
 
-                "LDX" => {
-                    self.ldx(&opcode.mode);
-                    self.program_counter += opcode.bytes - 1;
-                }
+            Mnemonic::JSR => {
+                let addr = self.get_operand_address(&opcode.mode);
+                let ret = self.program_counter.wrapping_add(opcode.bytes - 1 - 1);
+                self.stack_push_u16(ret);
+                self.program_counter = addr;
+            }
 
-                "LDY" => {
-                    self.ldy(&opcode.mode);
-                    self.program_counter += opcode.bytes - 1;
-                }
-                "LSR" => {
-                    let mut value;
-                    let addr;
-                    match opcode.mode {
-                        AddressingMode::Accumulator => {
-                            value = self.register_a;
-                            addr = 0xFFFF;
-                        }
-                        _ => {
-                            addr = self.get_operand_address(&opcode.mode);
-                            value = self.mem_read(addr);
-                        }
+            Mnemonic::LDX => {
+                self.ldx(&opcode.mode);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+
+            Mnemonic::LDY => {
+                self.ldy(&opcode.mode);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::LSR => {
+                let mut value;
+                let addr;
+                match opcode.mode {
+                    AddressingMode::Accumulator => {
+                        value = self.register_a;
+                        addr = 0xFFFF;
+                    }
+                    _ => {
+                        addr = self.get_operand_address(&opcode.mode);
+                        value = self.mem_read(addr);
                     }
+                }
 
-                    println!("Shifting value {value} one bit");
-                    self.status.set(StatusFlag::Carry, value & 0b1 == 0b1);
+                self.status.set(StatusFlag::Carry, value & 0b1 == 0b1);
 
-                    value >>= 1;
-                    self.set_zero_and_negative_flags(value);
+                value >>= 1;
+                self.set_zero_and_negative_flags(value);
 
-                    match opcode.mode {
-                        AddressingMode::Accumulator => {
-                            self.register_a = value;
-                        }
-                        _ => {
-                            self.mem_write(addr, value);
-                        }
+                match opcode.mode {
+                    AddressingMode::Accumulator => {
+                        self.register_a = value;
+                    }
+                    _ => {
+                        self.mem_write(addr, value);
                     }
-
-                    self.program_counter += opcode.bytes - 1;
                 }
-                "NOP" => {
-                    self.program_counter += opcode.bytes - 1;
+
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::NOP => {
+                // Unofficial NOPs with operands read memory. Only the page crossing cycle is emulated.
+                if !matches!(opcode.mode, AddressingMode::NoneAddressing) {
+                    self.get_operand_address(&opcode.mode);
                 }
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
 
-                "ORA" => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    let value = self.mem_read(addr);
-                    if self.trace {
-                        println!(
-                            "  ORA with value: 0b{:08b}. Register A is: 0b{:08b}",
-                            value, self.register_a
-                        );
-                    }
+            Mnemonic::ORA => {
+                let addr = self.get_operand_address(&opcode.mode);
+                let value = self.mem_read(addr);
 
-                    self.register_a = self.register_a | value;
-                    self.set_zero_and_negative_flags(self.register_a);
-                    self.program_counter += opcode.bytes - 1;
-                }
+                self.register_a = self.register_a | value;
+                self.set_zero_and_negative_flags(self.register_a);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
 
-                "PHA" => {
-                    self.stack_push(self.register_a);
-                    self.program_counter += opcode.bytes - 1;
-                }
+            Mnemonic::PHA => {
+                self.stack_push(self.register_a);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
 
-                "PHP" => {
-                    self.stack_push(self.status.get_all() | CPU::B_FLAG | CPU::ONE_FLAG);
-                    self.program_counter += opcode.bytes - 1;
-                }
+            Mnemonic::PHP => {
+                self.stack_push(self.status.get_all() | CPU::B_FLAG | CPU::ONE_FLAG);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
 
-                "PLA" => {
-                    self.register_a = self.stack_pop();
-                    self.set_zero_and_negative_flags(self.register_a);
-                    self.program_counter += opcode.bytes - 1;
-                }
+            Mnemonic::PLA => {
+                self.register_a = self.stack_pop();
+                self.set_zero_and_negative_flags(self.register_a);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
 
-                "PLP" => {
-                    let mut val = self.stack_pop();
-                    // Remove the B flag. See https://github.com/bugzmanov/nes_ebook/blob/c4f905346b27e3ab17277e9651d191ff310f480b/code/ch3.3/src/cpu.rs#L480
-                    val = val & !CPU::B_FLAG;
-                    self.status.set_all(val);
-                    self.program_counter += opcode.bytes - 1;
-                }
+            Mnemonic::PLP => {
+                let mut val = self.stack_pop();
+                // Remove the B flag. See https://github.com/bugzmanov/nes_ebook/blob/c4f905346b27e3ab17277e9651d191ff310f480b/code/ch3.3/src/cpu.rs#L480
+                val = val & !CPU::B_FLAG;
+                self.status.set_all(val);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
 
-                "ROL" => {
-                    let mut val;
-                    let mut addr = 0;
-                    match &opcode.mode {
-                        AddressingMode::Accumulator => {
-                            val = self.register_a;
-                        }
-                        mode => {
-                            addr = self.get_operand_address(&mode);
-                            val = self.mem_read(addr);
-                        }
-                    }
-                    let bit7set = (val & 0b1000_0000) == 0b1000_0000;
-                    let has_old_carry = self.status.get(StatusFlag::Carry);
-                    val <<= 1;
-                    if has_old_carry {
-                        val |= 0b1;
+            Mnemonic::ROL => {
+                let mut val;
+                let mut addr = 0;
+                match &opcode.mode {
+                    AddressingMode::Accumulator => {
+                        val = self.register_a;
                     }
-                    self.status.set(StatusFlag::Carry, bit7set);
-                    self.set_zero_and_negative_flags(val);
-
-                    match &opcode.mode {
-                        AddressingMode::Accumulator => {
-                            self.register_a = val;
-                        }
-                        _ => {
-                            self.mem_write(addr, val);
-                        }
+                    mode => {
+                        addr = self.get_operand_address(&mode);
+                        val = self.mem_read(addr);
                     }
-                    self.program_counter += opcode.bytes - 1;
                 }
+                let bit7set = (val & 0b1000_0000) == 0b1000_0000;
+                let has_old_carry = self.status.get(StatusFlag::Carry);
+                val <<= 1;
+                if has_old_carry {
+                    val |= 0b1;
+                }
+                self.status.set(StatusFlag::Carry, bit7set);
+                self.set_zero_and_negative_flags(val);
 
-                "ROR" => {
-                    let mut val;
-                    let mut addr = 0;
-                    match &opcode.mode {
-                        AddressingMode::Accumulator => {
-                            val = self.register_a;
-                        }
-                        mode => {
-                            addr = self.get_operand_address(&mode);
-                            val = self.mem_read(addr);
-                        }
+                match &opcode.mode {
+                    AddressingMode::Accumulator => {
+                        self.register_a = val;
                     }
-                    let bit0set = (val & 0b0000_0001) == 0b0000_0001;
-                    let has_old_carry = self.status.get(StatusFlag::Carry);
-                    val >>= 1;
-                    if has_old_carry {
-                        val |= 0b1000_0000;
+                    _ => {
+                        self.mem_write(addr, val);
                     }
-                    self.status.set(StatusFlag::Carry, bit0set);
-                    self.set_zero_and_negative_flags(val);
-
-                    match &opcode.mode {
-                        AddressingMode::Accumulator => {
-                            self.register_a = val;
-                        }
-                        _ => {
-                            self.mem_write(addr, val);
-                        }
-                    }
-                    self.program_counter += opcode.bytes - 1;
                 }
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
 
-                "RTI" => {
-                    let popped = self.stack_pop();
-                    // Remove B flag following https://github.com/bugzmanov/nes_ebook/blob/c4f905346b27e3ab17277e9651d191ff310f480b/code/ch3.3/src/cpu.rs#L710C21-L710C57
-                    self.status.set_all(popped);
-                    self.status.set(StatusFlag::B, false);
-                    self.program_counter = self.stack_pop_u16();
+            Mnemonic::ROR => {
+                let mut val;
+                let mut addr = 0;
+                match &opcode.mode {
+                    AddressingMode::Accumulator => {
+                        val = self.register_a;
+                    }
+                    mode => {
+                        addr = self.get_operand_address(&mode);
+                        val = self.mem_read(addr);
+                    }
                 }
-
-                "RTS" => {
-                    self.program_counter = self.stack_pop_u16() + 1;
+                let bit0set = (val & 0b0000_0001) == 0b0000_0001;
+                let has_old_carry = self.status.get(StatusFlag::Carry);
+                val >>= 1;
+                if has_old_carry {
+                    val |= 0b1000_0000;
                 }
-                "SBC" => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    let value = self.mem_read(addr);
-                    if self.trace {
-                        println!(
-                            "  SBC with value: 0x{:02x}. Carry is: {}",
-                            value,
-                            self.get_carry()
-                        );
-                    }
+                self.status.set(StatusFlag::Carry, bit0set);
+                self.set_zero_and_negative_flags(val);
 
-                    let carry = match self.get_carry() {
-                        0 => 0,
-                        _ => 1,
-                    };
-                    self.set_carry();
-                    // Subtract 1 - carry.
-                    let (res, underflowed) = self.register_a.overflowing_sub(1 - carry);
-                    if underflowed {
-                        self.clear_carry();
+                match &opcode.mode {
+                    AddressingMode::Accumulator => {
+                        self.register_a = val;
                     }
-                    self.register_a = res;
-
-                    // Subtract value.
-                    let (res, underflowed) = self.register_a.overflowing_sub(value);
-                    if underflowed {
-                        self.clear_carry();
+                    _ => {
+                        self.mem_write(addr, val);
                     }
-                    self.register_a = res;
+                }
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
 
-                    self.status.set(StatusFlag::Overflow, underflowed);
-                    self.set_zero_and_negative_flags(self.register_a);
+            Mnemonic::RTI => {
+                let popped = self.stack_pop();
+                // Remove B flag following https://github.com/bugzmanov/nes_ebook/blob/c4f905346b27e3ab17277e9651d191ff310f480b/code/ch3.3/src/cpu.rs#L710C21-L710C57
+                self.status.set_all(popped);
+                self.status.set(StatusFlag::B, false);
+                self.status.set(StatusFlag::One, false);
+                self.program_counter = self.stack_pop_u16();
+            }
 
-                    self.program_counter += opcode.bytes - 1;
-                }
+            Mnemonic::RTS => {
+                self.program_counter = self.stack_pop_u16().wrapping_add(1);
+            }
+            Mnemonic::SBC => {
+                let addr = self.get_operand_address(&opcode.mode);
+                let value = self.mem_read(addr);
+                self.sub_from_a(value);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
 
-                "SEC" => {
-                    self.status.set(StatusFlag::Carry, true);
-                    self.program_counter += opcode.bytes - 1;
-                }
+            Mnemonic::SEC => {
+                self.status.set(StatusFlag::Carry, true);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
 
-                "SED" => {
-                    self.status.set(StatusFlag::Decimal, true);
-                    self.program_counter += opcode.bytes - 1;
-                }
+            Mnemonic::SED => {
+                self.status.set(StatusFlag::Decimal, true);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
 
-                "SEI" => {
-                    self.status.set(StatusFlag::InterruptDisable, true);
-                    self.program_counter += opcode.bytes - 1;
-                }
+            Mnemonic::SEI => {
+                self.status.set(StatusFlag::InterruptDisable, true);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
 
-                "STX" => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    self.mem_write(addr, self.register_x);
-                    self.program_counter += opcode.bytes - 1;
-                }
+            Mnemonic::STX => {
+                let addr = self.get_operand_address(&opcode.mode);
+                self.mem_write(addr, self.register_x);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
 
-                "STY" => {
-                    let addr = self.get_operand_address(&opcode.mode);
-                    self.mem_write(addr, self.register_y);
-                    self.program_counter += opcode.bytes - 1;
-                }
+            Mnemonic::STY => {
+                let addr = self.get_operand_address(&opcode.mode);
+                self.mem_write(addr, self.register_y);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
 
-                "TAX" => {
-                    self.register_x = self.register_a;
-                    self.set_zero_and_negative_flags(self.register_x);
-                    self.program_counter += opcode.bytes - 1;
-                }
-                "TAY" => {
-                    self.register_y = self.register_a;
-                    self.set_zero_and_negative_flags(self.register_y);
-                    self.program_counter += opcode.bytes - 1;
-                }
-                "TSX" => {
-                    self.register_x = self.stack_pointer;
-                    self.set_zero_and_negative_flags(self.register_x);
-                    self.program_counter += opcode.bytes - 1;
-                }
-                "TXA" => {
-                    self.register_a = self.register_x;
-                    self.set_zero_and_negative_flags(self.register_a);
-                    self.program_counter += opcode.bytes - 1;
-                }
-                "TXS" => {
-                    self.stack_pointer = self.register_x;
-                    // Flags are not set.
-                    self.program_counter += opcode.bytes - 1;
-                }
-                "TYA" => {
-                    self.register_a = self.register_y;
-                    self.set_zero_and_negative_flags(self.register_a);
-                    self.program_counter += opcode.bytes - 1;
-                }
-                _ => {
-                    todo!();
-                }
+            Mnemonic::TAX => {
+                self.register_x = self.register_a;
+                self.set_zero_and_negative_flags(self.register_x);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::TAY => {
+                self.register_y = self.register_a;
+                self.set_zero_and_negative_flags(self.register_y);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::TSX => {
+                self.register_x = self.stack_pointer;
+                self.set_zero_and_negative_flags(self.register_x);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::TXA => {
+                self.register_a = self.register_x;
+                self.set_zero_and_negative_flags(self.register_a);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::TXS => {
+                self.stack_pointer = self.register_x;
+                // Flags are not set.
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::TYA => {
+                self.register_a = self.register_y;
+                self.set_zero_and_negative_flags(self.register_a);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+
+            // Unofficial opcodes. See https://www.nesdev.org/wiki/Programming_with_unofficial_opcodes
+            Mnemonic::LAX => {
+                // LDA and LDX.
+                self.lda(&opcode.mode);
+                self.register_x = self.register_a;
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::SAX => {
+                let addr = self.get_operand_address(&opcode.mode);
+                self.mem_write(addr, self.register_a & self.register_x);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::DCP => {
+                // DEC and CMP.
+                let m = self.read_modify_write(&opcode.mode, |_, m| m.wrapping_sub(1));
+                self.compare(self.register_a, m);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::ISB => {
+                // INC and SBC.
+                let m = self.read_modify_write(&opcode.mode, |_, m| m.wrapping_add(1));
+                self.sub_from_a(m);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::SLO => {
+                // ASL and ORA.
+                let m = self.read_modify_write(&opcode.mode, |cpu, m| cpu.shift_left(m, false));
+                self.register_a |= m;
+                self.set_zero_and_negative_flags(self.register_a);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::RLA => {
+                // ROL and AND.
+                let carry = self.status.get(StatusFlag::Carry);
+                let m = self.read_modify_write(&opcode.mode, |cpu, m| cpu.shift_left(m, carry));
+                self.register_a &= m;
+                self.set_zero_and_negative_flags(self.register_a);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::SRE => {
+                // LSR and EOR.
+                let m = self.read_modify_write(&opcode.mode, |cpu, m| cpu.shift_right(m, false));
+                self.register_a ^= m;
+                self.set_zero_and_negative_flags(self.register_a);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::RRA => {
+                // ROR and ADC.
+                let carry = self.status.get(StatusFlag::Carry);
+                let m = self.read_modify_write(&opcode.mode, |cpu, m| cpu.shift_right(m, carry));
+                self.add_to_a(m);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            // 65C02 opcodes.
+            Mnemonic::BRA => {
+                self.branch(true);
+            }
+            Mnemonic::PHX => {
+                self.stack_push(self.register_x);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
             }
+            Mnemonic::PHY => {
+                self.stack_push(self.register_y);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::PLX => {
+                self.register_x = self.stack_pop();
+                self.set_zero_and_negative_flags(self.register_x);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::PLY => {
+                self.register_y = self.stack_pop();
+                self.set_zero_and_negative_flags(self.register_y);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::STZ => {
+                let addr = self.get_operand_address(&opcode.mode);
+                self.mem_write(addr, 0);
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            Mnemonic::TRB | Mnemonic::TSB => {
+                // Clear or set the bits of A in memory. Z is set from A & M before the change.
+                let a = self.register_a;
+                let set = opcode.mnemonic == Mnemonic::TSB;
+                self.read_modify_write(&opcode.mode, |cpu, m| {
+                    cpu.status.set(StatusFlag::Zero, a & m == 0);
+                    if set {
+                        m | a
+                    } else {
+                        m & !a
+                    }
+                });
+                self.program_counter = self.program_counter.wrapping_add(opcode.bytes - 1);
+            }
+            // JAM is rejected before dispatch.
+            Mnemonic::JAM => unreachable!(),
+        }
+
+        if self.page_crossed && opcode.adds_cycle_on_page_cross() {
+            self.extra_cycles += 1;
         }
+        self.bus
+            .tick(opcode.cycles as u16 + self.extra_cycles as u16);
+        Ok(true)
     }
 
     pub const ZERO_FLAG: u8 = 0b0000_0010;
@@ -1,16 +1,17 @@
 use super::*;
+use crate::asm::asm;
 
 #[test]
 fn test_0xa9_sets_zero_flag() {
     let mut cpu = CPU::new();
-    cpu.load_and_run(vec![0xA9, 0x00, 0x00]);
+    cpu.load_and_run(asm!("LDA #0\nBRK")).unwrap();
     assert!(cpu.status.get(StatusFlag::Zero));
 }
 
 #[test]
 fn test_0xaa_sets_x() {
     let mut cpu = CPU::new();
-    cpu.load_and_run(vec![0xA9, 0xFF, 0xAA, 0x00]);
+    cpu.load_and_run(asm!("LDA #$FF\nTAX\nBRK")).unwrap();
     assert_eq!(cpu.register_a, 0xFF);
     assert_eq!(cpu.register_x, 0xFF);
 }
@@ -18,14 +19,14 @@ fn test_0xaa_sets_x() {
 #[test]
 fn test_0xaa_sets_zero_flag() {
     let mut cpu = CPU::new();
-    cpu.load_and_run(vec![0xA9, 0x00, 0xAA, 0x00]);
+    cpu.load_and_run(asm!("LDA #0\nTAX\nBRK")).unwrap();
     assert!(cpu.status.get(StatusFlag::Zero));
 }
 
 #[test]
 fn test_5_ops_working_together() {
     let mut cpu = CPU::new();
-    cpu.load_and_run(vec![0xa9, 0xc0, 0xaa, 0xe8, 0x00]);
+    cpu.load_and_run(asm!("LDA #$C0\nTAX\nINX\nBRK")).unwrap();
 
     assert_eq!(cpu.register_x, 0xc1)
 }
@@ -37,7 +38,7 @@ fn test_inx_overflow() {
     // Do not call `load_and_run` to avoid resetting `register_x`.
     cpu.load(vec![0xe8, 0xe8, 0x00]);
     cpu.program_counter = 0x8000;
-    cpu.run();
+    cpu.run().unwrap();
 
     assert_eq!(cpu.register_x, 1)
 }
@@ -47,7 +48,7 @@ fn test_0xa5() {
     // Load A from Zero Page.
     let mut cpu = CPU::new();
     cpu.mem_write(0x01, 123);
-    cpu.load_and_run(vec![0xA5, 0x01]);
+    cpu.load_and_run(vec![0xA5, 0x01]).unwrap();
     assert_eq!(cpu.register_a, 123);
 }
 
@@ -59,10 +60,21 @@ fn test_0xb5() {
     cpu.program_counter = cpu.mem_read_u16(0xFFFC);
     cpu.mem_write(0x03, 123);
     cpu.register_x = 2;
-    cpu.run();
+    cpu.run().unwrap();
     assert_eq!(cpu.register_a, 123);
 }
 
+#[test]
+fn test_zeropage_indexed_wraps() {
+    // $80 + $FF is $7F in the zero page, not $017F.
+    let mut cpu = CPU::new();
+    cpu.mem_write(0x7F, 0x11);
+    cpu.mem_write(0x017F, 0x22);
+    cpu.load_and_run(asm!("LDX #$FF\nLDA $80,X\nLDY #$FF\nLDX $80,Y\nBRK")).unwrap();
+    assert_eq!(cpu.register_a, 0x11);
+    assert_eq!(cpu.register_x, 0x11);
+}
+
 #[test]
 fn test_0xad() {
     // Load A from Absolute.
@@ -70,7 +82,7 @@ fn test_0xad() {
     cpu.load(vec![0xAD, 0x01, 0x00]);
     cpu.program_counter = cpu.mem_read_u16(0xFFFC);
     cpu.mem_write(0x01, 123);
-    cpu.run();
+    cpu.run().unwrap();
     assert_eq!(cpu.register_a, 123);
 }
 
@@ -81,7 +93,7 @@ fn test_0x85() {
     cpu.load(vec![0x85, 0x01]);
     cpu.register_a = 123;
     cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-    cpu.run();
+    cpu.run().unwrap();
     assert_eq!(cpu.mem_read(1), 123);
 }
 
@@ -93,7 +105,7 @@ fn test_0x95() {
     cpu.register_a = 123;
     cpu.register_x = 2;
     cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-    cpu.run();
+    cpu.run().unwrap();
     assert_eq!(cpu.mem_read(3), 123);
 }
 
@@ -104,7 +116,7 @@ fn test_0x8d() {
     cpu.load(vec![0x8d, 0x01, 0x00]);
     cpu.register_a = 123;
     cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-    cpu.run();
+    cpu.run().unwrap();
     assert_eq!(cpu.mem_read(1), 123);
 }
 
@@ -116,7 +128,7 @@ fn test_0x9d() {
     cpu.register_a = 123;
     cpu.register_x = 2;
     cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-    cpu.run();
+    cpu.run().unwrap();
     assert_eq!(cpu.mem_read(3), 123);
 }
 
@@ -128,7 +140,7 @@ fn test_0x99() {
     cpu.register_a = 123;
     cpu.register_y = 2;
     cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-    cpu.run();
+    cpu.run().unwrap();
     assert_eq!(cpu.mem_read(3), 123);
 }
 
@@ -143,7 +155,7 @@ fn test_jmp_absolute() {
         0x01,
     ]);
     cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-    cpu.run();
+    cpu.run().unwrap();
     assert_eq!(cpu.register_a, 0x01);
 }
 
@@ -159,10 +171,30 @@ fn test_jmp_indirect() {
         0x01,
     ]);
     cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-    cpu.run();
+    cpu.run().unwrap();
     assert_eq!(cpu.register_a, 0x01);
 }
 
+#[test]
+fn test_jmp_indirect_page_wrap() {
+    // JMP ($02FF) reads the high byte from $0200 on NMOS CPUs and from $0300 on the 65C02.
+    for (variant, target) in [
+        (Variant::Nes2A03, 0x8034),
+        (Variant::Nmos6502, 0x8034),
+        (Variant::Cmos65C02, 0x9034),
+    ] {
+        let mut cpu = CPU::new();
+        cpu.variant = variant;
+        cpu.load(vec![0x6C, 0xFF, 0x02]);
+        cpu.program_counter = cpu.mem_read_u16(0xFFFC);
+        cpu.mem_write(0x02FF, 0x34);
+        cpu.mem_write(0x0200, 0x80);
+        cpu.mem_write(0x0300, 0x90);
+        cpu.step();
+        assert_eq!(cpu.program_counter, target, "{:?}", variant);
+    }
+}
+
 // TODO: test LDA with indirect addressing mode.
 
 #[test]
@@ -175,7 +207,7 @@ fn test_0x69_adc_immediate() {
             0x69, 0x01, // Add 1.
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0x01);
         assert_eq!(cpu.get_carry(), 0);
     }
@@ -187,7 +219,7 @@ fn test_0x69_adc_immediate() {
             0x69, 0x01, // Add 1.
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0x00);
         assert_eq!(cpu.get_carry(), 1);
     }
@@ -200,7 +232,7 @@ fn test_0x69_adc_immediate() {
             0x69, 0x00, // Add 0.
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0x01);
         assert_eq!(cpu.get_carry(), 0);
     }
@@ -217,7 +249,7 @@ fn test_0x65_adc_zeropage() {
         ]);
         cpu.mem_write(0x01, 123);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 123);
         assert_eq!(cpu.get_carry(), 0);
     }
@@ -235,7 +267,7 @@ fn test_0x75_adc_zeropage_x() {
         cpu.register_x = 2;
         cpu.mem_write(0x03, 123);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 123);
         assert_eq!(cpu.get_carry(), 0);
     }
@@ -252,7 +284,7 @@ fn test_0x6d_adc_absolute() {
         ]);
         cpu.mem_write(0x03, 123);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 123);
         assert_eq!(cpu.get_carry(), 0);
     }
@@ -270,7 +302,7 @@ fn test_0x7d_adc_absolute_x() {
         cpu.register_x = 2;
         cpu.mem_write(0x03, 123);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 123);
         assert_eq!(cpu.get_carry(), 0);
     }
@@ -288,7 +320,7 @@ fn test_0x79_adc_absolute_y() {
         cpu.register_y = 2;
         cpu.mem_write(0x03, 123);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 123);
         assert_eq!(cpu.get_carry(), 0);
     }
@@ -307,7 +339,7 @@ fn test_0x61_adc_indirect_x() {
         cpu.mem_write_u16(0x0003, 0x0005); // Value at 0x0003 is 0x0005
         cpu.mem_write(0x0005, 123);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 123);
         assert_eq!(cpu.get_carry(), 0);
     }
@@ -326,7 +358,7 @@ fn test_0x71_adc_indirect_y() {
         cpu.mem_write_u16(0x0001, 0x0003);
         cpu.mem_write(0x0005, 123);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 123);
         assert_eq!(cpu.get_carry(), 0);
     }
@@ -341,7 +373,7 @@ fn test_0x29_and_immediate() {
         cpu.load(vec![0x29, 0b1101]);
         cpu.register_a = 0b1001;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0b1001);
     }
 
@@ -351,7 +383,7 @@ fn test_0x29_and_immediate() {
         cpu.load(vec![0x29, 0b1000_0000]);
         cpu.register_a = 0b1000_0000;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0b1000_0000);
         // Check that negative flag is set.
         assert!(cpu.status.get(StatusFlag::Negative));
@@ -363,7 +395,7 @@ fn test_0x29_and_immediate() {
         cpu.load(vec![0x29, 0b1]);
         cpu.register_a = 0b0;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0b0);
         // Check that zero flag is set.
         assert!(cpu.status.get(StatusFlag::Zero));
@@ -381,7 +413,7 @@ fn test_0x0a_asl_immediate() {
         cpu.load(vec![0x0A]);
         cpu.register_a = 0b0000_0001;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0b0000_0010);
         assert!(!cpu.status.get(StatusFlag::Zero));
         assert!(!cpu.status.get(StatusFlag::Carry));
@@ -394,7 +426,7 @@ fn test_0x0a_asl_immediate() {
         cpu.load(vec![0x0A]);
         cpu.register_a = 0b1000_0001;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0b0000_0010);
         assert!(!cpu.status.get(StatusFlag::Zero));
         assert!(cpu.status.get(StatusFlag::Carry));
@@ -407,7 +439,7 @@ fn test_0x0a_asl_immediate() {
         cpu.load(vec![0x0A]);
         cpu.register_a = 0b0100_0001;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0b1000_0010);
         assert!(!cpu.status.get(StatusFlag::Zero));
         assert!(!cpu.status.get(StatusFlag::Carry));
@@ -420,7 +452,7 @@ fn test_0x0a_asl_immediate() {
         cpu.load(vec![0x0A]);
         cpu.register_a = 0b0000_0000;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0b0000_0000);
         assert!(cpu.status.get(StatusFlag::Zero));
         assert!(!cpu.status.get(StatusFlag::Carry));
@@ -428,35 +460,52 @@ fn test_0x0a_asl_immediate() {
     }
 }
 
+#[test]
+fn test_0x06_asl_zeropage() {
+    // Memory modes shift memory, not A, and continue after the operand.
+    let mut cpu = CPU::new();
+    cpu.mem_write(0x10, 0b0100_0001);
+    cpu.load_and_run(asm!("LDA #$07\nASL $10\nLDX #$01\nBRK")).unwrap();
+    assert_eq!(cpu.mem_read(0x10), 0b1000_0010);
+    assert_eq!(cpu.register_a, 0x07);
+    assert_eq!(cpu.register_x, 0x01);
+    assert!(!cpu.status.get(StatusFlag::Carry));
+}
+
 #[test]
 fn test_0x90_bcc() {
     let mut cpu = CPU::new();
     {
         cpu.reset();
-        cpu.load(vec![
-            0x90, 0x01, // Jump one instruction ahead.
-            0xFF, // Invalid instruction.
-            0xA9, 123, 0x00, // LDA value 123.
-        ]);
+        cpu.load(asm!(
+            "
+            BCC load
+            .byte $FF ; Skipped.
+        load:
+            LDA #123
+            BRK
+            "
+        ));
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 123);
     }
     // Test jumping negative.
     {
         cpu.reset();
-        cpu.load(vec![
-            0x90,
-            0x04, // Jump four instructions ahead.
-            0xFF, // Invalid instruction.
-            0xA9,
-            123,
-            0x00, // LDA value 123.
-            0x90,
-            (-5i8 as u8), // Jump five instructions behind.
-        ]);
+        cpu.load(asm!(
+            "
+            BCC back
+            .byte $FF ; Skipped.
+        load:
+            LDA #123
+            BRK
+        back:
+            BCC load
+            "
+        ));
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 123);
     }
 }
@@ -473,7 +522,7 @@ fn test_0xb0_bcs() {
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
         cpu.status.set(StatusFlag::Carry, true);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 123);
     }
     // Does not jump if carry not set.
@@ -485,7 +534,7 @@ fn test_0xb0_bcs() {
             0xA9, 123, 0x00, // LDA value 123.
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0);
     }
 }
@@ -502,7 +551,7 @@ fn test_0xfo_beq() {
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
         cpu.status.set(StatusFlag::Zero, true);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 123);
     }
     // Does not jump if zero not set.
@@ -514,7 +563,7 @@ fn test_0xfo_beq() {
             0xA9, 123, 0x00, // LDA value 123.
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0);
     }
 }
@@ -530,7 +579,7 @@ fn test_0xfo_bne() {
             0xA9, 123, 0x00, // LDA value 123.
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 123);
     }
     // Does not jump if zero is set.
@@ -543,7 +592,7 @@ fn test_0xfo_bne() {
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
         cpu.status.set(StatusFlag::Zero, true);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0);
     }
 }
@@ -562,7 +611,7 @@ fn test_0x24_bit_zeropage() {
             0x24, 0x01, // BIT with address 0x01.
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0xFF);
         assert!(!cpu.status.get(StatusFlag::Zero));
         assert!(cpu.status.get(StatusFlag::Negative));
@@ -579,7 +628,7 @@ fn test_0x24_bit_zeropage() {
             0x24, 0x01, // BIT with address 0x01.
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0x0F);
         assert!(cpu.status.get(StatusFlag::Zero));
         assert!(cpu.status.get(StatusFlag::Negative));
@@ -601,7 +650,7 @@ fn test_0x2c_bit_absolute() {
             0x2c, 0x01, 0x00, // BIT with address 0x01.
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0xFF);
         assert!(!cpu.status.get(StatusFlag::Zero));
         assert!(cpu.status.get(StatusFlag::Negative));
@@ -621,7 +670,7 @@ fn test_0x30_bmi() {
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
         cpu.status.set(StatusFlag::Negative, true);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 123);
     }
     // Does not jump if negative not set.
@@ -633,7 +682,7 @@ fn test_0x30_bmi() {
             0xA9, 123, 0x00, // LDA value 123.
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0);
     }
 }
@@ -650,7 +699,7 @@ fn test_0x10_bpl() {
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
         cpu.status.reset();
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 123);
     }
     // Does not jump if negative set.
@@ -663,7 +712,7 @@ fn test_0x10_bpl() {
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
         cpu.status.set(StatusFlag::Negative, true);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0);
     }
 }
@@ -680,7 +729,7 @@ fn test_0x50_bvc() {
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
         cpu.status.reset();
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 123);
     }
     // Does not jump if overflow set.
@@ -693,7 +742,7 @@ fn test_0x50_bvc() {
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
         cpu.status.set(StatusFlag::Overflow, true);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0);
     }
 }
@@ -710,7 +759,7 @@ fn test_0x70_bvs() {
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
         cpu.status.set(StatusFlag::Overflow, true);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 123);
     }
     // Does not jump if overflow not set.
@@ -723,7 +772,7 @@ fn test_0x70_bvs() {
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
         cpu.status.reset();
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0);
     }
 }
@@ -736,7 +785,7 @@ fn test_0x18_clc() {
     cpu.load(vec![0x18, 0x00]);
     cpu.program_counter = cpu.mem_read_u16(0xFFFC);
     cpu.status.set_all(0xFF);
-    cpu.run();
+    cpu.run().unwrap();
     assert_eq!(cpu.status.get_all(), 0xFF & !(StatusFlag::Carry as u8));
 }
 
@@ -748,7 +797,7 @@ fn test_0xd8_cld() {
     cpu.load(vec![0xd8, 0x00]);
     cpu.program_counter = cpu.mem_read_u16(0xFFFC);
     cpu.status.set_all(0xFF);
-    cpu.run();
+    cpu.run().unwrap();
     assert_eq!(cpu.status.get_all(), 0xFF & !(StatusFlag::Decimal as u8));
 }
 
@@ -760,7 +809,7 @@ fn test_0x58_cli() {
     cpu.load(vec![0x58, 0x00]);
     cpu.program_counter = cpu.mem_read_u16(0xFFFC);
     cpu.status.set_all(0xFF);
-    cpu.run();
+    cpu.run().unwrap();
     assert_eq!(
         cpu.status.get_all(),
         0xFF & !(StatusFlag::InterruptDisable as u8)
@@ -775,7 +824,7 @@ fn test_0xb8_clv() {
     cpu.load(vec![0xb8, 0x00]);
     cpu.program_counter = cpu.mem_read_u16(0xFFFC);
     cpu.status.set_all(0xFF);
-    cpu.run();
+    cpu.run().unwrap();
     assert_eq!(cpu.status.get_all(), 0xFF & !(StatusFlag::Overflow as u8));
 }
 
@@ -791,7 +840,7 @@ fn test_cmp() {
             0xc9, 0x01, // CMP with 1.
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(cpu.status.get(StatusFlag::Carry));
     }
 
@@ -803,7 +852,7 @@ fn test_cmp() {
             0xc9, 0x01, // CMP with 1.
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(cpu.status.get(StatusFlag::Carry));
         assert!(cpu.status.get(StatusFlag::Zero));
     }
@@ -816,7 +865,7 @@ fn test_cmp() {
             0xc9, 0x02, // CMP with 2.
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(cpu.status.get(StatusFlag::Negative));
     }
 }
@@ -836,7 +885,7 @@ fn test_cpx() {
         ]);
         cpu.register_x = 2;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(cpu.status.get(StatusFlag::Carry));
     }
 
@@ -848,7 +897,7 @@ fn test_cpx() {
         ]);
         cpu.register_x = 1;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(cpu.status.get(StatusFlag::Carry));
         assert!(cpu.status.get(StatusFlag::Zero));
     }
@@ -861,7 +910,7 @@ fn test_cpx() {
         ]);
         cpu.register_x = 1;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(cpu.status.get(StatusFlag::Negative));
     }
 }
@@ -881,7 +930,7 @@ fn test_cpy() {
         ]);
         cpu.register_y = 2;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(cpu.status.get(StatusFlag::Carry));
     }
 
@@ -893,7 +942,7 @@ fn test_cpy() {
         ]);
         cpu.register_y = 1;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(cpu.status.get(StatusFlag::Carry));
         assert!(cpu.status.get(StatusFlag::Zero));
     }
@@ -906,7 +955,7 @@ fn test_cpy() {
         ]);
         cpu.register_y = 1;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(cpu.status.get(StatusFlag::Negative));
     }
 }
@@ -926,7 +975,7 @@ fn test_0xc6_dec() {
             0xc6, 0x01, // DEC.
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(!cpu.status.get(StatusFlag::Zero));
         assert!(!cpu.status.get(StatusFlag::Negative));
         let got = cpu.mem_read(0x0001);
@@ -942,7 +991,7 @@ fn test_0xc6_dec() {
             0xc6, 0x01, // DEC.
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(cpu.status.get(StatusFlag::Zero));
         assert!(!cpu.status.get(StatusFlag::Negative));
         let got = cpu.mem_read(0x0001);
@@ -958,7 +1007,7 @@ fn test_0xc6_dec() {
             0xc6, 0x01, // DEC.
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(!cpu.status.get(StatusFlag::Zero));
         assert!(cpu.status.get(StatusFlag::Negative));
         let got = cpu.mem_read(0x0001);
@@ -978,7 +1027,7 @@ fn test_0xca_dex() {
         cpu.load(vec![0xca]);
         cpu.register_x = 123;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(!cpu.status.get(StatusFlag::Zero));
         assert!(!cpu.status.get(StatusFlag::Negative));
         assert_eq!(cpu.register_x, 122);
@@ -990,7 +1039,7 @@ fn test_0xca_dex() {
         cpu.load(vec![0xca]);
         cpu.register_x = 1;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(cpu.status.get(StatusFlag::Zero));
         assert!(!cpu.status.get(StatusFlag::Negative));
         assert_eq!(cpu.register_x, 0);
@@ -1001,7 +1050,7 @@ fn test_0xca_dex() {
         cpu.load(vec![0xca]);
         cpu.register_x = 0;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(!cpu.status.get(StatusFlag::Zero));
         assert!(cpu.status.get(StatusFlag::Negative));
         assert_eq!(cpu.register_x, 255);
@@ -1017,7 +1066,7 @@ fn test_0x88_dey() {
         cpu.load(vec![0x88]);
         cpu.register_y = 123;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(!cpu.status.get(StatusFlag::Zero));
         assert!(!cpu.status.get(StatusFlag::Negative));
         assert_eq!(cpu.register_y, 122);
@@ -1029,7 +1078,7 @@ fn test_0x88_dey() {
         cpu.load(vec![0x88]);
         cpu.register_y = 1;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(cpu.status.get(StatusFlag::Zero));
         assert!(!cpu.status.get(StatusFlag::Negative));
         assert_eq!(cpu.register_y, 0);
@@ -1040,7 +1089,7 @@ fn test_0x88_dey() {
         cpu.load(vec![0x88]);
         cpu.register_y = 0;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(!cpu.status.get(StatusFlag::Zero));
         assert!(cpu.status.get(StatusFlag::Negative));
         assert_eq!(cpu.register_y, 255);
@@ -1060,7 +1109,7 @@ fn test_0x49_eor() {
             0b0000_0101, // XOR.
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(!cpu.status.get(StatusFlag::Zero));
         assert!(!cpu.status.get(StatusFlag::Negative));
         assert_eq!(cpu.register_a, 0b0000_0110);
@@ -1076,7 +1125,7 @@ fn test_0x49_eor() {
             0b0000_0011, // XOR.
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(cpu.status.get(StatusFlag::Zero));
         assert!(!cpu.status.get(StatusFlag::Negative));
         assert_eq!(cpu.register_a, 0b0000_0000);
@@ -1092,7 +1141,7 @@ fn test_0x49_eor() {
             0b0000_0000, // XOR.
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(!cpu.status.get(StatusFlag::Zero));
         assert!(cpu.status.get(StatusFlag::Negative));
         assert_eq!(cpu.register_a, 0b1000_0000);
@@ -1113,7 +1162,7 @@ fn test_0xe6_inc() {
             0xe6, 0x01, // INC.
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(!cpu.status.get(StatusFlag::Zero));
         assert!(!cpu.status.get(StatusFlag::Negative));
         let got = cpu.mem_read(0x0001);
@@ -1130,7 +1179,7 @@ fn test_0xe6_inc() {
             0xe6, 0x01, // INC.
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(cpu.status.get(StatusFlag::Zero));
         assert!(!cpu.status.get(StatusFlag::Negative));
         let got = cpu.mem_read(0x0001);
@@ -1149,7 +1198,7 @@ fn test_0xe6_inc() {
             0x01, // INC.
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(!cpu.status.get(StatusFlag::Zero));
         assert!(cpu.status.get(StatusFlag::Negative));
         let got = cpu.mem_read(0x0001);
@@ -1169,7 +1218,7 @@ fn test_0xe8_inx() {
         cpu.load(vec![0xe8]);
         cpu.register_x = 123;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(!cpu.status.get(StatusFlag::Zero));
         assert!(!cpu.status.get(StatusFlag::Negative));
         assert_eq!(cpu.register_x, 124);
@@ -1181,7 +1230,7 @@ fn test_0xe8_inx() {
         cpu.load(vec![0xe8]);
         cpu.register_x = 255;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(cpu.status.get(StatusFlag::Zero));
         assert!(!cpu.status.get(StatusFlag::Negative));
         assert_eq!(cpu.register_x, 0);
@@ -1192,7 +1241,7 @@ fn test_0xe8_inx() {
         cpu.load(vec![0xe8]);
         cpu.register_x = 0b0111_1111;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(!cpu.status.get(StatusFlag::Zero));
         assert!(cpu.status.get(StatusFlag::Negative));
         assert_eq!(cpu.register_x, 0b1000_0000);
@@ -1208,7 +1257,7 @@ fn test_0xc8_iny() {
         cpu.load(vec![0xc8]);
         cpu.register_y = 123;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(!cpu.status.get(StatusFlag::Zero));
         assert!(!cpu.status.get(StatusFlag::Negative));
         assert_eq!(cpu.register_y, 124);
@@ -1220,7 +1269,7 @@ fn test_0xc8_iny() {
         cpu.load(vec![0xc8]);
         cpu.register_y = 255;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(cpu.status.get(StatusFlag::Zero));
         assert!(!cpu.status.get(StatusFlag::Negative));
         assert_eq!(cpu.register_y, 0);
@@ -1231,7 +1280,7 @@ fn test_0xc8_iny() {
         cpu.load(vec![0xc8]);
         cpu.register_y = 0b0111_1111;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(!cpu.status.get(StatusFlag::Zero));
         assert!(cpu.status.get(StatusFlag::Negative));
         assert_eq!(cpu.register_y, 0b1000_0000);
@@ -1267,14 +1316,16 @@ fn test_0x20_jsr() {
 
     {
         cpu.reset();
-        // 0x8000 is starting address of instructions.
-        cpu.load(vec![
-            0x20, 0x04, 0x80, // JSR to 0x8004
-            0xFF, // Invalid instruction.
-            0xa9, 123, // LDA 123.
-        ]);
+        cpu.load(asm!(
+            "
+            JSR load
+            .byte $FF ; Skipped.
+        load:
+            LDA #123
+            "
+        ));
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 123);
         let got = cpu.stack_pop_u16();
         let expect = 0x8002;
@@ -1290,7 +1341,7 @@ fn test_0xa2_ldx() {
         cpu.reset();
         cpu.load(vec![0xa2, 123]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_x, 123);
         assert!(!cpu.status.get(StatusFlag::Zero));
         assert!(!cpu.status.get(StatusFlag::Negative));
@@ -1308,7 +1359,7 @@ fn test_0xa0_ldy() {
         cpu.reset();
         cpu.load(vec![0xa0, 123]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_y, 123);
         assert!(!cpu.status.get(StatusFlag::Zero));
         assert!(!cpu.status.get(StatusFlag::Negative));
@@ -1329,7 +1380,7 @@ fn test_0x4a_lsr() {
             0x4A,
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0b01);
         assert!(!cpu.status.get(StatusFlag::Carry));
         assert!(!cpu.status.get(StatusFlag::Zero));
@@ -1344,7 +1395,7 @@ fn test_0x4a_lsr() {
             0x4A,
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0b00);
         assert!(cpu.status.get(StatusFlag::Carry));
         assert!(cpu.status.get(StatusFlag::Zero));
@@ -1364,7 +1415,7 @@ fn test_0x46_lsr() {
             0x46, 0x02, // LSR
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.mem_read(0x0002), 0b01);
         assert!(!cpu.status.get(StatusFlag::Carry));
         assert!(!cpu.status.get(StatusFlag::Zero));
@@ -1383,7 +1434,7 @@ fn test_0xea_nop() {
         cpu.reset();
         cpu.load(vec![0xEA]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
     }
 }
 
@@ -1396,7 +1447,7 @@ fn test_0x09_ora_immediate() {
         cpu.load(vec![0x09, 0b1101]);
         cpu.register_a = 0b1001;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(!cpu.status.get(StatusFlag::Zero));
         assert!(!cpu.status.get(StatusFlag::Negative));
         assert_eq!(cpu.register_a, 0b1101);
@@ -1408,7 +1459,7 @@ fn test_0x09_ora_immediate() {
         cpu.load(vec![0x09, 0b1000_0000]);
         cpu.register_a = 0b0000_0000;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0b1000_0000);
         // Check that negative flag is set.
         assert!(!cpu.status.get(StatusFlag::Zero));
@@ -1421,7 +1472,7 @@ fn test_0x09_ora_immediate() {
         cpu.load(vec![0x09, 0b0]);
         cpu.register_a = 0b0;
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0b0);
         // Check that zero flag is set.
         assert!(cpu.status.get(StatusFlag::Zero));
@@ -1444,7 +1495,7 @@ fn test_0x48_pha() {
             0x48, // PHA
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 123);
         let got = cpu.stack_pop();
         let expect = 123;
@@ -1464,9 +1515,10 @@ fn test_0x08_php() {
             0x08, // PHP
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         let got = cpu.stack_pop();
-        let expect = CPU::ZERO_FLAG | CPU::B_FLAG | CPU::ONE_FLAG;
+        // Reset sets InterruptDisable.
+        let expect = CPU::ZERO_FLAG | CPU::INTERRUPT_DISABLE_FLAG | CPU::B_FLAG | CPU::ONE_FLAG;
         assert_eq!(got, expect);
     }
 }
@@ -1485,7 +1537,7 @@ fn test_0x68_pla() {
             0x68, // PLA
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 123);
         assert!(!cpu.status.get(StatusFlag::Zero));
         assert!(!cpu.status.get(StatusFlag::Negative));
@@ -1506,7 +1558,7 @@ fn test_0x28_plp() {
             0x28, // PLP
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert!(cpu.status.get(StatusFlag::Zero));
         assert!(cpu.status.get(StatusFlag::One));
     }
@@ -1524,7 +1576,7 @@ fn test_0x2a_rol() {
             0x2A,        // ROL
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0b0000_0010);
         assert!(!cpu.status.get(StatusFlag::Carry));
         assert!(!cpu.status.get(StatusFlag::Zero));
@@ -1540,7 +1592,7 @@ fn test_0x2a_rol() {
             0x2A,        // ROL
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0b0000_0010);
         assert!(cpu.status.get(StatusFlag::Carry));
         assert!(!cpu.status.get(StatusFlag::Zero));
@@ -1557,7 +1609,7 @@ fn test_0x2a_rol() {
             0x2A,        // ROL
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0b0000_0101);
         assert!(!cpu.status.get(StatusFlag::Carry));
         assert!(!cpu.status.get(StatusFlag::Zero));
@@ -1580,7 +1632,7 @@ fn test_0x6a_ror() {
             0x6A,        // ROR
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0b0000_0001);
         assert!(!cpu.status.get(StatusFlag::Carry));
         assert!(!cpu.status.get(StatusFlag::Zero));
@@ -1596,7 +1648,7 @@ fn test_0x6a_ror() {
             0x6A,        // ROR
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0b0100_0000);
         assert!(cpu.status.get(StatusFlag::Carry));
         assert!(!cpu.status.get(StatusFlag::Zero));
@@ -1613,7 +1665,7 @@ fn test_0x6a_ror() {
             0x6A,        // ROR
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         assert_eq!(cpu.register_a, 0b1010_0000);
         assert!(!cpu.status.get(StatusFlag::Carry));
         assert!(!cpu.status.get(StatusFlag::Zero));
@@ -1640,7 +1692,7 @@ fn test_0x40_rti() {
         // Push status flags.
         cpu.stack_push(0xF0);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         // Assert that B flag is removed.
         assert!(!cpu.status.get(StatusFlag::B));
     }
@@ -1661,7 +1713,7 @@ fn test_0x60_rts() {
         // Push return address 0x8001
         cpu.stack_push_u16(0x8001);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
     }
 }
 
@@ -1677,7 +1729,7 @@ fn test_0xe9_sbc_immediate() {
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
         cpu.status.set(StatusFlag::Carry, true);
-        cpu.run();
+        cpu.run().unwrap();
         // With the carry set, expect subtracted by 1.
         assert_eq!(cpu.register_a, 0x00);
         // No underflow occurred. Expect carry to be set.
@@ -1694,12 +1746,41 @@ fn test_0xe9_sbc_immediate() {
             0xE9, 0x01, // SBC 1.
         ]);
         cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-        cpu.run();
+        cpu.run().unwrap();
         // With the carry set, expect subtracted by 2.
         assert_eq!(cpu.register_a, 0xFF);
         assert_eq!(cpu.get_carry(), 0);
         assert!(cpu.status.get(StatusFlag::Negative));
-        assert!(cpu.status.get(StatusFlag::Overflow));
+        // 1 - 2 = -1 fits in a signed byte.
+        assert!(!cpu.status.get(StatusFlag::Overflow));
+    }
+}
+
+#[test]
+fn test_adc_sbc_overflow() {
+    // V is signed overflow, not the carry. Cases from http://www.6502.org/tutorials/vflag.html
+    let cases = [
+        // (instruction, A, operand, carry, result, V)
+        ("ADC", 0x50, 0x10, false, 0x60, false),
+        ("ADC", 0x50, 0x50, false, 0xA0, true),
+        ("ADC", 0x50, 0xD0, false, 0x20, false),
+        ("ADC", 0xD0, 0x90, false, 0x60, true),
+        ("ADC", 0xFF, 0x01, false, 0x00, false),
+        ("ADC", 0x7F, 0x00, true, 0x80, true),
+        ("SBC", 0x50, 0xF0, true, 0x60, false),
+        ("SBC", 0x50, 0xB0, true, 0xA0, true),
+        ("SBC", 0xD0, 0x70, true, 0x60, true),
+        ("SBC", 0xD0, 0x30, true, 0xA0, false),
+        ("SBC", 0x80, 0x00, false, 0x7F, true),
+    ];
+    for (instruction, a, m, carry, result, v) in cases {
+        let mut cpu = CPU::new();
+        let set_carry = if carry { "SEC" } else { "CLC" };
+        let program = format!("{}\nLDA #${:02X}\n{} #${:02X}\nBRK", set_carry, a, instruction, m);
+        cpu.load_and_run(asm!(&program)).unwrap();
+        let case = format!("{:02X} {} {:02X} with carry {}", a, instruction, m, carry);
+        assert_eq!(cpu.register_a, result, "{}", case);
+        assert_eq!(cpu.status.get(StatusFlag::Overflow), v, "{}", case);
     }
 }
 
@@ -1712,7 +1793,7 @@ fn test_0x38_sec() {
     cpu.reset();
     cpu.load(vec![0x38]);
     cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-    cpu.run();
+    cpu.run().unwrap();
     assert!(cpu.status.get(StatusFlag::Carry));
 }
 
@@ -1722,7 +1803,7 @@ fn test_0xf8_sed() {
     cpu.reset();
     cpu.load(vec![0xf8]);
     cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-    cpu.run();
+    cpu.run().unwrap();
     assert!(cpu.status.get(StatusFlag::Decimal));
 }
 
@@ -1732,7 +1813,7 @@ fn test_0x78_sei() {
     cpu.reset();
     cpu.load(vec![0x78]);
     cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-    cpu.run();
+    cpu.run().unwrap();
     assert!(cpu.status.get(StatusFlag::InterruptDisable));
 }
 
@@ -1745,7 +1826,7 @@ fn test_0x86_stx_immediate() {
         0x86, 0x01, // STX
     ]);
     cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-    cpu.run();
+    cpu.run().unwrap();
     assert_eq!(cpu.mem_read(0x0001), 123)
 }
 
@@ -1761,7 +1842,7 @@ fn test_0x86_sty_immediate() {
         0x84, 0x01, // STY
     ]);
     cpu.program_counter = cpu.mem_read_u16(0xFFFC);
-    cpu.run();
+    cpu.run().unwrap();
     assert_eq!(cpu.mem_read(0x0001), 123)
 }
 
@@ -1775,7 +1856,7 @@ fn test_0xaa_tax() {
     cpu.load(vec![0xaa]);
     cpu.program_counter = cpu.mem_read_u16(0xFFFC);
     cpu.register_a = 123;
-    cpu.run();
+    cpu.run().unwrap();
     assert_eq!(cpu.register_x, 123);
 }
 #[test]
@@ -1785,7 +1866,7 @@ fn test_0xa8_tay() {
     cpu.load(vec![0xa8]);
     cpu.program_counter = cpu.mem_read_u16(0xFFFC);
     cpu.register_a = 123;
-    cpu.run();
+    cpu.run().unwrap();
     assert_eq!(cpu.register_y, 123);
 }
 #[test]
@@ -1795,7 +1876,7 @@ fn test_0xba_tsx() {
     cpu.load(vec![0xba]);
     cpu.program_counter = cpu.mem_read_u16(0xFFFC);
     cpu.stack_pointer = 123;
-    cpu.run();
+    cpu.run().unwrap();
     assert_eq!(cpu.register_x, 123);
 }
 #[test]
@@ -1805,7 +1886,7 @@ fn test_0x8a_txa() {
     cpu.load(vec![0x8a]);
     cpu.program_counter = cpu.mem_read_u16(0xFFFC);
     cpu.register_x = 123;
-    cpu.run();
+    cpu.run().unwrap();
     assert_eq!(cpu.register_a, 123);
 }
 #[test]
@@ -1815,7 +1896,7 @@ fn test_0x9a_txs() {
     cpu.load(vec![0x9a]);
     cpu.program_counter = cpu.mem_read_u16(0xFFFC);
     cpu.register_x = 123;
-    cpu.run();
+    cpu.run().unwrap();
     assert_eq!(cpu.stack_pointer, 123);
 }
 #[test]
@@ -1825,6 +1906,584 @@ fn test_0x98_tya() {
     cpu.load(vec![0x98]);
     cpu.program_counter = cpu.mem_read_u16(0xFFFC);
     cpu.register_y = 123;
-    cpu.run();
+    cpu.run().unwrap();
     assert_eq!(cpu.register_a, 123);
 }
+
+#[test]
+fn test_brk_interrupt() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![
+        0xEA, // NOP.
+        0x00, // BRK.
+        0xFF, // Padding.
+    ]);
+    cpu.reset();
+    cpu.halt_on_brk = false;
+    cpu.mem_write_u16(0xFFFE, 0x9000);
+    // The handler sets X and halts.
+    cpu.bus.load(0x9000, &[0xA2, 0x05]);
+    cpu.run_with_callback(|cpu| {
+        if cpu.program_counter == 0x9002 {
+            cpu.halt_on_brk = true;
+        }
+    })
+    .unwrap();
+    assert_eq!(cpu.register_x, 5);
+    assert!(cpu.status.get(StatusFlag::InterruptDisable));
+    // The pushed status has B set.
+    let flags = cpu.mem_read(STACK + STACK_RESET as u16 - 2);
+    assert_eq!(flags & CPU::B_FLAG, CPU::B_FLAG);
+    assert_eq!(flags & CPU::ONE_FLAG, CPU::ONE_FLAG);
+    // The return address skips the padding byte.
+    cpu.stack_pop();
+    assert_eq!(cpu.stack_pop_u16(), 0x8003);
+}
+
+#[test]
+fn test_nmi() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![
+        0xA9, 0x01, // LDA #1.
+        0x00, // BRK.
+    ]);
+    cpu.reset();
+    // Clear InterruptDisable, which reset set, so RTI has to restore it.
+    cpu.status.set(StatusFlag::InterruptDisable, false);
+    cpu.mem_write_u16(0xFFFA, 0x9000);
+    // The handler increments X and returns.
+    cpu.bus.load(0x9000, &[0xE8, 0x40]);
+    cpu.trigger_nmi();
+    cpu.run().unwrap();
+    assert_eq!(cpu.register_x, 1);
+    assert_eq!(cpu.register_a, 1);
+    assert_eq!(cpu.stack_pointer, STACK_RESET);
+    // The pushed status has B clear.
+    let flags = cpu.mem_read(STACK + STACK_RESET as u16 - 2);
+    assert_eq!(flags & CPU::B_FLAG, 0);
+    // RTI restores InterruptDisable from the pushed status.
+    assert!(!cpu.status.get(StatusFlag::InterruptDisable));
+}
+
+#[test]
+fn test_irq() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![
+        0x78, // SEI.
+        0xE8, // INX.
+        0x58, // CLI.
+        0xEA, // NOP.
+        0x00, // BRK.
+    ]);
+    cpu.reset();
+    cpu.mem_write_u16(0xFFFE, 0x9000);
+    // The handler increments Y and returns.
+    cpu.bus.load(0x9000, &[0xC8, 0x40]);
+    cpu.set_irq_line(true);
+    let mut serviced = false;
+    cpu.run_with_callback(|cpu| {
+        if cpu.program_counter == 0x9000 {
+            // Acknowledge the IRQ.
+            serviced = true;
+            cpu.set_irq_line(false);
+        }
+    })
+    .unwrap();
+    // IRQ is masked until CLI.
+    assert_eq!(cpu.register_x, 1);
+    assert_eq!(cpu.register_y, 1);
+    assert!(serviced);
+    assert!(!cpu.status.get(StatusFlag::InterruptDisable));
+}
+
+#[test]
+fn test_nmi_from_ppu() {
+    let mut cpu = CPU::new();
+    cpu.load(asm!(
+        "
+        LDA #$80
+        STA $2000 ; Enable NMI on vblank.
+    loop:
+        JMP loop
+        "
+    ));
+    cpu.reset();
+    cpu.mem_write_u16(0xFFFA, 0x9000);
+    // The handler halts.
+    cpu.bus.load(0x9000, &[0x00]);
+    cpu.run().unwrap();
+    assert_eq!(cpu.bus.ppu.scanline, 241);
+}
+
+#[test]
+fn test_0xa7_lax() {
+    let mut cpu = CPU::new();
+    cpu.bus.load(0x0010, &[0x80]);
+    cpu.load_and_run(vec![0xA7, 0x10, 0x00]).unwrap();
+    assert_eq!(cpu.register_a, 0x80);
+    assert_eq!(cpu.register_x, 0x80);
+    assert!(cpu.status.get(StatusFlag::Negative));
+}
+
+#[test]
+fn test_0x87_sax() {
+    let mut cpu = CPU::new();
+    cpu.load_and_run(vec![
+        0xA9, 0b1100, // LDA #%1100.
+        0xA2, 0b1010, // LDX #%1010.
+        0x87, 0x10, // SAX $10.
+        0x00,
+    ])
+    .unwrap();
+    assert_eq!(cpu.mem_read(0x0010), 0b1000);
+}
+
+#[test]
+fn test_0xc7_dcp() {
+    let mut cpu = CPU::new();
+    cpu.bus.load(0x0010, &[0x06]);
+    cpu.load_and_run(vec![
+        0xA9, 0x05, // LDA #$05.
+        0xC7, 0x10, // DCP $10.
+        0x00,
+    ])
+    .unwrap();
+    assert_eq!(cpu.mem_read(0x0010), 0x05);
+    assert!(cpu.status.get(StatusFlag::Zero));
+    assert!(cpu.status.get(StatusFlag::Carry));
+}
+
+#[test]
+fn test_0xe7_isb() {
+    let mut cpu = CPU::new();
+    cpu.bus.load(0x0010, &[0x01]);
+    cpu.load_and_run(vec![
+        0x38, // SEC.
+        0xA9, 0x05, // LDA #$05.
+        0xE7, 0x10, // ISB $10.
+        0x00,
+    ])
+    .unwrap();
+    assert_eq!(cpu.mem_read(0x0010), 0x02);
+    assert_eq!(cpu.register_a, 0x03);
+    assert!(cpu.status.get(StatusFlag::Carry));
+}
+
+#[test]
+fn test_0x07_slo() {
+    let mut cpu = CPU::new();
+    cpu.bus.load(0x0010, &[0b1000_0001]);
+    cpu.load_and_run(vec![
+        0xA9,
+        0b0000_0100, // LDA.
+        0x07,
+        0x10, // SLO $10.
+        0x00,
+    ])
+    .unwrap();
+    assert_eq!(cpu.mem_read(0x0010), 0b0000_0010);
+    assert_eq!(cpu.register_a, 0b0000_0110);
+    assert!(cpu.status.get(StatusFlag::Carry));
+}
+
+#[test]
+fn test_0x27_rla() {
+    let mut cpu = CPU::new();
+    cpu.bus.load(0x0010, &[0b0100_0001]);
+    cpu.load_and_run(vec![
+        0x38, // SEC.
+        0xA9,
+        0b1000_0011, // LDA.
+        0x27,
+        0x10, // RLA $10.
+        0x00,
+    ])
+    .unwrap();
+    assert_eq!(cpu.mem_read(0x0010), 0b1000_0011);
+    assert_eq!(cpu.register_a, 0b1000_0011);
+    assert!(!cpu.status.get(StatusFlag::Carry));
+    assert!(cpu.status.get(StatusFlag::Negative));
+}
+
+#[test]
+fn test_0x47_sre() {
+    let mut cpu = CPU::new();
+    cpu.bus.load(0x0010, &[0b0000_0011]);
+    cpu.load_and_run(vec![
+        0xA9,
+        0b0000_0001, // LDA.
+        0x47,
+        0x10, // SRE $10.
+        0x00,
+    ])
+    .unwrap();
+    assert_eq!(cpu.mem_read(0x0010), 0b0000_0001);
+    assert_eq!(cpu.register_a, 0);
+    assert!(cpu.status.get(StatusFlag::Carry));
+    assert!(cpu.status.get(StatusFlag::Zero));
+}
+
+#[test]
+fn test_0x67_rra() {
+    let mut cpu = CPU::new();
+    cpu.bus.load(0x0010, &[0b0000_0100]);
+    cpu.load_and_run(vec![
+        0x38, // SEC.
+        0xA9, 0x01, // LDA #$01.
+        0x67, 0x10, // RRA $10.
+        0x00,
+    ])
+    .unwrap();
+    // $04 rotates to $82 with carry in, and the carry out (0) is added.
+    assert_eq!(cpu.mem_read(0x0010), 0x82);
+    assert_eq!(cpu.register_a, 0x83);
+}
+
+#[test]
+fn test_unofficial_nop_and_sbc() {
+    let mut cpu = CPU::new();
+    cpu.load_and_run(vec![
+        0x1A, // NOP.
+        0x80, 0xFF, // NOP #$FF.
+        0x1C, 0xFF, 0x00, // NOP $00FF,X.
+        0x38, // SEC.
+        0xA9, 0x05, // LDA #$05.
+        0xEB, 0x02, // SBC #$02.
+        0x00,
+    ])
+    .unwrap();
+    assert_eq!(cpu.register_a, 0x03);
+    assert_eq!(cpu.program_counter, 0x800C);
+}
+
+#[test]
+fn test_unofficial_page_cross_cycles() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![
+        0xA0, 0x01, // LDY #$01.
+        0xBF, 0xFF, 0x00, // LAX $00FF,Y. Crosses a page.
+        0xDB, 0xFF, 0x00, // DCP $00FF,Y. Read-modify-write, always 7 cycles.
+    ]);
+    cpu.reset();
+    let start = cpu.bus.cycles;
+    cpu.step();
+    cpu.step();
+    assert_eq!(cpu.bus.cycles - start, 2 + 5);
+    cpu.step();
+    assert_eq!(cpu.bus.cycles - start, 2 + 5 + 7);
+}
+
+#[test]
+fn test_strict_mode() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![
+        0xEA, // NOP.
+        0xA7, 0x10, // LAX $10.
+        0x8B, // XAA. Not implemented.
+    ]);
+    cpu.reset();
+    cpu.strict = true;
+    assert_eq!(cpu.try_step(), Ok(true));
+    assert_eq!(
+        cpu.try_step(),
+        Err(CpuError::UnofficialOpcode {
+            opcode: 0xA7,
+            addr: 0x8001
+        })
+    );
+    // The CPU stays at the failing instruction.
+    assert_eq!(cpu.program_counter, 0x8001);
+
+    cpu.strict = false;
+    assert_eq!(cpu.try_step(), Ok(true));
+    assert_eq!(
+        cpu.try_step(),
+        Err(CpuError::UnknownOpcode {
+            opcode: 0x8B,
+            addr: 0x8003
+        })
+    );
+    assert_eq!(
+        cpu.try_step().unwrap_err().to_string(),
+        "unknown opcode $8B at $8003"
+    );
+}
+
+#[test]
+fn test_run_stop_reasons() {
+    let mut cpu = CPU::new();
+    assert_eq!(cpu.load_and_run(vec![0xE8, 0x00]), Ok(StopReason::Halted));
+
+    // A callback stops the CPU before the second INX.
+    cpu.load(vec![0xE8, 0xE8, 0x00]);
+    cpu.reset();
+    let result = cpu.run_with_callback(|cpu| {
+        if cpu.register_x == 1 {
+            cpu.request_stop();
+        }
+    });
+    assert_eq!(result, Ok(StopReason::Requested));
+    assert_eq!(cpu.program_counter, 0x8001);
+    // Running again continues.
+    assert_eq!(cpu.run(), Ok(StopReason::Halted));
+    assert_eq!(cpu.register_x, 2);
+}
+
+#[test]
+fn test_run_errors() {
+    let mut cpu = CPU::new();
+    assert_eq!(
+        cpu.load_and_run(vec![0xE8, 0x12]),
+        Err(CpuError::Jam {
+            opcode: 0x12,
+            addr: 0x8001
+        })
+    );
+
+    // LDA # is truncated by the end of the program.
+    assert_eq!(
+        cpu.load_and_run(vec![0xE8, 0xA9]),
+        Err(CpuError::RanOffProgram { addr: 0x8001 })
+    );
+
+    // The bytes left after a shorter program are not run.
+    cpu.load(vec![0xE8, 0xE8, 0xE8, 0x00]);
+    assert_eq!(
+        cpu.load_and_run(vec![0xE8]),
+        Err(CpuError::RanOffProgram { addr: 0x8001 })
+    );
+
+    // JMP $8000.
+    cpu.step_limit = Some(100);
+    assert_eq!(
+        cpu.load_and_run(vec![0x4C, 0x00, 0x80]),
+        Err(CpuError::StepLimitExceeded { limit: 100 })
+    );
+    assert_eq!(
+        CpuError::StepLimitExceeded { limit: 100 }.to_string(),
+        "step limit of 100 exceeded"
+    );
+}
+
+#[test]
+fn test_stack_wraps() {
+    // The stack pointer wraps within page 1 rather than stopping the CPU.
+    let mut cpu = CPU::new();
+    cpu.load_and_run(asm!("LDX #$00\nTXS\nLDA #$42\nPHA\nPHA\nPLA\nPLA\nTSX\nBRK"))
+        .unwrap();
+    assert_eq!(cpu.mem_read(0x0100), 0x42);
+    assert_eq!(cpu.mem_read(0x01FF), 0x42);
+    assert_eq!(cpu.register_x, 0x00);
+
+    // Unbounded recursion overwrites the stack until the step limit.
+    cpu.step_limit = Some(1000);
+    assert_eq!(
+        cpu.load_and_run(asm!("JSR $8000")),
+        Err(CpuError::StepLimitExceeded { limit: 1000 })
+    );
+}
+
+#[test]
+fn test_variant_parse() {
+    assert_eq!(Variant::parse("65C02"), Ok(Variant::Cmos65C02));
+    assert_eq!(Variant::parse("6502"), Ok(Variant::Nmos6502));
+    assert_eq!(Variant::parse("2a03"), Ok(Variant::Nes2A03));
+    assert!(Variant::parse("z80").is_err());
+}
+
+#[test]
+fn test_decimal_ignored_on_2a03() {
+    let mut cpu = CPU::new();
+    cpu.load_and_run(asm!("SED\nCLC\nLDA #$09\nADC #$01\nBRK"))
+        .unwrap();
+    assert_eq!(cpu.register_a, 0x0A);
+}
+
+// run_decimal executes the loaded `ADC $10` or `SBC $10` in decimal mode with A = `a`, M = `m` and `carry`.
+// Returns A and P.
+fn run_decimal(cpu: &mut CPU, a: u8, m: u8, carry: bool) -> (u8, u8) {
+    cpu.program_counter = 0x8000;
+    cpu.register_a = a;
+    cpu.mem_write(0x10, m);
+    // Mask the APU frame IRQ so the step executes the instruction.
+    cpu.status
+        .set_all(CPU::DECIMAL_FLAG | CPU::INTERRUPT_DISABLE_FLAG | carry as u8);
+    cpu.step();
+    (cpu.register_a, cpu.status.get_all())
+}
+
+#[test]
+fn test_decimal_mode() {
+    let bcd = |n: i32| (((n / 10) << 4) | (n % 10)) as u8;
+    for variant in [Variant::Nmos6502, Variant::Cmos65C02] {
+        for (op, sign) in [("ADC", 1), ("SBC", -1)] {
+            let mut cpu = CPU::new();
+            cpu.variant = variant;
+            cpu.load(asm!(&format!("{} $10", op)));
+            // Every valid BCD operand gives the decimal result and carry.
+            for a in 0..100 {
+                for m in 0..100 {
+                    for carry in [false, true] {
+                        let want = match sign {
+                            1 => a + m + carry as i32,
+                            _ => a - m - 1 + carry as i32,
+                        };
+                        let (got, p) = run_decimal(&mut cpu, bcd(a), bcd(m), carry);
+                        let case = format!("{:?} {} {} {} {}", variant, a, op, m, carry);
+                        assert_eq!(got, bcd(want.rem_euclid(100)), "{}", case);
+                        assert_eq!(
+                            p & CPU::CARRY_FLAG != 0,
+                            if sign == 1 { want >= 100 } else { want >= 0 },
+                            "{}",
+                            case
+                        );
+                        if variant == Variant::Cmos65C02 {
+                            assert_eq!(p & CPU::ZERO_FLAG != 0, got == 0, "{}", case);
+                            assert_eq!(p & CPU::NEGATIVE_FLAG != 0, got & 0x80 != 0, "{}", case);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_decimal_flags_by_variant() {
+    // See http://www.6502.org/tutorials/decimal_mode.html#3.3
+    let mut nmos = CPU::new();
+    nmos.variant = Variant::Nmos6502;
+    nmos.load(asm!("ADC $10"));
+    let mut cmos = CPU::new();
+    cmos.variant = Variant::Cmos65C02;
+    cmos.load(asm!("ADC $10"));
+
+    // $99 + $01 = $00. The NMOS 6502 sets Z from the binary sum $9A and N from the unadjusted sum.
+    let (a, p) = run_decimal(&mut nmos, 0x99, 0x01, false);
+    assert_eq!(
+        (a, p & CPU::ZERO_FLAG, p & CPU::NEGATIVE_FLAG),
+        (0x00, 0, CPU::NEGATIVE_FLAG)
+    );
+    let (a, p) = run_decimal(&mut cmos, 0x99, 0x01, false);
+    assert_eq!(
+        (a, p & CPU::ZERO_FLAG, p & CPU::NEGATIVE_FLAG),
+        (0x00, CPU::ZERO_FLAG, 0)
+    );
+
+    // $79 + $00 + carry = $80 overflows on both.
+    for cpu in [&mut nmos, &mut cmos] {
+        let (a, p) = run_decimal(cpu, 0x79, 0x00, true);
+        assert_eq!((a, p & CPU::OVERFLOW_FLAG), (0x80, CPU::OVERFLOW_FLAG));
+    }
+
+    // The 65C02 takes an extra cycle in decimal mode.
+    let cycles = |cpu: &mut CPU| {
+        let start = cpu.bus.cycles;
+        run_decimal(cpu, 0x12, 0x34, false);
+        cpu.bus.cycles - start
+    };
+    assert_eq!(cycles(&mut nmos), 3);
+    assert_eq!(cycles(&mut cmos), 4);
+}
+
+#[test]
+fn test_65c02_instructions() {
+    let mut cpu = CPU::new();
+    cpu.variant = Variant::Cmos65C02;
+    cpu.load_and_run(asm!(
+        "
+        LDX #$12
+        LDY #$34
+        PHX
+        PHY
+        PLX
+        PLY
+        LDA #$FF
+        STA $10
+        STZ $10
+        LDA #$0F
+        STA $11
+        LDA #$3C
+        TSB $11 ; $3F
+        LDA #$0C
+        TRB $11 ; $33
+        BRK
+        "
+    ))
+    .unwrap();
+    assert_eq!((cpu.register_x, cpu.register_y), (0x34, 0x12));
+    assert_eq!(cpu.mem_read(0x10), 0x00);
+    assert_eq!(cpu.mem_read(0x11), 0x33);
+    assert!(!cpu.status.get(StatusFlag::Zero));
+
+    let mut cpu = CPU::new();
+    cpu.variant = Variant::Cmos65C02;
+    cpu.load_and_run(asm!(
+        "
+        LDA #<data
+        STA $20
+        LDA #>data
+        STA $21
+        LDA ($20)
+        INC A
+        BRA skip
+        .byte $FF ; Skipped.
+    skip:
+        LDX #2
+        JMP (table,X)
+        .byte $FF ; Skipped.
+    table:
+        .word 0, done
+    done:
+        BIT #$C0 ; Only sets Z.
+        BRK
+    data:
+        .byte $41
+        "
+    ))
+    .unwrap();
+    assert_eq!(cpu.register_a, 0x42);
+    assert!(!cpu.status.get(StatusFlag::Zero));
+    assert!(!cpu.status.get(StatusFlag::Negative));
+    assert!(!cpu.status.get(StatusFlag::Overflow));
+}
+
+#[test]
+fn test_65c02_has_no_unofficial_opcodes() {
+    let mut cpu = CPU::new();
+    cpu.variant = Variant::Cmos65C02;
+    assert_eq!(
+        cpu.load_and_run(asm!("LAX $10")),
+        Err(CpuError::UnknownOpcode {
+            opcode: 0xA7,
+            addr: 0x8000
+        })
+    );
+}
+
+#[test]
+fn test_65c02_interrupts_clear_decimal() {
+    for (variant, decimal) in [(Variant::Nmos6502, true), (Variant::Cmos65C02, false)] {
+        let mut cpu = CPU::new();
+        cpu.variant = variant;
+        cpu.load(asm!("SED\nNOP\nBRK"));
+        cpu.reset();
+        cpu.mem_write_u16(0xFFFA, 0x9000);
+        // The handler halts.
+        cpu.bus.load(0x9000, &[0x00]);
+        cpu.run_with_callback(|cpu| {
+            if cpu.program_counter == 0x8001 {
+                cpu.trigger_nmi();
+            }
+        })
+        .unwrap();
+        // BRK halted after its opcode.
+        assert_eq!(cpu.program_counter, 0x9001);
+        assert_eq!(
+            cpu.status.get(StatusFlag::Decimal),
+            decimal,
+            "{:?}",
+            variant
+        );
+    }
+}
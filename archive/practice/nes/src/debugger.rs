@@ -0,0 +1,363 @@
+// debugger runs a CPU with breakpoints and watchpoints.
+// `Debugger` is the programmatic API. `repl` reads commands from a reader so sessions can be scripted.
+use crate::bus::{Access, WatchHit};
+use crate::cpu::{CpuError, CPU};
+use crate::disasm;
+use crate::trace::trace;
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+
+const JSR: u8 = 0x20;
+
+// Break is the reason the debugger stopped.
+#[derive(Debug, PartialEq)]
+pub enum Break {
+    // Step is returned when a step completes without hitting a breakpoint or watchpoint.
+    Step,
+    Breakpoint(u16),
+    Watchpoint(WatchHit),
+    // Halted is returned when the CPU stops (e.g. BRK with `halt_on_brk`).
+    Halted,
+    // Error is returned when the CPU cannot execute the next instruction (e.g. an unknown opcode).
+    Error(CpuError),
+}
+
+impl std::fmt::Display for Break {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Break::Step => write!(f, "step"),
+            Break::Breakpoint(pc) => write!(f, "breakpoint at ${:04X}", pc),
+            Break::Watchpoint(hit) => {
+                let access = match hit.access {
+                    Access::Read => "read",
+                    Access::Write => "write",
+                };
+                write!(
+                    f,
+                    "watchpoint: {} ${:04X} = {:02X}",
+                    access, hit.addr, hit.value
+                )
+            }
+            Break::Halted => write!(f, "halted"),
+            Break::Error(e) => write!(f, "error: {}", e),
+        }
+    }
+}
+
+pub struct Debugger {
+    pub cpu: CPU,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: BTreeSet<u16>,
+}
+
+impl Debugger {
+    pub fn new(cpu: CPU) -> Self {
+        Debugger {
+            cpu,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+        }
+    }
+
+    // add_breakpoint stops execution before the instruction at `pc`.
+    pub fn add_breakpoint(&mut self, pc: u16) {
+        self.breakpoints.insert(pc);
+    }
+
+    pub fn remove_breakpoint(&mut self, pc: u16) {
+        self.breakpoints.remove(&pc);
+    }
+
+    // add_watchpoint stops execution after an instruction that accesses `addr`.
+    pub fn add_watchpoint(&mut self, addr: u16, access: Access) {
+        self.watchpoints.insert(addr);
+        self.cpu.bus.add_watchpoint(addr, access);
+    }
+
+    pub fn remove_watchpoint(&mut self, addr: u16) {
+        self.watchpoints.remove(&addr);
+        self.cpu.bus.remove_watchpoint(addr);
+    }
+
+    // step executes one instruction, or services one interrupt.
+    pub fn step(&mut self) -> Break {
+        self.cpu.bus.watch_hits.clear();
+        match self.cpu.try_step() {
+            Ok(true) => {}
+            Ok(false) => return Break::Halted,
+            Err(e) => return Break::Error(e),
+        }
+        match self.cpu.bus.watch_hits.first() {
+            Some(hit) => Break::Watchpoint(*hit),
+            None => Break::Step,
+        }
+    }
+
+    // step_over executes one instruction. If it is JSR, it runs until the subroutine returns.
+    pub fn step_over(&mut self) -> Break {
+        let pc = self.cpu.program_counter;
+        if self.cpu.bus.peek(pc) != JSR {
+            return self.step();
+        }
+        let return_pc = pc.wrapping_add(3);
+        let stack_pointer = self.cpu.stack_pointer;
+        self.run_until(|cpu| cpu.program_counter == return_pc && cpu.stack_pointer >= stack_pointer)
+    }
+
+    // cont runs until a breakpoint, a watchpoint or the CPU halts.
+    pub fn cont(&mut self) -> Break {
+        self.run_until(|_| false)
+    }
+
+    // run_until steps at least once, then until `done` returns true or execution breaks.
+    fn run_until<F>(&mut self, done: F) -> Break
+    where
+        F: Fn(&CPU) -> bool,
+    {
+        loop {
+            let result = self.step();
+            if result != Break::Step || done(&self.cpu) {
+                return result;
+            }
+            let pc = self.cpu.program_counter;
+            if self.breakpoints.contains(&pc) {
+                return Break::Breakpoint(pc);
+            }
+        }
+    }
+
+    // registers formats the registers. Set flags are upper case.
+    pub fn registers(&self) -> String {
+        let cpu = &self.cpu;
+        let status = cpu.status.get_all();
+        let flags: String = "NV-BDIZC"
+            .chars()
+            .enumerate()
+            .map(|(i, c)| {
+                if status & (0x80 >> i) != 0 {
+                    c
+                } else {
+                    c.to_ascii_lowercase()
+                }
+            })
+            .collect();
+        format!(
+            "PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} SP:{:02X} P:{:02X} {} CYC:{}",
+            cpu.program_counter,
+            cpu.register_a,
+            cpu.register_x,
+            cpu.register_y,
+            cpu.stack_pointer,
+            status,
+            flags,
+            cpu.bus.cycles,
+        )
+    }
+
+    // hexdump formats `len` bytes of memory starting at `addr`, 16 bytes per line. Reads have no side effects.
+    pub fn hexdump(&self, addr: u16, len: usize) -> String {
+        let bytes: Vec<u8> = (0..len)
+            .map(|i| self.cpu.bus.peek(addr.wrapping_add(i as u16)))
+            .collect();
+        let mut lines = Vec::new();
+        for (i, chunk) in bytes.chunks(16).enumerate() {
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{:02X}", b)).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            lines.push(format!(
+                "{:04X}  {:<47}  |{}|",
+                addr.wrapping_add(i as u16 * 16),
+                hex.join(" "),
+                ascii
+            ));
+        }
+        lines.join("\n")
+    }
+
+    // list disassembles `count` instructions starting at `addr`.
+    pub fn list(&self, addr: u16, count: usize) -> String {
+        let mut lines = Vec::new();
+        let mut addr = addr;
+        for _ in 0..count {
+            // 3 bytes is the longest instruction.
+            let bytes: Vec<u8> = (0..3)
+                .map(|i| self.cpu.bus.peek(addr.wrapping_add(i)))
+                .collect();
+            let instruction = disasm::decode(&bytes, addr, self.cpu.variant);
+            let marker = if self.breakpoints.contains(&addr) {
+                "*"
+            } else {
+                " "
+            };
+            lines.push(format!("{}{}", marker, instruction));
+            addr = addr.wrapping_add(instruction.bytes.len() as u16);
+        }
+        lines.join("\n")
+    }
+
+    // execute runs one command of the REPL and returns its output.
+    pub fn execute(&mut self, line: &str) -> Result<String, String> {
+        let args: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = args.split_first() else {
+            return Ok(String::new());
+        };
+        let arg = |i: usize| -> Result<u16, String> {
+            let s = args
+                .get(i)
+                .ok_or(format!("{}: missing argument", command))?;
+            parse_u16(s)
+        };
+        let stopped = |debugger: &Debugger, result: Break| -> String {
+            match result {
+                Break::Step => trace(&debugger.cpu),
+                _ => format!("{}\n{}", result, trace(&debugger.cpu)),
+            }
+        };
+        match command {
+            "s" | "step" => {
+                let count = if args.is_empty() { 1 } else { arg(0)? };
+                let mut result = Break::Step;
+                for _ in 0..count {
+                    result = self.step();
+                    if result != Break::Step {
+                        break;
+                    }
+                }
+                Ok(stopped(self, result))
+            }
+            "n" | "next" => {
+                let result = self.step_over();
+                Ok(stopped(self, result))
+            }
+            "c" | "continue" => {
+                let result = self.cont();
+                Ok(stopped(self, result))
+            }
+            "b" | "break" => {
+                let pc = arg(0)?;
+                self.add_breakpoint(pc);
+                Ok(format!("breakpoint at ${:04X}", pc))
+            }
+            "w" | "watch" => {
+                let addr = arg(0)?;
+                let kinds = args.get(1).copied().unwrap_or("rw");
+                if kinds.is_empty() || !kinds.chars().all(|c| c == 'r' || c == 'w') {
+                    return Err(format!(
+                        "watch: invalid access {:?}, want r, w or rw",
+                        kinds
+                    ));
+                }
+                if kinds.contains('r') {
+                    self.add_watchpoint(addr, Access::Read);
+                }
+                if kinds.contains('w') {
+                    self.add_watchpoint(addr, Access::Write);
+                }
+                Ok(format!("watchpoint ({}) at ${:04X}", kinds, addr))
+            }
+            "d" | "delete" => {
+                let addr = arg(0)?;
+                self.remove_breakpoint(addr);
+                self.remove_watchpoint(addr);
+                Ok(format!("deleted ${:04X}", addr))
+            }
+            "i" | "info" => {
+                let breakpoints: Vec<String> = self
+                    .breakpoints
+                    .iter()
+                    .map(|a| format!("${:04X}", a))
+                    .collect();
+                let watchpoints: Vec<String> = self
+                    .watchpoints
+                    .iter()
+                    .map(|a| format!("${:04X}", a))
+                    .collect();
+                Ok(format!(
+                    "breakpoints: {}\nwatchpoints: {}",
+                    breakpoints.join(" "),
+                    watchpoints.join(" ")
+                ))
+            }
+            "r" | "regs" => Ok(self.registers()),
+            "x" | "mem" => {
+                let len = if args.len() > 1 { arg(1)? } else { 64 };
+                Ok(self.hexdump(arg(0)?, len as usize))
+            }
+            "l" | "list" => {
+                let addr = if args.is_empty() {
+                    self.cpu.program_counter
+                } else {
+                    arg(0)?
+                };
+                let count = if args.len() > 1 { arg(1)? } else { 10 };
+                Ok(self.list(addr, count as usize))
+            }
+            "h" | "help" => Ok(HELP.to_string()),
+            _ => Err(format!("unknown command {:?}. Try \"help\".", command)),
+        }
+    }
+
+    // repl reads commands from `input` until "quit" or end of input. An empty line repeats the last command.
+    pub fn repl<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> std::io::Result<()> {
+        writeln!(output, "{}", trace(&self.cpu))?;
+        let mut last = String::new();
+        let mut lines = input.lines();
+        loop {
+            write!(output, "> ")?;
+            output.flush()?;
+            let Some(line) = lines.next() else {
+                return Ok(());
+            };
+            let line = line?;
+            let line = if line.trim().is_empty() {
+                last.clone()
+            } else {
+                line
+            };
+            if matches!(line.trim(), "q" | "quit") {
+                return Ok(());
+            }
+            match self.execute(&line) {
+                Ok(out) if out.is_empty() => {}
+                Ok(out) => writeln!(output, "{}", out)?,
+                Err(e) => writeln!(output, "error: {}", e)?,
+            }
+            last = line;
+        }
+    }
+}
+
+// parse_u16 parses a hex address ("$8000" or "0x8000") or a decimal number.
+pub fn parse_u16(s: &str) -> Result<u16, String> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix('$')) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|e| format!("invalid number {:?}: {}", s, e))
+}
+
+const HELP: &str = "commands:
+  s, step [N]          execute N instructions (default 1)
+  n, next              step over JSR
+  c, continue          run until a breakpoint, a watchpoint or halt
+  b, break ADDR        set a breakpoint
+  w, watch ADDR [r|w|rw]
+                       set a watchpoint (default rw)
+  d, delete ADDR       delete breakpoints and watchpoints on ADDR
+  i, info              list breakpoints and watchpoints
+  r, regs              show registers and flags
+  x, mem ADDR [LEN]    dump memory (default 64 bytes)
+  l, list [ADDR] [N]   disassemble N instructions (default 10 at PC)
+  q, quit              exit
+Addresses are hex with a $ or 0x prefix, or decimal.";
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,171 @@
+use super::*;
+
+// make_debugger loads `program` at 0x8000.
+fn make_debugger(program: Vec<u8>) -> Debugger {
+    let mut cpu = CPU::new();
+    cpu.load(program);
+    cpu.reset();
+    Debugger::new(cpu)
+}
+
+// subroutine_program calls a subroutine that increments X twice, then stores X at 0x0200.
+fn subroutine_program() -> Vec<u8> {
+    vec![
+        0x20, 0x07, 0x80, // 8000: JSR $8007.
+        0x8E, 0x00, 0x02, // 8003: STX $0200.
+        0x00, // 8006: BRK.
+        0xE8, // 8007: INX.
+        0xE8, // 8008: INX.
+        0x60, // 8009: RTS.
+    ]
+}
+
+#[test]
+fn test_step() {
+    let mut debugger = make_debugger(subroutine_program());
+    assert_eq!(debugger.step(), Break::Step);
+    assert_eq!(debugger.cpu.program_counter, 0x8007);
+    assert_eq!(debugger.step(), Break::Step);
+    assert_eq!(debugger.cpu.register_x, 1);
+}
+
+#[test]
+fn test_step_error() {
+    let mut debugger = make_debugger(vec![0xE8, 0x02]);
+    assert_eq!(
+        debugger.cont(),
+        Break::Error(CpuError::Jam {
+            opcode: 0x02,
+            addr: 0x8001
+        })
+    );
+    let output = debugger.execute("s").unwrap();
+    assert!(
+        output.starts_with("error: CPU jammed by opcode $02 at $8001\n8001  02       *JAM"),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn test_step_over() {
+    let mut debugger = make_debugger(subroutine_program());
+    assert_eq!(debugger.step_over(), Break::Step);
+    assert_eq!(debugger.cpu.program_counter, 0x8003);
+    assert_eq!(debugger.cpu.register_x, 2);
+    // Not JSR. Steps one instruction.
+    assert_eq!(debugger.step_over(), Break::Step);
+    assert_eq!(debugger.cpu.program_counter, 0x8006);
+}
+
+#[test]
+fn test_step_over_stops_at_breakpoint() {
+    let mut debugger = make_debugger(subroutine_program());
+    debugger.add_breakpoint(0x8008);
+    assert_eq!(debugger.step_over(), Break::Breakpoint(0x8008));
+    assert_eq!(debugger.cpu.register_x, 1);
+}
+
+#[test]
+fn test_breakpoints() {
+    let mut debugger = make_debugger(subroutine_program());
+    debugger.add_breakpoint(0x8003);
+    assert_eq!(debugger.cont(), Break::Breakpoint(0x8003));
+    // Continuing from a breakpoint executes the instruction at the breakpoint.
+    assert_eq!(debugger.cont(), Break::Halted);
+}
+
+#[test]
+fn test_watchpoints() {
+    let mut debugger = make_debugger(vec![
+        0xA5, 0x10, // LDA $10.
+        0x85, 0x11, // STA $11.
+        0xA5, 0x11, // LDA $11.
+        0x00, // BRK.
+    ]);
+    debugger.cpu.mem_write(0x10, 0x42);
+    debugger.add_watchpoint(0x11, Access::Write);
+    let hit = WatchHit {
+        addr: 0x11,
+        access: Access::Write,
+        value: 0x42,
+    };
+    assert_eq!(debugger.cont(), Break::Watchpoint(hit));
+    assert_eq!(debugger.cpu.program_counter, 0x8004);
+    // Write watchpoints ignore reads.
+    assert_eq!(debugger.cont(), Break::Halted);
+
+    let mut debugger = make_debugger(vec![
+        0xA5, 0x10, // LDA $10.
+        0x00, // BRK.
+    ]);
+    debugger.add_watchpoint(0x10, Access::Read);
+    debugger.remove_watchpoint(0x10);
+    assert_eq!(debugger.cont(), Break::Halted);
+}
+
+#[test]
+fn test_registers() {
+    let mut debugger = make_debugger(vec![0x00]);
+    debugger.cpu.register_a = 0x12;
+    debugger
+        .cpu
+        .status
+        .set_all(CPU::NEGATIVE_FLAG | CPU::CARRY_FLAG);
+    assert_eq!(
+        debugger.registers(),
+        "PC:8000 A:12 X:00 Y:00 SP:FD P:81 Nv-bdizC CYC:7"
+    );
+}
+
+#[test]
+fn test_hexdump() {
+    let mut debugger = make_debugger(vec![0x00]);
+    for (i, b) in b"Hello".iter().enumerate() {
+        debugger.cpu.mem_write(0x0200 + i as u16, *b);
+    }
+    assert_eq!(
+        debugger.hexdump(0x0200, 20),
+        "0200  48 65 6C 6C 6F 00 00 00 00 00 00 00 00 00 00 00  |Hello...........|\n\
+         0210  00 00 00 00                                      |....|"
+    );
+}
+
+#[test]
+fn test_repl_session() {
+    let mut debugger = make_debugger(subroutine_program());
+    let input = "\
+break $8009
+list $8007 3
+continue
+regs
+
+next
+watch $0200 w
+c
+x 0x0200 1
+bogus
+quit
+step
+";
+    let mut output = Vec::new();
+    debugger.repl(input.as_bytes(), &mut output).unwrap();
+    let want = "\
+8000  20 07 80  JSR $8007                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+> breakpoint at $8009
+>  8007  E8        INX
+ 8008  E8        INX
+*8009  60        RTS
+> breakpoint at $8009
+8009  60        RTS                             A:00 X:02 Y:00 P:24 SP:FB PPU:  0, 51 CYC:17
+> PC:8009 A:00 X:02 Y:00 SP:FB P:04 nv-bdIzc CYC:17
+> PC:8009 A:00 X:02 Y:00 SP:FB P:04 nv-bdIzc CYC:17
+> 8003  8E 00 02  STX $0200 = 00                  A:00 X:02 Y:00 P:24 SP:FD PPU:  0, 69 CYC:23
+> watchpoint (w) at $0200
+> watchpoint: write $0200 = 02
+8006  00        BRK                             A:00 X:02 Y:00 P:24 SP:FD PPU:  0, 81 CYC:27
+> 0200  02                                               |.|
+> error: unknown command \"bogus\". Try \"help\".
+> ";
+    assert_eq!(String::from_utf8(output).unwrap(), want);
+}
@@ -0,0 +1,89 @@
+// disasm disassembles 6502 machine code using `opcodes::lookup`.
+use crate::cpu::{AddressingMode, Variant};
+use crate::opcodes::{self, OpCode};
+
+pub struct Instruction {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    // opcode is None if the bytes are not a known instruction.
+    pub opcode: Option<&'static OpCode>,
+    pub text: String,
+}
+
+impl std::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let hex: Vec<String> = self.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        write!(f, "{:04X}  {:8}  {}", self.addr, hex.join(" "), self.text)
+    }
+}
+
+// format_operand formats the operand of an instruction at `addr`. `bytes` includes the opcode.
+pub fn format_operand(opcode: &OpCode, bytes: &[u8], addr: u16) -> String {
+    let u16_operand = || u16::from_le_bytes([bytes[1], bytes[2]]);
+    match opcode.mode {
+        AddressingMode::Accumulator => "A".to_string(),
+        AddressingMode::Immediate => format!("#${:02X}", bytes[1]),
+        AddressingMode::ZeroPage => format!("${:02X}", bytes[1]),
+        AddressingMode::ZeroPage_X => format!("${:02X},X", bytes[1]),
+        AddressingMode::ZeroPage_Y => format!("${:02X},Y", bytes[1]),
+        AddressingMode::Absolute => format!("${:04X}", u16_operand()),
+        AddressingMode::Absolute_X => format!("${:04X},X", u16_operand()),
+        AddressingMode::Absolute_Y => format!("${:04X},Y", u16_operand()),
+        AddressingMode::Indirect => format!("(${:04X})", u16_operand()),
+        AddressingMode::Indirect_X => format!("(${:02X},X)", bytes[1]),
+        AddressingMode::Indirect_Y => format!("(${:02X}),Y", bytes[1]),
+        AddressingMode::ZeroPage_Indirect => format!("(${:02X})", bytes[1]),
+        AddressingMode::Absolute_Indirect_X => format!("(${:04X},X)", u16_operand()),
+        AddressingMode::Relative => {
+            // Branch targets are relative to the next instruction.
+            let next = addr.wrapping_add(2);
+            format!("${:04X}", next.wrapping_add_signed(bytes[1] as i8 as i16))
+        }
+        AddressingMode::NoneAddressing => String::new(),
+    }
+}
+
+// decode decodes the instruction of `variant` at the start of `bytes`, which is located at `addr`.
+// Unofficial opcodes are prefixed with "*". Unknown opcodes, and instructions truncated by the end of `bytes`,
+// decode as a single `.byte`.
+pub fn decode(bytes: &[u8], addr: u16, variant: Variant) -> Instruction {
+    if let Some(opcode) = opcodes::lookup_variant(variant, bytes[0]) {
+        let len = opcode.bytes as usize;
+        if bytes.len() >= len {
+            let operand = format_operand(opcode, &bytes[..len], addr);
+            let text = if operand.is_empty() {
+                opcode.display_name()
+            } else {
+                format!("{} {}", opcode.display_name(), operand)
+            };
+            return Instruction {
+                addr,
+                bytes: bytes[..len].to_vec(),
+                opcode: Some(opcode),
+                text,
+            };
+        }
+    }
+    Instruction {
+        addr,
+        bytes: vec![bytes[0]],
+        opcode: None,
+        text: format!(".byte ${:02X}", bytes[0]),
+    }
+}
+
+// disassemble decodes all of `bytes`, which are loaded at `origin`.
+pub fn disassemble(bytes: &[u8], origin: u16, variant: Variant) -> Vec<Instruction> {
+    let mut result = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let addr = origin.wrapping_add(offset as u16);
+        let instruction = decode(&bytes[offset..], addr, variant);
+        offset += instruction.bytes.len();
+        result.push(instruction);
+    }
+    result
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,117 @@
+use super::*;
+
+fn disassemble_to_strings(bytes: &[u8], origin: u16) -> Vec<String> {
+    disassemble(bytes, origin, Variant::Nes2A03)
+        .iter()
+        .map(|i| i.to_string())
+        .collect()
+}
+
+#[test]
+fn test_disassemble() {
+    let got = disassemble_to_strings(&[0xa9, 0xc0, 0xaa, 0xe8, 0x00], 0x8000);
+    assert_eq!(
+        got,
+        vec![
+            "8000  A9 C0     LDA #$C0",
+            "8002  AA        TAX",
+            "8003  E8        INX",
+            "8004  00        BRK",
+        ]
+    );
+}
+
+#[test]
+fn test_addressing_modes() {
+    let got: Vec<String> = disassemble(
+        &[
+            0x4A, // LSR A.
+            0xA5, 0x44, // LDA $44.
+            0xB5, 0x44, // LDA $44,X.
+            0xB6, 0x44, // LDX $44,Y.
+            0xAD, 0x00, 0x04, // LDA $0400.
+            0xBD, 0x00, 0x04, // LDA $0400,X.
+            0xB9, 0x00, 0x04, // LDA $0400,Y.
+            0x6C, 0x00, 0x02, // JMP ($0200).
+            0xA1, 0x20, // LDA ($20,X).
+            0xB1, 0x20, // LDA ($20),Y.
+        ],
+        0x0600,
+        Variant::Nes2A03,
+    )
+    .iter()
+    .map(|i| i.text.clone())
+    .collect();
+    assert_eq!(
+        got,
+        vec![
+            "LSR A",
+            "LDA $44",
+            "LDA $44,X",
+            "LDX $44,Y",
+            "LDA $0400",
+            "LDA $0400,X",
+            "LDA $0400,Y",
+            "JMP ($0200)",
+            "LDA ($20,X)",
+            "LDA ($20),Y",
+        ]
+    );
+}
+
+#[test]
+fn test_relative_branch_targets() {
+    let got = disassemble_to_strings(
+        &[
+            0xD0, 0x02, // BNE forward.
+            0xF0, 0xFC, // BEQ backward.
+        ],
+        0x0600,
+    );
+    assert_eq!(
+        got,
+        vec!["0600  D0 02     BNE $0604", "0602  F0 FC     BEQ $0600",]
+    );
+}
+
+#[test]
+fn test_unknown_and_truncated_bytes() {
+    let got = disassemble_to_strings(
+        &[
+            0x8B, // Unknown (XAA).
+            0xAD, 0x00, // Truncated LDA $xx00.
+        ],
+        0x8000,
+    );
+    assert_eq!(
+        got,
+        vec![
+            "8000  8B        .byte $8B",
+            "8001  AD        .byte $AD",
+            "8002  00        BRK",
+        ]
+    );
+}
+
+#[test]
+fn test_65c02() {
+    let bytes = [
+        0xB2, 0x20, // LDA ($20).
+        0x7C, 0x00, 0x02, // JMP ($0200,X).
+        0xA7, 0x20, // Unofficial LAX on the NMOS 6502, unknown on the 65C02.
+    ];
+    let text = |variant| -> Vec<String> {
+        disassemble(&bytes, 0x8000, variant)
+            .iter()
+            .map(|i| i.text.clone())
+            .collect()
+    };
+    assert_eq!(
+        text(Variant::Cmos65C02),
+        vec!["LDA ($20)", "JMP ($0200,X)", ".byte $A7", ".byte $20"]
+    );
+    assert_eq!(
+        text(Variant::Nmos6502),
+        vec!["*JAM", "JSR $007C", "*JAM", "*LAX $20"]
+    );
+}
@@ -0,0 +1,133 @@
+// headless runs a machine without a display, e.g. on CI. Selected frames are hashed for golden-image tests
+// and optionally written as PNG files. Audio can be recorded to a WAV file, and input to a movie.
+use crate::input::Input;
+use crate::machine::Machine;
+use crate::movie::Movie;
+use crate::png;
+use crate::wav::WavWriter;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+#[derive(Default)]
+pub struct Options {
+    // frames stops the run after this many frames.
+    pub frames: Option<u64>,
+    // cycles stops the run at the end of the frame in which the CPU cycle count reaches this value.
+    pub cycles: Option<usize>,
+    // select lists the frames to output. If empty, the last frame is output.
+    pub select: Vec<u64>,
+    // png_dir is where selected frames are written as frame_NNNNN.png.
+    pub png_dir: Option<PathBuf>,
+    // wav is where the audio of the whole run is written.
+    pub wav: Option<PathBuf>,
+    // sample_rate is the sample rate of the WAV file. Defaults to DEFAULT_SAMPLE_RATE.
+    pub sample_rate: Option<u32>,
+    // record is where the input of the whole run is written as a movie.
+    pub record: Option<PathBuf>,
+}
+
+// FrameHash is the hash of a selected frame. Frames are numbered from 1.
+#[derive(Debug, PartialEq)]
+pub struct FrameHash {
+    pub frame: u64,
+    pub hash: u64,
+}
+
+impl std::fmt::Display for FrameHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "frame {}: {:016x}", self.frame, self.hash)
+    }
+}
+
+// hash_frame is the 64-bit FNV-1a hash of RGB24 frame data.
+pub fn hash_frame(rgb: &[u8]) -> u64 {
+    rgb.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+// run runs `machine` until the frame or cycle limit is reached or the CPU halts. At least one limit is required.
+pub fn run(
+    machine: &mut dyn Machine,
+    input: &mut dyn Input,
+    options: &Options,
+) -> Result<Vec<FrameHash>, String> {
+    if options.frames.is_none() && options.cycles.is_none() {
+        return Err("a frame or cycle limit is required".to_string());
+    }
+    let mut wav = match &options.wav {
+        Some(path) => {
+            let rate = options.sample_rate.unwrap_or(DEFAULT_SAMPLE_RATE);
+            machine.set_sample_rate(Some(rate));
+            let file = File::create(path)
+                .map_err(|e| format!("failed to create {}: {}", path.display(), e))?;
+            let writer = WavWriter::new(BufWriter::new(file), rate)
+                .map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+            Some((path, writer))
+        }
+        None => None,
+    };
+    let mut movie = options.record.as_ref().map(|_| Movie::new(machine));
+    let mut hashes = Vec::new();
+    let mut frame = 0;
+    loop {
+        if options.frames.is_some_and(|n| frame >= n)
+            || options
+                .cycles
+                .is_some_and(|n| machine.cpu().bus.cycles >= n)
+        {
+            break;
+        }
+        let buttons = input.poll(frame + 1);
+        if let Some(movie) = &mut movie {
+            movie.record(frame + 1, buttons);
+        }
+        machine.set_buttons(buttons);
+        if !machine.run_frame() {
+            break;
+        }
+        frame += 1;
+        if let Some((path, writer)) = &mut wav {
+            writer
+                .write_samples(&machine.take_samples())
+                .map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+        }
+        if options.select.contains(&frame) {
+            hashes.push(output_frame(machine, frame, options)?);
+        }
+    }
+    if options.select.is_empty() && frame > 0 {
+        hashes.push(output_frame(machine, frame, options)?);
+    }
+    if let Some((path, writer)) = wav {
+        writer
+            .finish()
+            .map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+    }
+    if let (Some(path), Some(movie)) = (&options.record, movie) {
+        movie.save(path)?;
+    }
+    Ok(hashes)
+}
+
+fn output_frame(machine: &dyn Machine, frame: u64, options: &Options) -> Result<FrameHash, String> {
+    let rgb = machine.frame();
+    if let Some(dir) = &options.png_dir {
+        let path = dir.join(format!("frame_{:05}.png", frame));
+        std::fs::write(
+            &path,
+            png::encode_rgb(machine.width(), machine.height(), rgb),
+        )
+        .map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+    }
+    Ok(FrameHash {
+        frame,
+        hash: hash_frame(rgb),
+    })
+}
+
+#[cfg(test)]
+mod test;
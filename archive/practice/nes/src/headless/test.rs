@@ -0,0 +1,177 @@
+use super::*;
+use crate::cartridge::test::make_counter_rom;
+use crate::cartridge::Rom;
+use crate::cpu::CPU;
+use crate::input::ScriptInput;
+use crate::joypad::Button;
+use crate::machine::Nes;
+use crate::snake::Snake;
+
+// no_input holds no buttons.
+fn no_input() -> ScriptInput {
+    ScriptInput::new(Vec::new())
+}
+
+fn make_nes() -> Nes {
+    let mut cpu = CPU::new();
+    cpu.load_rom(Rom::new(&make_counter_rom()).unwrap());
+    cpu.reset();
+    Nes::new(cpu)
+}
+
+#[test]
+fn test_golden_frames() {
+    let options = Options {
+        frames: Some(5),
+        select: vec![2, 5],
+        ..Default::default()
+    };
+    let hashes = run(&mut make_nes(), &mut no_input(), &options).unwrap();
+    assert_eq!(
+        hashes,
+        vec![
+            FrameHash {
+                frame: 2,
+                hash: 0xfcf0_fbc4_12c0_65a5
+            },
+            FrameHash {
+                frame: 5,
+                hash: 0x8369_a7cb_c81d_7d25
+            },
+        ]
+    );
+    // The same run gives the same frames.
+    assert_eq!(
+        run(&mut make_nes(), &mut no_input(), &options).unwrap(),
+        hashes
+    );
+}
+
+#[test]
+fn test_last_frame_by_default() {
+    let mut nes = make_nes();
+    let hashes = run(
+        &mut nes,
+        &mut no_input(),
+        &Options {
+            frames: Some(3),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(hashes.len(), 1);
+    assert_eq!(hashes[0].frame, 3);
+    assert_eq!(hashes[0].hash, hash_frame(nes.frame()));
+}
+
+#[test]
+fn test_cycle_limit() {
+    let mut nes = make_nes();
+    let hashes = run(
+        &mut nes,
+        &mut no_input(),
+        &Options {
+            cycles: Some(40_000),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    // A frame is about 29780 cycles.
+    assert_eq!(hashes[0].frame, 2);
+    assert!(nes.cpu.bus.cycles >= 40_000);
+
+    assert_eq!(
+        run(&mut make_nes(), &mut no_input(), &Options::default()),
+        Err("a frame or cycle limit is required".to_string())
+    );
+}
+
+#[test]
+fn test_png_output() {
+    let dir = std::env::temp_dir().join(format!("nes-headless-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let options = Options {
+        frames: Some(2),
+        select: vec![1, 2],
+        png_dir: Some(dir.clone()),
+        ..Default::default()
+    };
+    run(&mut make_nes(), &mut no_input(), &options).unwrap();
+    for frame in [1, 2] {
+        let data = std::fs::read(dir.join(format!("frame_0000{}.png", frame))).unwrap();
+        assert_eq!(data[1..4], *b"PNG");
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_snake_input() {
+    let run_snake = |script: &str| {
+        let options = Options {
+            frames: Some(30),
+            ..Default::default()
+        };
+        let mut input = ScriptInput::parse(script).unwrap();
+        run(&mut Snake::new(1), &mut input, &options).unwrap()
+    };
+    // Runs are reproducible for a seed.
+    assert_eq!(run_snake("5:+down"), run_snake("5:+down"));
+    assert_ne!(run_snake("5:+down"), run_snake("5:+up"));
+}
+
+#[test]
+fn test_snake_runs() {
+    // The APU frame IRQ fires every 29830 cycles, about 4 frames. Reset masks it, so the game keeps running.
+    let mut snake = Snake::new(1);
+    // Steer in a small square so the snake does not hit a wall.
+    let turns = [Button::Down, Button::Left, Button::Up, Button::Right];
+    for frame in 1..=120 {
+        snake.set_buttons([turns[frame / 16 % 4] as u8, 0]);
+        assert!(snake.run_frame(), "frame {}: PC:{:04X}", frame, snake.cpu.program_counter);
+        let pc = snake.cpu.program_counter;
+        assert!((0x0600..0x0736).contains(&pc), "frame {}: PC:{:04X}", frame, pc);
+    }
+}
+
+// make_input_nes runs a ROM that reads controller 1 every NMI and stores the buttons at 0x00 (A is bit 7).
+fn make_input_nes() -> Nes {
+    let mut prg = vec![
+        0xA9, 0x80, 0x8D, 0x00, 0x20, // 8000: LDA #$80. STA $2000. Enable NMI.
+        0x4C, 0x05, 0x80, // 8005: JMP $8005.
+        // 8008: NMI handler.
+        0xA9, 0x01, 0x8D, 0x16, 0x40, // LDA #$01. STA $4016.
+        0xA9, 0x00, 0x8D, 0x16, 0x40, // LDA #$00. STA $4016.
+        0xA2, 0x08, // LDX #$08.
+        0xAD, 0x16, 0x40, // 8014: LDA $4016.
+        0x4A, // LSR A.
+        0x26, 0x00, // ROL $00.
+        0xCA, // DEX.
+        0xD0, 0xF7, // BNE $8014.
+        0x40, // RTI.
+    ];
+    prg.resize(crate::cartridge::PRG_ROM_BANK_SIZE, 0);
+    prg[0x3FFA..].copy_from_slice(&[0x08, 0x80, 0x00, 0x80, 0x00, 0x80]);
+    let raw = crate::cartridge::test::make_ines(&prg, &[], 0);
+    let mut cpu = CPU::new();
+    cpu.load_rom(Rom::new(&raw).unwrap());
+    cpu.reset();
+    Nes::new(cpu)
+}
+
+#[test]
+fn test_nes_input() {
+    let run_frames = |frames: u64| {
+        let mut nes = make_input_nes();
+        let mut input = ScriptInput::parse("2:+a,2:+right,3:-a").unwrap();
+        let options = Options {
+            frames: Some(frames),
+            ..Default::default()
+        };
+        run(&mut nes, &mut input, &options).unwrap();
+        nes.cpu.bus.peek(0x00)
+    };
+    // The NMI at the end of each frame reads the buttons held during the frame.
+    assert_eq!(run_frames(1), 0);
+    assert_eq!(run_frames(2), 0b1000_0001);
+    assert_eq!(run_frames(3), 0b0000_0001);
+}
@@ -0,0 +1,187 @@
+// input supplies controller state to machines. Frontends poll an `Input` once per frame and pass the buttons
+// to `Machine::set_buttons`, so the keyboard, input scripts and recordings are interchangeable.
+use crate::joypad::Button;
+use std::collections::HashMap;
+
+// Input returns the buttons held on controllers 1 and 2 during a frame. Frames are numbered from 1.
+// See `Button` for the bits.
+pub trait Input {
+    fn poll(&mut self, frame: u64) -> [u8; 2];
+}
+
+// parse_button parses a button of controller 1 ("start") or controller 2 ("p2.start").
+// Returns the controller index and the button.
+pub fn parse_button(s: &str) -> Result<(usize, Button), String> {
+    let (controller, name) = match s.strip_prefix("p2.") {
+        Some(name) => (1, name),
+        None => (0, s.strip_prefix("p1.").unwrap_or(s)),
+    };
+    let button = Button::from_name(name).ok_or(format!("unknown button {:?}", name))?;
+    Ok((controller, button))
+}
+
+// InputEvent presses or releases a button before frame `frame` runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputEvent {
+    pub frame: u64,
+    pub controller: usize,
+    pub button: Button,
+    pub pressed: bool,
+}
+
+impl std::fmt::Display for InputEvent {
+    // The format is the one read by `parse_event`.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let sign = if self.pressed { '+' } else { '-' };
+        let prefix = if self.controller == 1 { "p2." } else { "" };
+        write!(f, "{}:{}{}{}", self.frame, sign, prefix, self.button.name())
+    }
+}
+
+// parse_event parses FRAME:+BUTTON (press) or FRAME:-BUTTON (release).
+pub fn parse_event(event: &str) -> Result<InputEvent, String> {
+    let (frame, button) = event.split_once(':').ok_or(format!(
+        "invalid input event {:?}, want FRAME:+BUTTON",
+        event
+    ))?;
+    let frame = frame
+        .parse()
+        .map_err(|e| format!("invalid frame in {:?}: {}", event, e))?;
+    let (pressed, button) = match (button.strip_prefix('+'), button.strip_prefix('-')) {
+        (Some(button), _) => (true, button),
+        (_, Some(button)) => (false, button),
+        _ => return Err(format!("invalid input event {:?}, want + or -", event)),
+    };
+    let (controller, button) = parse_button(button)?;
+    Ok(InputEvent {
+        frame,
+        controller,
+        button,
+        pressed,
+    })
+}
+
+// ScriptInput replays a list of input events.
+pub struct ScriptInput {
+    events: Vec<InputEvent>,
+    next: usize,
+    buttons: [u8; 2],
+}
+
+impl ScriptInput {
+    pub fn new(mut events: Vec<InputEvent>) -> Self {
+        events.sort_by_key(|e| e.frame);
+        ScriptInput {
+            events,
+            next: 0,
+            buttons: [0; 2],
+        }
+    }
+
+    // parse parses a comma-separated input script of events in the format of `parse_event`.
+    // Example: "10:+right,20:-right,20:+p2.a".
+    pub fn parse(script: &str) -> Result<Self, String> {
+        let events = script
+            .split(',')
+            .map(str::trim)
+            .filter(|e| !e.is_empty())
+            .map(parse_event)
+            .collect::<Result<_, _>>()?;
+        Ok(ScriptInput::new(events))
+    }
+}
+
+impl Input for ScriptInput {
+    fn poll(&mut self, frame: u64) -> [u8; 2] {
+        while let Some(event) = self.events.get(self.next).filter(|e| e.frame <= frame) {
+            let bit = event.button as u8;
+            if event.pressed {
+                self.buttons[event.controller] |= bit;
+            } else {
+                self.buttons[event.controller] &= !bit;
+            }
+            self.next += 1;
+        }
+        self.buttons
+    }
+}
+
+// Bindings maps key names to controller buttons. Key names are compared case-insensitively.
+pub struct Bindings {
+    keys: HashMap<String, (usize, Button)>,
+}
+
+impl Bindings {
+    // get returns the controller index and button bound to `key`.
+    pub fn get(&self, key: &str) -> Option<(usize, Button)> {
+        self.keys.get(&key.to_lowercase()).copied()
+    }
+
+    pub fn bind(&mut self, key: &str, controller: usize, button: Button) {
+        self.keys.insert(key.to_lowercase(), (controller, button));
+    }
+
+    // parse adds comma-separated bindings of the form KEY=BUTTON or KEY=p2.BUTTON. Example: "Space=a,Tab=select".
+    pub fn parse(&mut self, spec: &str) -> Result<(), String> {
+        for binding in spec.split(',').map(str::trim).filter(|b| !b.is_empty()) {
+            let (key, button) = binding
+                .split_once('=')
+                .ok_or(format!("invalid binding {:?}, want KEY=BUTTON", binding))?;
+            let (controller, button) = parse_button(button.trim())?;
+            self.bind(key.trim(), controller, button);
+        }
+        Ok(())
+    }
+}
+
+impl Default for Bindings {
+    // The default bindings use SDL key names. Controller 1 uses WASD, controller 2 the arrow keys and keypad.
+    fn default() -> Self {
+        let mut bindings = Bindings {
+            keys: HashMap::new(),
+        };
+        bindings
+            .parse(
+                "W=up,S=down,A=left,D=right,K=a,J=b,Right Shift=select,Return=start,\
+                 Up=p2.up,Down=p2.down,Left=p2.left,Right=p2.right,\
+                 Keypad 2=p2.a,Keypad 1=p2.b,Keypad 0=p2.select,Keypad Enter=p2.start",
+            )
+            .unwrap();
+        bindings
+    }
+}
+
+// KeyboardInput tracks the buttons held through key presses. It does not depend on a particular frontend.
+pub struct KeyboardInput {
+    bindings: Bindings,
+    buttons: [u8; 2],
+}
+
+impl KeyboardInput {
+    pub fn new(bindings: Bindings) -> Self {
+        KeyboardInput {
+            bindings,
+            buttons: [0; 2],
+        }
+    }
+
+    // key handles a key press or release. Unbound keys are ignored.
+    pub fn key(&mut self, key: &str, pressed: bool) {
+        if let Some((controller, button)) = self.bindings.get(key) {
+            if pressed {
+                self.buttons[controller] |= button as u8;
+            } else {
+                self.buttons[controller] &= !(button as u8);
+            }
+        }
+    }
+}
+
+impl Input for KeyboardInput {
+    fn poll(&mut self, _frame: u64) -> [u8; 2] {
+        self.buttons
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,65 @@
+use super::*;
+
+#[test]
+fn test_parse_button() {
+    assert_eq!(parse_button("start"), Ok((0, Button::Start)));
+    assert_eq!(parse_button("p1.a"), Ok((0, Button::A)));
+    assert_eq!(parse_button("p2.left"), Ok((1, Button::Left)));
+    assert_eq!(
+        parse_button("p3.a"),
+        Err("unknown button \"p3.a\"".to_string())
+    );
+}
+
+#[test]
+fn test_script_input() {
+    let mut input = ScriptInput::parse("3:-right, 2:+right,2:+p2.a,").unwrap();
+    assert_eq!(input.poll(1), [0, 0]);
+    assert_eq!(input.poll(2), [Button::Right as u8, Button::A as u8]);
+    assert_eq!(input.poll(3), [0, Button::A as u8]);
+    assert_eq!(input.poll(10), [0, Button::A as u8]);
+}
+
+#[test]
+fn test_event_round_trip() {
+    for event in ["0:+a", "12:-p2.start", "7:+right"] {
+        assert_eq!(parse_event(event).unwrap().to_string(), event);
+    }
+    assert_eq!(parse_event("3:+p1.b").unwrap().to_string(), "3:+b");
+}
+
+#[test]
+fn test_parse_script_errors() {
+    let err = |script: &str| ScriptInput::parse(script).err().unwrap();
+    assert_eq!(
+        err("10+up"),
+        "invalid input event \"10+up\", want FRAME:+BUTTON"
+    );
+    assert_eq!(err("10:up"), "invalid input event \"10:up\", want + or -");
+    assert_eq!(err("1:é"), "invalid input event \"1:é\", want + or -");
+    assert_eq!(err("1:+é"), "unknown button \"é\"");
+    assert_eq!(err("10:+jump"), "unknown button \"jump\"");
+    assert!(err("x:+up").starts_with("invalid frame in \"x:+up\""));
+}
+
+#[test]
+fn test_keyboard_input() {
+    let mut bindings = Bindings::default();
+    bindings.parse("space=a, Tab=p2.select").unwrap();
+    let mut input = KeyboardInput::new(bindings);
+    input.key("W", true);
+    input.key("Space", true);
+    input.key("tab", true);
+    input.key("Q", true); // Unbound.
+    assert_eq!(
+        input.poll(1),
+        [Button::Up as u8 | Button::A as u8, Button::Select as u8]
+    );
+    input.key("w", false);
+    assert_eq!(input.poll(2), [Button::A as u8, Button::Select as u8]);
+
+    assert_eq!(
+        Bindings::default().parse("Space"),
+        Err("invalid binding \"Space\", want KEY=BUTTON".to_string())
+    );
+}
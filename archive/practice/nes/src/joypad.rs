@@ -0,0 +1,112 @@
+// Joypad emulates the standard controller. See https://www.nesdev.org/wiki/Standard_controller
+//
+// Writing 1 then 0 to 0x4016 latches the buttons of both controllers. Each read of 0x4016 (controller 1)
+// or 0x4017 (controller 2) then returns the next button, in the order A, B, Select, Start, Up, Down, Left, Right.
+use crate::savestate::{StateReader, StateWriter};
+
+// Button is a button of the standard controller. The value is its bit in `Joypad::buttons`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Button {
+    A = 0b0000_0001,
+    B = 0b0000_0010,
+    Select = 0b0000_0100,
+    Start = 0b0000_1000,
+    Up = 0b0001_0000,
+    Down = 0b0010_0000,
+    Left = 0b0100_0000,
+    Right = 0b1000_0000,
+}
+
+impl Button {
+    // ALL is in the order the buttons are read.
+    pub const ALL: [Button; 8] = [
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+        Button::Up,
+        Button::Down,
+        Button::Left,
+        Button::Right,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Button::A => "a",
+            Button::B => "b",
+            Button::Select => "select",
+            Button::Start => "start",
+            Button::Up => "up",
+            Button::Down => "down",
+            Button::Left => "left",
+            Button::Right => "right",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Button> {
+        Button::ALL.iter().copied().find(|b| b.name() == name)
+    }
+}
+
+#[derive(Default)]
+pub struct Joypad {
+    // buttons has a bit set for each button held. See `Button`.
+    pub buttons: u8,
+    strobe: bool,
+    // index is the next button to read.
+    index: u8,
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Joypad::default()
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        if pressed {
+            self.buttons |= button as u8;
+        } else {
+            self.buttons &= !(button as u8);
+        }
+    }
+
+    pub fn write(&mut self, val: u8) {
+        self.strobe = val & 1 == 1;
+        if self.strobe {
+            self.index = 0;
+        }
+    }
+
+    pub fn read(&mut self) -> u8 {
+        let val = self.peek();
+        if !self.strobe && self.index < 8 {
+            self.index += 1;
+        }
+        val
+    }
+
+    // peek returns the value `read` would return, without side effects.
+    pub fn peek(&self) -> u8 {
+        // After all 8 buttons, official controllers return 1.
+        if self.index >= 8 {
+            return 1;
+        }
+        (self.buttons >> self.index) & 1
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.buttons);
+        w.bool(self.strobe);
+        w.u8(self.index);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        self.buttons = r.u8()?;
+        self.strobe = r.bool()?;
+        self.index = r.u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,41 @@
+use super::*;
+
+// read_all reads the 8 buttons after strobing.
+fn read_all(joypad: &mut Joypad) -> Vec<u8> {
+    joypad.write(1);
+    joypad.write(0);
+    (0..8).map(|_| joypad.read()).collect()
+}
+
+#[test]
+fn test_read_order() {
+    let mut joypad = Joypad::new();
+    joypad.set_button(Button::A, true);
+    joypad.set_button(Button::Start, true);
+    joypad.set_button(Button::Right, true);
+    assert_eq!(read_all(&mut joypad), vec![1, 0, 0, 1, 0, 0, 0, 1]);
+    // Reads after the 8 buttons return 1.
+    assert_eq!(joypad.read(), 1);
+
+    joypad.set_button(Button::A, false);
+    assert_eq!(read_all(&mut joypad), vec![0, 0, 0, 1, 0, 0, 0, 1]);
+}
+
+#[test]
+fn test_strobe_high_reads_a() {
+    let mut joypad = Joypad::new();
+    joypad.set_button(Button::A, true);
+    joypad.write(1);
+    assert_eq!(joypad.read(), 1);
+    assert_eq!(joypad.read(), 1);
+    joypad.set_button(Button::A, false);
+    assert_eq!(joypad.read(), 0);
+}
+
+#[test]
+fn test_button_names() {
+    for button in Button::ALL {
+        assert_eq!(Button::from_name(button.name()), Some(button));
+    }
+    assert_eq!(Button::from_name("jump"), None);
+}
@@ -0,0 +1,94 @@
+// machine abstracts the emulated systems (the NES and the snake demo) from the frontends that display them
+// (SDL and headless). A machine produces RGB24 frames and audio samples, and accepts controller input.
+use crate::cpu::CPU;
+use crate::ppu::frame::Frame;
+
+pub trait Machine {
+    // width and height are the size of frames in pixels.
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+    // run_frame runs until the next frame is ready. Returns false if the CPU halted.
+    fn run_frame(&mut self) -> bool;
+    // frame returns the last frame as RGB24.
+    fn frame(&self) -> &[u8];
+    // set_buttons sets the buttons held on controllers 1 and 2. See `joypad::Button` for the bits.
+    fn set_buttons(&mut self, buttons: [u8; 2]);
+    fn cpu(&self) -> &CPU;
+    // save_state snapshots the machine. See `CPU::save_state`.
+    fn save_state(&self) -> Vec<u8>;
+    // load_state restores a state returned by `save_state`, including the last frame.
+    fn load_state(&mut self, data: &[u8]) -> Result<(), String>;
+    // seed is the seed of the machine's random numbers. Movies record it so playback draws the same numbers.
+    fn seed(&self) -> u64 {
+        0
+    }
+    // set_sample_rate enables audio output at `rate` Hz, or disables it. Machines without audio ignore it.
+    fn set_sample_rate(&mut self, _rate: Option<u32>) {}
+    // take_samples returns the 16-bit mono samples output since the last call.
+    fn take_samples(&mut self) -> Vec<i16> {
+        Vec::new()
+    }
+}
+
+// Nes runs a cartridge, or a raw program, and displays the frames rendered by the PPU.
+pub struct Nes {
+    pub cpu: CPU,
+}
+
+impl Nes {
+    // new wraps a CPU that has been loaded and reset.
+    pub fn new(cpu: CPU) -> Self {
+        Nes { cpu }
+    }
+}
+
+impl Machine for Nes {
+    fn width(&self) -> usize {
+        Frame::WIDTH
+    }
+
+    fn height(&self) -> usize {
+        Frame::HEIGHT
+    }
+
+    fn run_frame(&mut self) -> bool {
+        loop {
+            if !self.cpu.step() {
+                return false;
+            }
+            if self.cpu.bus.ppu.poll_frame_complete() {
+                return true;
+            }
+        }
+    }
+
+    fn frame(&self) -> &[u8] {
+        &self.cpu.bus.ppu.frame.data
+    }
+
+    fn set_buttons(&mut self, buttons: [u8; 2]) {
+        for (joypad, buttons) in self.cpu.bus.joypads.iter_mut().zip(buttons) {
+            joypad.buttons = buttons;
+        }
+    }
+
+    fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        self.cpu.save_state()
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<(), String> {
+        self.cpu.load_state(data)
+    }
+
+    fn set_sample_rate(&mut self, rate: Option<u32>) {
+        self.cpu.bus.apu.set_sample_rate(rate);
+    }
+
+    fn take_samples(&mut self) -> Vec<i16> {
+        self.cpu.bus.apu.take_samples()
+    }
+}
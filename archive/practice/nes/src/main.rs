@@ -1,144 +1,429 @@
+pub mod apu;
+pub mod asm;
+pub mod bench;
+pub mod bus;
+pub mod cartridge;
 pub mod cpu;
+pub mod debugger;
+pub mod disasm;
+pub mod headless;
+pub mod input;
+pub mod joypad;
+pub mod machine;
+pub mod movie;
 pub mod opcodes;
+pub mod png;
+pub mod ppu;
+pub mod rewind;
+pub mod savestate;
+pub mod snake;
+pub mod trace;
+pub mod wav;
 
-use rand::Rng;
+use cpu::Variant;
+use input::{Bindings, Input, KeyboardInput, ScriptInput};
+use machine::{Machine, Nes};
+use movie::Movie;
+use rewind::Rewind;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
 use sdl2::pixels::PixelFormatEnum;
-use sdl2::EventPump;
-
-fn handle_user_input(cpu: &mut cpu::CPU, event_pump: &mut EventPump) {
-    for event in event_pump.poll_iter() {
-        match event {
-            Event::Quit { .. }
-            | Event::KeyDown {
-                keycode: Some(Keycode::Escape),
-                ..
-            } => std::process::exit(0),
-            Event::KeyDown {
-                keycode: Some(Keycode::W),
-                ..
-            } => {
-                cpu.mem_write(0xff, 0x77);
+use snake::{Snake, SNAKE_GAME_CODE};
+use std::path::{Path, PathBuf};
+
+// run_sdl displays the frames of `machine` in a window until it halts or the window is closed.
+// `movie` is played back before the keyboard takes over, and `record` is where the input is written as a movie.
+// Holding Backspace rewinds.
+fn run_sdl(
+    machine: &mut dyn Machine,
+    bindings: Bindings,
+    movie: Option<Movie>,
+    record: Option<PathBuf>,
+    title: &str,
+    scale: u32,
+) {
+    if let Some(movie) = &movie {
+        movie.check(machine).unwrap_or_else(|e| panic!("{}", e));
+    }
+    let (width, height) = (machine.width(), machine.height());
+    let sdl_context = sdl2::init().unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
+    let window = video_subsystem
+        .window(title, width as u32 * scale, height as u32 * scale)
+        .position_centered()
+        .build()
+        .unwrap();
+
+    let mut canvas = window.into_canvas().present_vsync().build().unwrap();
+    let mut event_pump = sdl_context.event_pump().unwrap();
+    canvas.set_scale(scale as f32, scale as f32).unwrap();
+
+    let creator = canvas.texture_creator();
+    let mut texture = creator
+        .create_texture_target(PixelFormatEnum::RGB24, width as u32, height as u32)
+        .unwrap();
+
+    let mut input = KeyboardInput::new(bindings);
+    let mut recording = record.map(|path| (path, Movie::new(machine)));
+    let mut rewind = Rewind::new(rewind::INTERVAL, rewind::SECONDS);
+    rewind.record(0, machine);
+    let mut rewinding = false;
+    let mut frame = 0;
+    'run: loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => break 'run,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => rewinding = true,
+                Event::KeyUp {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => rewinding = false,
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } => input.key(&keycode.name(), true),
+                Event::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } => input.key(&keycode.name(), false),
+                _ => { /* do nothing */ }
             }
-            Event::KeyDown {
-                keycode: Some(Keycode::S),
-                ..
-            } => {
-                cpu.mem_write(0xff, 0x73);
+        }
+        if rewinding {
+            // The restored state includes its frame, so there is nothing to run.
+            if let Some(f) = rewind
+                .rewind(frame, machine)
+                .unwrap_or_else(|e| panic!("{}", e))
+            {
+                frame = f;
+                if let Some((_, movie)) = &mut recording {
+                    movie.truncate(frame);
+                }
             }
-            Event::KeyDown {
-                keycode: Some(Keycode::A),
-                ..
-            } => {
-                cpu.mem_write(0xff, 0x61);
+        } else {
+            frame += 1;
+            let buttons = match &movie {
+                Some(movie) if frame <= movie.len() => movie.buttons(frame),
+                _ => input.poll(frame),
+            };
+            if let Some((_, movie)) = &mut recording {
+                movie.record(frame, buttons);
             }
-            Event::KeyDown {
-                keycode: Some(Keycode::D),
-                ..
-            } => {
-                cpu.mem_write(0xff, 0x64);
+            machine.set_buttons(buttons);
+            if !machine.run_frame() {
+                break;
             }
-            _ => { /* do nothing */ }
+            rewind.record(frame, machine);
         }
+        texture.update(None, machine.frame(), width * 3).unwrap();
+        canvas.copy(&texture, None, None).unwrap();
+        canvas.present();
+    }
+    if let Some((path, movie)) = recording {
+        movie.save(&path).unwrap_or_else(|e| panic!("{}", e));
     }
 }
 
-fn color(byte: u8) -> Color {
-    match byte {
-        0 => sdl2::pixels::Color::BLACK,
-        1 => sdl2::pixels::Color::WHITE,
-        2 | 9 => sdl2::pixels::Color::GREY,
-        3 | 10 => sdl2::pixels::Color::RED,
-        4 | 11 => sdl2::pixels::Color::GREEN,
-        5 | 12 => sdl2::pixels::Color::BLUE,
-        6 | 13 => sdl2::pixels::Color::MAGENTA,
-        7 | 14 => sdl2::pixels::Color::YELLOW,
-        _ => sdl2::pixels::Color::CYAN,
+// load_cpu loads an iNES file, or a raw binary at 0x8000, and resets a CPU of `variant`.
+fn load_cpu(path: &str, variant: Variant) -> cpu::CPU {
+    let raw = std::fs::read(path).unwrap_or_else(|e| panic!("failed to read {}: {}", path, e));
+    let mut cpu = cpu::CPU::new();
+    cpu.variant = variant;
+    if raw.starts_with(&cartridge::NES_TAG) {
+        let rom =
+            cartridge::Rom::new(&raw).unwrap_or_else(|e| panic!("failed to load {}: {}", path, e));
+        cpu.load_rom(rom);
+    } else {
+        cpu.load(raw);
     }
+    cpu.reset();
+    cpu
 }
 
-fn read_screen_state(cpu: &cpu::CPU, frame: &mut [u8; 32 * 3 * 32]) -> bool {
-    let mut frame_idx = 0;
-    let mut update = false;
-    for i in 0x0200..0x600 {
-        let color_idx = cpu.mem_read(i as u16);
-        let (b1, b2, b3) = color(color_idx).rgb();
-        if frame[frame_idx] != b1 || frame[frame_idx + 1] != b2 || frame[frame_idx + 2] != b3 {
-            frame[frame_idx] = b1;
-            frame[frame_idx + 1] = b2;
-            frame[frame_idx + 2] = b3;
-            update = true;
+// run_disasm disassembles `path` and prints one instruction per line.
+// `path` is an iNES file, a raw binary loaded at `origin`, or "snake" for the snake game.
+// For iNES files, `bank` selects the 16KiB PRG ROM bank to disassemble.
+fn run_disasm(path: &str, origin: Option<u16>, bank: usize, variant: Variant) {
+    let (bytes, default_origin) = if path == "snake" {
+        (SNAKE_GAME_CODE.to_vec(), 0x0600)
+    } else {
+        let raw = std::fs::read(path).unwrap_or_else(|e| panic!("failed to read {}: {}", path, e));
+        if raw.starts_with(&cartridge::NES_TAG) {
+            let rom = cartridge::Rom::new(&raw)
+                .unwrap_or_else(|e| panic!("failed to load {}: {}", path, e));
+            let banks: Vec<&[u8]> = rom.prg_rom.chunks(cartridge::PRG_ROM_BANK_SIZE).collect();
+            let prg_bank = banks.get(bank).unwrap_or_else(|| {
+                panic!(
+                    "bank {} is out of range: {} has {} banks",
+                    bank,
+                    path,
+                    banks.len()
+                )
+            });
+            // NROM maps the first bank at 0x8000 and the last bank at 0xC000.
+            let bank_origin = if bank == 0 { 0x8000 } else { 0xC000 };
+            (prg_bank.to_vec(), bank_origin)
+        } else {
+            (raw, 0x8000)
         }
-        frame_idx += 3;
+    };
+    for instruction in disasm::disassemble(&bytes, origin.unwrap_or(default_origin), variant) {
+        println!("{}", instruction);
     }
-    update
 }
 
-fn main() {
-    // Initialize sdl2
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
-    let window = video_subsystem
-        .window("Snake game", (32.0 * 10.0) as u32, (32.0 * 10.0) as u32)
-        .position_centered()
-        .build()
-        .unwrap();
+fn parse_u16(s: &str) -> u16 {
+    debugger::parse_u16(s).unwrap_or_else(|e| panic!("{}", e))
+}
 
-    let mut canvas = window.into_canvas().present_vsync().build().unwrap();
-    let mut event_pump = sdl_context.event_pump().unwrap();
-    canvas.set_scale(10.0, 10.0).unwrap();
+// run_asm assembles `path` at `origin` and writes the machine code to `out`, or to `path` with a .bin extension.
+fn run_asm(path: &str, origin: u16, out: Option<PathBuf>) {
+    let source =
+        std::fs::read_to_string(path).unwrap_or_else(|e| panic!("failed to read {}: {}", path, e));
+    let bytes = asm::assemble(&source, origin).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    let out = out.unwrap_or_else(|| PathBuf::from(path).with_extension("bin"));
+    std::fs::write(&out, bytes)
+        .unwrap_or_else(|e| panic!("failed to write {}: {}", out.display(), e));
+}
 
-    // Create a texture.
-    let creator = canvas.texture_creator();
-    let mut texture = creator
-        .create_texture_target(PixelFormatEnum::RGB24, 32, 32)
+// run_debugger starts the debugger REPL on stdin. `path` is an iNES file, a raw binary loaded at 0x8000,
+// or "snake" for the snake game.
+fn run_debugger(path: &str, variant: Variant) {
+    let cpu = if path == "snake" {
+        Snake::new(0).cpu
+    } else {
+        load_cpu(path, variant)
+    };
+    let mut debugger = debugger::Debugger::new(cpu);
+    debugger
+        .repl(std::io::stdin().lock(), std::io::stdout())
         .unwrap();
+}
 
-    let snake_game_code = vec![
-        0x20, 0x06, 0x06, 0x20, 0x38, 0x06, 0x20, 0x0d, 0x06, 0x20, 0x2a, 0x06, 0x60, 0xa9, 0x02,
-        0x85, 0x02, 0xa9, 0x04, 0x85, 0x03, 0xa9, 0x11, 0x85, 0x10, 0xa9, 0x10, 0x85, 0x12, 0xa9,
-        0x0f, 0x85, 0x14, 0xa9, 0x04, 0x85, 0x11, 0x85, 0x13, 0x85, 0x15, 0x60, 0xa5, 0xfe, 0x85,
-        0x00, 0xa5, 0xfe, 0x29, 0x03, 0x18, 0x69, 0x02, 0x85, 0x01, 0x60, 0x20, 0x4d, 0x06, 0x20,
-        0x8d, 0x06, 0x20, 0xc3, 0x06, 0x20, 0x19, 0x07, 0x20, 0x20, 0x07, 0x20, 0x2d, 0x07, 0x4c,
-        0x38, 0x06, 0xa5, 0xff, 0xc9, 0x77, 0xf0, 0x0d, 0xc9, 0x64, 0xf0, 0x14, 0xc9, 0x73, 0xf0,
-        0x1b, 0xc9, 0x61, 0xf0, 0x22, 0x60, 0xa9, 0x04, 0x24, 0x02, 0xd0, 0x26, 0xa9, 0x01, 0x85,
-        0x02, 0x60, 0xa9, 0x08, 0x24, 0x02, 0xd0, 0x1b, 0xa9, 0x02, 0x85, 0x02, 0x60, 0xa9, 0x01,
-        0x24, 0x02, 0xd0, 0x10, 0xa9, 0x04, 0x85, 0x02, 0x60, 0xa9, 0x02, 0x24, 0x02, 0xd0, 0x05,
-        0xa9, 0x08, 0x85, 0x02, 0x60, 0x60, 0x20, 0x94, 0x06, 0x20, 0xa8, 0x06, 0x60, 0xa5, 0x00,
-        0xc5, 0x10, 0xd0, 0x0d, 0xa5, 0x01, 0xc5, 0x11, 0xd0, 0x07, 0xe6, 0x03, 0xe6, 0x03, 0x20,
-        0x2a, 0x06, 0x60, 0xa2, 0x02, 0xb5, 0x10, 0xc5, 0x10, 0xd0, 0x06, 0xb5, 0x11, 0xc5, 0x11,
-        0xf0, 0x09, 0xe8, 0xe8, 0xe4, 0x03, 0xf0, 0x06, 0x4c, 0xaa, 0x06, 0x4c, 0x35, 0x07, 0x60,
-        0xa6, 0x03, 0xca, 0x8a, 0xb5, 0x10, 0x95, 0x12, 0xca, 0x10, 0xf9, 0xa5, 0x02, 0x4a, 0xb0,
-        0x09, 0x4a, 0xb0, 0x19, 0x4a, 0xb0, 0x1f, 0x4a, 0xb0, 0x2f, 0xa5, 0x10, 0x38, 0xe9, 0x20,
-        0x85, 0x10, 0x90, 0x01, 0x60, 0xc6, 0x11, 0xa9, 0x01, 0xc5, 0x11, 0xf0, 0x28, 0x60, 0xe6,
-        0x10, 0xa9, 0x1f, 0x24, 0x10, 0xf0, 0x1f, 0x60, 0xa5, 0x10, 0x18, 0x69, 0x20, 0x85, 0x10,
-        0xb0, 0x01, 0x60, 0xe6, 0x11, 0xa9, 0x06, 0xc5, 0x11, 0xf0, 0x0c, 0x60, 0xc6, 0x10, 0xa5,
-        0x10, 0x29, 0x1f, 0xc9, 0x1f, 0xf0, 0x01, 0x60, 0x4c, 0x35, 0x07, 0xa0, 0x00, 0xa5, 0xfe,
-        0x91, 0x00, 0x60, 0xa6, 0x03, 0xa9, 0x00, 0x81, 0x10, 0xa2, 0x00, 0xa9, 0x01, 0x81, 0x10,
-        0x60, 0xa2, 0x00, 0xea, 0xea, 0xca, 0xd0, 0xfb, 0x60,
-    ];
+// run_headless runs `path` without a display and prints the hashes of the selected frames.
+// `path` is an iNES file, a raw binary loaded at 0x8000, or "snake" for the snake game.
+// If `movie` is given, it replaces `input` and `seed`, and runs to its end unless a limit is set.
+fn run_headless(
+    path: &str,
+    input: ScriptInput,
+    movie: Option<Movie>,
+    options: &mut headless::Options,
+    seed: u64,
+    variant: Variant,
+) {
+    let seed = movie.as_ref().map_or(seed, |movie| movie.seed);
+    let mut machine: Box<dyn Machine> = if path == "snake" {
+        Box::new(Snake::new(seed))
+    } else {
+        Box::new(Nes::new(load_cpu(path, variant)))
+    };
+    let mut input: Box<dyn Input> = match movie {
+        Some(movie) => {
+            movie
+                .check(machine.as_ref())
+                .unwrap_or_else(|e| panic!("{}", e));
+            if options.frames.is_none() && options.cycles.is_none() {
+                options.frames = Some(movie.len());
+            }
+            Box::new(movie)
+        }
+        None => Box::new(input),
+    };
+    let hashes = headless::run(machine.as_mut(), input.as_mut(), options)
+        .unwrap_or_else(|e| panic!("{}", e));
+    for hash in hashes {
+        println!("{}", hash);
+    }
+}
 
-    let mut cpu = cpu::CPU::new();
-    cpu.snake_mode = true;
-    cpu.set_trace_mode(true);
-    cpu.load(snake_game_code);
-    cpu.reset();
+// run_bench runs `path`, or the built-in benchmark program, for `instructions` instructions and prints the speed.
+fn run_bench(path: Option<&str>, instructions: u64, variant: Variant) {
+    let mut cpu = match path {
+        Some(path) => load_cpu(path, variant),
+        None => {
+            let mut cpu = bench::bench_cpu();
+            cpu.variant = variant;
+            cpu
+        }
+    };
+    match bench::run(&mut cpu, instructions) {
+        Ok(result) => println!("{}", result),
+        Err(e) => panic!("{}", e),
+    }
+}
 
-    let mut screen_state = [0 as u8; 32 * 3 * 32];
-    let mut rng = rand::thread_rng();
+const USAGE: &str = "usage:
+  nes [--bind BINDINGS] [--cpu CPU] [--movie FILE] [--record FILE] [<rom.nes>]
+                                              run an iNES ROM, or the snake game
+  nes disasm <file|snake> [--org ADDR] [--bank N]
+                                              disassemble an iNES PRG ROM bank, a raw binary or the snake game
+  nes asm <file> [--org ADDR] [--out FILE]    assemble 6502 source into a raw binary (default: at 0x8000, into
+                                              <file> with a .bin extension)
+  nes debug <file|snake>                      debug an iNES ROM, a raw binary or the snake game
+  nes headless <file|snake> [--frames N] [--cycles N] [--input SCRIPT] [--select N,...] [--png DIR] [--seed N]
+                [--wav FILE] [--sample-rate N]
+                                              run without a display and print hashes of the selected frames
+                                              (default: the last), optionally recording audio
+  nes bench [<file>] [--instructions N]       measure instructions per second of an iNES ROM, a raw binary or
+                                              a built-in program (default: 10000000 instructions)
 
-    cpu.run_with_callback(|cpu: &mut cpu::CPU| {
-        handle_user_input(cpu, &mut event_pump);
-        cpu.mem_write(0xfe, rng.gen_range(1, 16));
+CPU is 2a03 (default), 6502 or 65c02. It applies to every command except the snake game.
+BUTTON is a, b, select, start, up, down, left or right for controller 1, or p2.BUTTON for controller 2.
+BINDINGS is KEY=BUTTON, comma-separated, where KEY is an SDL key name (e.g. \"Space=a,Left Shift=p2.b\").
+SCRIPT is FRAME:+BUTTON (press) or FRAME:-BUTTON (release), comma-separated (e.g. \"10:+start,12:-start\").
+--movie plays back the input recorded in a movie file, and --record records the input into one. Both also apply
+to headless runs. A headless run of a movie stops at its end by default. Hold Backspace to rewind (up to 10s).";
 
-        if read_screen_state(cpu, &mut screen_state) {
-            texture.update(None, &screen_state, 32 * 3).unwrap();
-            canvas.copy(&texture, None, None).unwrap();
-            canvas.present();
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut bindings = Bindings::default();
+    if let Some(i) = args.iter().position(|a| a == "--bind") {
+        let spec = args.get(i + 1).map_or("", String::as_str);
+        bindings.parse(spec).unwrap_or_else(|e| panic!("{}", e));
+        args.drain(i..(i + 2).min(args.len()));
+    }
+    let mut variant = Variant::Nes2A03;
+    if let Some(i) = args.iter().position(|a| a == "--cpu") {
+        let spec = args.get(i + 1).map_or("", String::as_str);
+        variant = Variant::parse(spec).unwrap_or_else(|e| panic!("{}", e));
+        args.drain(i..(i + 2).min(args.len()));
+    }
+    let mut movie = None;
+    if let Some(i) = args.iter().position(|a| a == "--movie") {
+        let path = args.get(i + 1).map_or("", String::as_str);
+        movie = Some(Movie::load(Path::new(path)).unwrap_or_else(|e| panic!("{}", e)));
+        args.drain(i..(i + 2).min(args.len()));
+    }
+    let mut record = None;
+    if let Some(i) = args.iter().position(|a| a == "--record") {
+        record = args.get(i + 1).map(PathBuf::from);
+        args.drain(i..(i + 2).min(args.len()));
+    }
+    match args.first().map(String::as_str) {
+        None => {}
+        Some("disasm") => {
+            let mut path = None;
+            let mut origin = None;
+            let mut bank = 0;
+            let mut rest = args[1..].iter();
+            while let Some(arg) = rest.next() {
+                match arg.as_str() {
+                    "--org" => origin = rest.next().map(|s| parse_u16(s)),
+                    "--bank" => bank = rest.next().map_or(0, |s| parse_u16(s) as usize),
+                    _ => path = Some(arg.clone()),
+                }
+            }
+            match path {
+                Some(path) => run_disasm(&path, origin, bank, variant),
+                None => eprintln!("{}", USAGE),
+            }
+            return;
+        }
+        Some("asm") => {
+            let mut path = None;
+            let mut origin = 0x8000;
+            let mut out = None;
+            let mut rest = args[1..].iter();
+            while let Some(arg) = rest.next() {
+                match arg.as_str() {
+                    "--org" => origin = rest.next().map_or(origin, |s| parse_u16(s)),
+                    "--out" => out = rest.next().map(PathBuf::from),
+                    _ => path = Some(arg.clone()),
+                }
+            }
+            match path {
+                Some(path) => run_asm(&path, origin, out),
+                None => eprintln!("{}", USAGE),
+            }
+            return;
+        }
+        Some("debug") => {
+            match args.get(1) {
+                Some(path) => run_debugger(path, variant),
+                None => eprintln!("{}", USAGE),
+            }
+            return;
         }
+        Some("headless") => {
+            let mut path = None;
+            let mut options = headless::Options {
+                record,
+                ..Default::default()
+            };
+            let mut input = ScriptInput::new(Vec::new());
+            let mut seed = 0;
+            let mut rest = args[1..].iter();
+            let parse = |s: Option<&String>| -> u64 {
+                let s = s.unwrap_or_else(|| panic!("missing value\n{}", USAGE));
+                s.parse()
+                    .unwrap_or_else(|e| panic!("invalid number {}: {}", s, e))
+            };
+            while let Some(arg) = rest.next() {
+                match arg.as_str() {
+                    "--frames" => options.frames = Some(parse(rest.next())),
+                    "--cycles" => options.cycles = Some(parse(rest.next()) as usize),
+                    "--seed" => seed = parse(rest.next()),
+                    "--input" => {
+                        let script = rest.next().map_or("", String::as_str);
+                        input = ScriptInput::parse(script).unwrap_or_else(|e| panic!("{}", e));
+                    }
+                    "--select" => {
+                        let list = rest.next().map_or("", String::as_str);
+                        options.select = list
+                            .split(',')
+                            .map(|n| parse(Some(&n.to_string())))
+                            .collect();
+                    }
+                    "--png" => options.png_dir = rest.next().map(PathBuf::from),
+                    "--wav" => options.wav = rest.next().map(PathBuf::from),
+                    "--sample-rate" => options.sample_rate = Some(parse(rest.next()) as u32),
+                    _ => path = Some(arg.clone()),
+                }
+            }
+            match path {
+                Some(path) => run_headless(&path, input, movie, &mut options, seed, variant),
+                None => eprintln!("{}", USAGE),
+            }
+            return;
+        }
+        Some("bench") => {
+            let mut path = None;
+            let mut instructions = 10_000_000;
+            let mut rest = args[1..].iter();
+            while let Some(arg) = rest.next() {
+                match arg.as_str() {
+                    "--instructions" => {
+                        let n = rest.next().map_or("", String::as_str);
+                        instructions = n
+                            .parse()
+                            .unwrap_or_else(|e| panic!("invalid number {}: {}", n, e));
+                    }
+                    _ => path = Some(arg.as_str()),
+                }
+            }
+            run_bench(path, instructions, variant);
+            return;
+        }
+        Some("-h") | Some("--help") => {
+            eprintln!("{}", USAGE);
+            return;
+        }
+        Some(path) => {
+            let mut nes = Nes::new(load_cpu(path, variant));
+            run_sdl(&mut nes, bindings, movie, record, path, 3);
+            return;
+        }
+    }
 
-        ::std::thread::sleep(std::time::Duration::new(0, 70_000));
-    })
+    let seed = movie.as_ref().map_or_else(rand::random, |movie| movie.seed);
+    let mut snake = Snake::new(seed);
+    snake.cpu.set_trace_mode(true);
+    run_sdl(&mut snake, bindings, movie, record, "Snake game", 10);
 }
@@ -0,0 +1,226 @@
+// movie records the buttons held during each frame so a run can be played back exactly. Machines are
+// deterministic given their input and seed, so a movie reproduces a bug frame for frame.
+//
+// A movie is a text file: a header, then the input events in the format of `input::parse_event`, one per line.
+//
+//   nes-movie 1
+//   seed 42
+//   cartridge 1f2e3d4c
+//   frames 600
+//   10:+start
+//   12:-start
+//
+// "cartridge" is the PRG ROM checksum (see `Bus::cartridge_checksum`). It is omitted for raw programs.
+use crate::input::{self, Input, InputEvent};
+use crate::joypad::Button;
+use crate::machine::Machine;
+use std::path::Path;
+
+const HEADER: &str = "nes-movie";
+const VERSION: u32 = 1;
+// MAX_FRAMES limits the length of a parsed movie to a day at 60 frames per second, since every frame is stored.
+const MAX_FRAMES: u64 = 24 * 60 * 60 * 60;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movie {
+    pub seed: u64,
+    pub cartridge: Option<u32>,
+    // frames holds the buttons of each frame, starting with frame 1.
+    frames: Vec<[u8; 2]>,
+}
+
+impl Movie {
+    // new starts an empty movie of `machine`.
+    pub fn new(machine: &dyn Machine) -> Self {
+        Movie {
+            seed: machine.seed(),
+            cartridge: machine.cpu().bus.cartridge_checksum(),
+            frames: Vec::new(),
+        }
+    }
+
+    // len is the number of frames in the movie.
+    pub fn len(&self) -> u64 {
+        self.frames.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    // record sets the buttons of `frame`, which must be at least 1. Later frames are dropped, so recording after
+    // a rewind replaces the frames that were rewound. Skipped frames hold no buttons.
+    pub fn record(&mut self, frame: u64, buttons: [u8; 2]) {
+        assert!(frame >= 1, "movie frames start at 1");
+        self.frames.resize(frame as usize - 1, [0; 2]);
+        self.frames.push(buttons);
+    }
+
+    // truncate drops the frames after `frame`.
+    pub fn truncate(&mut self, frame: u64) {
+        self.frames.truncate(frame as usize);
+    }
+
+    // buttons returns the buttons of `frame`. No buttons are held after the end of the movie.
+    pub fn buttons(&self, frame: u64) -> [u8; 2] {
+        frame
+            .checked_sub(1)
+            .and_then(|i| self.frames.get(i as usize))
+            .copied()
+            .unwrap_or([0; 2])
+    }
+
+    // check returns an error if the movie was recorded on a machine with another cartridge or seed.
+    pub fn check(&self, machine: &dyn Machine) -> Result<(), String> {
+        if self.cartridge != machine.cpu().bus.cartridge_checksum() {
+            return Err("movie is for a different cartridge".to_string());
+        }
+        if self.seed != machine.seed() {
+            return Err(format!(
+                "movie was recorded with seed {}, not {}",
+                self.seed,
+                machine.seed()
+            ));
+        }
+        Ok(())
+    }
+
+    // events returns the presses and releases that produce the buttons of each frame.
+    pub fn events(&self) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+        let mut held = [0; 2];
+        for (frame, buttons) in (1..).zip(&self.frames) {
+            for controller in 0..2 {
+                for button in Button::ALL {
+                    let bit = button as u8;
+                    if (held[controller] ^ buttons[controller]) & bit != 0 {
+                        events.push(InputEvent {
+                            frame,
+                            controller,
+                            button,
+                            pressed: buttons[controller] & bit != 0,
+                        });
+                    }
+                }
+            }
+            held = *buttons;
+        }
+        events
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = (1..)
+            .zip(text.lines())
+            .filter(|(_, l)| !l.trim().is_empty());
+        let header = lines.next().map_or("", |(_, l)| l.trim());
+        let version = match header.split_once(' ') {
+            Some((HEADER, version)) => version,
+            _ => return Err("not a movie".to_string()),
+        };
+        if version != VERSION.to_string() {
+            return Err(format!(
+                "movie version {} is not supported, want {}",
+                version, VERSION
+            ));
+        }
+        let mut movie = Movie {
+            seed: 0,
+            cartridge: None,
+            frames: Vec::new(),
+        };
+        let mut len = None;
+        let mut events = Vec::new();
+        for (n, line) in lines {
+            let line = line.trim();
+            let err = |e: String| format!("line {}: {}", n, e);
+            if line.contains(':') {
+                let event = input::parse_event(line).map_err(err)?;
+                match len {
+                    None => return Err(err("input event before the frames line".to_string())),
+                    Some(len) if event.frame == 0 || event.frame > len => {
+                        return Err(err(format!(
+                            "frame {} is not in the movie of {} frames",
+                            event.frame, len
+                        )))
+                    }
+                    _ => events.push(event),
+                }
+                continue;
+            }
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let value = value.trim();
+            match key {
+                "seed" => movie.seed = value.parse().map_err(|e| err(format!("{}", e)))?,
+                "cartridge" => {
+                    let checksum = u32::from_str_radix(value, 16)
+                        .map_err(|_| err(format!("invalid checksum {:?}", value)))?;
+                    movie.cartridge = Some(checksum);
+                }
+                "frames" => {
+                    let frames: u64 = value.parse().map_err(|e| err(format!("{}", e)))?;
+                    if frames > MAX_FRAMES {
+                        return Err(err(format!(
+                            "{} frames is more than the maximum of {}",
+                            frames, MAX_FRAMES
+                        )));
+                    }
+                    len = Some(frames);
+                }
+                _ => return Err(err(format!("unknown key {:?}", key))),
+            }
+        }
+        let len = len.ok_or("movie has no frames line")?;
+        events.sort_by_key(|e| e.frame);
+        let mut events = events.iter().peekable();
+        let mut held = [0; 2];
+        for frame in 1..=len {
+            while let Some(event) = events.next_if(|e| e.frame == frame) {
+                let bit = event.button as u8;
+                if event.pressed {
+                    held[event.controller] |= bit;
+                } else {
+                    held[event.controller] &= !bit;
+                }
+            }
+            movie.frames.push(held);
+        }
+        Ok(movie)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        Movie::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_string())
+            .map_err(|e| format!("failed to write {}: {}", path.display(), e))
+    }
+}
+
+impl std::fmt::Display for Movie {
+    // The format is the one read by `parse`.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "{} {}", HEADER, VERSION)?;
+        writeln!(f, "seed {}", self.seed)?;
+        if let Some(checksum) = self.cartridge {
+            writeln!(f, "cartridge {:08x}", checksum)?;
+        }
+        writeln!(f, "frames {}", self.len())?;
+        for event in self.events() {
+            writeln!(f, "{}", event)?;
+        }
+        Ok(())
+    }
+}
+
+// A movie plays back as input. Frames are looked up by number, so playback can be rewound.
+impl Input for Movie {
+    fn poll(&mut self, frame: u64) -> [u8; 2] {
+        self.buttons(frame)
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,150 @@
+use super::*;
+use crate::cartridge::test::make_counter_rom;
+use crate::cartridge::Rom;
+use crate::cpu::CPU;
+use crate::headless::{self, Options};
+use crate::input::ScriptInput;
+use crate::machine::Nes;
+use crate::snake::Snake;
+
+fn make_nes() -> Nes {
+    let mut cpu = CPU::new();
+    cpu.load_rom(Rom::new(&make_counter_rom()).unwrap());
+    cpu.reset();
+    Nes::new(cpu)
+}
+
+#[test]
+fn test_record() {
+    let mut movie = Movie::new(&Snake::new(7));
+    assert!(movie.is_empty());
+    movie.record(2, [Button::A as u8, 0]);
+    movie.record(3, [Button::A as u8 | Button::Up as u8, Button::Start as u8]);
+    movie.record(4, [0, 0]);
+    assert_eq!(movie.len(), 4);
+    assert_eq!(movie.buttons(1), [0, 0]);
+    assert_eq!(movie.buttons(3), [0b0001_0001, 0b0000_1000]);
+    assert_eq!(movie.buttons(5), [0, 0]);
+    assert_eq!(
+        movie.to_string(),
+        "nes-movie 1\nseed 7\nframes 4\n2:+a\n3:+up\n3:+p2.start\n4:-a\n4:-up\n4:-p2.start\n"
+    );
+    assert_eq!(Movie::parse(&movie.to_string()), Ok(movie.clone()));
+
+    // Recording an earlier frame, e.g. after a rewind, drops the frames after it.
+    movie.record(3, [Button::B as u8, 0]);
+    assert_eq!(movie.len(), 3);
+    assert_eq!(movie.buttons(2), [Button::A as u8, 0]);
+    assert_eq!(movie.buttons(3), [Button::B as u8, 0]);
+    movie.truncate(1);
+    assert_eq!(movie.len(), 1);
+}
+
+#[test]
+#[should_panic(expected = "movie frames start at 1")]
+fn test_record_frame_0() {
+    Movie::new(&Snake::new(7)).record(0, [0, 0]);
+}
+
+#[test]
+fn test_parse() {
+    let movie = Movie::parse(
+        "nes-movie 1\n\nseed 3\ncartridge 0000abcd\nframes 3\n3:-right\n2:+right\n2:+p2.a\n",
+    )
+    .unwrap();
+    assert_eq!(movie.seed, 3);
+    assert_eq!(movie.cartridge, Some(0xabcd));
+    assert_eq!(movie.len(), 3);
+    assert_eq!(movie.buttons(2), [Button::Right as u8, Button::A as u8]);
+    assert_eq!(movie.buttons(3), [0, Button::A as u8]);
+}
+
+#[test]
+fn test_parse_errors() {
+    let cases = [
+        ("", "not a movie"),
+        (
+            "nes-movie 2\nframes 1",
+            "movie version 2 is not supported, want 1",
+        ),
+        ("nes-movie 1\nseed 1", "movie has no frames line"),
+        (
+            "nes-movie 1\nframes 99999999999",
+            "line 2: 99999999999 frames is more than the maximum of 5184000",
+        ),
+        (
+            "nes-movie 1\nspeed 1\nframes 1",
+            "line 2: unknown key \"speed\"",
+        ),
+        (
+            "nes-movie 1\ncartridge xyz\nframes 1",
+            "line 2: invalid checksum \"xyz\"",
+        ),
+        (
+            "nes-movie 1\n1:+a\nframes 1",
+            "line 2: input event before the frames line",
+        ),
+        (
+            "nes-movie 1\nframes 2\n3:+a",
+            "line 3: frame 3 is not in the movie of 2 frames",
+        ),
+        (
+            "nes-movie 1\nframes 2\n0:+a",
+            "line 3: frame 0 is not in the movie of 2 frames",
+        ),
+        (
+            "nes-movie 1\nframes 2\n1:é",
+            "line 3: invalid input event \"1:é\", want + or -",
+        ),
+        (
+            "nes-movie 1\nframes 2\n1:+jump",
+            "line 3: unknown button \"jump\"",
+        ),
+    ];
+    for (text, want) in cases {
+        assert_eq!(Movie::parse(text), Err(want.to_string()), "{:?}", text);
+    }
+}
+
+#[test]
+fn test_check() {
+    let movie = Movie::new(&make_nes());
+    assert!(movie.cartridge.is_some());
+    assert_eq!(movie.check(&make_nes()), Ok(()));
+    assert_eq!(
+        movie.check(&Snake::new(0)),
+        Err("movie is for a different cartridge".to_string())
+    );
+    assert_eq!(
+        Movie::new(&Snake::new(1)).check(&Snake::new(2)),
+        Err("movie was recorded with seed 1, not 2".to_string())
+    );
+}
+
+#[test]
+fn test_playback() {
+    // A movie recorded from a run reproduces it.
+    let path = std::env::temp_dir().join(format!("nes-movie-{}.txt", std::process::id()));
+    let options = Options {
+        frames: Some(40),
+        select: vec![20, 40],
+        record: Some(path.clone()),
+        ..Default::default()
+    };
+    let mut input = ScriptInput::parse("5:+down,12:-down,12:+left,30:+p2.b").unwrap();
+    let hashes = headless::run(&mut Snake::new(5), &mut input, &options).unwrap();
+
+    let mut movie = Movie::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(movie.len(), 40);
+    assert_eq!(movie.seed, 5);
+    let options = Options {
+        frames: Some(movie.len()),
+        select: vec![20, 40],
+        ..Default::default()
+    };
+    assert_eq!(
+        headless::run(&mut Snake::new(movie.seed), &mut movie, &options).unwrap(),
+        hashes
+    );
+}
@@ -1,227 +1,476 @@
-use crate::cpu::AddressingMode;
-use std::collections::HashMap;
+// opcodes describes the 6502 instruction set. `lookup` decodes an opcode with a table built at compile time.
+use crate::cpu::{AddressingMode, Variant};
+use Mnemonic::*;
+
+// mnemonics defines `Mnemonic` and its names.
+macro_rules! mnemonics {
+    ($($name:ident),* $(,)?) => {
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        pub enum Mnemonic {
+            $($name),*
+        }
+
+        impl Mnemonic {
+            pub const fn name(self) -> &'static str {
+                match self {
+                    $(Mnemonic::$name => stringify!($name)),*
+                }
+            }
+        }
+    };
+}
+
+mnemonics!(
+    ADC, AND, ASL, BCC, BCS, BEQ, BIT, BMI, BNE, BPL, BRK, BVC, BVS, CLC, CLD, CLI, CLV, CMP, CPX,
+    CPY, DEC, DEX, DEY, EOR, INC, INX, INY, JMP, JSR, LDA, LDX, LDY, LSR, NOP, ORA, PHA, PHP, PLA,
+    PLP, ROL, ROR, RTI, RTS, SBC, SEC, SED, SEI, STA, STX, STY, TAX, TAY, TSX, TXA, TXS, TYA,
+    // Unofficial.
+    DCP, ISB, JAM, LAX, RLA, RRA, SAX, SLO, SRE, // 65C02.
+    BRA, PHX, PHY, PLX, PLY, STZ, TRB, TSB,
+);
 
+#[derive(Clone, Copy)]
 pub struct OpCode {
     pub code: u8,
+    pub mnemonic: Mnemonic,
     pub name: &'static str,
     pub bytes: u16,
     pub cycles: u8,
     pub mode: AddressingMode,
+    // unofficial is set for opcodes outside the documented 6502 instruction set.
+    // See https://www.nesdev.org/wiki/CPU_unofficial_opcodes
+    pub unofficial: bool,
 }
 
 impl OpCode {
-    fn new(code: u8, name: &'static str, bytes: u16, cycles: u8, mode: AddressingMode) -> Self {
+    const fn new(
+        code: u8,
+        mnemonic: Mnemonic,
+        bytes: u16,
+        cycles: u8,
+        mode: AddressingMode,
+    ) -> Self {
         return OpCode {
             code,
-            name,
+            mnemonic,
+            name: mnemonic.name(),
             bytes,
             cycles,
             mode,
+            unofficial: false,
         };
     }
+
+    const fn new_unofficial(
+        code: u8,
+        mnemonic: Mnemonic,
+        bytes: u16,
+        cycles: u8,
+        mode: AddressingMode,
+    ) -> Self {
+        OpCode {
+            unofficial: true,
+            ..OpCode::new(code, mnemonic, bytes, cycles, mode)
+        }
+    }
+
+    // display_name returns the name, prefixed with "*" for unofficial opcodes as in nestest.log.
+    pub fn display_name(&self) -> String {
+        if self.unofficial {
+            format!("*{}", self.name)
+        } else {
+            self.name.to_string()
+        }
+    }
+
+    // adds_cycle_on_page_cross returns true if the instruction takes an extra cycle when indexing crosses a page.
+    // Stores and read-modify-write instructions always take the extra cycle, so it is included in `cycles`.
+    pub fn adds_cycle_on_page_cross(&self) -> bool {
+        let indexed = matches!(
+            self.mode,
+            AddressingMode::Absolute_X | AddressingMode::Absolute_Y | AddressingMode::Indirect_Y
+        );
+        let reads = matches!(
+            self.mnemonic,
+            LDA | LDX | LDY | ADC | SBC | AND | ORA | EOR | CMP | LAX | NOP | BIT
+        );
+        indexed && reads
+    }
 }
 
-// #[macro_use]
-// extern crate lazy_static;
-use lazy_static::lazy_static;
-
-lazy_static! {
-    pub static ref CPU_OP_CODES: Vec<OpCode> = vec![
-        // TODO: check and fix cycle counts.
-
-        OpCode::new(0x00, "BRK", 1, 7, AddressingMode::NoneAddressing),
-        OpCode::new(0xaa, "TAX", 1, 2, AddressingMode::NoneAddressing),
-        OpCode::new(0xe8, "INX", 1, 2, AddressingMode::NoneAddressing),
-
-        OpCode::new(0xa9, "LDA", 2, 2, AddressingMode::Immediate),
-        OpCode::new(0xa5, "LDA", 2, 3, AddressingMode::ZeroPage),
-        OpCode::new(0xb5, "LDA", 2, 4, AddressingMode::ZeroPage_X),
-        OpCode::new(0xad, "LDA", 3, 4, AddressingMode::Absolute),
-        OpCode::new(0xbd, "LDA", 3, 4/*+1 if page crossed*/, AddressingMode::Absolute_X),
-        OpCode::new(0xb9, "LDA", 3, 4/*+1 if page crossed*/, AddressingMode::Absolute_Y),
-        OpCode::new(0xa1, "LDA", 2, 6, AddressingMode::Indirect_X),
-        OpCode::new(0xb1, "LDA", 2, 5/*+1 if page crossed*/, AddressingMode::Indirect_Y),
-
-        OpCode::new(0x85, "STA", 2, 3, AddressingMode::ZeroPage),
-        OpCode::new(0x95, "STA", 2, 4, AddressingMode::ZeroPage_X),
-        OpCode::new(0x8d, "STA", 3, 4, AddressingMode::Absolute),
-        OpCode::new(0x9d, "STA", 3, 5, AddressingMode::Absolute_X),
-        OpCode::new(0x99, "STA", 3, 5, AddressingMode::Absolute_Y),
-        OpCode::new(0x81, "STA", 2, 6, AddressingMode::Indirect_X),
-        OpCode::new(0x91, "STA", 2, 6, AddressingMode::Indirect_Y),
-
-        OpCode::new(0x4C, "JMP", 3, 3, AddressingMode::Absolute),
-        OpCode::new(0x6C, "JMP", 3, 5, AddressingMode::Indirect),
-
-        OpCode::new(0x69, "ADC", 2, 2, AddressingMode::Immediate),
-        OpCode::new(0x65, "ADC", 2, 3, AddressingMode::ZeroPage),
-        OpCode::new(0x75, "ADC", 2, 4, AddressingMode::ZeroPage_X),
-        OpCode::new(0x6d, "ADC", 3, 4, AddressingMode::Absolute),
-        OpCode::new(0x7d, "ADC", 3, 4/*+1 if page crossed */, AddressingMode::Absolute_X),
-        OpCode::new(0x79, "ADC", 3, 4/*+1 if page crossed */, AddressingMode::Absolute_Y),
-        OpCode::new(0x61, "ADC", 2, 6, AddressingMode::Indirect_X),
-        OpCode::new(0x71, "ADC", 2, 5/*+1 if page crossed */, AddressingMode::Indirect_Y),
-
-        OpCode::new(0x29, "AND", 2, 2, AddressingMode::Immediate),
-        OpCode::new(0x25, "AND", 2, 3, AddressingMode::ZeroPage),
-        OpCode::new(0x35, "AND", 2, 4, AddressingMode::ZeroPage_X),
-        OpCode::new(0x2d, "AND", 3, 4, AddressingMode::Absolute),
-        OpCode::new(0x3d, "AND", 3, 4, AddressingMode::Absolute_X),
-        OpCode::new(0x39, "AND", 3, 4/*+1 if page crossed*/, AddressingMode::Absolute_Y),
-        OpCode::new(0x21, "AND", 2, 6, AddressingMode::Indirect_X),
-        OpCode::new(0x31, "AND", 2, 5/*+1 if page crossed*/, AddressingMode::Indirect_Y),
-
-        OpCode::new(0x0a, "ASL", 1, 2, AddressingMode::Immediate),
-        OpCode::new(0x06, "ASL", 2, 5, AddressingMode::ZeroPage),
-        OpCode::new(0x16, "ASL", 2, 6, AddressingMode::ZeroPage_X),
-        OpCode::new(0x0E, "ASL", 3, 6, AddressingMode::Absolute),
-        OpCode::new(0x1E, "ASL", 3, 7, AddressingMode::Absolute_X),
-
-        OpCode::new(0x90, "BCC", 2, 2/*+1 if branch succeeds +2 if to a new page. */, AddressingMode::Relative),
-        OpCode::new(0xB0, "BCS", 2, 2/*+1 if branch succeeds +2 if to a new page. */, AddressingMode::Relative),
-        OpCode::new(0xF0, "BEQ", 2, 2/*+1 if branch succeeds +2 if to a new page. */, AddressingMode::Relative),
-        OpCode::new(0xD0, "BNE", 2, 2/*+1 if branch succeeds +2 if to a new page. */, AddressingMode::Relative),
-
-        OpCode::new(0x24, "BIT", 2, 3, AddressingMode::ZeroPage),
-        OpCode::new(0x2c, "BIT", 3, 4, AddressingMode::Absolute),
-
-        OpCode::new(0x30, "BMI", 2, 2/*+1 if branch succeeds +2 if to a new page. */, AddressingMode::Relative),
-        OpCode::new(0x10, "BPL", 2, 2/*+1 if branch succeeds +2 if to a new page. */, AddressingMode::Relative),
-        OpCode::new(0x50, "BVC", 2, 2/*+1 if branch succeeds +2 if to a new page. */, AddressingMode::Relative),
-        OpCode::new(0x70, "BVS", 2, 2/*+1 if branch succeeds +2 if to a new page. */, AddressingMode::Relative),
-
-        OpCode::new(0x18, "CLC", 1, 2, AddressingMode::NoneAddressing),
-        OpCode::new(0xD8, "CLD", 1, 2, AddressingMode::NoneAddressing),
-        OpCode::new(0x58, "CLI", 1, 2, AddressingMode::NoneAddressing),
-        OpCode::new(0xB8, "CLV", 1, 2, AddressingMode::NoneAddressing),
-
-        OpCode::new(0xC9, "CMP", 2, 2, AddressingMode::Immediate),
-        OpCode::new(0xC5, "CMP", 2, 3, AddressingMode::ZeroPage),
-        OpCode::new(0xD5, "CMP", 2, 4, AddressingMode::ZeroPage_X),
-        OpCode::new(0xCD, "CMP", 3, 4, AddressingMode::Absolute),
-        OpCode::new(0xDD, "CMP", 3, 4/*+1 if page crossed */, AddressingMode::Absolute_X),
-        OpCode::new(0xD9, "CMP", 3, 4/*+1 if page crossed */, AddressingMode::Absolute_Y),
-        OpCode::new(0xC1, "CMP", 2, 6, AddressingMode::Indirect_X),
-        OpCode::new(0xD1, "CMP", 2, 5/*+1 if page crossed */, AddressingMode::Indirect_Y),
-
-        OpCode::new(0xE0, "CPX", 2, 2, AddressingMode::Immediate),
-        OpCode::new(0xE4, "CPX", 2, 3, AddressingMode::ZeroPage),
-        OpCode::new(0xEC, "CPX", 3, 4, AddressingMode::Absolute),
-
-        OpCode::new(0xC0, "CPY", 2, 2, AddressingMode::Immediate),
-        OpCode::new(0xC4, "CPY", 2, 3, AddressingMode::ZeroPage),
-        OpCode::new(0xCC, "CPY", 3, 4, AddressingMode::Absolute),
-
-        OpCode::new(0xC6, "DEC", 2, 5, AddressingMode::ZeroPage),
-        OpCode::new(0xD6, "DEC", 2, 6, AddressingMode::ZeroPage_X),
-        OpCode::new(0xCE, "DEC", 3, 6, AddressingMode::Absolute),
-        OpCode::new(0xDE, "DEC", 3, 7, AddressingMode::Absolute_X),
-
-        OpCode::new(0xCA, "DEX", 1, 2, AddressingMode::NoneAddressing),
-        OpCode::new(0x88, "DEY", 1, 2, AddressingMode::NoneAddressing),
-
-        OpCode::new(0x49, "EOR", 2, 2, AddressingMode::Immediate),
-        OpCode::new(0x45, "EOR", 2, 3, AddressingMode::ZeroPage),
-        OpCode::new(0x55, "EOR", 2, 4, AddressingMode::ZeroPage_X),
-        OpCode::new(0x4D, "EOR", 3, 4, AddressingMode::Absolute),
-        OpCode::new(0x5D, "EOR", 3, 4/*+1 if page crossed*/, AddressingMode::Absolute_X),
-        OpCode::new(0x59, "EOR", 3, 4/*+1 if page crossed*/, AddressingMode::Absolute_Y),
-        OpCode::new(0x41, "EOR", 2, 6, AddressingMode::Indirect_X),
-        OpCode::new(0x51, "EOR", 2, 5/*+1 if page crossed*/, AddressingMode::Indirect_Y),
-
-        OpCode::new(0xE6, "INC", 2, 5, AddressingMode::ZeroPage),
-        OpCode::new(0xF6, "INC", 2, 6, AddressingMode::ZeroPage_X),
-        OpCode::new(0xEE, "INC", 3, 6, AddressingMode::Absolute),
-        OpCode::new(0xFE, "INC", 3, 7, AddressingMode::Absolute_X),
-
-        OpCode::new(0xE8, "INX", 1, 2, AddressingMode::NoneAddressing),
-        OpCode::new(0xC8, "INY", 1, 2, AddressingMode::NoneAddressing),
-
-        OpCode::new(0x20, "JSR", 3, 6, AddressingMode::Absolute),
-
-        OpCode::new(0xA2, "LDX", 2, 2, AddressingMode::Immediate),
-        OpCode::new(0xA6, "LDX", 2, 3, AddressingMode::ZeroPage),
-        OpCode::new(0xB6, "LDX", 2, 4, AddressingMode::ZeroPage_Y),
-        OpCode::new(0xAE, "LDX", 3, 4, AddressingMode::Absolute),
-        OpCode::new(0xBE, "LDX", 3, 4 /*+1 if page crossed*/, AddressingMode::Absolute_Y),
-
-        OpCode::new(0xA0, "LDY", 2, 2, AddressingMode::Immediate),
-        OpCode::new(0xA4, "LDY", 2, 3, AddressingMode::ZeroPage),
-        OpCode::new(0xB4, "LDY", 2, 4, AddressingMode::ZeroPage_X),
-        OpCode::new(0xAC, "LDY", 3, 4, AddressingMode::Absolute),
-        OpCode::new(0xBC, "LDY", 3, 4 /*+1 if page crossed*/, AddressingMode::Absolute_X),
-
-        OpCode::new(0x4A, "LSR", 1, 2, AddressingMode::Accumulator),
-        OpCode::new(0x46, "LSR", 2, 5, AddressingMode::ZeroPage),
-        OpCode::new(0x56, "LSR", 2, 6, AddressingMode::ZeroPage_X),
-        OpCode::new(0x4E, "LSR", 3, 6, AddressingMode::Absolute),
-        OpCode::new(0x5E, "LSR", 3, 7, AddressingMode::Absolute_X),
-
-        OpCode::new(0xEA, "NOP", 1, 2, AddressingMode::NoneAddressing),
-
-        OpCode::new(0x09, "ORA", 2, 2, AddressingMode::Immediate),
-        OpCode::new(0x05, "ORA", 2, 3, AddressingMode::ZeroPage),
-        OpCode::new(0x15, "ORA", 2, 4, AddressingMode::ZeroPage_X),
-        OpCode::new(0x0D, "ORA", 3, 4, AddressingMode::Absolute),
-        OpCode::new(0x1D, "ORA", 3, 4/*+1 if page crossed*/, AddressingMode::Absolute_X),
-        OpCode::new(0x19, "ORA", 3, 4/*+1 if page crossed*/, AddressingMode::Absolute_Y),
-        OpCode::new(0x01, "ORA", 2, 6, AddressingMode::Indirect_X),
-        OpCode::new(0x11, "ORA", 2, 5/*+1 if page crossed*/, AddressingMode::Indirect_Y),
-
-        OpCode::new(0x48, "PHA", 1, 3, AddressingMode::NoneAddressing),
-        OpCode::new(0x08, "PHP", 1, 3, AddressingMode::NoneAddressing),
-        OpCode::new(0x68, "PLA", 1, 4, AddressingMode::NoneAddressing),
-        OpCode::new(0x28, "PLP", 1, 4, AddressingMode::NoneAddressing),
-
-        OpCode::new(0x2A, "ROL", 1, 1, AddressingMode::Accumulator),
-        OpCode::new(0x26, "ROL", 2, 5, AddressingMode::ZeroPage),
-        OpCode::new(0x36, "ROL", 2, 6, AddressingMode::ZeroPage_X),
-        OpCode::new(0x2E, "ROL", 3, 6, AddressingMode::Absolute),
-        OpCode::new(0x3E, "ROL", 3, 7, AddressingMode::Absolute_X),
-
-        OpCode::new(0x6A, "ROR", 1, 2, AddressingMode::Accumulator),
-        OpCode::new(0x66, "ROR", 2, 5, AddressingMode::ZeroPage),
-        OpCode::new(0x76, "ROR", 2, 6, AddressingMode::ZeroPage_X),
-        OpCode::new(0x6E, "ROR", 3, 6, AddressingMode::Absolute),
-        OpCode::new(0x7E, "ROR", 3, 7, AddressingMode::Absolute_X),
-
-        OpCode::new(0x40, "RTI", 1, 6, AddressingMode::NoneAddressing),
-        OpCode::new(0x60, "RTS", 1, 6, AddressingMode::NoneAddressing),
-
-        OpCode::new(0xE9, "SBC", 2, 2, AddressingMode::Immediate),
-        OpCode::new(0xE5, "SBC", 2, 3, AddressingMode::ZeroPage),
-        OpCode::new(0xF5, "SBC", 2, 4, AddressingMode::ZeroPage_X),
-        OpCode::new(0xEd, "SBC", 3, 4, AddressingMode::Absolute),
-        OpCode::new(0xFd, "SBC", 3, 4/*+1 if page crossed */, AddressingMode::Absolute_X),
-        OpCode::new(0xF9, "SBC", 3, 4/*+1 if page crossed */, AddressingMode::Absolute_Y),
-        OpCode::new(0xE1, "SBC", 2, 6, AddressingMode::Indirect_X),
-        OpCode::new(0xF1, "SBC", 2, 5/*+1 if page crossed */, AddressingMode::Indirect_Y),
-
-        OpCode::new(0x38, "SEC", 1, 2, AddressingMode::NoneAddressing),
-        OpCode::new(0xF8, "SED", 1, 2, AddressingMode::NoneAddressing),
-        OpCode::new(0x78, "SEI", 1, 2, AddressingMode::NoneAddressing),
-
-        OpCode::new(0x86, "STX", 2, 3, AddressingMode::ZeroPage),
-        OpCode::new(0x96, "STX", 2, 4, AddressingMode::ZeroPage_X),
-        OpCode::new(0x8e, "STX", 3, 4, AddressingMode::Absolute),
-
-        OpCode::new(0x84, "STY", 2, 3, AddressingMode::ZeroPage),
-        OpCode::new(0x94, "STY", 2, 4, AddressingMode::ZeroPage_Y),
-        OpCode::new(0x8C, "STY", 3, 4, AddressingMode::Absolute),
-
-        OpCode::new(0xAA, "TAX", 1, 2, AddressingMode::NoneAddressing),
-        OpCode::new(0xA8, "TAY", 1, 2, AddressingMode::NoneAddressing),
-        OpCode::new(0xBA, "TSX", 1, 2, AddressingMode::NoneAddressing),
-        OpCode::new(0x8A, "TXA", 1, 2, AddressingMode::NoneAddressing),
-        OpCode::new(0x9A, "TXS", 1, 2, AddressingMode::NoneAddressing),
-        OpCode::new(0x98, "TYA", 1, 2, AddressingMode::NoneAddressing),
-    ];
-
-    pub static ref OPCODES_MAP: HashMap<u8, &'static OpCode> = {
-        let mut map = HashMap::new();
-        for cpuop in &*CPU_OP_CODES {
-            map.insert(cpuop.code, cpuop);
+// CPU_OP_CODES lists the implemented opcodes.
+#[rustfmt::skip]
+pub const CPU_OP_CODES: &[OpCode] = &[
+    // TODO: check and fix cycle counts.
+
+    OpCode::new(0x00, BRK, 1, 7, AddressingMode::NoneAddressing),
+    OpCode::new(0xaa, TAX, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0xe8, INX, 1, 2, AddressingMode::NoneAddressing),
+
+    OpCode::new(0xa9, LDA, 2, 2, AddressingMode::Immediate),
+    OpCode::new(0xa5, LDA, 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0xb5, LDA, 2, 4, AddressingMode::ZeroPage_X),
+    OpCode::new(0xad, LDA, 3, 4, AddressingMode::Absolute),
+    OpCode::new(0xbd, LDA, 3, 4/*+1 if page crossed*/, AddressingMode::Absolute_X),
+    OpCode::new(0xb9, LDA, 3, 4/*+1 if page crossed*/, AddressingMode::Absolute_Y),
+    OpCode::new(0xa1, LDA, 2, 6, AddressingMode::Indirect_X),
+    OpCode::new(0xb1, LDA, 2, 5/*+1 if page crossed*/, AddressingMode::Indirect_Y),
+
+    OpCode::new(0x85, STA, 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0x95, STA, 2, 4, AddressingMode::ZeroPage_X),
+    OpCode::new(0x8d, STA, 3, 4, AddressingMode::Absolute),
+    OpCode::new(0x9d, STA, 3, 5, AddressingMode::Absolute_X),
+    OpCode::new(0x99, STA, 3, 5, AddressingMode::Absolute_Y),
+    OpCode::new(0x81, STA, 2, 6, AddressingMode::Indirect_X),
+    OpCode::new(0x91, STA, 2, 6, AddressingMode::Indirect_Y),
+
+    OpCode::new(0x4C, JMP, 3, 3, AddressingMode::Absolute),
+    OpCode::new(0x6C, JMP, 3, 5, AddressingMode::Indirect),
+
+    OpCode::new(0x69, ADC, 2, 2, AddressingMode::Immediate),
+    OpCode::new(0x65, ADC, 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0x75, ADC, 2, 4, AddressingMode::ZeroPage_X),
+    OpCode::new(0x6d, ADC, 3, 4, AddressingMode::Absolute),
+    OpCode::new(0x7d, ADC, 3, 4/*+1 if page crossed */, AddressingMode::Absolute_X),
+    OpCode::new(0x79, ADC, 3, 4/*+1 if page crossed */, AddressingMode::Absolute_Y),
+    OpCode::new(0x61, ADC, 2, 6, AddressingMode::Indirect_X),
+    OpCode::new(0x71, ADC, 2, 5/*+1 if page crossed */, AddressingMode::Indirect_Y),
+
+    OpCode::new(0x29, AND, 2, 2, AddressingMode::Immediate),
+    OpCode::new(0x25, AND, 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0x35, AND, 2, 4, AddressingMode::ZeroPage_X),
+    OpCode::new(0x2d, AND, 3, 4, AddressingMode::Absolute),
+    OpCode::new(0x3d, AND, 3, 4, AddressingMode::Absolute_X),
+    OpCode::new(0x39, AND, 3, 4/*+1 if page crossed*/, AddressingMode::Absolute_Y),
+    OpCode::new(0x21, AND, 2, 6, AddressingMode::Indirect_X),
+    OpCode::new(0x31, AND, 2, 5/*+1 if page crossed*/, AddressingMode::Indirect_Y),
+
+    OpCode::new(0x0a, ASL, 1, 2, AddressingMode::Accumulator),
+    OpCode::new(0x06, ASL, 2, 5, AddressingMode::ZeroPage),
+    OpCode::new(0x16, ASL, 2, 6, AddressingMode::ZeroPage_X),
+    OpCode::new(0x0E, ASL, 3, 6, AddressingMode::Absolute),
+    OpCode::new(0x1E, ASL, 3, 7, AddressingMode::Absolute_X),
+
+    OpCode::new(0x90, BCC, 2, 2/*+1 if branch succeeds +2 if to a new page. */, AddressingMode::Relative),
+    OpCode::new(0xB0, BCS, 2, 2/*+1 if branch succeeds +2 if to a new page. */, AddressingMode::Relative),
+    OpCode::new(0xF0, BEQ, 2, 2/*+1 if branch succeeds +2 if to a new page. */, AddressingMode::Relative),
+    OpCode::new(0xD0, BNE, 2, 2/*+1 if branch succeeds +2 if to a new page. */, AddressingMode::Relative),
+
+    OpCode::new(0x24, BIT, 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0x2c, BIT, 3, 4, AddressingMode::Absolute),
+
+    OpCode::new(0x30, BMI, 2, 2/*+1 if branch succeeds +2 if to a new page. */, AddressingMode::Relative),
+    OpCode::new(0x10, BPL, 2, 2/*+1 if branch succeeds +2 if to a new page. */, AddressingMode::Relative),
+    OpCode::new(0x50, BVC, 2, 2/*+1 if branch succeeds +2 if to a new page. */, AddressingMode::Relative),
+    OpCode::new(0x70, BVS, 2, 2/*+1 if branch succeeds +2 if to a new page. */, AddressingMode::Relative),
+
+    OpCode::new(0x18, CLC, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0xD8, CLD, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0x58, CLI, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0xB8, CLV, 1, 2, AddressingMode::NoneAddressing),
+
+    OpCode::new(0xC9, CMP, 2, 2, AddressingMode::Immediate),
+    OpCode::new(0xC5, CMP, 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0xD5, CMP, 2, 4, AddressingMode::ZeroPage_X),
+    OpCode::new(0xCD, CMP, 3, 4, AddressingMode::Absolute),
+    OpCode::new(0xDD, CMP, 3, 4/*+1 if page crossed */, AddressingMode::Absolute_X),
+    OpCode::new(0xD9, CMP, 3, 4/*+1 if page crossed */, AddressingMode::Absolute_Y),
+    OpCode::new(0xC1, CMP, 2, 6, AddressingMode::Indirect_X),
+    OpCode::new(0xD1, CMP, 2, 5/*+1 if page crossed */, AddressingMode::Indirect_Y),
+
+    OpCode::new(0xE0, CPX, 2, 2, AddressingMode::Immediate),
+    OpCode::new(0xE4, CPX, 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0xEC, CPX, 3, 4, AddressingMode::Absolute),
+
+    OpCode::new(0xC0, CPY, 2, 2, AddressingMode::Immediate),
+    OpCode::new(0xC4, CPY, 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0xCC, CPY, 3, 4, AddressingMode::Absolute),
+
+    OpCode::new(0xC6, DEC, 2, 5, AddressingMode::ZeroPage),
+    OpCode::new(0xD6, DEC, 2, 6, AddressingMode::ZeroPage_X),
+    OpCode::new(0xCE, DEC, 3, 6, AddressingMode::Absolute),
+    OpCode::new(0xDE, DEC, 3, 7, AddressingMode::Absolute_X),
+
+    OpCode::new(0xCA, DEX, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0x88, DEY, 1, 2, AddressingMode::NoneAddressing),
+
+    OpCode::new(0x49, EOR, 2, 2, AddressingMode::Immediate),
+    OpCode::new(0x45, EOR, 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0x55, EOR, 2, 4, AddressingMode::ZeroPage_X),
+    OpCode::new(0x4D, EOR, 3, 4, AddressingMode::Absolute),
+    OpCode::new(0x5D, EOR, 3, 4/*+1 if page crossed*/, AddressingMode::Absolute_X),
+    OpCode::new(0x59, EOR, 3, 4/*+1 if page crossed*/, AddressingMode::Absolute_Y),
+    OpCode::new(0x41, EOR, 2, 6, AddressingMode::Indirect_X),
+    OpCode::new(0x51, EOR, 2, 5/*+1 if page crossed*/, AddressingMode::Indirect_Y),
+
+    OpCode::new(0xE6, INC, 2, 5, AddressingMode::ZeroPage),
+    OpCode::new(0xF6, INC, 2, 6, AddressingMode::ZeroPage_X),
+    OpCode::new(0xEE, INC, 3, 6, AddressingMode::Absolute),
+    OpCode::new(0xFE, INC, 3, 7, AddressingMode::Absolute_X),
+
+    OpCode::new(0xE8, INX, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0xC8, INY, 1, 2, AddressingMode::NoneAddressing),
+
+    OpCode::new(0x20, JSR, 3, 6, AddressingMode::Absolute),
+
+    OpCode::new(0xA2, LDX, 2, 2, AddressingMode::Immediate),
+    OpCode::new(0xA6, LDX, 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0xB6, LDX, 2, 4, AddressingMode::ZeroPage_Y),
+    OpCode::new(0xAE, LDX, 3, 4, AddressingMode::Absolute),
+    OpCode::new(0xBE, LDX, 3, 4 /*+1 if page crossed*/, AddressingMode::Absolute_Y),
+
+    OpCode::new(0xA0, LDY, 2, 2, AddressingMode::Immediate),
+    OpCode::new(0xA4, LDY, 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0xB4, LDY, 2, 4, AddressingMode::ZeroPage_X),
+    OpCode::new(0xAC, LDY, 3, 4, AddressingMode::Absolute),
+    OpCode::new(0xBC, LDY, 3, 4 /*+1 if page crossed*/, AddressingMode::Absolute_X),
+
+    OpCode::new(0x4A, LSR, 1, 2, AddressingMode::Accumulator),
+    OpCode::new(0x46, LSR, 2, 5, AddressingMode::ZeroPage),
+    OpCode::new(0x56, LSR, 2, 6, AddressingMode::ZeroPage_X),
+    OpCode::new(0x4E, LSR, 3, 6, AddressingMode::Absolute),
+    OpCode::new(0x5E, LSR, 3, 7, AddressingMode::Absolute_X),
+
+    OpCode::new(0xEA, NOP, 1, 2, AddressingMode::NoneAddressing),
+
+    OpCode::new(0x09, ORA, 2, 2, AddressingMode::Immediate),
+    OpCode::new(0x05, ORA, 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0x15, ORA, 2, 4, AddressingMode::ZeroPage_X),
+    OpCode::new(0x0D, ORA, 3, 4, AddressingMode::Absolute),
+    OpCode::new(0x1D, ORA, 3, 4/*+1 if page crossed*/, AddressingMode::Absolute_X),
+    OpCode::new(0x19, ORA, 3, 4/*+1 if page crossed*/, AddressingMode::Absolute_Y),
+    OpCode::new(0x01, ORA, 2, 6, AddressingMode::Indirect_X),
+    OpCode::new(0x11, ORA, 2, 5/*+1 if page crossed*/, AddressingMode::Indirect_Y),
+
+    OpCode::new(0x48, PHA, 1, 3, AddressingMode::NoneAddressing),
+    OpCode::new(0x08, PHP, 1, 3, AddressingMode::NoneAddressing),
+    OpCode::new(0x68, PLA, 1, 4, AddressingMode::NoneAddressing),
+    OpCode::new(0x28, PLP, 1, 4, AddressingMode::NoneAddressing),
+
+    OpCode::new(0x2A, ROL, 1, 2, AddressingMode::Accumulator),
+    OpCode::new(0x26, ROL, 2, 5, AddressingMode::ZeroPage),
+    OpCode::new(0x36, ROL, 2, 6, AddressingMode::ZeroPage_X),
+    OpCode::new(0x2E, ROL, 3, 6, AddressingMode::Absolute),
+    OpCode::new(0x3E, ROL, 3, 7, AddressingMode::Absolute_X),
+
+    OpCode::new(0x6A, ROR, 1, 2, AddressingMode::Accumulator),
+    OpCode::new(0x66, ROR, 2, 5, AddressingMode::ZeroPage),
+    OpCode::new(0x76, ROR, 2, 6, AddressingMode::ZeroPage_X),
+    OpCode::new(0x6E, ROR, 3, 6, AddressingMode::Absolute),
+    OpCode::new(0x7E, ROR, 3, 7, AddressingMode::Absolute_X),
+
+    OpCode::new(0x40, RTI, 1, 6, AddressingMode::NoneAddressing),
+    OpCode::new(0x60, RTS, 1, 6, AddressingMode::NoneAddressing),
+
+    OpCode::new(0xE9, SBC, 2, 2, AddressingMode::Immediate),
+    OpCode::new(0xE5, SBC, 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0xF5, SBC, 2, 4, AddressingMode::ZeroPage_X),
+    OpCode::new(0xEd, SBC, 3, 4, AddressingMode::Absolute),
+    OpCode::new(0xFd, SBC, 3, 4/*+1 if page crossed */, AddressingMode::Absolute_X),
+    OpCode::new(0xF9, SBC, 3, 4/*+1 if page crossed */, AddressingMode::Absolute_Y),
+    OpCode::new(0xE1, SBC, 2, 6, AddressingMode::Indirect_X),
+    OpCode::new(0xF1, SBC, 2, 5/*+1 if page crossed */, AddressingMode::Indirect_Y),
+
+    OpCode::new(0x38, SEC, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0xF8, SED, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0x78, SEI, 1, 2, AddressingMode::NoneAddressing),
+
+    OpCode::new(0x86, STX, 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0x96, STX, 2, 4, AddressingMode::ZeroPage_X),
+    OpCode::new(0x8e, STX, 3, 4, AddressingMode::Absolute),
+
+    OpCode::new(0x84, STY, 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0x94, STY, 2, 4, AddressingMode::ZeroPage_Y),
+    OpCode::new(0x8C, STY, 3, 4, AddressingMode::Absolute),
+
+    OpCode::new(0xAA, TAX, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0xA8, TAY, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0xBA, TSX, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0x8A, TXA, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0x9A, TXS, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0x98, TYA, 1, 2, AddressingMode::NoneAddressing),
+
+    // Unofficial opcodes. Unstable ones (e.g. XAA) are not included.
+
+    // JAM (also called KIL) locks up the CPU. See `CpuError::Jam`.
+    OpCode::new_unofficial(0x02, JAM, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new_unofficial(0x12, JAM, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new_unofficial(0x22, JAM, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new_unofficial(0x32, JAM, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new_unofficial(0x42, JAM, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new_unofficial(0x52, JAM, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new_unofficial(0x62, JAM, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new_unofficial(0x72, JAM, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new_unofficial(0x92, JAM, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new_unofficial(0xB2, JAM, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new_unofficial(0xD2, JAM, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new_unofficial(0xF2, JAM, 1, 2, AddressingMode::NoneAddressing),
+
+    OpCode::new_unofficial(0x1A, NOP, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new_unofficial(0x3A, NOP, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new_unofficial(0x5A, NOP, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new_unofficial(0x7A, NOP, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new_unofficial(0xDA, NOP, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new_unofficial(0xFA, NOP, 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new_unofficial(0x80, NOP, 2, 2, AddressingMode::Immediate),
+    OpCode::new_unofficial(0x82, NOP, 2, 2, AddressingMode::Immediate),
+    OpCode::new_unofficial(0x89, NOP, 2, 2, AddressingMode::Immediate),
+    OpCode::new_unofficial(0xC2, NOP, 2, 2, AddressingMode::Immediate),
+    OpCode::new_unofficial(0xE2, NOP, 2, 2, AddressingMode::Immediate),
+    OpCode::new_unofficial(0x04, NOP, 2, 3, AddressingMode::ZeroPage),
+    OpCode::new_unofficial(0x44, NOP, 2, 3, AddressingMode::ZeroPage),
+    OpCode::new_unofficial(0x64, NOP, 2, 3, AddressingMode::ZeroPage),
+    OpCode::new_unofficial(0x14, NOP, 2, 4, AddressingMode::ZeroPage_X),
+    OpCode::new_unofficial(0x34, NOP, 2, 4, AddressingMode::ZeroPage_X),
+    OpCode::new_unofficial(0x54, NOP, 2, 4, AddressingMode::ZeroPage_X),
+    OpCode::new_unofficial(0x74, NOP, 2, 4, AddressingMode::ZeroPage_X),
+    OpCode::new_unofficial(0xD4, NOP, 2, 4, AddressingMode::ZeroPage_X),
+    OpCode::new_unofficial(0xF4, NOP, 2, 4, AddressingMode::ZeroPage_X),
+    OpCode::new_unofficial(0x0C, NOP, 3, 4, AddressingMode::Absolute),
+    OpCode::new_unofficial(0x1C, NOP, 3, 4/*+1 if page crossed */, AddressingMode::Absolute_X),
+    OpCode::new_unofficial(0x3C, NOP, 3, 4/*+1 if page crossed */, AddressingMode::Absolute_X),
+    OpCode::new_unofficial(0x5C, NOP, 3, 4/*+1 if page crossed */, AddressingMode::Absolute_X),
+    OpCode::new_unofficial(0x7C, NOP, 3, 4/*+1 if page crossed */, AddressingMode::Absolute_X),
+    OpCode::new_unofficial(0xDC, NOP, 3, 4/*+1 if page crossed */, AddressingMode::Absolute_X),
+    OpCode::new_unofficial(0xFC, NOP, 3, 4/*+1 if page crossed */, AddressingMode::Absolute_X),
+
+    OpCode::new_unofficial(0xA7, LAX, 2, 3, AddressingMode::ZeroPage),
+    OpCode::new_unofficial(0xB7, LAX, 2, 4, AddressingMode::ZeroPage_Y),
+    OpCode::new_unofficial(0xAF, LAX, 3, 4, AddressingMode::Absolute),
+    OpCode::new_unofficial(0xBF, LAX, 3, 4/*+1 if page crossed */, AddressingMode::Absolute_Y),
+    OpCode::new_unofficial(0xA3, LAX, 2, 6, AddressingMode::Indirect_X),
+    OpCode::new_unofficial(0xB3, LAX, 2, 5/*+1 if page crossed */, AddressingMode::Indirect_Y),
+
+    OpCode::new_unofficial(0x87, SAX, 2, 3, AddressingMode::ZeroPage),
+    OpCode::new_unofficial(0x97, SAX, 2, 4, AddressingMode::ZeroPage_Y),
+    OpCode::new_unofficial(0x8F, SAX, 3, 4, AddressingMode::Absolute),
+    OpCode::new_unofficial(0x83, SAX, 2, 6, AddressingMode::Indirect_X),
+
+    OpCode::new_unofficial(0xEB, SBC, 2, 2, AddressingMode::Immediate),
+
+    OpCode::new_unofficial(0x07, SLO, 2, 5, AddressingMode::ZeroPage),
+    OpCode::new_unofficial(0x17, SLO, 2, 6, AddressingMode::ZeroPage_X),
+    OpCode::new_unofficial(0x0F, SLO, 3, 6, AddressingMode::Absolute),
+    OpCode::new_unofficial(0x1F, SLO, 3, 7, AddressingMode::Absolute_X),
+    OpCode::new_unofficial(0x1B, SLO, 3, 7, AddressingMode::Absolute_Y),
+    OpCode::new_unofficial(0x03, SLO, 2, 8, AddressingMode::Indirect_X),
+    OpCode::new_unofficial(0x13, SLO, 2, 8, AddressingMode::Indirect_Y),
+
+    OpCode::new_unofficial(0x27, RLA, 2, 5, AddressingMode::ZeroPage),
+    OpCode::new_unofficial(0x37, RLA, 2, 6, AddressingMode::ZeroPage_X),
+    OpCode::new_unofficial(0x2F, RLA, 3, 6, AddressingMode::Absolute),
+    OpCode::new_unofficial(0x3F, RLA, 3, 7, AddressingMode::Absolute_X),
+    OpCode::new_unofficial(0x3B, RLA, 3, 7, AddressingMode::Absolute_Y),
+    OpCode::new_unofficial(0x23, RLA, 2, 8, AddressingMode::Indirect_X),
+    OpCode::new_unofficial(0x33, RLA, 2, 8, AddressingMode::Indirect_Y),
+
+    OpCode::new_unofficial(0x47, SRE, 2, 5, AddressingMode::ZeroPage),
+    OpCode::new_unofficial(0x57, SRE, 2, 6, AddressingMode::ZeroPage_X),
+    OpCode::new_unofficial(0x4F, SRE, 3, 6, AddressingMode::Absolute),
+    OpCode::new_unofficial(0x5F, SRE, 3, 7, AddressingMode::Absolute_X),
+    OpCode::new_unofficial(0x5B, SRE, 3, 7, AddressingMode::Absolute_Y),
+    OpCode::new_unofficial(0x43, SRE, 2, 8, AddressingMode::Indirect_X),
+    OpCode::new_unofficial(0x53, SRE, 2, 8, AddressingMode::Indirect_Y),
+
+    OpCode::new_unofficial(0x67, RRA, 2, 5, AddressingMode::ZeroPage),
+    OpCode::new_unofficial(0x77, RRA, 2, 6, AddressingMode::ZeroPage_X),
+    OpCode::new_unofficial(0x6F, RRA, 3, 6, AddressingMode::Absolute),
+    OpCode::new_unofficial(0x7F, RRA, 3, 7, AddressingMode::Absolute_X),
+    OpCode::new_unofficial(0x7B, RRA, 3, 7, AddressingMode::Absolute_Y),
+    OpCode::new_unofficial(0x63, RRA, 2, 8, AddressingMode::Indirect_X),
+    OpCode::new_unofficial(0x73, RRA, 2, 8, AddressingMode::Indirect_Y),
+
+    OpCode::new_unofficial(0xC7, DCP, 2, 5, AddressingMode::ZeroPage),
+    OpCode::new_unofficial(0xD7, DCP, 2, 6, AddressingMode::ZeroPage_X),
+    OpCode::new_unofficial(0xCF, DCP, 3, 6, AddressingMode::Absolute),
+    OpCode::new_unofficial(0xDF, DCP, 3, 7, AddressingMode::Absolute_X),
+    OpCode::new_unofficial(0xDB, DCP, 3, 7, AddressingMode::Absolute_Y),
+    OpCode::new_unofficial(0xC3, DCP, 2, 8, AddressingMode::Indirect_X),
+    OpCode::new_unofficial(0xD3, DCP, 2, 8, AddressingMode::Indirect_Y),
+
+    OpCode::new_unofficial(0xE7, ISB, 2, 5, AddressingMode::ZeroPage),
+    OpCode::new_unofficial(0xF7, ISB, 2, 6, AddressingMode::ZeroPage_X),
+    OpCode::new_unofficial(0xEF, ISB, 3, 6, AddressingMode::Absolute),
+    OpCode::new_unofficial(0xFF, ISB, 3, 7, AddressingMode::Absolute_X),
+    OpCode::new_unofficial(0xFB, ISB, 3, 7, AddressingMode::Absolute_Y),
+    OpCode::new_unofficial(0xE3, ISB, 2, 8, AddressingMode::Indirect_X),
+    OpCode::new_unofficial(0xF3, ISB, 2, 8, AddressingMode::Indirect_Y),
+];
+
+// CMOS_OP_CODES lists the opcodes added by the 65C02. See http://www.6502.org/tutorials/65c02opcodes.html
+// The Rockwell and WDC bit instructions (BBR, BBS, RMB and SMB), WAI and STP are not implemented.
+#[rustfmt::skip]
+pub const CMOS_OP_CODES: &[OpCode] = &[
+    OpCode::new(0x80, BRA, 2, 3/*+1 if to a new page. */, AddressingMode::Relative),
+
+    OpCode::new(0xDA, PHX, 1, 3, AddressingMode::NoneAddressing),
+    OpCode::new(0x5A, PHY, 1, 3, AddressingMode::NoneAddressing),
+    OpCode::new(0xFA, PLX, 1, 4, AddressingMode::NoneAddressing),
+    OpCode::new(0x7A, PLY, 1, 4, AddressingMode::NoneAddressing),
+
+    OpCode::new(0x64, STZ, 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0x74, STZ, 2, 4, AddressingMode::ZeroPage_X),
+    OpCode::new(0x9C, STZ, 3, 4, AddressingMode::Absolute),
+    OpCode::new(0x9E, STZ, 3, 5, AddressingMode::Absolute_X),
+
+    OpCode::new(0x14, TRB, 2, 5, AddressingMode::ZeroPage),
+    OpCode::new(0x1C, TRB, 3, 6, AddressingMode::Absolute),
+    OpCode::new(0x04, TSB, 2, 5, AddressingMode::ZeroPage),
+    OpCode::new(0x0C, TSB, 3, 6, AddressingMode::Absolute),
+
+    OpCode::new(0x1A, INC, 1, 2, AddressingMode::Accumulator),
+    OpCode::new(0x3A, DEC, 1, 2, AddressingMode::Accumulator),
+
+    OpCode::new(0x89, BIT, 2, 2, AddressingMode::Immediate),
+    OpCode::new(0x34, BIT, 2, 4, AddressingMode::ZeroPage_X),
+    OpCode::new(0x3C, BIT, 3, 4/*+1 if page crossed*/, AddressingMode::Absolute_X),
+
+    OpCode::new(0x7C, JMP, 3, 6, AddressingMode::Absolute_Indirect_X),
+
+    OpCode::new(0x12, ORA, 2, 5, AddressingMode::ZeroPage_Indirect),
+    OpCode::new(0x32, AND, 2, 5, AddressingMode::ZeroPage_Indirect),
+    OpCode::new(0x52, EOR, 2, 5, AddressingMode::ZeroPage_Indirect),
+    OpCode::new(0x72, ADC, 2, 5, AddressingMode::ZeroPage_Indirect),
+    OpCode::new(0x92, STA, 2, 5, AddressingMode::ZeroPage_Indirect),
+    OpCode::new(0xB2, LDA, 2, 5, AddressingMode::ZeroPage_Indirect),
+    OpCode::new(0xD2, CMP, 2, 5, AddressingMode::ZeroPage_Indirect),
+    OpCode::new(0xF2, SBC, 2, 5, AddressingMode::ZeroPage_Indirect),
+];
+
+// table maps each opcode in `lists` to its description. Later entries take precedence over earlier duplicates.
+// Unofficial opcodes are skipped unless `unofficial` is set.
+const fn table(lists: &[&[OpCode]], unofficial: bool) -> [Option<OpCode>; 256] {
+    let mut table = [None; 256];
+    let mut i = 0;
+    while i < lists.len() {
+        let mut j = 0;
+        while j < lists[i].len() {
+            let opcode = lists[i][j];
+            if unofficial || !opcode.unofficial {
+                table[opcode.code as usize] = Some(opcode);
+            }
+            j += 1;
         }
-        map
-    };
+        i += 1;
+    }
+    table
+}
+
+// OPCODES maps each opcode of the NMOS 6502, including the 2A03, to its description.
+pub static OPCODES: [Option<OpCode>; 256] = table(&[CPU_OP_CODES], true);
+
+// CMOS_OPCODES maps each opcode of the 65C02 to its description. It has no unofficial opcodes.
+pub static CMOS_OPCODES: [Option<OpCode>; 256] = table(&[CPU_OP_CODES, CMOS_OP_CODES], false);
+
+// lookup returns the description of `code` on the NMOS 6502, or None if it is not implemented.
+pub fn lookup(code: u8) -> Option<&'static OpCode> {
+    OPCODES[code as usize].as_ref()
+}
+
+// lookup_variant returns the description of `code` on `variant`, or None if it is not implemented.
+pub fn lookup_variant(variant: Variant, code: u8) -> Option<&'static OpCode> {
+    match variant {
+        Variant::Nes2A03 | Variant::Nmos6502 => lookup(code),
+        Variant::Cmos65C02 => CMOS_OPCODES[code as usize].as_ref(),
+    }
 }
@@ -0,0 +1,74 @@
+use super::*;
+
+// read_chunks splits a PNG into (type, data) pairs and checks each CRC.
+fn read_chunks(png: &[u8]) -> Vec<(String, Vec<u8>)> {
+    assert_eq!(png[..8], SIGNATURE);
+    let mut chunks = Vec::new();
+    let mut pos = 8;
+    while pos < png.len() {
+        let len = u32::from_be_bytes(png[pos..pos + 4].try_into().unwrap()) as usize;
+        let body = &png[pos + 4..pos + 8 + len];
+        let crc = u32::from_be_bytes(png[pos + 8 + len..pos + 12 + len].try_into().unwrap());
+        assert_eq!(crc, crc32(body));
+        chunks.push((
+            String::from_utf8(body[..4].to_vec()).unwrap(),
+            body[4..].to_vec(),
+        ));
+        pos += 12 + len;
+    }
+    chunks
+}
+
+// inflate_stored decodes a zlib stream made of stored blocks.
+fn inflate_stored(zlib: &[u8]) -> Vec<u8> {
+    assert_eq!((zlib[0] as u16 * 256 + zlib[1] as u16) % 31, 0);
+    let mut out = Vec::new();
+    let mut pos = 2;
+    loop {
+        let header = zlib[pos];
+        assert_eq!(header & 0b110, 0, "not a stored block");
+        let len = u16::from_le_bytes([zlib[pos + 1], zlib[pos + 2]]) as usize;
+        let nlen = u16::from_le_bytes([zlib[pos + 3], zlib[pos + 4]]);
+        assert_eq!(!nlen as usize, len);
+        out.extend_from_slice(&zlib[pos + 5..pos + 5 + len]);
+        pos += 5 + len;
+        if header & 1 == 1 {
+            break;
+        }
+    }
+    assert_eq!(zlib[pos..], adler32(&out).to_be_bytes());
+    out
+}
+
+#[test]
+fn test_encode_rgb() {
+    let rgb = [
+        255, 0, 0, 0, 255, 0, // Red, green.
+        0, 0, 255, 255, 255, 255, // Blue, white.
+    ];
+    let png = encode_rgb(2, 2, &rgb);
+    let chunks = read_chunks(&png);
+    let kinds: Vec<&str> = chunks.iter().map(|(k, _)| k.as_str()).collect();
+    assert_eq!(kinds, vec!["IHDR", "IDAT", "IEND"]);
+    assert_eq!(chunks[0].1, vec![0, 0, 0, 2, 0, 0, 0, 2, 8, 2, 0, 0, 0]);
+    assert_eq!(
+        inflate_stored(&chunks[1].1),
+        vec![0, 255, 0, 0, 0, 255, 0, 0, 0, 0, 255, 255, 255, 255]
+    );
+}
+
+#[test]
+fn test_large_image_uses_multiple_blocks() {
+    let rgb: Vec<u8> = (0..256 * 240 * 3).map(|i| i as u8).collect();
+    let chunks = read_chunks(&encode_rgb(256, 240, &rgb));
+    let raw = inflate_stored(&chunks[1].1);
+    assert_eq!(raw.len(), 240 * (256 * 3 + 1));
+    assert_eq!(raw[1..256 * 3 + 1], rgb[..256 * 3]);
+}
+
+#[test]
+fn test_checksums() {
+    assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    assert_eq!(crc32(b"IEND"), 0xAE426082);
+    assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+}
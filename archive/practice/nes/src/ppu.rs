@@ -0,0 +1,597 @@
+// Ppu emulates the NES Picture Processing Unit (2C02).
+// See https://www.nesdev.org/wiki/PPU_registers and https://www.nesdev.org/wiki/PPU_rendering
+//
+// Rendering is done one scanline at a time rather than one dot at a time. Scroll registers follow
+// the "loopy" model described in https://www.nesdev.org/wiki/PPU_scrolling so that mid-frame
+// scroll changes (e.g. status bars) render correctly.
+use crate::cartridge::Mirroring;
+use crate::savestate::{StateReader, StateWriter};
+
+pub mod frame;
+pub mod palette;
+
+use frame::Frame;
+use palette::SYSTEM_PALETTE;
+
+// Dots per scanline and scanlines per frame (NTSC).
+pub const DOTS_PER_SCANLINE: u16 = 341;
+pub const SCANLINES_PER_FRAME: u16 = 262;
+const VBLANK_SCANLINE: u16 = 241;
+const PRERENDER_SCANLINE: u16 = 261;
+
+const CHR_RAM_SIZE: usize = 0x2000;
+
+pub struct Ppu {
+    // chr is the pattern table memory (0x0000..0x2000). It is CHR ROM from the cartridge, or CHR RAM if the cartridge has none.
+    pub chr: Vec<u8>,
+    chr_is_ram: bool,
+    pub mirroring: Mirroring,
+    // vram holds the nametables. Four-screen mirroring uses all 4KiB. Otherwise only 2KiB is used.
+    vram: [u8; 0x1000],
+    palette_table: [u8; 32],
+    oam_data: [u8; 256],
+
+    ctrl: u8,
+    mask: u8,
+    status: u8,
+    oam_addr: u8,
+    // v, t, fine_x, and w are the internal registers described in https://www.nesdev.org/wiki/PPU_scrolling#PPU_internal_registers
+    v: u16,
+    t: u16,
+    fine_x: u8,
+    w: bool,
+    // read_buffer holds the value returned by the next read of PPUDATA.
+    read_buffer: u8,
+    // io_latch is the value last written to any register. It is returned when reading write-only registers.
+    io_latch: u8,
+
+    pub scanline: u16,
+    pub cycle: u16,
+    pub frame_count: u64,
+    nmi_interrupt: bool,
+    frame_complete: bool,
+    pub frame: Frame,
+}
+
+impl Ppu {
+    pub const CTRL_NAMETABLE: u8 = 0b0000_0011;
+    pub const CTRL_VRAM_INCREMENT: u8 = 0b0000_0100;
+    pub const CTRL_SPRITE_PATTERN: u8 = 0b0000_1000;
+    pub const CTRL_BACKGROUND_PATTERN: u8 = 0b0001_0000;
+    pub const CTRL_SPRITE_SIZE: u8 = 0b0010_0000;
+    pub const CTRL_GENERATE_NMI: u8 = 0b1000_0000;
+
+    pub const MASK_GREYSCALE: u8 = 0b0000_0001;
+    pub const MASK_BACKGROUND_LEFT: u8 = 0b0000_0010;
+    pub const MASK_SPRITES_LEFT: u8 = 0b0000_0100;
+    pub const MASK_BACKGROUND: u8 = 0b0000_1000;
+    pub const MASK_SPRITES: u8 = 0b0001_0000;
+
+    pub const STATUS_SPRITE_OVERFLOW: u8 = 0b0010_0000;
+    pub const STATUS_SPRITE_ZERO_HIT: u8 = 0b0100_0000;
+    pub const STATUS_VBLANK: u8 = 0b1000_0000;
+
+    pub fn new(chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+        let chr_is_ram = chr_rom.is_empty();
+        let chr = if chr_is_ram {
+            vec![0; CHR_RAM_SIZE]
+        } else {
+            chr_rom
+        };
+        Ppu {
+            chr,
+            chr_is_ram,
+            mirroring,
+            vram: [0; 0x1000],
+            palette_table: [0; 32],
+            oam_data: [0; 256],
+            ctrl: 0,
+            mask: 0,
+            status: 0,
+            oam_addr: 0,
+            v: 0,
+            t: 0,
+            fine_x: 0,
+            w: false,
+            read_buffer: 0,
+            io_latch: 0,
+            scanline: 0,
+            cycle: 0,
+            frame_count: 0,
+            nmi_interrupt: false,
+            frame_complete: false,
+            frame: Frame::new(),
+        }
+    }
+
+    // save_state writes everything except CHR ROM and mirroring, which come from the cartridge.
+    pub fn save_state(&self, w: &mut StateWriter) {
+        if self.chr_is_ram {
+            w.bytes(&self.chr);
+        }
+        w.bytes(&self.vram);
+        w.bytes(&self.palette_table);
+        w.bytes(&self.oam_data);
+        w.u8(self.ctrl);
+        w.u8(self.mask);
+        w.u8(self.status);
+        w.u8(self.oam_addr);
+        w.u16(self.v);
+        w.u16(self.t);
+        w.u8(self.fine_x);
+        w.bool(self.w);
+        w.u8(self.read_buffer);
+        w.u8(self.io_latch);
+        w.u16(self.scanline);
+        w.u16(self.cycle);
+        w.u64(self.frame_count);
+        w.bool(self.nmi_interrupt);
+        w.bool(self.frame_complete);
+        // The frame is saved so a state saved mid-frame resumes with the scanlines already rendered.
+        w.bytes(&self.frame.data);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), String> {
+        if self.chr_is_ram {
+            self.chr = r.bytes(CHR_RAM_SIZE)?.to_vec();
+        }
+        self.vram.copy_from_slice(r.bytes(0x1000)?);
+        self.palette_table.copy_from_slice(r.bytes(32)?);
+        self.oam_data.copy_from_slice(r.bytes(256)?);
+        self.ctrl = r.u8()?;
+        self.mask = r.u8()?;
+        self.status = r.u8()?;
+        self.oam_addr = r.u8()?;
+        self.v = r.u16()?;
+        self.t = r.u16()?;
+        self.fine_x = r.u8()?;
+        self.w = r.bool()?;
+        self.read_buffer = r.u8()?;
+        self.io_latch = r.u8()?;
+        self.scanline = r.u16()?;
+        self.cycle = r.u16()?;
+        self.frame_count = r.u64()?;
+        self.nmi_interrupt = r.bool()?;
+        self.frame_complete = r.bool()?;
+        self.frame.data = r.bytes(self.frame.data.len())?.to_vec();
+        Ok(())
+    }
+
+    // read_register reads the register mapped at 0x2000 + (addr & 7).
+    pub fn read_register(&mut self, addr: u16) -> u8 {
+        match addr & 0b111 {
+            2 => self.read_status(),
+            4 => self.read_oam_data(),
+            7 => self.read_data(),
+            // Write-only registers.
+            _ => self.io_latch,
+        }
+    }
+
+    // peek_register returns the value `read_register` would return, without side effects.
+    pub fn peek_register(&self, addr: u16) -> u8 {
+        match addr & 0b111 {
+            2 => (self.status & 0b1110_0000) | (self.io_latch & 0b0001_1111),
+            4 => self.read_oam_data(),
+            7 => {
+                let addr = self.v & 0x3FFF;
+                if addr >= 0x3F00 {
+                    self.read_vram(addr)
+                } else {
+                    self.read_buffer
+                }
+            }
+            _ => self.io_latch,
+        }
+    }
+
+    // write_register writes the register mapped at 0x2000 + (addr & 7).
+    pub fn write_register(&mut self, addr: u16, val: u8) {
+        self.io_latch = val;
+        match addr & 0b111 {
+            0 => self.write_to_ctrl(val),
+            1 => self.mask = val,
+            2 => { /* PPUSTATUS is read-only. */ }
+            3 => self.oam_addr = val,
+            4 => self.write_to_oam_data(val),
+            5 => self.write_to_scroll(val),
+            6 => self.write_to_ppu_addr(val),
+            7 => self.write_to_data(val),
+            _ => unreachable!(),
+        }
+    }
+
+    fn write_to_ctrl(&mut self, val: u8) {
+        let nmi_was_enabled = self.ctrl & Ppu::CTRL_GENERATE_NMI != 0;
+        self.ctrl = val;
+        // t: ...GH.. ........ <- d: ......GH
+        self.t = (self.t & !0x0C00) | (((val & Ppu::CTRL_NAMETABLE) as u16) << 10);
+        // Enabling NMI during vblank immediately generates an NMI.
+        if !nmi_was_enabled
+            && self.ctrl & Ppu::CTRL_GENERATE_NMI != 0
+            && self.status & Ppu::STATUS_VBLANK != 0
+        {
+            self.nmi_interrupt = true;
+        }
+    }
+
+    fn read_status(&mut self) -> u8 {
+        // The low 5 bits are open bus.
+        let val = (self.status & 0b1110_0000) | (self.io_latch & 0b0001_1111);
+        self.status &= !Ppu::STATUS_VBLANK;
+        self.w = false;
+        val
+    }
+
+    fn read_oam_data(&self) -> u8 {
+        self.oam_data[self.oam_addr as usize]
+    }
+
+    fn write_to_oam_data(&mut self, val: u8) {
+        self.oam_data[self.oam_addr as usize] = val;
+        self.oam_addr = self.oam_addr.wrapping_add(1);
+    }
+
+    // write_oam_dma copies a page of CPU memory into OAM. It is triggered by writing to 0x4014.
+    pub fn write_oam_dma(&mut self, page: &[u8; 256]) {
+        for b in page.iter() {
+            self.write_to_oam_data(*b);
+        }
+    }
+
+    fn write_to_scroll(&mut self, val: u8) {
+        if !self.w {
+            // t: ....... ...ABCDE <- d: ABCDE...
+            self.t = (self.t & !0x001F) | ((val >> 3) as u16);
+            self.fine_x = val & 0b111;
+        } else {
+            // t: FGH..AB CDE..... <- d: ABCDEFGH
+            self.t =
+                (self.t & !0x73E0) | (((val & 0b111) as u16) << 12) | (((val >> 3) as u16) << 5);
+        }
+        self.w = !self.w;
+    }
+
+    fn write_to_ppu_addr(&mut self, val: u8) {
+        if !self.w {
+            // t: .CDEFGH ........ <- d: ..CDEFGH. Bit 14 is cleared.
+            self.t = (self.t & 0x00FF) | (((val & 0b0011_1111) as u16) << 8);
+        } else {
+            self.t = (self.t & 0xFF00) | val as u16;
+            self.v = self.t;
+        }
+        self.w = !self.w;
+    }
+
+    fn increment_vram_addr(&mut self) {
+        let inc = if self.ctrl & Ppu::CTRL_VRAM_INCREMENT != 0 {
+            32
+        } else {
+            1
+        };
+        self.v = self.v.wrapping_add(inc) & 0x7FFF;
+    }
+
+    fn read_data(&mut self) -> u8 {
+        let addr = self.v & 0x3FFF;
+        self.increment_vram_addr();
+        if addr >= 0x3F00 {
+            // Palette reads are not buffered. The buffer is filled with the nametable byte "underneath" the palette.
+            self.read_buffer = self.read_vram(addr - 0x1000);
+            return self.read_vram(addr);
+        }
+        let val = self.read_buffer;
+        self.read_buffer = self.read_vram(addr);
+        val
+    }
+
+    fn write_to_data(&mut self, val: u8) {
+        let addr = self.v & 0x3FFF;
+        self.write_vram(addr, val);
+        self.increment_vram_addr();
+    }
+
+    // mirror_nametable_addr maps an address in 0x2000..0x3F00 to an index into `vram`.
+    fn mirror_nametable_addr(&self, addr: u16) -> usize {
+        let index = (addr & 0x0FFF) as usize;
+        let nametable = index / 0x400;
+        let offset = index % 0x400;
+        let physical = match (self.mirroring, nametable) {
+            (Mirroring::FourScreen, n) => n,
+            (Mirroring::Vertical, n) => n % 2,
+            (Mirroring::Horizontal, n) => n / 2,
+        };
+        physical * 0x400 + offset
+    }
+
+    fn mirror_palette_addr(addr: u16) -> usize {
+        let index = (addr & 0x1F) as usize;
+        // 0x3F10, 0x3F14, 0x3F18, 0x3F1C mirror 0x3F00, 0x3F04, 0x3F08, 0x3F0C.
+        if index >= 0x10 && index.is_multiple_of(4) {
+            return index - 0x10;
+        }
+        index
+    }
+
+    pub fn read_vram(&self, addr: u16) -> u8 {
+        let addr = addr & 0x3FFF;
+        match addr {
+            0x0000..=0x1FFF => self.chr[addr as usize],
+            0x2000..=0x3EFF => self.vram[self.mirror_nametable_addr(addr)],
+            _ => self.palette_table[Ppu::mirror_palette_addr(addr)],
+        }
+    }
+
+    pub fn write_vram(&mut self, addr: u16, val: u8) {
+        let addr = addr & 0x3FFF;
+        match addr {
+            0x0000..=0x1FFF => {
+                if self.chr_is_ram {
+                    self.chr[addr as usize] = val;
+                }
+            }
+            0x2000..=0x3EFF => {
+                let index = self.mirror_nametable_addr(addr);
+                self.vram[index] = val;
+            }
+            _ => self.palette_table[Ppu::mirror_palette_addr(addr)] = val,
+        }
+    }
+
+    // poll_nmi_interrupt returns true once for each NMI generated.
+    pub fn poll_nmi_interrupt(&mut self) -> bool {
+        core::mem::replace(&mut self.nmi_interrupt, false)
+    }
+
+    // poll_frame_complete returns true once for each frame, when vblank starts.
+    pub fn poll_frame_complete(&mut self) -> bool {
+        core::mem::replace(&mut self.frame_complete, false)
+    }
+
+    fn rendering_enabled(&self) -> bool {
+        self.mask & (Ppu::MASK_BACKGROUND | Ppu::MASK_SPRITES) != 0
+    }
+
+    // tick advances the PPU by `dots` PPU cycles. The PPU runs 3 dots per CPU cycle.
+    pub fn tick(&mut self, dots: usize) {
+        for _ in 0..dots {
+            self.step_dot();
+        }
+    }
+
+    fn step_dot(&mut self) {
+        self.cycle += 1;
+        if self.cycle == DOTS_PER_SCANLINE {
+            self.cycle = 0;
+            self.scanline += 1;
+            if self.scanline == SCANLINES_PER_FRAME {
+                self.scanline = 0;
+                self.frame_count += 1;
+            }
+        }
+
+        let visible = self.scanline < 240;
+        let prerender = self.scanline == PRERENDER_SCANLINE;
+        let rendering = self.rendering_enabled();
+
+        if visible && self.cycle == 256 {
+            self.render_scanline(self.scanline as usize);
+        }
+        if (visible || prerender) && rendering {
+            if self.cycle == 256 {
+                self.increment_y();
+            } else if self.cycle == 257 {
+                // v: ....A.. ...BCDEF <- t: ....A.. ...BCDEF
+                self.v = (self.v & !0x041F) | (self.t & 0x041F);
+            }
+        }
+        if prerender && rendering && self.cycle == 280 {
+            // v: GHIA.BC DEF..... <- t: GHIA.BC DEF.....
+            self.v = (self.v & !0x7BE0) | (self.t & 0x7BE0);
+        }
+
+        if self.scanline == VBLANK_SCANLINE && self.cycle == 1 {
+            self.status |= Ppu::STATUS_VBLANK;
+            self.frame_complete = true;
+            if self.ctrl & Ppu::CTRL_GENERATE_NMI != 0 {
+                self.nmi_interrupt = true;
+            }
+        }
+        if prerender && self.cycle == 1 {
+            self.status &=
+                !(Ppu::STATUS_VBLANK | Ppu::STATUS_SPRITE_ZERO_HIT | Ppu::STATUS_SPRITE_OVERFLOW);
+        }
+    }
+
+    // increment_y follows https://www.nesdev.org/wiki/PPU_scrolling#Wrapping_around
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !0x7000;
+        let mut coarse_y = (self.v & 0x03E0) >> 5;
+        if coarse_y == 29 {
+            coarse_y = 0;
+            self.v ^= 0x0800;
+        } else if coarse_y == 31 {
+            coarse_y = 0;
+        } else {
+            coarse_y += 1;
+        }
+        self.v = (self.v & !0x03E0) | (coarse_y << 5);
+    }
+
+    fn palette_color(&self, index: usize) -> (u8, u8, u8) {
+        let mut color = self.palette_table[index] & 0x3F;
+        if self.mask & Ppu::MASK_GREYSCALE != 0 {
+            color &= 0x30;
+        }
+        SYSTEM_PALETTE[color as usize]
+    }
+
+    // render_background_line returns the (palette, color) of each background pixel on the current line.
+    // A color of 0 is transparent.
+    fn render_background_line(&self) -> [(u8, u8); Frame::WIDTH] {
+        let mut line = [(0, 0); Frame::WIDTH];
+        if self.mask & Ppu::MASK_BACKGROUND == 0 {
+            return line;
+        }
+        let pattern_base: u16 = if self.ctrl & Ppu::CTRL_BACKGROUND_PATTERN != 0 {
+            0x1000
+        } else {
+            0
+        };
+        let mut v = self.v;
+        let fine_y = (v >> 12) & 0b111;
+        // Render 33 tiles to account for fine X scroll.
+        for tile in 0..33 {
+            let tile_index = self.read_vram(0x2000 | (v & 0x0FFF)) as u16;
+            let attribute =
+                self.read_vram(0x23C0 | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07));
+            let shift = ((v >> 4) & 0b100) | (v & 0b10);
+            let palette = (attribute >> shift) & 0b11;
+            let pattern_addr = pattern_base + tile_index * 16 + fine_y;
+            let lo = self.chr[pattern_addr as usize];
+            let hi = self.chr[(pattern_addr + 8) as usize];
+            for px in 0..8 {
+                let x = (tile * 8 + px) as isize - self.fine_x as isize;
+                if !(0..Frame::WIDTH as isize).contains(&x) {
+                    continue;
+                }
+                let color = (((hi >> (7 - px)) & 1) << 1) | ((lo >> (7 - px)) & 1);
+                line[x as usize] = (palette, color);
+            }
+            // Increment coarse X, switching horizontal nametable on wrap.
+            if v & 0x001F == 31 {
+                v &= !0x001F;
+                v ^= 0x0400;
+            } else {
+                v += 1;
+            }
+        }
+        line
+    }
+
+    // render_sprite_line returns the (palette, color, behind background, is sprite zero) of each sprite pixel on `scanline`.
+    // A color of 0 is transparent.
+    fn render_sprite_line(&mut self, scanline: usize) -> [(u8, u8, bool, bool); Frame::WIDTH] {
+        let mut line = [(0, 0, false, false); Frame::WIDTH];
+        let height: usize = if self.ctrl & Ppu::CTRL_SPRITE_SIZE != 0 {
+            16
+        } else {
+            8
+        };
+
+        // Find the first 8 sprites on this scanline.
+        let mut found = Vec::with_capacity(8);
+        for i in 0..64 {
+            // Sprite data is delayed by one scanline.
+            let top = self.oam_data[i * 4] as usize + 1;
+            if scanline < top || scanline >= top + height {
+                continue;
+            }
+            if found.len() == 8 {
+                self.status |= Ppu::STATUS_SPRITE_OVERFLOW;
+                break;
+            }
+            found.push(i);
+        }
+
+        if self.mask & Ppu::MASK_SPRITES == 0 {
+            return line;
+        }
+
+        for &i in found.iter() {
+            let top = self.oam_data[i * 4] as usize + 1;
+            let tile = self.oam_data[i * 4 + 1] as u16;
+            let attributes = self.oam_data[i * 4 + 2];
+            let left = self.oam_data[i * 4 + 3] as usize;
+            let flip_vertical = attributes & 0b1000_0000 != 0;
+            let flip_horizontal = attributes & 0b0100_0000 != 0;
+            let behind_background = attributes & 0b0010_0000 != 0;
+            let palette = attributes & 0b11;
+
+            let mut row = (scanline - top) as u16;
+            if flip_vertical {
+                row = height as u16 - 1 - row;
+            }
+            let pattern_addr = if height == 16 {
+                let bank = (tile & 1) * 0x1000;
+                let mut tile = tile & 0xFE;
+                if row >= 8 {
+                    tile += 1;
+                    row -= 8;
+                }
+                bank + tile * 16 + row
+            } else {
+                let bank = if self.ctrl & Ppu::CTRL_SPRITE_PATTERN != 0 {
+                    0x1000
+                } else {
+                    0
+                };
+                bank + tile * 16 + row
+            };
+            let lo = self.chr[pattern_addr as usize];
+            let hi = self.chr[(pattern_addr + 8) as usize];
+
+            for px in 0..8 {
+                let x = left + px;
+                if x >= Frame::WIDTH {
+                    break;
+                }
+                // Lower OAM indexes have priority.
+                if line[x].1 != 0 {
+                    continue;
+                }
+                let bit = if flip_horizontal { px } else { 7 - px };
+                let color = (((hi >> bit) & 1) << 1) | ((lo >> bit) & 1);
+                line[x] = (palette, color, behind_background, i == 0);
+            }
+        }
+        line
+    }
+
+    fn render_scanline(&mut self, scanline: usize) {
+        if !self.rendering_enabled() {
+            let backdrop = self.palette_color(0);
+            for x in 0..Frame::WIDTH {
+                self.frame.set_pixel(x, scanline, backdrop);
+            }
+            return;
+        }
+
+        let background = self.render_background_line();
+        let sprites = self.render_sprite_line(scanline);
+        let show_background_left = self.mask & Ppu::MASK_BACKGROUND_LEFT != 0;
+        let show_sprites_left = self.mask & Ppu::MASK_SPRITES_LEFT != 0;
+
+        for x in 0..Frame::WIDTH {
+            let (bg_palette, mut bg_color) = background[x];
+            let (sprite_palette, mut sprite_color, behind_background, sprite_zero) = sprites[x];
+            if x < 8 && !show_background_left {
+                bg_color = 0;
+            }
+            if x < 8 && !show_sprites_left {
+                sprite_color = 0;
+            }
+
+            if sprite_zero && sprite_color != 0 && bg_color != 0 && x != 255 {
+                self.status |= Ppu::STATUS_SPRITE_ZERO_HIT;
+            }
+
+            let index = if sprite_color != 0 && (!behind_background || bg_color == 0) {
+                0x10 + (sprite_palette * 4 + sprite_color) as usize
+            } else if bg_color != 0 {
+                (bg_palette * 4 + bg_color) as usize
+            } else {
+                0
+            };
+            let rgb = self.palette_color(index);
+            self.frame.set_pixel(x, scanline, rgb);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,278 @@
+use super::*;
+
+fn write_ppu_addr(ppu: &mut Ppu, addr: u16) {
+    ppu.write_register(0x2006, (addr >> 8) as u8);
+    ppu.write_register(0x2006, addr as u8);
+}
+
+// tick_to advances `ppu` until it reaches the given scanline and cycle.
+fn tick_to(ppu: &mut Ppu, scanline: u16, cycle: u16) {
+    while ppu.scanline != scanline || ppu.cycle != cycle {
+        ppu.tick(1);
+    }
+}
+
+// make_ppu returns a PPU with CHR RAM. Tile 1 is solid color 1. Tile 2 is solid color 3.
+fn make_ppu() -> Ppu {
+    let mut ppu = Ppu::new(Vec::new(), Mirroring::Horizontal);
+    for row in 0..8 {
+        ppu.chr[16 + row] = 0xFF;
+        ppu.chr[32 + row] = 0xFF;
+        ppu.chr[32 + row + 8] = 0xFF;
+    }
+    ppu
+}
+
+#[test]
+fn test_ppudata_read_is_buffered() {
+    let mut ppu = Ppu::new(Vec::new(), Mirroring::Horizontal);
+    ppu.write_vram(0x2305, 0x66);
+    ppu.write_vram(0x2306, 0x77);
+
+    write_ppu_addr(&mut ppu, 0x2305);
+    ppu.read_register(0x2007); // Load into buffer.
+    assert_eq!(ppu.read_register(0x2007), 0x66);
+    assert_eq!(ppu.read_register(0x2007), 0x77);
+}
+
+#[test]
+fn test_ppudata_write_increments() {
+    let mut ppu = Ppu::new(Vec::new(), Mirroring::Horizontal);
+    write_ppu_addr(&mut ppu, 0x2000);
+    ppu.write_register(0x2007, 1);
+    ppu.write_register(0x2007, 2);
+    assert_eq!(ppu.read_vram(0x2000), 1);
+    assert_eq!(ppu.read_vram(0x2001), 2);
+
+    // Increment by 32.
+    ppu.write_register(0x2000, Ppu::CTRL_VRAM_INCREMENT);
+    write_ppu_addr(&mut ppu, 0x2000);
+    ppu.write_register(0x2007, 3);
+    ppu.write_register(0x2007, 4);
+    assert_eq!(ppu.read_vram(0x2000), 3);
+    assert_eq!(ppu.read_vram(0x2020), 4);
+}
+
+#[test]
+fn test_nametable_mirroring() {
+    let mut ppu = Ppu::new(Vec::new(), Mirroring::Horizontal);
+    ppu.write_vram(0x2005, 1);
+    ppu.write_vram(0x2805, 2);
+    assert_eq!(ppu.read_vram(0x2405), 1);
+    assert_eq!(ppu.read_vram(0x2C05), 2);
+    // 0x3000..0x3F00 mirrors 0x2000..0x2F00.
+    assert_eq!(ppu.read_vram(0x3005), 1);
+
+    let mut ppu = Ppu::new(Vec::new(), Mirroring::Vertical);
+    ppu.write_vram(0x2005, 1);
+    ppu.write_vram(0x2405, 2);
+    assert_eq!(ppu.read_vram(0x2805), 1);
+    assert_eq!(ppu.read_vram(0x2C05), 2);
+}
+
+#[test]
+fn test_palette_mirroring() {
+    let mut ppu = Ppu::new(Vec::new(), Mirroring::Horizontal);
+    ppu.write_vram(0x3F10, 0x21);
+    assert_eq!(ppu.read_vram(0x3F00), 0x21);
+    ppu.write_vram(0x3F25, 0x22);
+    assert_eq!(ppu.read_vram(0x3F05), 0x22);
+
+    // Palette reads are not buffered.
+    write_ppu_addr(&mut ppu, 0x3F05);
+    assert_eq!(ppu.read_register(0x2007), 0x22);
+}
+
+#[test]
+fn test_chr_rom_is_read_only() {
+    let mut ppu = Ppu::new(vec![7; 0x2000], Mirroring::Horizontal);
+    ppu.write_vram(0x0000, 1);
+    assert_eq!(ppu.read_vram(0x0000), 7);
+}
+
+#[test]
+fn test_vblank_and_nmi() {
+    let mut ppu = Ppu::new(Vec::new(), Mirroring::Horizontal);
+    ppu.write_register(0x2000, Ppu::CTRL_GENERATE_NMI);
+    tick_to(&mut ppu, 241, 0);
+    assert!(!ppu.poll_nmi_interrupt());
+    ppu.tick(1);
+    assert!(ppu.poll_nmi_interrupt());
+    assert!(
+        !ppu.poll_nmi_interrupt(),
+        "expected NMI to be reported once"
+    );
+    assert!(ppu.poll_frame_complete());
+
+    // Reading PPUSTATUS clears vblank.
+    assert_eq!(
+        ppu.read_register(0x2002) & Ppu::STATUS_VBLANK,
+        Ppu::STATUS_VBLANK
+    );
+    assert_eq!(ppu.read_register(0x2002) & Ppu::STATUS_VBLANK, 0);
+}
+
+#[test]
+fn test_enabling_nmi_during_vblank() {
+    let mut ppu = Ppu::new(Vec::new(), Mirroring::Horizontal);
+    tick_to(&mut ppu, 241, 10);
+    assert!(!ppu.poll_nmi_interrupt());
+    ppu.write_register(0x2000, Ppu::CTRL_GENERATE_NMI);
+    assert!(ppu.poll_nmi_interrupt());
+}
+
+#[test]
+fn test_vblank_cleared_on_prerender() {
+    let mut ppu = Ppu::new(Vec::new(), Mirroring::Horizontal);
+    tick_to(&mut ppu, 241, 1);
+    tick_to(&mut ppu, 261, 1);
+    assert_eq!(ppu.read_register(0x2002) & Ppu::STATUS_VBLANK, 0);
+    tick_to(&mut ppu, 0, 0);
+    assert_eq!(ppu.frame_count, 1);
+}
+
+#[test]
+fn test_oam() {
+    let mut ppu = Ppu::new(Vec::new(), Mirroring::Horizontal);
+    ppu.write_register(0x2003, 0x10);
+    ppu.write_register(0x2004, 0x66);
+    ppu.write_register(0x2004, 0x77);
+    ppu.write_register(0x2003, 0x11);
+    assert_eq!(ppu.read_register(0x2004), 0x77);
+
+    let mut page = [0u8; 256];
+    for (i, b) in page.iter_mut().enumerate() {
+        *b = i as u8;
+    }
+    ppu.write_register(0x2003, 0x00);
+    ppu.write_oam_dma(&page);
+    ppu.write_register(0x2003, 0x80);
+    assert_eq!(ppu.read_register(0x2004), 0x80);
+}
+
+#[test]
+fn test_scroll_registers() {
+    let mut ppu = Ppu::new(Vec::new(), Mirroring::Horizontal);
+    ppu.write_register(0x2000, 0b10);
+    ppu.write_register(0x2005, 0b0111_1101); // Coarse X = 15, fine X = 5.
+    ppu.write_register(0x2005, 0b0101_1110); // Coarse Y = 11, fine Y = 6.
+    assert_eq!(ppu.fine_x, 5);
+    assert_eq!(ppu.t, 0b110_1001_0110_1111);
+
+    // Reading PPUSTATUS resets the write toggle.
+    ppu.write_register(0x2005, 0);
+    ppu.read_register(0x2002);
+    ppu.write_register(0x2006, 0x21);
+    ppu.write_register(0x2006, 0x08);
+    assert_eq!(ppu.v, 0x2108);
+}
+
+#[test]
+fn test_render_backdrop_when_disabled() {
+    let mut ppu = make_ppu();
+    ppu.write_vram(0x3F00, 0x30);
+    tick_to(&mut ppu, 241, 0);
+    assert_eq!(ppu.frame.get_pixel(0, 0), SYSTEM_PALETTE[0x30]);
+    assert_eq!(ppu.frame.get_pixel(255, 239), SYSTEM_PALETTE[0x30]);
+}
+
+#[test]
+fn test_render_background() {
+    let mut ppu = make_ppu();
+    ppu.write_vram(0x3F00, 0x0F);
+    ppu.write_vram(0x3F01, 0x16);
+    ppu.write_vram(0x3F09, 0x2A);
+    // Tile (1, 0) uses tile 1 with palette 0.
+    ppu.write_vram(0x2001, 1);
+    // Tile (2, 2) uses tile 1. The attribute byte selects palette 2 for the bottom-right quadrant.
+    ppu.write_vram(0x2042, 1);
+    ppu.write_vram(0x23C0, 0b1000_0000);
+    ppu.write_register(0x2001, Ppu::MASK_BACKGROUND | Ppu::MASK_BACKGROUND_LEFT);
+    tick_to(&mut ppu, 241, 0);
+
+    assert_eq!(ppu.frame.get_pixel(0, 0), SYSTEM_PALETTE[0x0F]);
+    assert_eq!(ppu.frame.get_pixel(8, 0), SYSTEM_PALETTE[0x16]);
+    assert_eq!(ppu.frame.get_pixel(15, 7), SYSTEM_PALETTE[0x16]);
+    assert_eq!(ppu.frame.get_pixel(16, 16), SYSTEM_PALETTE[0x2A]);
+}
+
+#[test]
+fn test_render_background_scrolled() {
+    let mut ppu = make_ppu();
+    ppu.write_vram(0x3F00, 0x0F);
+    ppu.write_vram(0x3F01, 0x16);
+    ppu.write_vram(0x2001, 1);
+    // Scroll right by 3 pixels and down by 8 pixels.
+    ppu.write_register(0x2005, 3);
+    ppu.write_register(0x2005, 8);
+    ppu.write_vram(0x2021, 1);
+    ppu.write_register(0x2001, Ppu::MASK_BACKGROUND | Ppu::MASK_BACKGROUND_LEFT);
+    // Scroll is applied on the pre-render scanline.
+    ppu.tick(1);
+    tick_to(&mut ppu, 0, 0);
+    tick_to(&mut ppu, 241, 0);
+
+    assert_eq!(ppu.frame.get_pixel(4, 0), SYSTEM_PALETTE[0x0F]);
+    assert_eq!(ppu.frame.get_pixel(5, 0), SYSTEM_PALETTE[0x16]);
+    assert_eq!(ppu.frame.get_pixel(12, 7), SYSTEM_PALETTE[0x16]);
+    assert_eq!(ppu.frame.get_pixel(13, 0), SYSTEM_PALETTE[0x0F]);
+}
+
+#[test]
+fn test_render_sprite() {
+    let mut ppu = make_ppu();
+    ppu.write_vram(0x3F00, 0x0F);
+    ppu.write_vram(0x3F13, 0x12);
+    let mut page = [0xFFu8; 256]; // Y=0xFF hides sprites.
+    page[0..4].copy_from_slice(&[9, 2, 0b0000_0000, 20]);
+    ppu.write_oam_dma(&page);
+    ppu.write_register(0x2001, Ppu::MASK_SPRITES | Ppu::MASK_SPRITES_LEFT);
+    tick_to(&mut ppu, 241, 0);
+
+    // Sprites are drawn one scanline below their Y coordinate.
+    assert_eq!(ppu.frame.get_pixel(20, 9), SYSTEM_PALETTE[0x0F]);
+    assert_eq!(ppu.frame.get_pixel(20, 10), SYSTEM_PALETTE[0x12]);
+    assert_eq!(ppu.frame.get_pixel(27, 17), SYSTEM_PALETTE[0x12]);
+    assert_eq!(ppu.frame.get_pixel(28, 17), SYSTEM_PALETTE[0x0F]);
+    // Sprite zero hit requires background rendering.
+    assert_eq!(ppu.read_register(0x2002) & Ppu::STATUS_SPRITE_ZERO_HIT, 0);
+}
+
+#[test]
+fn test_sprite_zero_hit() {
+    let mut ppu = make_ppu();
+    ppu.write_vram(0x2042, 1); // Background tile at (16, 16).
+    let mut page = [0xFFu8; 256];
+    page[0..4].copy_from_slice(&[19, 2, 0, 20]);
+    ppu.write_oam_dma(&page);
+    ppu.write_register(0x2001, Ppu::MASK_SPRITES | Ppu::MASK_BACKGROUND);
+
+    tick_to(&mut ppu, 19, 0);
+    assert_eq!(ppu.read_register(0x2002) & Ppu::STATUS_SPRITE_ZERO_HIT, 0);
+    tick_to(&mut ppu, 21, 0);
+    assert_ne!(ppu.read_register(0x2002) & Ppu::STATUS_SPRITE_ZERO_HIT, 0);
+    // Cleared on the pre-render scanline.
+    tick_to(&mut ppu, 261, 2);
+    assert_eq!(ppu.read_register(0x2002) & Ppu::STATUS_SPRITE_ZERO_HIT, 0);
+}
+
+#[test]
+fn test_sprite_behind_background() {
+    let mut ppu = make_ppu();
+    ppu.write_vram(0x3F01, 0x16);
+    ppu.write_vram(0x3F13, 0x12);
+    ppu.write_vram(0x2042, 1);
+    let mut page = [0xFFu8; 256];
+    page[4..8].copy_from_slice(&[15, 2, 0b0010_0000, 20]);
+    ppu.write_oam_dma(&page);
+    ppu.write_register(
+        0x2001,
+        Ppu::MASK_SPRITES | Ppu::MASK_BACKGROUND | Ppu::MASK_SPRITES_LEFT,
+    );
+    tick_to(&mut ppu, 241, 0);
+
+    // Background pixel wins where opaque.
+    assert_eq!(ppu.frame.get_pixel(20, 16), SYSTEM_PALETTE[0x16]);
+    // Sprite shows through transparent background.
+    assert_eq!(ppu.frame.get_pixel(24, 16), SYSTEM_PALETTE[0x12]);
+}
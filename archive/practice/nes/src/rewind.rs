@@ -0,0 +1,63 @@
+// rewind keeps a ring buffer of recent save states so a frontend can step back in time.
+use crate::machine::Machine;
+use std::collections::VecDeque;
+
+// By default a snapshot is taken every INTERVAL frames and SECONDS seconds are kept, at 60 frames per second.
+pub const INTERVAL: u64 = 6;
+pub const SECONDS: u64 = 10;
+const FRAMES_PER_SECOND: u64 = 60;
+
+pub struct Rewind {
+    interval: u64,
+    capacity: usize,
+    // states holds the frame and save state of each snapshot, oldest first.
+    states: VecDeque<(u64, Vec<u8>)>,
+}
+
+impl Rewind {
+    // new keeps `seconds` of history, with a snapshot every `interval` frames.
+    pub fn new(interval: u64, seconds: u64) -> Self {
+        let capacity = (seconds * FRAMES_PER_SECOND / interval).max(1) as usize;
+        Rewind {
+            interval,
+            capacity,
+            states: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    // record snapshots `machine` after `frame` has run, if `frame` is a multiple of the interval. Frame 0 is the
+    // state before the first frame. The oldest snapshot is dropped when the buffer is full.
+    pub fn record(&mut self, frame: u64, machine: &dyn Machine) {
+        if !frame.is_multiple_of(self.interval) {
+            return;
+        }
+        if self.states.len() == self.capacity {
+            self.states.pop_front();
+        }
+        self.states.push_back((frame, machine.save_state()));
+    }
+
+    // rewind restores the newest snapshot before `frame` and returns its frame, or returns None if there is none.
+    // Snapshots after it are dropped, so calling it again steps further back.
+    pub fn rewind(&mut self, frame: u64, machine: &mut dyn Machine) -> Result<Option<u64>, String> {
+        let Some(i) = self.states.iter().rposition(|(f, _)| *f < frame) else {
+            return Ok(None);
+        };
+        self.states.truncate(i + 1);
+        let (f, state) = &self.states[i];
+        machine.load_state(state)?;
+        Ok(Some(*f))
+    }
+
+    // len is the number of snapshots.
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,95 @@
+use super::*;
+use crate::cartridge::test::make_counter_rom;
+use crate::cartridge::Rom;
+use crate::cpu::CPU;
+use crate::headless::hash_frame;
+use crate::machine::Nes;
+use crate::snake::Snake;
+
+fn make_nes() -> Nes {
+    let mut cpu = CPU::new();
+    cpu.load_rom(Rom::new(&make_counter_rom()).unwrap());
+    cpu.reset();
+    Nes::new(cpu)
+}
+
+// buttons is the input of each frame in the tests.
+fn buttons(frame: u64) -> [u8; 2] {
+    let direction = [0x10, 0x80, 0x20, 0x40][(frame / 7 % 4) as usize];
+    [direction, 0]
+}
+
+// check_replay runs `machine` for 30 frames, rewinds, and checks that running the same input again gives the
+// same frames.
+fn check_replay(machine: &mut dyn Machine) {
+    let mut rewind = Rewind::new(INTERVAL, SECONDS);
+    rewind.record(0, machine);
+    let mut hashes = vec![hash_frame(machine.frame())];
+    for frame in 1..=30 {
+        machine.set_buttons(buttons(frame));
+        assert!(machine.run_frame());
+        rewind.record(frame, machine);
+        hashes.push(hash_frame(machine.frame()));
+    }
+    assert_eq!(rewind.len(), 6);
+
+    assert_eq!(rewind.rewind(30, machine), Ok(Some(24)));
+    assert_eq!(rewind.rewind(24, machine), Ok(Some(18)));
+    assert_eq!(hash_frame(machine.frame()), hashes[18]);
+    for frame in 19..=30 {
+        machine.set_buttons(buttons(frame));
+        assert!(machine.run_frame());
+        assert_eq!(
+            hash_frame(machine.frame()),
+            hashes[frame as usize],
+            "frame {}",
+            frame
+        );
+    }
+}
+
+#[test]
+fn test_replay_nes() {
+    check_replay(&mut make_nes());
+}
+
+#[test]
+fn test_replay_snake() {
+    // The random numbers drawn after the snapshot are drawn again.
+    check_replay(&mut Snake::new(3));
+}
+
+#[test]
+fn test_capacity() {
+    let mut snake = Snake::new(0);
+    // One second, at one snapshot per frame.
+    let mut rewind = Rewind::new(1, 1);
+    for frame in 0..100 {
+        if frame > 0 {
+            snake.run_frame();
+        }
+        rewind.record(frame, &snake);
+    }
+    assert_eq!(rewind.len(), 60);
+    // The oldest snapshots were dropped.
+    let mut frame = 100;
+    while let Some(f) = rewind.rewind(frame, &mut snake).unwrap() {
+        assert_eq!(f, frame - 1);
+        frame = f;
+    }
+    assert_eq!(frame, 40);
+    assert_eq!(rewind.len(), 1);
+}
+
+#[test]
+fn test_load_errors() {
+    let mut snake = Snake::new(0);
+    let state = snake.save_state();
+    assert_eq!(
+        snake.load_state(&state[..4]),
+        Err("save state is truncated at offset 0".to_string())
+    );
+    // A snake state is not a NES state.
+    assert!(make_nes().load_state(&state).is_err());
+    assert_eq!(snake.load_state(&state), Ok(()));
+}
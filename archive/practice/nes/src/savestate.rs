@@ -0,0 +1,126 @@
+// savestate defines the binary format of save states. See `CPU::save_state` and `CPU::load_state`.
+//
+// A save state is MAGIC, VERSION (u32) and then the state of each component in a fixed order. Integers are little-endian.
+// Bump VERSION when the layout changes. States with another version are rejected rather than migrated.
+
+pub const MAGIC: [u8; 4] = *b"NESS";
+pub const VERSION: u32 = 3;
+
+// StateWriter appends values to a save state.
+pub struct StateWriter {
+    pub data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        let mut w = StateWriter { data: Vec::new() };
+        w.bytes(&MAGIC);
+        w.u32(VERSION);
+        w
+    }
+
+    pub fn u8(&mut self, val: u8) {
+        self.data.push(val);
+    }
+
+    pub fn bool(&mut self, val: bool) {
+        self.u8(val as u8);
+    }
+
+    pub fn u16(&mut self, val: u16) {
+        self.data.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, val: u32) {
+        self.data.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, val: u64) {
+        self.data.extend_from_slice(&val.to_le_bytes());
+    }
+
+    // bytes appends `val` without its length. The reader must know the length.
+    pub fn bytes(&mut self, val: &[u8]) {
+        self.data.extend_from_slice(val);
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// StateReader reads values written by StateWriter.
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    // new checks the header of `data`.
+    pub fn new(data: &'a [u8]) -> Result<Self, String> {
+        let mut r = StateReader { data, pos: 0 };
+        if r.bytes(MAGIC.len())? != MAGIC {
+            return Err("not a save state".to_string());
+        }
+        let version = r.u32()?;
+        if version != VERSION {
+            return Err(format!(
+                "save state version {} is not supported, want {}",
+                version, VERSION
+            ));
+        }
+        Ok(r)
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.data.len() - self.pos < len {
+            return Err(format!("save state is truncated at offset {}", self.pos));
+        }
+        let val = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(val)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, String> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    // finish returns an error if there is unread data.
+    pub fn finish(&self) -> Result<(), String> {
+        if self.pos != self.data.len() {
+            return Err(format!(
+                "save state has {} unexpected trailing bytes",
+                self.data.len() - self.pos
+            ));
+        }
+        Ok(())
+    }
+}
+
+// checksum is the 32-bit FNV-1a hash of `data`. It identifies the cartridge a save state belongs to.
+pub fn checksum(data: &[u8]) -> u32 {
+    data.iter().fold(0x811c_9dc5, |hash, &b| {
+        (hash ^ b as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,110 @@
+use super::*;
+use crate::cartridge::test::{make_counter_rom, make_ines};
+use crate::cartridge::Rom;
+use crate::cpu::CPU;
+use crate::ppu::frame::Frame;
+
+fn make_cpu(raw: &[u8]) -> CPU {
+    let mut cpu = CPU::new();
+    cpu.load_rom(Rom::new(raw).unwrap());
+    cpu.reset();
+    cpu
+}
+
+// run_frames runs until `count` frames complete and returns them.
+fn run_frames(cpu: &mut CPU, count: usize) -> Vec<Frame> {
+    let mut frames = Vec::new();
+    while frames.len() < count {
+        assert!(cpu.step());
+        if cpu.bus.ppu.poll_frame_complete() {
+            frames.push(cpu.bus.ppu.frame.clone());
+        }
+    }
+    frames
+}
+
+#[test]
+fn test_round_trip_is_deterministic() {
+    let raw = make_counter_rom();
+    let mut cpu = make_cpu(&raw);
+    run_frames(&mut cpu, 3);
+    // Save mid-frame.
+    for _ in 0..1000 {
+        cpu.step();
+    }
+    let state = cpu.save_state();
+    let want = run_frames(&mut cpu, 4);
+    assert!(
+        want.windows(2).all(|w| w[0] != w[1]),
+        "frames should differ"
+    );
+
+    cpu.load_state(&state).unwrap();
+    assert_eq!(cpu.save_state(), state);
+    assert!(run_frames(&mut cpu, 4) == want);
+
+    // A fresh CPU with the same cartridge resumes the same way.
+    let mut cpu = make_cpu(&raw);
+    cpu.load_state(&state).unwrap();
+    assert!(run_frames(&mut cpu, 4) == want);
+}
+
+#[test]
+fn test_raw_program_round_trip() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![0xA9, 0x05, 0xAA, 0xE8, 0x00]);
+    cpu.reset();
+    cpu.step();
+    let state = cpu.save_state();
+    cpu.run().unwrap();
+    assert_eq!(cpu.register_x, 6);
+
+    cpu.load_state(&state).unwrap();
+    assert_eq!(cpu.register_a, 5);
+    assert_eq!(cpu.register_x, 0);
+    assert_eq!(cpu.program_counter, 0x8002);
+    cpu.run().unwrap();
+    assert_eq!(cpu.register_x, 6);
+}
+
+#[test]
+fn test_load_errors() {
+    let mut cpu = make_cpu(&make_counter_rom());
+    cpu.step();
+    let state = cpu.save_state();
+    let pc = cpu.program_counter;
+
+    let mut bad_magic = state.clone();
+    bad_magic[0] = b'X';
+    let mut bad_version = state.clone();
+    bad_version[4..8].copy_from_slice(&(VERSION + 1).to_le_bytes());
+    let other_cartridge = make_cpu(&make_ines(&[0xEA], &[], 0)).save_state();
+    let truncated = format!(
+        "save state is {} bytes, want {}",
+        state.len() - 1,
+        state.len()
+    );
+    let unsupported = format!(
+        "save state version {} is not supported, want {}",
+        VERSION + 1,
+        VERSION
+    );
+    let cases: Vec<(&[u8], &str)> = vec![
+        (&bad_magic, "not a save state"),
+        (&bad_version, &unsupported),
+        (&state[..state.len() - 1], &truncated),
+        (&[], "save state is truncated at offset 0"),
+        (&other_cartridge, "save state is for a different cartridge"),
+    ];
+    for (data, want) in cases {
+        assert_eq!(cpu.load_state(data), Err(want.to_string()));
+        // The CPU is unchanged.
+        assert_eq!(cpu.program_counter, pc);
+    }
+}
+
+#[test]
+fn test_checksum() {
+    assert_eq!(checksum(b""), 0x811c9dc5);
+    assert_eq!(checksum(b"a"), 0xe40c292c);
+}
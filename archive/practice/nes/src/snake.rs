@@ -0,0 +1,161 @@
+// snake runs the snake game described in https://bugzmanov.github.io/nes_ebook/chapter_3_4.html.
+//
+// The game reads a random number from 0xFE and the last key pressed from 0xFF, and draws a 32x32 screen
+// of color indices stored at 0x0200..0x0600.
+use crate::cpu::CPU;
+use crate::joypad::Button;
+use crate::machine::Machine;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+pub const WIDTH: usize = 32;
+pub const HEIGHT: usize = 32;
+// INSTRUCTIONS_PER_FRAME sets the speed of the game. At 60 frames per second, one game tick takes about 4 frames.
+const INSTRUCTIONS_PER_FRAME: usize = 240;
+const SCREEN: u16 = 0x0200;
+const RANDOM: u16 = 0xFE;
+const LAST_KEY: u16 = 0xFF;
+
+// SNAKE_GAME_CODE is loaded at 0x0600.
+pub static SNAKE_GAME_CODE: &[u8] = &[
+    0x20, 0x06, 0x06, 0x20, 0x38, 0x06, 0x20, 0x0d, 0x06, 0x20, 0x2a, 0x06, 0x60, 0xa9, 0x02, 0x85,
+    0x02, 0xa9, 0x04, 0x85, 0x03, 0xa9, 0x11, 0x85, 0x10, 0xa9, 0x10, 0x85, 0x12, 0xa9, 0x0f, 0x85,
+    0x14, 0xa9, 0x04, 0x85, 0x11, 0x85, 0x13, 0x85, 0x15, 0x60, 0xa5, 0xfe, 0x85, 0x00, 0xa5, 0xfe,
+    0x29, 0x03, 0x18, 0x69, 0x02, 0x85, 0x01, 0x60, 0x20, 0x4d, 0x06, 0x20, 0x8d, 0x06, 0x20, 0xc3,
+    0x06, 0x20, 0x19, 0x07, 0x20, 0x20, 0x07, 0x20, 0x2d, 0x07, 0x4c, 0x38, 0x06, 0xa5, 0xff, 0xc9,
+    0x77, 0xf0, 0x0d, 0xc9, 0x64, 0xf0, 0x14, 0xc9, 0x73, 0xf0, 0x1b, 0xc9, 0x61, 0xf0, 0x22, 0x60,
+    0xa9, 0x04, 0x24, 0x02, 0xd0, 0x26, 0xa9, 0x01, 0x85, 0x02, 0x60, 0xa9, 0x08, 0x24, 0x02, 0xd0,
+    0x1b, 0xa9, 0x02, 0x85, 0x02, 0x60, 0xa9, 0x01, 0x24, 0x02, 0xd0, 0x10, 0xa9, 0x04, 0x85, 0x02,
+    0x60, 0xa9, 0x02, 0x24, 0x02, 0xd0, 0x05, 0xa9, 0x08, 0x85, 0x02, 0x60, 0x60, 0x20, 0x94, 0x06,
+    0x20, 0xa8, 0x06, 0x60, 0xa5, 0x00, 0xc5, 0x10, 0xd0, 0x0d, 0xa5, 0x01, 0xc5, 0x11, 0xd0, 0x07,
+    0xe6, 0x03, 0xe6, 0x03, 0x20, 0x2a, 0x06, 0x60, 0xa2, 0x02, 0xb5, 0x10, 0xc5, 0x10, 0xd0, 0x06,
+    0xb5, 0x11, 0xc5, 0x11, 0xf0, 0x09, 0xe8, 0xe8, 0xe4, 0x03, 0xf0, 0x06, 0x4c, 0xaa, 0x06, 0x4c,
+    0x35, 0x07, 0x60, 0xa6, 0x03, 0xca, 0x8a, 0xb5, 0x10, 0x95, 0x12, 0xca, 0x10, 0xf9, 0xa5, 0x02,
+    0x4a, 0xb0, 0x09, 0x4a, 0xb0, 0x19, 0x4a, 0xb0, 0x1f, 0x4a, 0xb0, 0x2f, 0xa5, 0x10, 0x38, 0xe9,
+    0x20, 0x85, 0x10, 0x90, 0x01, 0x60, 0xc6, 0x11, 0xa9, 0x01, 0xc5, 0x11, 0xf0, 0x28, 0x60, 0xe6,
+    0x10, 0xa9, 0x1f, 0x24, 0x10, 0xf0, 0x1f, 0x60, 0xa5, 0x10, 0x18, 0x69, 0x20, 0x85, 0x10, 0xb0,
+    0x01, 0x60, 0xe6, 0x11, 0xa9, 0x06, 0xc5, 0x11, 0xf0, 0x0c, 0x60, 0xc6, 0x10, 0xa5, 0x10, 0x29,
+    0x1f, 0xc9, 0x1f, 0xf0, 0x01, 0x60, 0x4c, 0x35, 0x07, 0xa0, 0x00, 0xa5, 0xfe, 0x91, 0x00, 0x60,
+    0xa6, 0x03, 0xa9, 0x00, 0x81, 0x10, 0xa2, 0x00, 0xa9, 0x01, 0x81, 0x10, 0x60, 0xa2, 0x00, 0xea,
+    0xea, 0xca, 0xd0, 0xfb, 0x60,
+];
+
+fn color(byte: u8) -> (u8, u8, u8) {
+    match byte {
+        0 => (0, 0, 0),           // Black.
+        1 => (255, 255, 255),     // White.
+        2 | 9 => (128, 128, 128), // Grey.
+        3 | 10 => (255, 0, 0),    // Red.
+        4 | 11 => (0, 255, 0),    // Green.
+        5 | 12 => (0, 0, 255),    // Blue.
+        6 | 13 => (255, 0, 255),  // Magenta.
+        7 | 14 => (255, 255, 0),  // Yellow.
+        _ => (0, 255, 255),       // Cyan.
+    }
+}
+
+pub struct Snake {
+    pub cpu: CPU,
+    screen: [u8; WIDTH * HEIGHT * 3],
+    seed: u64,
+    // frames counts the frames run. Each frame draws its random numbers from an RNG seeded with `seed` and the frame
+    // number, so a save state only needs the frame number to restore them.
+    frames: u64,
+}
+
+impl Snake {
+    // new loads the game. `seed` seeds the random numbers so runs are reproducible.
+    pub fn new(seed: u64) -> Self {
+        let mut cpu = CPU::new();
+        cpu.snake_mode = true;
+        cpu.load(SNAKE_GAME_CODE.to_vec());
+        cpu.reset();
+        Snake {
+            cpu,
+            screen: [0; WIDTH * HEIGHT * 3],
+            seed,
+            frames: 0,
+        }
+    }
+
+    // frame_rng returns the RNG of the current frame.
+    fn frame_rng(&self) -> StdRng {
+        let mut key = [0; 32];
+        key[..8].copy_from_slice(&self.seed.to_le_bytes());
+        key[8..16].copy_from_slice(&self.frames.to_le_bytes());
+        StdRng::from_seed(key)
+    }
+
+    fn draw_screen(&mut self) {
+        for (i, pixel) in self.screen.chunks_mut(3).enumerate() {
+            let (r, g, b) = color(self.cpu.bus.peek(SCREEN + i as u16));
+            pixel.copy_from_slice(&[r, g, b]);
+        }
+    }
+}
+
+impl Machine for Snake {
+    fn width(&self) -> usize {
+        WIDTH
+    }
+
+    fn height(&self) -> usize {
+        HEIGHT
+    }
+
+    fn run_frame(&mut self) -> bool {
+        let mut rng = self.frame_rng();
+        self.frames += 1;
+        for _ in 0..INSTRUCTIONS_PER_FRAME {
+            self.cpu.mem_write(RANDOM, rng.gen_range(1, 16));
+            if !self.cpu.step() {
+                return false;
+            }
+        }
+        self.draw_screen();
+        true
+    }
+
+    fn frame(&self) -> &[u8] {
+        &self.screen
+    }
+
+    fn set_buttons(&mut self, buttons: [u8; 2]) {
+        // The game only sees the last direction pressed, as a WASD key.
+        let keys = [
+            (Button::Up, b'w'),
+            (Button::Down, b's'),
+            (Button::Left, b'a'),
+            (Button::Right, b'd'),
+        ];
+        for (button, ascii) in keys {
+            if buttons[0] & button as u8 != 0 {
+                self.cpu.mem_write(LAST_KEY, ascii);
+                return;
+            }
+        }
+    }
+
+    fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    // The state is the CPU state followed by the number of frames run, as a u64.
+    fn save_state(&self) -> Vec<u8> {
+        let mut data = self.cpu.save_state();
+        data.extend(self.frames.to_le_bytes());
+        data
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<(), String> {
+        let (cpu, frames) = data.split_at(data.len().saturating_sub(8));
+        self.cpu.load_state(cpu)?;
+        self.frames = u64::from_le_bytes(frames.try_into().unwrap());
+        self.draw_screen();
+        Ok(())
+    }
+
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+}
@@ -0,0 +1,107 @@
+// trace formats CPU state in the format of nestest.log so traces can be diffed against reference logs.
+// See https://www.nesdev.org/wiki/Emulator_tests
+use crate::cpu::{AddressingMode, CPU};
+use crate::disasm;
+use crate::opcodes::{self, Mnemonic, OpCode};
+
+// trace returns the state of `cpu` before executing the instruction at the program counter. Example:
+// C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+pub fn trace(cpu: &CPU) -> String {
+    let pc = cpu.program_counter;
+    let code = cpu.bus.peek(pc);
+
+    let (bytes, asm) = match opcodes::lookup_variant(cpu.variant, code) {
+        Some(opcode) => {
+            let bytes: Vec<u8> = (0..opcode.bytes)
+                .map(|i| cpu.bus.peek(pc.wrapping_add(i)))
+                .collect();
+            let operand = format_operand(cpu, opcode, &bytes);
+            (bytes, format!("{:>4} {}", opcode.display_name(), operand))
+        }
+        None => (vec![code], format!("{:>4} ${:02X}", ".byte", code)),
+    };
+
+    let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+    format!(
+        "{:04X}  {:8} {:<33}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+        pc,
+        hex.join(" "),
+        asm,
+        cpu.register_a,
+        cpu.register_x,
+        cpu.register_y,
+        cpu.status.get_all() | CPU::ONE_FLAG,
+        cpu.stack_pointer,
+        cpu.bus.ppu.scanline,
+        cpu.bus.ppu.cycle,
+        cpu.bus.cycles,
+    )
+}
+
+// format_operand formats the operand of an instruction and the memory it resolves to.
+fn format_operand(cpu: &CPU, opcode: &OpCode, bytes: &[u8]) -> String {
+    let peek = |addr: u16| cpu.bus.peek(addr);
+    let peek_u16 = |lo: u16, hi: u16| (peek(hi) as u16) << 8 | peek(lo) as u16;
+
+    let operand = disasm::format_operand(opcode, bytes, cpu.program_counter);
+    let resolved = match opcode.mode {
+        AddressingMode::ZeroPage => format!(" = {:02X}", peek(bytes[1] as u16)),
+        AddressingMode::ZeroPage_X | AddressingMode::ZeroPage_Y => {
+            let reg = match opcode.mode {
+                AddressingMode::ZeroPage_X => cpu.register_x,
+                _ => cpu.register_y,
+            };
+            let addr = bytes[1].wrapping_add(reg) as u16;
+            format!(" @ {:02X} = {:02X}", addr, peek(addr))
+        }
+        AddressingMode::Absolute if matches!(opcode.mnemonic, Mnemonic::JMP | Mnemonic::JSR) => {
+            String::new()
+        }
+        AddressingMode::Absolute => {
+            let addr = u16::from_le_bytes([bytes[1], bytes[2]]);
+            format!(" = {:02X}", peek(addr))
+        }
+        AddressingMode::Absolute_X | AddressingMode::Absolute_Y => {
+            let reg = match opcode.mode {
+                AddressingMode::Absolute_X => cpu.register_x,
+                _ => cpu.register_y,
+            };
+            let addr = u16::from_le_bytes([bytes[1], bytes[2]]).wrapping_add(reg as u16);
+            format!(" @ {:04X} = {:02X}", addr, peek(addr))
+        }
+        AddressingMode::Indirect => {
+            let ptr = u16::from_le_bytes([bytes[1], bytes[2]]);
+            format!(" = {:04X}", peek_u16(ptr, cpu.variant.indirect_high(ptr)))
+        }
+        AddressingMode::Indirect_X => {
+            let ptr = bytes[1].wrapping_add(cpu.register_x);
+            let addr = peek_u16(ptr as u16, ptr.wrapping_add(1) as u16);
+            format!(" @ {:02X} = {:04X} = {:02X}", ptr, addr, peek(addr))
+        }
+        AddressingMode::Indirect_Y => {
+            let base = peek_u16(bytes[1] as u16, bytes[1].wrapping_add(1) as u16);
+            let addr = base.wrapping_add(cpu.register_y as u16);
+            format!(" = {:04X} @ {:04X} = {:02X}", base, addr, peek(addr))
+        }
+        AddressingMode::ZeroPage_Indirect => {
+            let addr = peek_u16(bytes[1] as u16, bytes[1].wrapping_add(1) as u16);
+            format!(" = {:04X} = {:02X}", addr, peek(addr))
+        }
+        AddressingMode::Absolute_Indirect_X => {
+            let ptr = u16::from_le_bytes([bytes[1], bytes[2]]).wrapping_add(cpu.register_x as u16);
+            format!(
+                " @ {:04X} = {:04X}",
+                ptr,
+                peek_u16(ptr, ptr.wrapping_add(1))
+            )
+        }
+        AddressingMode::Immediate
+        | AddressingMode::Relative
+        | AddressingMode::Accumulator
+        | AddressingMode::NoneAddressing => String::new(),
+    };
+    operand + &resolved
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,216 @@
+use super::*;
+use crate::cartridge::test::make_ines;
+use crate::cartridge::Rom;
+use std::path::PathBuf;
+
+// Expected lines follow the format of nestest.log.
+
+#[test]
+fn test_format_trace() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![
+        0xA2, 0x01, // LDX #$01.
+        0xCA, // DEX.
+        0x88, // DEY.
+        0x00, // BRK.
+    ]);
+    cpu.reset();
+    cpu.register_a = 1;
+    cpu.register_x = 2;
+    cpu.register_y = 3;
+    cpu.status.set_all(0x24);
+
+    let mut result: Vec<String> = vec![];
+    cpu.run_with_callback(|cpu| {
+        result.push(trace(cpu));
+    })
+    .unwrap();
+    assert_eq!(
+        result,
+        vec![
+            "8000  A2 01     LDX #$01                        A:01 X:02 Y:03 P:24 SP:FD PPU:  0, 21 CYC:7",
+            "8002  CA        DEX                             A:01 X:01 Y:03 P:24 SP:FD PPU:  0, 27 CYC:9",
+            "8003  88        DEY                             A:01 X:00 Y:03 P:26 SP:FD PPU:  0, 33 CYC:11",
+            "8004  00        BRK                             A:01 X:00 Y:02 P:24 SP:FD PPU:  0, 39 CYC:13",
+        ]
+    );
+}
+
+// Unofficial opcodes are marked with "*" in place of the leading space, as in nestest.log.
+#[test]
+fn test_format_unofficial() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![
+        0x04, 0xA9, // NOP $A9.
+        0xA7, 0xA9, // LAX $A9.
+        0x00, // BRK.
+    ]);
+    cpu.reset();
+    cpu.status.set_all(0x24);
+
+    let mut result: Vec<String> = vec![];
+    cpu.run_with_callback(|cpu| {
+        result.push(trace(cpu));
+    })
+    .unwrap();
+    assert_eq!(
+        result[..2],
+        [
+            "8000  04 A9    *NOP $A9 = 00                    A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7",
+            "8002  A7 A9    *LAX $A9 = 00                    A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 30 CYC:10",
+        ]
+    );
+}
+
+#[test]
+fn test_format_memory_access() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![
+        0x11, 0x33, // ORA ($33),Y.
+        0xA1, 0x80, // LDA ($80,X).
+        0xB5, 0x10, // LDA $10,X.
+        0xBD, 0x00, 0x03, // LDA $0300,X.
+        0x6C, 0x00, 0x02, // JMP ($0200).
+    ]);
+    cpu.reset();
+    cpu.register_x = 0x00;
+    cpu.register_y = 0x34;
+    cpu.mem_write(0x33, 0x00);
+    cpu.mem_write(0x34, 0x04);
+    cpu.mem_write(0x0434, 0x5A);
+    cpu.mem_write(0x80, 0x00);
+    cpu.mem_write(0x81, 0x02);
+    cpu.mem_write(0x0200, 0x7E);
+    cpu.mem_write(0x0201, 0xDB);
+
+    let mut result: Vec<String> = vec![];
+    cpu.run_with_callback(|cpu| {
+        if cpu.program_counter == 0xDB7E {
+            // Stop after the jump.
+            cpu.mem_write(0xDB7E, 0x00);
+            return;
+        }
+        // Strip the register state.
+        result.push(trace(cpu)[0..47].trim_end().to_string());
+    })
+    .unwrap();
+    assert_eq!(
+        result,
+        vec![
+            "8000  11 33     ORA ($33),Y = 0400 @ 0434 = 5A",
+            "8002  A1 80     LDA ($80,X) @ 80 = 0200 = 7E",
+            "8004  B5 10     LDA $10,X @ 10 = 00",
+            "8006  BD 00 03  LDA $0300,X @ 0300 = 00",
+            "8009  6C 00 02  JMP ($0200) = DB7E",
+        ]
+    );
+}
+
+#[test]
+fn test_format_branch_cycles() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![
+        0xD0, 0x00, // BNE $8002. Taken.
+        0xF0, 0x00, // BEQ $8004. Not taken.
+        0x00, // BRK.
+    ]);
+    cpu.reset();
+    let mut result: Vec<String> = vec![];
+    cpu.run_with_callback(|cpu| {
+        result.push(trace(cpu)[0..47].trim_end().to_string() + &format!(" CYC:{}", cpu.bus.cycles));
+    })
+    .unwrap();
+    assert_eq!(
+        result,
+        vec![
+            "8000  D0 00     BNE $8002 CYC:7",
+            "8002  F0 00     BEQ $8004 CYC:10",
+            "8004  00        BRK CYC:12",
+        ]
+    );
+}
+
+#[test]
+fn test_page_cross_cycles() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![
+        0xBD, 0xFF, 0x02, // LDA $02FF,X. Crosses a page.
+        0x9D, 0xFF, 0x02, // STA $02FF,X. Always 5 cycles.
+        0x00, // BRK.
+    ]);
+    cpu.reset();
+    cpu.register_x = 1;
+    let mut cycles: Vec<usize> = vec![];
+    cpu.run_with_callback(|cpu| {
+        cycles.push(cpu.bus.cycles);
+    })
+    .unwrap();
+    assert_eq!(cycles, vec![7, 12, 17]);
+}
+
+fn test_roms_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_roms")
+}
+
+// compare_trace runs `cpu` and compares the trace of each instruction to the lines of `expected`.
+// Returns an error describing the first divergence.
+fn compare_trace(cpu: &mut CPU, expected: &str) -> Result<(), String> {
+    let mut previous = String::from("(none)");
+    for (i, want) in expected.lines().enumerate() {
+        let want = want.trim_end();
+        let got = trace(cpu);
+        if got != want {
+            return Err(format!(
+                "trace diverged at line {}:\nprevious: {}\nexpected: {}\n     got: {}",
+                i + 1,
+                previous,
+                want,
+                got
+            ));
+        }
+        previous = got;
+        cpu.step();
+    }
+    Ok(())
+}
+
+#[test]
+fn test_compare_trace_reports_divergence() {
+    let raw = make_ines(&[0xEA, 0xEA], &[], 0);
+    let mut cpu = CPU::new();
+    cpu.load_rom(Rom::new(&raw).unwrap());
+    cpu.reset();
+    cpu.program_counter = 0x8000;
+    cpu.status.set_all(0x24);
+    let expected = "\
+8000  EA        NOP                             A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7\r
+8001  EA        NOP                             A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 27 CYC:8\r
+";
+    let err = compare_trace(&mut cpu, expected).unwrap_err();
+    assert!(err.starts_with("trace diverged at line 2:"), "{}", err);
+    assert!(err.ends_with("CYC:9"), "{}", err);
+}
+
+// test_nestest runs nestest.nes in automation mode and compares against nestest.log.
+// The files are not checked in, so it only runs with `cargo test -- --ignored`. See test_roms/README.md.
+#[test]
+#[ignore = "needs test_roms/nestest.nes and test_roms/nestest.log"]
+fn test_nestest() {
+    let rom_path = test_roms_dir().join("nestest.nes");
+    let log_path = test_roms_dir().join("nestest.log");
+    for path in [&rom_path, &log_path] {
+        assert!(path.exists(), "{} is not present. See test_roms/README.md", path.display());
+    }
+
+    let rom = Rom::new(&std::fs::read(rom_path).unwrap()).unwrap();
+    let expected = std::fs::read_to_string(log_path).unwrap();
+    let mut cpu = CPU::new();
+    cpu.load_rom(rom);
+    cpu.reset();
+    // Automation mode starts at 0xC000 with InterruptDisable set.
+    cpu.program_counter = 0xC000;
+    cpu.status.set_all(0x24);
+    if let Err(e) = compare_trace(&mut cpu, &expected) {
+        panic!("{}", e);
+    }
+}
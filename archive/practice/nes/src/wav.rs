@@ -0,0 +1,72 @@
+// wav writes 16-bit mono PCM audio as WAV files. See http://soundfile.sapp.org/doc/WaveFormat/
+use std::io::{self, Seek, SeekFrom, Write};
+
+const HEADER_SIZE: u32 = 44;
+
+// WavWriter streams samples to `w`. The sizes in the header are written by `finish`, so `w` must be seekable.
+pub struct WavWriter<W: Write + Seek> {
+    w: W,
+    samples: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut w: W, sample_rate: u32) -> io::Result<Self> {
+        w.write_all(&header(sample_rate, 0))?;
+        Ok(WavWriter { w, samples: 0 })
+    }
+
+    pub fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(samples.len() * 2);
+        for sample in samples {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+        self.w.write_all(&bytes)?;
+        self.samples += samples.len() as u32;
+        Ok(())
+    }
+
+    // finish patches the chunk sizes and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let data_size = self.samples * 2;
+        self.w.seek(SeekFrom::Start(4))?;
+        self.w
+            .write_all(&(HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+        self.w.seek(SeekFrom::Start(40))?;
+        self.w.write_all(&data_size.to_le_bytes())?;
+        self.w.seek(SeekFrom::End(0))?;
+        self.w.flush()?;
+        Ok(self.w)
+    }
+}
+
+// encode returns a complete WAV file.
+pub fn encode(sample_rate: u32, samples: &[i16]) -> Vec<u8> {
+    let mut wav = header(sample_rate, samples.len() as u32 * 2).to_vec();
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    wav
+}
+
+fn header(sample_rate: u32, data_size: u32) -> [u8; HEADER_SIZE as usize] {
+    let mut h = Vec::with_capacity(HEADER_SIZE as usize);
+    h.extend_from_slice(b"RIFF");
+    h.extend_from_slice(&(HEADER_SIZE - 8 + data_size).to_le_bytes());
+    h.extend_from_slice(b"WAVE");
+    h.extend_from_slice(b"fmt ");
+    h.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, 1 channel.
+    h.extend_from_slice(&1u16.to_le_bytes());
+    h.extend_from_slice(&1u16.to_le_bytes());
+    h.extend_from_slice(&sample_rate.to_le_bytes());
+    // Byte rate and block align, for 2 bytes per sample.
+    h.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+    h.extend_from_slice(&2u16.to_le_bytes());
+    h.extend_from_slice(&16u16.to_le_bytes());
+    h.extend_from_slice(b"data");
+    h.extend_from_slice(&data_size.to_le_bytes());
+    h.try_into().unwrap()
+}
+
+#[cfg(test)]
+pub mod test;
@@ -0,0 +1,52 @@
+use super::*;
+use std::io::Cursor;
+
+// decode parses a 16-bit mono PCM WAV file written by this module, or a reference capture in the same format.
+// Returns the sample rate and the samples.
+pub fn decode(wav: &[u8]) -> Result<(u32, Vec<i16>), String> {
+    if wav.len() < HEADER_SIZE as usize || &wav[..4] != b"RIFF" || &wav[8..12] != b"WAVE" {
+        return Err("not a WAV file".to_string());
+    }
+    let u16_at = |i: usize| u16::from_le_bytes([wav[i], wav[i + 1]]);
+    let u32_at = |i: usize| u32::from_le_bytes(wav[i..i + 4].try_into().unwrap());
+    if u16_at(20) != 1 || u16_at(22) != 1 || u16_at(34) != 16 {
+        return Err("want 16-bit mono PCM".to_string());
+    }
+    let data_size = u32_at(40) as usize;
+    let data = wav.get(44..44 + data_size).ok_or("truncated data chunk")?;
+    let samples = data
+        .chunks(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+        .collect();
+    Ok((u32_at(24), samples))
+}
+
+#[test]
+fn test_encode() {
+    let wav = encode(44100, &[0, 1, -1, i16::MAX]);
+    assert_eq!(wav.len(), 44 + 8);
+    assert_eq!(&wav[..4], b"RIFF");
+    assert_eq!(u32::from_le_bytes(wav[4..8].try_into().unwrap()), 36 + 8);
+    assert_eq!(u32::from_le_bytes(wav[28..32].try_into().unwrap()), 88200);
+    assert_eq!(decode(&wav), Ok((44100, vec![0, 1, -1, i16::MAX])));
+}
+
+#[test]
+fn test_writer_matches_encode() -> Result<(), String> {
+    let mut writer = WavWriter::new(Cursor::new(Vec::new()), 22050).map_err(|e| e.to_string())?;
+    writer
+        .write_samples(&[1, 2, 3])
+        .map_err(|e| e.to_string())?;
+    writer.write_samples(&[-4]).map_err(|e| e.to_string())?;
+    let wav = writer.finish().map_err(|e| e.to_string())?.into_inner();
+    assert_eq!(wav, encode(22050, &[1, 2, 3, -4]));
+    Ok(())
+}
+
+#[test]
+fn test_decode_errors() {
+    assert_eq!(decode(b"RIFF"), Err("not a WAV file".to_string()));
+    let mut wav = encode(44100, &[1, 2]);
+    wav.truncate(46);
+    assert_eq!(decode(&wav), Err("truncated data chunk".to_string()));
+}